@@ -1,12 +1,18 @@
 //! Axis Aligned Bounding Boxes.
 
-use std::f32;
-use std::fmt;
-use std::ops::Index;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::ops::Index;
 
 use crate::{Point3, Vector3};
 
 use crate::axis::Axis;
+use crate::ray::Ray;
 
 /// AABB struct.
 #[derive(Debug, Copy, Clone)]
@@ -26,6 +32,127 @@ impl fmt::Display for AABB {
     }
 }
 
+/// One of the two sides of an [`AABB`] along an [`Axis`].
+///
+/// [`AABB`]: struct.AABB.html
+/// [`Axis`]: ../axis/enum.Axis.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Side {
+    /// The side at the minimum bound.
+    Min,
+
+    /// The side at the maximum bound.
+    Max,
+}
+
+/// How an [`AABB`] lies relative to the plane of a half-space, see
+/// [`AABB::classify_half_space`].
+///
+/// [`AABB`]: struct.AABB.html
+/// [`AABB::classify_half_space`]: struct.AABB.html#method.classify_half_space
+///
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HalfSpaceClass {
+    /// The [`AABB`] lies completely on the positive side of the plane, which it may touch.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    Positive,
+
+    /// The [`AABB`] lies completely on the negative side of the plane.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    Negative,
+
+    /// The plane passes through the [`AABB`].
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    Spanning,
+}
+
+/// An [`AABB`] which implements [`Eq`] and [`Hash`] by the bit patterns of its bounds, so
+/// that it can be the key of a `HashMap`, e.g. to cache expensive computations per box.
+///
+/// The bounds of an [`AABB`] are `f32`s, which are only [`PartialEq`]: `NaN` is not equal to
+/// itself, and `-0.0` is equal to `0.0` although their bits differ. `OrderedAABB` compares the
+/// bits instead, after replacing `-0.0` by `0.0` and every `NaN` by the same `NaN`. So bounds
+/// which are equal as floats are equal keys, and so are any two `NaN` bounds.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{OrderedAABB, AABB};
+/// use bvh::Point3;
+/// use std::collections::HashMap;
+///
+/// let aabb = AABB::with_bounds(Point3::new(-0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+/// let mut volumes = HashMap::new();
+/// volumes.insert(OrderedAABB(aabb), aabb.volume());
+///
+/// let same = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+/// assert_eq!(volumes.get(&OrderedAABB(same)), Some(&1.0));
+/// ```
+///
+/// [`AABB`]: struct.AABB.html
+/// [`Eq`]: https://doc.rust-lang.org/std/cmp/trait.Eq.html
+/// [`Hash`]: https://doc.rust-lang.org/std/hash/trait.Hash.html
+/// [`PartialEq`]: https://doc.rust-lang.org/std/cmp/trait.PartialEq.html
+///
+#[derive(Debug, Copy, Clone)]
+pub struct OrderedAABB(pub AABB);
+
+impl OrderedAABB {
+    /// Returns the bits of the bounds, with `-0.0` and `NaN` replaced by canonical values.
+    fn key(&self) -> [u32; 6] {
+        let bits = |x: f32| {
+            if x == 0.0 {
+                0
+            } else if x.is_nan() {
+                f32::NAN.to_bits()
+            } else {
+                x.to_bits()
+            }
+        };
+        let AABB { min, max } = self.0;
+        [
+            bits(min.x),
+            bits(min.y),
+            bits(min.z),
+            bits(max.x),
+            bits(max.y),
+            bits(max.z),
+        ]
+    }
+}
+
+impl PartialEq for OrderedAABB {
+    fn eq(&self, other: &OrderedAABB) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for OrderedAABB {}
+
+impl Hash for OrderedAABB {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl From<AABB> for OrderedAABB {
+    fn from(aabb: AABB) -> OrderedAABB {
+        OrderedAABB(aabb)
+    }
+}
+
+impl From<OrderedAABB> for AABB {
+    fn from(ordered: OrderedAABB) -> AABB {
+        ordered.0
+    }
+}
+
 /// A trait implemented by things which can be bounded by an [`AABB`].
 ///
 /// [`AABB`]: struct.AABB.html
@@ -60,6 +187,28 @@ pub trait Bounded {
     fn aabb(&self) -> AABB;
 }
 
+/// A trait implemented by things which can compute the point on themselves which is closest
+/// to some query point. Used by [`BVH::nearest_point`].
+///
+/// [`BVH::nearest_point`]: ../bvh/struct.BVH.html#method.nearest_point
+///
+pub trait SurfacePoint {
+    /// Returns the point on this object which is closest to `p`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, SurfacePoint};
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(1.0,1.0,1.0));
+    /// let closest = aabb.closest_point_on(&Point3::new(2.0,0.5,-1.0));
+    ///
+    /// assert_eq!(closest, Point3::new(1.0,0.5,0.0));
+    /// ```
+    ///
+    fn closest_point_on(&self, p: &Point3) -> Point3;
+}
+
 impl AABB {
     /// Creates a new [`AABB`] with the given bounds.
     ///
@@ -79,6 +228,59 @@ impl AABB {
         AABB { min, max }
     }
 
+    /// Creates the [`AABB`] of an oriented bounding box, given by its `center`, its
+    /// `half_extents` along its local axes and its `rotation`. The result is conservative:
+    /// it contains the whole oriented box, but is larger than it unless the box is aligned
+    /// with the world axes. It is the [`AABB`] of the eight rotated corners, computed by
+    /// projecting the rotated half extents onto the world axes.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::{Point3, Vector3};
+    /// use glam::Quat;
+    ///
+    /// let center = Point3::new(0.0, 0.0, 5.0);
+    /// let half_extents = Vector3::new(1.0, 1.0, 1.0);
+    /// let rotation = Quat::from_rotation_z(std::f32::consts::FRAC_PI_4);
+    /// let aabb = AABB::from_obb(&center, &half_extents, &rotation);
+    ///
+    /// let half_diagonal = 2.0f32.sqrt();
+    /// assert!((aabb.min - Point3::new(-half_diagonal, -half_diagonal, 4.0)).length() < 1e-6);
+    /// assert!((aabb.max - Point3::new(half_diagonal, half_diagonal, 6.0)).length() < 1e-6);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn from_obb(center: &Point3, half_extents: &Vector3, rotation: &glam::Quat) -> AABB {
+        let extents = (*rotation * Vector3::new(half_extents.x, 0.0, 0.0)).abs()
+            + (*rotation * Vector3::new(0.0, half_extents.y, 0.0)).abs()
+            + (*rotation * Vector3::new(0.0, 0.0, half_extents.z)).abs();
+        AABB::with_bounds(*center - extents, *center + extents)
+    }
+
+    /// Creates the [`AABB`] of a sphere, given by its `center` and `radius`. This is the
+    /// tightest [`AABB`] which contains the whole sphere: it spans `center - radius` to
+    /// `center + radius` along every axis, and touches the sphere in the middle of each face.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::from_sphere(&Point3::new(1.0, 2.0, 3.0), 0.5);
+    ///
+    /// assert_eq!(aabb.min, Point3::new(0.5, 1.5, 2.5));
+    /// assert_eq!(aabb.max, Point3::new(1.5, 2.5, 3.5));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn from_sphere(center: &Point3, radius: f32) -> AABB {
+        let extents = Vector3::splat(radius);
+        AABB::with_bounds(*center - extents, *center + extents)
+    }
+
     /// Creates a new empty [`AABB`].
     ///
     /// # Examples
@@ -112,6 +314,62 @@ impl AABB {
         }
     }
 
+    /// Creates a new infinite [`AABB`], which contains every point. This is the bounding box
+    /// of unbounded objects, such as infinite planes or environment maps. Joining it with
+    /// any other [`AABB`] gives an infinite [`AABB`] again, and every [`Ray`] hits it.
+    ///
+    /// Its [`surface_area`] and [`volume`] are infinite, and its [`center`] is NaN. The split
+    /// heuristics of the hierarchies can't place such a shape sensibly, so it is better kept
+    /// in a separate list which is tested against every ray.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::infinite();
+    /// assert!(aabb.contains(&Point3::new(1e30, -1e30, 0.0)));
+    /// assert!(!aabb.is_empty());
+    /// assert_eq!(aabb.volume(), f32::INFINITY);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`Ray`]: ../ray/struct.Ray.html
+    /// [`surface_area`]: struct.AABB.html#method.surface_area
+    /// [`volume`]: struct.AABB.html#method.volume
+    /// [`center`]: struct.AABB.html#method.center
+    ///
+    pub fn infinite() -> AABB {
+        AABB {
+            min: Point3::splat(f32::NEG_INFINITY),
+            max: Point3::splat(f32::INFINITY),
+        }
+    }
+
+    /// Creates the [`AABB`] of the unit cube centered at the origin, which spans
+    /// `[-0.5, 0.5]` along every axis. Together with [`transform`] this gives the
+    /// [`AABB`] of an arbitrarily placed box.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::unit();
+    /// assert_eq!(aabb.volume(), 1.0);
+    /// assert_eq!(aabb.center(), Point3::ZERO);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`transform`]: #method.transform
+    ///
+    pub fn unit() -> AABB {
+        AABB {
+            min: Point3::splat(-0.5),
+            max: Point3::splat(0.5),
+        }
+    }
+
     /// Returns true if the [`Point3`] is inside the [`AABB`].
     ///
     /// # Examples
@@ -139,6 +397,52 @@ impl AABB {
             && p.z <= self.max.z
     }
 
+    /// Returns true if the `other` [`AABB`] lies entirely inside this [`AABB`].
+    /// Unlike an intersection test, this fails if `other` only partially overlaps `self`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    /// let inner = AABB::with_bounds(Point3::new(-0.5, 0.0, 0.0), Point3::new(0.5, 1.0, 0.25));
+    /// let overlapping = AABB::with_bounds(Point3::new(0.5, 0.5, 0.5), Point3::new(2.0, 2.0, 2.0));
+    ///
+    /// assert!(aabb.contains_aabb(&inner));
+    /// assert!(!aabb.contains_aabb(&overlapping));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn contains_aabb(&self, other: &AABB) -> bool {
+        self.contains(&other.min) && self.contains(&other.max)
+    }
+
+    /// Returns true if this [`AABB`] and `other` overlap. [`AABB`]s which only touch
+    /// each other count as overlapping.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    /// let overlapping = AABB::with_bounds(Point3::new(0.5, 0.5, 0.5), Point3::new(2.0, 2.0, 2.0));
+    /// let touching = AABB::with_bounds(Point3::new(1.0, 0.0, 0.0), Point3::new(2.0, 1.0, 1.0));
+    /// let separate = AABB::with_bounds(Point3::new(1.5, 0.0, 0.0), Point3::new(2.0, 1.0, 1.0));
+    ///
+    /// assert!(aabb.intersects_aabb(&overlapping));
+    /// assert!(aabb.intersects_aabb(&touching));
+    /// assert!(!aabb.intersects_aabb(&separate));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn intersects_aabb(&self, other: &AABB) -> bool {
+        self.min.cmple(other.max).all() && other.min.cmple(self.max).all()
+    }
+
     /// Returns true if the [`Point3`] is approximately inside the [`AABB`]
     /// with respect to some `epsilon`.
     ///
@@ -168,6 +472,41 @@ impl AABB {
             && (p.z - self.max.z) < epsilon
     }
 
+    /// Returns true if the [`Point3`] `p` lies inside this [`AABB`] grown by `margin` on
+    /// every side, e.g. for trigger volumes with some slack. Like [`AABB::contains`], points
+    /// on the boundary of the grown [`AABB`] are contained, so a `margin` of `0.0` gives the
+    /// same result as [`AABB::contains`]. A negative `margin` shrinks the [`AABB`] instead.
+    ///
+    /// Unlike [`AABB::approx_contains_eps`], which is meant to absorb rounding errors, points
+    /// at a distance of exactly `margin` outside of a face are contained.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    /// let point_in_band = Point3::new(1.25, 0.0, -1.5);
+    ///
+    /// assert!(!aabb.contains(&point_in_band));
+    /// assert!(aabb.contains_eps_margin(&point_in_band, 0.5));
+    /// assert!(!aabb.contains_eps_margin(&point_in_band, 0.25));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`AABB::approx_contains_eps`]: struct.AABB.html#method.approx_contains_eps
+    /// [`AABB::contains`]: struct.AABB.html#method.contains
+    /// [`Point3`]: glam::Vec3
+    ///
+    pub fn contains_eps_margin(&self, p: &Point3, margin: f32) -> bool {
+        p.x >= self.min.x - margin
+            && p.x <= self.max.x + margin
+            && p.y >= self.min.y - margin
+            && p.y <= self.max.y + margin
+            && p.z >= self.min.z - margin
+            && p.z <= self.max.z + margin
+    }
+
     /// Returns true if the `other` [`AABB`] is approximately inside this [`AABB`]
     /// with respect to some `epsilon`.
     ///
@@ -218,6 +557,31 @@ impl AABB {
             && f32::abs(self.max.z - other.max.z) < epsilon
     }
 
+    /// Returns true if all bounds of the `other` [`AABB`] differ from the bounds of this
+    /// [`AABB`] by at most `epsilon`. Unlike [`relative_eq`], bounds which differ by exactly
+    /// `epsilon` are considered equal, so an `epsilon` of `0.0` tests for exact equality.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+    /// let shifted = AABB::with_bounds(Point3::new(-0.75, -1.0, -1.0), Point3::new(1.25, 1.0, 1.0));
+    ///
+    /// assert!(aabb.approx_eq(&aabb, 0.0));
+    /// assert!(aabb.approx_eq(&shifted, 0.25));
+    /// assert!(!aabb.approx_eq(&shifted, 0.125));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`relative_eq`]: #method.relative_eq
+    ///
+    pub fn approx_eq(&self, other: &AABB, epsilon: f32) -> bool {
+        (self.min - other.min).abs().max_element() <= epsilon
+            && (self.max - other.max).abs().max_element() <= epsilon
+    }
+
     /// Returns a new minimal [`AABB`] which contains both this [`AABB`] and `other`.
     /// The result is the convex hull of the both [`AABB`]s.
     ///
@@ -425,6 +789,161 @@ impl AABB {
         self.join(&other.aabb())
     }
 
+    /// Creates the joint [`AABB`] of all `shapes`, which is empty if there are none.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let points = [
+    ///     Point3::new(1.0,0.0,0.0),
+    ///     Point3::new(-1.0,2.0,0.0),
+    ///     Point3::new(0.0,0.0,3.0),
+    /// ];
+    /// let aabb = AABB::from_bounded(&points);
+    /// assert_eq!(aabb.min, Point3::new(-1.0,0.0,0.0));
+    /// assert_eq!(aabb.max, Point3::new(1.0,2.0,3.0));
+    ///
+    /// // Any iterator works, e.g. over the shapes of some indices.
+    /// let aabb = AABB::from_bounded([0, 2].iter().map(|&i| &points[i]));
+    /// assert_eq!(aabb.min, Point3::new(0.0,0.0,0.0));
+    /// assert!(AABB::from_bounded(&points[..0]).is_empty());
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn from_bounded<I>(shapes: I) -> AABB
+    where
+        I: IntoIterator,
+        I::Item: Bounded,
+    {
+        let mut aabb = AABB::empty();
+        for shape in shapes {
+            aabb.join_mut(&shape.aabb());
+        }
+        aabb
+    }
+
+    /// Linearly interpolates the bounds of this [`AABB`] and `other`, e.g. to animate a
+    /// bounding volume between two keyframes. `t = 0` returns this [`AABB`] and `t = 1`
+    /// returns `other` exactly. Joining the results for `t = 0` and `t = 1` gives bounds
+    /// which enclose the whole motion interval.
+    ///
+    /// The bounds of an empty [`AABB`] are infinite, so interpolating them would give
+    /// meaningless bounds. Instead, if one of the [`AABB`]s is empty, the other one is
+    /// returned for every `t`, and if both are empty, the result is empty.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let start = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(1.0,1.0,1.0));
+    /// let end = AABB::with_bounds(Point3::new(4.0,0.0,0.0), Point3::new(7.0,1.0,1.0));
+    ///
+    /// let halfway = start.lerp(&end, 0.5);
+    /// assert_eq!(halfway.min, Point3::new(2.0,0.0,0.0));
+    /// assert_eq!(halfway.max, Point3::new(4.0,1.0,1.0));
+    ///
+    /// assert_eq!(start.lerp(&AABB::empty(), 0.5).max, start.max);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn lerp(&self, other: &AABB, t: f32) -> AABB {
+        match (self.is_empty(), other.is_empty()) {
+            (true, true) => AABB::empty(),
+            (true, false) => *other,
+            (false, true) => *self,
+            // Weighting both ends makes `t = 0` and `t = 1` exact.
+            (false, false) => AABB::with_bounds(
+                self.min * (1.0 - t) + other.min * t,
+                self.max * (1.0 - t) + other.max * t,
+            ),
+        }
+    }
+
+    /// Returns this [`AABB`] moved by `v`. An empty [`AABB`] stays empty.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(1.0,1.0,1.0));
+    /// let moved = aabb.translate(&Vector3::new(2.0,0.0,-1.0));
+    /// assert_eq!(moved.min, Point3::new(2.0,0.0,-1.0));
+    /// assert_eq!(moved.max, Point3::new(3.0,1.0,0.0));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn translate(&self, v: &Vector3) -> AABB {
+        AABB::with_bounds(self.min + *v, self.max + *v)
+    }
+
+    /// Returns the joint [`AABB`] of this [`AABB`] and this [`AABB`] moved by `motion`.
+    /// It encloses the box during the whole motion, so a broadphase which tests the swept
+    /// bounds of moving objects doesn't miss objects which they tunnel through within a frame.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(1.0,1.0,1.0));
+    /// let swept = aabb.swept(&Vector3::new(5.0,-2.0,0.0));
+    /// assert_eq!(swept.min, Point3::new(0.0,-2.0,0.0));
+    /// assert_eq!(swept.max, Point3::new(6.0,1.0,1.0));
+    ///
+    /// // A thin wall which the box passes through.
+    /// let wall = AABB::with_bounds(Point3::new(3.0,-5.0,-5.0), Point3::new(3.1,5.0,5.0));
+    /// assert!(!aabb.intersects_aabb(&wall));
+    /// assert!(swept.intersects_aabb(&wall));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn swept(&self, motion: &Vector3) -> AABB {
+        self.join(&self.translate(motion))
+    }
+
+    /// Returns this [`AABB`] with every axis along which it is smaller than `min_size` grown
+    /// symmetrically about its center to `min_size`. This gives flat shapes, e.g. axis-aligned
+    /// triangles, bounds with a volume, so that ray slab tests against them don't compute
+    /// `0 * inf = NaN` for rays parallel to them. An empty [`AABB`] stays empty.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let flat = AABB::with_bounds(Point3::new(0.0,0.0,2.0), Point3::new(4.0,4.0,2.0));
+    /// let padded = flat.pad_to_minimum_size(0.5);
+    /// assert_eq!(padded.min, Point3::new(0.0,0.0,1.75));
+    /// assert_eq!(padded.max, Point3::new(4.0,4.0,2.25));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn pad_to_minimum_size(&self, min_size: f32) -> AABB {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let mut padded = *self;
+        let center = self.center();
+        for axis in 0..3 {
+            if self.max[axis] - self.min[axis] < min_size {
+                // Rounding must not move the bounds inwards.
+                padded.min[axis] = self.min[axis].min(center[axis] - min_size / 2.0);
+                padded.max[axis] = self.max[axis].max(center[axis] + min_size / 2.0);
+            }
+        }
+        padded
+    }
+
     /// Returns the size of this [`AABB`] in all three dimensions.
     ///
     /// # Examples
@@ -489,29 +1008,58 @@ impl AABB {
         self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
     }
 
-    /// Returns the total surface area of this [`AABB`].
+    /// Returns true if the lower bound of this [`AABB`] is at most its upper bound in every
+    /// component, i.e. if it is neither empty nor contains a `NaN`. Such corrupt bounds come
+    /// e.g. from offsetting a `NaN` position, and are rejected by the builders of a [`BVH`].
     ///
     /// # Examples
     /// ```
     /// use bvh::aabb::AABB;
     /// use bvh::Point3;
     ///
-    /// let min = Point3::new(41.0,41.0,41.0);
-    /// let max = Point3::new(43.0,43.0,43.0);
+    /// let aabb = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(1.0,0.0,1.0));
+    /// assert!(aabb.is_valid());
+    /// assert!(AABB::infinite().is_valid());
     ///
-    /// let aabb = AABB::with_bounds(min, max);
-    /// let surface_area = aabb.surface_area();
-    /// assert!(surface_area == 24.0);
+    /// assert!(!AABB::empty().is_valid());
+    /// let corrupt = AABB::with_bounds(Point3::new(0.0,f32::NAN,0.0), Point3::new(1.0,1.0,1.0));
+    /// assert!(!corrupt.is_valid());
     /// ```
     ///
     /// [`AABB`]: struct.AABB.html
+    /// [`BVH`]: ../bvh/struct.BVH.html
     ///
-    pub fn surface_area(&self) -> f32 {
-        let size = self.size();
-        2.0 * (size.x * size.y + size.x * size.z + size.y * size.z)
+    pub fn is_valid(&self) -> bool {
+        // Every comparison with a `NaN` is false.
+        self.min.x <= self.max.x && self.min.y <= self.max.y && self.min.z <= self.max.z
+    }
+
+    /// Returns the total surface area of this [`AABB`].
+    /// The surface area of an [`AABB::infinite`] is infinite.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let min = Point3::new(41.0,41.0,41.0);
+    /// let max = Point3::new(43.0,43.0,43.0);
+    ///
+    /// let aabb = AABB::with_bounds(min, max);
+    /// let surface_area = aabb.surface_area();
+    /// assert!(surface_area == 24.0);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`AABB::infinite`]: struct.AABB.html#method.infinite
+    ///
+    pub fn surface_area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.x * size.z + size.y * size.z)
     }
 
     /// Returns the volume of this [`AABB`].
+    /// The volume of an [`AABB::infinite`] is infinite.
     ///
     /// # Examples
     /// ```
@@ -527,12 +1075,61 @@ impl AABB {
     /// ```
     ///
     /// [`AABB`]: struct.AABB.html
+    /// [`AABB::infinite`]: struct.AABB.html#method.infinite
     ///
     pub fn volume(&self) -> f32 {
         let size = self.size();
         size.x * size.y * size.z
     }
 
+    /// Returns the aspect ratio of the faces of this [`AABB`] in the xy plane: the length of
+    /// the longer of the x and y edges divided by the shorter one. Square faces have an aspect
+    /// ratio of `1.0`, while large values indicate slab-like or needle-like bounds.
+    ///
+    /// If only the shorter edge has length zero, the aspect ratio is infinite. If both edges
+    /// have length zero, e.g. for a point, the aspect ratio is `NaN`, and so it is for an
+    /// empty [`AABB`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(1.0,4.0,0.0));
+    /// assert_eq!(aabb.xy_aspect(), 4.0);
+    /// assert_eq!(aabb.xz_aspect(), f32::INFINITY);
+    ///
+    /// let point = AABB::with_bounds(Point3::new(1.0,2.0,3.0), Point3::new(1.0,2.0,3.0));
+    /// assert!(point.xy_aspect().is_nan());
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn xy_aspect(&self) -> f32 {
+        let size = self.size();
+        aspect(size.x, size.y)
+    }
+
+    /// Returns the aspect ratio of the faces of this [`AABB`] in the xz plane, like
+    /// [`AABB::xy_aspect`]. It is `NaN` if both the x and z edges have length zero.
+    ///
+    /// [`AABB::xy_aspect`]: struct.AABB.html#method.xy_aspect
+    ///
+    pub fn xz_aspect(&self) -> f32 {
+        let size = self.size();
+        aspect(size.x, size.z)
+    }
+
+    /// Returns the aspect ratio of the faces of this [`AABB`] in the yz plane, like
+    /// [`AABB::xy_aspect`]. It is `NaN` if both the y and z edges have length zero.
+    ///
+    /// [`AABB::xy_aspect`]: struct.AABB.html#method.xy_aspect
+    ///
+    pub fn yz_aspect(&self) -> f32 {
+        let size = self.size();
+        aspect(size.y, size.z)
+    }
+
     /// Returns the axis along which the [`AABB`] is stretched the most.
     ///
     /// # Examples
@@ -561,6 +1158,638 @@ impl AABB {
             Axis::Z
         }
     }
+
+    /// Returns the squared distance from `p` to the closest point of this [`AABB`].
+    /// Points inside the [`AABB`] have a distance of `0.0`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0,-1.0,-1.0), Point3::new(1.0,1.0,1.0));
+    ///
+    /// assert_eq!(aabb.distance_squared_to_point(&Point3::new(0.5,0.0,0.0)), 0.0);
+    /// assert_eq!(aabb.distance_squared_to_point(&Point3::new(3.0,-3.0,0.0)), 8.0);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn distance_squared_to_point(&self, p: &Point3) -> f32 {
+        let outside = (self.min - *p).max(*p - self.max).max(Vector3::ZERO);
+        outside.length_squared()
+    }
+
+    /// Returns the minimum Euclidean distance between this [`AABB`] and `other`.
+    /// Overlapping or touching [`AABB`]s have a distance of `0.0`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb1 = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(1.0,1.0,1.0));
+    /// let aabb2 = AABB::with_bounds(Point3::new(4.0,5.0,0.5), Point3::new(6.0,6.0,2.0));
+    ///
+    /// assert_eq!(aabb1.distance_to_aabb(&aabb2), 5.0);
+    /// assert_eq!(aabb1.distance_to_aabb(&aabb1), 0.0);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn distance_to_aabb(&self, other: &AABB) -> f32 {
+        let gap = (other.min - self.max)
+            .max(self.min - other.max)
+            .max(Vector3::ZERO);
+        gap.length()
+    }
+
+    /// Returns the face of this [`AABB`] on the given `side` of `axis`.
+    /// The face is a degenerate [`AABB`] with zero thickness along `axis`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Side};
+    /// use bvh::axis::Axis;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0,-2.0,-3.0), Point3::new(1.0,2.0,3.0));
+    /// let face = aabb.face_aabb(Axis::Y, Side::Max);
+    ///
+    /// assert_eq!(face.min, Point3::new(-1.0,2.0,-3.0));
+    /// assert_eq!(face.max, Point3::new(1.0,2.0,3.0));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn face_aabb(&self, axis: Axis, side: Side) -> AABB {
+        let mut face = *self;
+        match side {
+            Side::Min => face.max[axis] = self.min[axis],
+            Side::Max => face.min[axis] = self.max[axis],
+        }
+        face
+    }
+
+    /// Returns where `ray` leaves this [`AABB`]: the distance `t` along the ray, and the
+    /// [`Axis`] and [`Side`] of the face it exits through. If the origin of the ray lies
+    /// inside, this is the face in front of it. Chaining rays from the exit point through
+    /// adjacent cells of a grid visits them in order.
+    ///
+    /// Returns `None` if the ray misses the [`AABB`] or lies behind it. If the ray leaves
+    /// through an edge or corner, the first of the touching faces in the order x, y, z is
+    /// returned.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Side};
+    /// use bvh::axis::Axis;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0,-1.0,-1.0), Point3::new(1.0,1.0,1.0));
+    ///
+    /// let inside = Ray::new(Point3::new(0.0,0.0,0.0), Vector3::new(0.0,-1.0,0.0));
+    /// assert_eq!(aabb.exit_face(&inside), Some((1.0, Axis::Y, Side::Min)));
+    ///
+    /// let outside = Ray::new(Point3::new(-5.0,0.5,0.0), Vector3::new(1.0,0.0,0.0));
+    /// assert_eq!(aabb.exit_face(&outside), Some((6.0, Axis::X, Side::Max)));
+    ///
+    /// let behind = Ray::new(Point3::new(5.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    /// assert_eq!(aabb.exit_face(&behind), None);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`Axis`]: ../axis/enum.Axis.html
+    /// [`Side`]: enum.Side.html
+    ///
+    pub fn exit_face(&self, ray: &Ray) -> Option<(f32, Axis, Side)> {
+        self.ray_slabs(ray).map(|(_, exit)| exit)
+    }
+
+    /// Returns the points where `ray` enters and leaves this [`AABB`]. If the origin of the
+    /// ray lies inside, the entry point is the origin itself. Both points are clamped to the
+    /// [`AABB`], so rounding errors never move them outside of it.
+    ///
+    /// Returns `None` if the ray misses the [`AABB`] or lies behind it. Use
+    /// [`Ray::intersection_interval`] for the distances along the ray instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0,-1.0,-1.0), Point3::new(1.0,1.0,1.0));
+    ///
+    /// let outside = Ray::new(Point3::new(-5.0,0.5,0.0), Vector3::new(1.0,0.0,0.0));
+    /// assert_eq!(
+    ///     aabb.intersect_ray_points(&outside),
+    ///     Some((Point3::new(-1.0,0.5,0.0), Point3::new(1.0,0.5,0.0)))
+    /// );
+    ///
+    /// let inside = Ray::new(Point3::new(0.0,0.0,0.0), Vector3::new(0.0,0.0,-1.0));
+    /// assert_eq!(
+    ///     aabb.intersect_ray_points(&inside),
+    ///     Some((Point3::new(0.0,0.0,0.0), Point3::new(0.0,0.0,-1.0)))
+    /// );
+    ///
+    /// let behind = Ray::new(Point3::new(5.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    /// assert_eq!(aabb.intersect_ray_points(&behind), None);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`Ray::intersection_interval`]: ../ray/struct.Ray.html#method.intersection_interval
+    ///
+    pub fn intersect_ray_points(&self, ray: &Ray) -> Option<(Point3, Point3)> {
+        self.ray_slabs(ray).map(|(entry_t, (exit_t, _, _))| {
+            let point = |t: f32| ray.at(t).clamp(self.min, self.max);
+            (point(entry_t.max(0.0)), point(exit_t))
+        })
+    }
+
+    /// Tests many `rays` against this [`AABB`] at once, e.g. for culling the rays that can
+    /// reach a light through its bounding volume. `out[i]` is set to whether `rays[i]`
+    /// intersects this [`AABB`], with exactly the same result as [`Ray::intersects_aabb`].
+    ///
+    /// The slab test runs branch free in a tight loop, which keeps the bounds of this
+    /// [`AABB`] in registers and lets the compiler vectorize it. This is faster than
+    /// calling [`Ray::intersects_aabb`] for each ray.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rays` and `out` differ in length.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let light = AABB::with_bounds(Point3::new(-1.0,-1.0,-1.0), Point3::new(1.0,1.0,1.0));
+    /// let rays = [
+    ///     Ray::new(Point3::new(-5.0,0.0,0.0), Vector3::new(1.0,0.0,0.0)),
+    ///     Ray::new(Point3::new(-5.0,0.0,0.0), Vector3::new(-1.0,0.0,0.0)),
+    ///     Ray::new(Point3::new(0.0,5.0,0.0), Vector3::new(0.1,-1.0,0.0)),
+    /// ];
+    /// let mut hits = [false; 3];
+    /// light.intersects_rays(&rays, &mut hits);
+    ///
+    /// assert_eq!(hits, [true, false, true]);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`Ray::intersects_aabb`]: ../ray/struct.Ray.html#method.intersects_aabb
+    ///
+    pub fn intersects_rays(&self, rays: &[Ray], out: &mut [bool]) {
+        assert_eq!(
+            rays.len(),
+            out.len(),
+            "Every ray needs exactly one output slot."
+        );
+        let bounds = [self.min, self.max];
+        for (hit, ray) in out.iter_mut().zip(rays) {
+            // Mirrors `Ray::intersects_aabb`, but collects the misses instead of returning early.
+            let mut ray_min = (bounds[ray.sign_x].x - ray.origin.x) * ray.inv_direction.x;
+            let mut ray_max = (bounds[1 - ray.sign_x].x - ray.origin.x) * ray.inv_direction.x;
+
+            let y_min = (bounds[ray.sign_y].y - ray.origin.y) * ray.inv_direction.y;
+            let y_max = (bounds[1 - ray.sign_y].y - ray.origin.y) * ray.inv_direction.y;
+
+            let mut miss = (ray_min > y_max) | (y_min > ray_max);
+            if y_min > ray_min {
+                ray_min = y_min;
+            }
+            if y_max < ray_max {
+                ray_max = y_max;
+            }
+
+            let z_min = (bounds[ray.sign_z].z - ray.origin.z) * ray.inv_direction.z;
+            let z_max = (bounds[1 - ray.sign_z].z - ray.origin.z) * ray.inv_direction.z;
+
+            miss |= (ray_min > z_max) | (z_min > ray_max);
+            if z_max < ray_max {
+                ray_max = z_max;
+            }
+
+            *hit = !miss & (ray_max > 0.0);
+        }
+    }
+
+    /// Intersects `ray` with the slabs of this [`AABB`]. Returns the distance along the ray
+    /// at which it enters, and the distance, [`Axis`] and [`Side`] of the face it leaves
+    /// through, or `None` if it misses the [`AABB`] or lies behind it.
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`Axis`]: ../axis/enum.Axis.html
+    /// [`Side`]: enum.Side.html
+    ///
+    fn ray_slabs(&self, ray: &Ray) -> Option<(f32, (f32, Axis, Side))> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut entry_t = f32::NEG_INFINITY;
+        let mut exit = None;
+        let mut exit_t = f32::INFINITY;
+        for axis in [Axis::X, Axis::Y, Axis::Z] {
+            let (near, far, side) = if ray.direction[axis] > 0.0 {
+                (self.min[axis], self.max[axis], Side::Max)
+            } else if ray.direction[axis] < 0.0 {
+                (self.max[axis], self.min[axis], Side::Min)
+            } else if self.min[axis] <= ray.origin[axis] && ray.origin[axis] <= self.max[axis] {
+                // The ray never leaves the slab of this axis.
+                continue;
+            } else {
+                return None;
+            };
+            entry_t = entry_t.max((near - ray.origin[axis]) * ray.inv_direction[axis]);
+            let t = (far - ray.origin[axis]) * ray.inv_direction[axis];
+            if t < exit_t {
+                exit_t = t;
+                exit = Some((t, axis, side));
+            }
+        }
+
+        match exit {
+            Some(exit) if exit_t >= entry_t && exit_t >= 0.0 => Some((entry_t, exit)),
+            _ => None,
+        }
+    }
+
+    /// Samples a random point on the surface of this [`AABB`], and returns it together with
+    /// the outward normal of the face it lies on. The points are distributed uniformly over the
+    /// surface, so each face is chosen with a probability proportional to its area.
+    ///
+    /// Requires the `std` feature, which enables `rand`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0,-2.0,-3.0), Point3::new(1.0,2.0,3.0));
+    /// let mut rng = rand::thread_rng();
+    /// let (point, normal) = aabb.sample_surface(&mut rng);
+    ///
+    /// assert!(aabb.contains(&point));
+    /// assert_eq!(normal.abs().max_element(), 1.0);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    #[cfg(feature = "rand")]
+    pub fn sample_surface<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> (Point3, Vector3) {
+        let size = self.size();
+        let axes = [Axis::X, Axis::Y, Axis::Z];
+        let face_areas = [size.y * size.z, size.x * size.z, size.x * size.y];
+
+        // Each axis has two faces of the same area, so choose the axis first, then the side.
+        let mut choice = rng.gen::<f32>() * face_areas.iter().sum::<f32>();
+        let mut axis = Axis::Z;
+        for (&face_axis, &area) in axes.iter().zip(&face_areas) {
+            if choice < area {
+                axis = face_axis;
+                break;
+            }
+            choice -= area;
+        }
+        let (side, sign) = if rng.gen() {
+            (Side::Max, 1.0)
+        } else {
+            (Side::Min, -1.0)
+        };
+
+        let face = self.face_aabb(axis, side);
+        let offset = Vector3::new(rng.gen(), rng.gen(), rng.gen());
+        let mut normal = Vector3::ZERO;
+        normal[axis] = sign;
+        (face.min + face.size() * offset, normal)
+    }
+
+    /// Splits this [`AABB`] at `position` along `axis`.
+    /// Returns the part below and the part above `position`.
+    ///
+    /// `position` should lie within the bounds of this [`AABB`] along `axis`.
+    /// This is checked in debug builds, in release builds it is clamped to the bounds.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::axis::Axis;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(4.0,1.0,1.0));
+    /// let (lower, upper) = aabb.split_along_axis(Axis::X, 1.0);
+    ///
+    /// assert_eq!(lower.max, Point3::new(1.0,1.0,1.0));
+    /// assert_eq!(upper.min, Point3::new(1.0,0.0,0.0));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn split_along_axis(&self, axis: Axis, position: f32) -> (AABB, AABB) {
+        debug_assert!(
+            self.min[axis] <= position && position <= self.max[axis],
+            "Split position {} is outside of [{}, {}] along axis {}.",
+            position,
+            self.min[axis],
+            self.max[axis],
+            axis
+        );
+        let position = position.max(self.min[axis]).min(self.max[axis]);
+
+        let mut lower = *self;
+        let mut upper = *self;
+        lower.max[axis] = position;
+        upper.min[axis] = position;
+        (lower, upper)
+    }
+
+    /// Classifies on which side of the plane `normal.dot(p) + d = 0` this [`AABB`] lies. The
+    /// positive side, where `normal.dot(p) + d >= 0`, includes the plane itself, like the
+    /// inside of a [`Plane`].
+    ///
+    /// Only two corners are tested: the one furthest along `normal` (the p-vertex), which
+    /// must be on the positive side unless the [`AABB`] is [`HalfSpaceClass::Negative`], and
+    /// the one furthest against it (the n-vertex), which must be on the positive side if the
+    /// [`AABB`] is [`HalfSpaceClass::Positive`]. The result for an empty [`AABB`] is
+    /// meaningless.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{HalfSpaceClass, AABB};
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0,0.0,0.0), Point3::new(1.0,1.0,1.0));
+    /// // The plane x + y = 1 through two of the corners.
+    /// let normal = Vector3::new(1.0,1.0,0.0);
+    ///
+    /// assert_eq!(aabb.classify_half_space(&normal, -1.0), HalfSpaceClass::Spanning);
+    /// assert_eq!(aabb.classify_half_space(&normal, 0.0), HalfSpaceClass::Positive);
+    /// assert_eq!(aabb.classify_half_space(&normal, -2.5), HalfSpaceClass::Negative);
+    /// assert_eq!(aabb.classify_half_space(&-normal, 2.5), HalfSpaceClass::Positive);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`HalfSpaceClass::Negative`]: enum.HalfSpaceClass.html#variant.Negative
+    /// [`HalfSpaceClass::Positive`]: enum.HalfSpaceClass.html#variant.Positive
+    /// [`Plane`]: ../frustum/struct.Plane.html
+    ///
+    pub fn classify_half_space(&self, normal: &Vector3, d: f32) -> HalfSpaceClass {
+        let positive = normal.cmpge(Vector3::ZERO);
+        let p_vertex = Vector3::select(positive, self.max, self.min);
+        if normal.dot(p_vertex) + d < 0.0 {
+            return HalfSpaceClass::Negative;
+        }
+        let n_vertex = Vector3::select(positive, self.min, self.max);
+        if normal.dot(n_vertex) + d < 0.0 {
+            HalfSpaceClass::Spanning
+        } else {
+            HalfSpaceClass::Positive
+        }
+    }
+
+    /// Returns the eight corners of this [`AABB`] and the twelve edges between them, e.g.
+    /// for drawing it as a wireframe. Bit 0, 1 and 2 of a corner's index select the
+    /// maximum instead of the minimum bound along x, y and z. Each edge is a pair of
+    /// corner indices. The first four edges loop around the bottom face (z = min), the
+    /// next four loop around the top face (z = max) in the same direction, and the last
+    /// four connect the two faces.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 2.0, 3.0));
+    /// let (corners, edges) = aabb.to_wireframe();
+    ///
+    /// assert_eq!(corners[0], aabb.min);
+    /// assert_eq!(corners[7], aabb.max);
+    /// for [a, b] in edges {
+    ///     let length = (corners[a] - corners[b]).length();
+    ///     assert!(length == 1.0 || length == 2.0 || length == 3.0);
+    /// }
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn to_wireframe(&self) -> ([Point3; 8], [[usize; 2]; 12]) {
+        let mut corners = [self.min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            for axis in 0..3 {
+                if i & (1 << axis) != 0 {
+                    corner[axis] = self.max[axis];
+                }
+            }
+        }
+
+        let edges = [
+            [0, 1],
+            [1, 3],
+            [3, 2],
+            [2, 0],
+            [4, 5],
+            [5, 7],
+            [7, 6],
+            [6, 4],
+            [0, 4],
+            [1, 5],
+            [3, 7],
+            [2, 6],
+        ];
+        (corners, edges)
+    }
+
+    /// Splits this [`AABB`] into its eight octants, e.g. for building an octree. Like the
+    /// corners of [`AABB::to_wireframe`], bit 0, 1 and 2 of an octant's index select the
+    /// upper instead of the lower half along x, y and z. Adjacent octants share their faces
+    /// exactly, so they don't overlap, and together they cover this [`AABB`] exactly.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 4.0, 8.0));
+    /// let octants = aabb.subdivide();
+    ///
+    /// assert_eq!(octants[0].min, aabb.min);
+    /// assert_eq!(octants[0].max, Point3::new(1.0, 2.0, 4.0));
+    /// assert_eq!(octants[7].min, Point3::new(1.0, 2.0, 4.0));
+    /// assert_eq!(octants[7].max, aabb.max);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`AABB::to_wireframe`]: struct.AABB.html#method.to_wireframe
+    ///
+    pub fn subdivide(&self) -> [AABB; 8] {
+        let center = self.center();
+        let mut octants = [*self; 8];
+        for (i, octant) in octants.iter_mut().enumerate() {
+            for axis in 0..3 {
+                if i & (1 << axis) != 0 {
+                    octant.min[axis] = center[axis];
+                } else {
+                    octant.max[axis] = center[axis];
+                }
+            }
+        }
+        octants
+    }
+
+    /// Splits this [`AABB`] into a grid of `nx * ny * nz` cells of equal size, with `nx`
+    /// cells along x, `ny` along y and `nz` along z. The cells are ordered with x varying
+    /// fastest, then y, then z, so the cell at `(x, y, z)` has the index
+    /// `x + nx * (y + ny * z)`. Adjacent cells share their faces exactly, and the outer
+    /// faces of the grid are the faces of this [`AABB`]. Returns no cells if any of the
+    /// counts is `0`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(4.0, 1.0, 1.0));
+    /// let cells = aabb.subdivide_along_axes(4, 1, 1);
+    ///
+    /// assert_eq!(cells.len(), 4);
+    /// assert_eq!(cells[2].min, Point3::new(2.0, 0.0, 0.0));
+    /// assert_eq!(cells[2].max, Point3::new(3.0, 1.0, 1.0));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn subdivide_along_axes(&self, nx: usize, ny: usize, nz: usize) -> Vec<AABB> {
+        // Compute every plane once, so that neighbouring cells use the same values.
+        let planes = |axis: usize, n: usize| {
+            (0..=n)
+                .map(|i| {
+                    if i == n {
+                        self.max[axis]
+                    } else {
+                        self.min[axis] + (self.max[axis] - self.min[axis]) * (i as f32 / n as f32)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let (xs, ys, zs) = (planes(0, nx), planes(1, ny), planes(2, nz));
+
+        let mut cells = Vec::with_capacity(nx * ny * nz);
+        for z in zs.windows(2) {
+            for y in ys.windows(2) {
+                for x in xs.windows(2) {
+                    cells.push(AABB::with_bounds(
+                        Point3::new(x[0], y[0], z[0]),
+                        Point3::new(x[1], y[1], z[1]),
+                    ));
+                }
+            }
+        }
+        cells
+    }
+
+    /// Returns the [`AABB`] of this [`AABB`] transformed by the affine transformation `m`.
+    /// Uses Arvo's method, which is cheaper than transforming all eight corners, but gives
+    /// the same result. An empty [`AABB`] stays empty.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    /// use glam::{Mat4, Quat};
+    ///
+    /// let m = Mat4::from_scale_rotation_translation(
+    ///     Point3::new(2.0, 1.0, 1.0),
+    ///     Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+    ///     Point3::new(0.0, 0.0, 5.0),
+    /// );
+    /// let aabb = AABB::unit().transform(&m);
+    ///
+    /// assert!((aabb.min - Point3::new(-0.5, -1.0, 4.5)).length() < 1e-6);
+    /// assert!((aabb.max - Point3::new(0.5, 1.0, 5.5)).length() < 1e-6);
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    ///
+    pub fn transform(&self, m: &glam::Mat4) -> AABB {
+        if self.is_empty() {
+            return AABB::empty();
+        }
+
+        let translation = m.w_axis.truncate();
+        let mut min = translation;
+        let mut max = translation;
+        for axis in 0..3 {
+            let column = m.col(axis).truncate();
+            let a = column * self.min[axis];
+            let b = column * self.max[axis];
+            min += a.min(b);
+            max += a.max(b);
+        }
+        AABB { min, max }
+    }
+
+    /// Returns the [`AABB`] which encloses all `boxes` after transforming them by the
+    /// affine transformation `m`. Each box is transformed with Arvo's method like in
+    /// [`AABB::transform`], and joined in the same pass. Empty boxes are skipped.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::Point3;
+    /// use glam::Mat4;
+    ///
+    /// let boxes = [
+    ///     AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)),
+    ///     AABB::with_bounds(Point3::new(3.0, 0.0, 0.0), Point3::new(4.0, 2.0, 1.0)),
+    /// ];
+    /// let m = Mat4::from_translation(Point3::new(0.0, 0.0, -1.0));
+    /// let aabb = AABB::enclosing_transformed(boxes.iter(), &m);
+    ///
+    /// assert_eq!(aabb.min, Point3::new(0.0, 0.0, -1.0));
+    /// assert_eq!(aabb.max, Point3::new(4.0, 2.0, 0.0));
+    /// ```
+    ///
+    /// [`AABB`]: struct.AABB.html
+    /// [`AABB::transform`]: struct.AABB.html#method.transform
+    ///
+    pub fn enclosing_transformed<'a>(
+        boxes: impl IntoIterator<Item = &'a AABB>,
+        m: &glam::Mat4,
+    ) -> AABB {
+        let translation = m.w_axis.truncate();
+        let columns = [
+            m.x_axis.truncate(),
+            m.y_axis.truncate(),
+            m.z_axis.truncate(),
+        ];
+
+        let mut enclosing = AABB::empty();
+        for aabb in boxes {
+            if aabb.is_empty() {
+                continue;
+            }
+
+            let mut min = translation;
+            let mut max = translation;
+            for (axis, column) in columns.iter().enumerate() {
+                let a = *column * aabb.min[axis];
+                let b = *column * aabb.max[axis];
+                min += a.min(b);
+                max += a.max(b);
+            }
+            enclosing.min = enclosing.min.min(min);
+            enclosing.max = enclosing.max.max(max);
+        }
+        enclosing
+    }
+}
+
+/// Returns the ratio of the longer to the shorter of two edge lengths.
+fn aspect(a: f32, b: f32) -> f32 {
+    a.max(b) / a.min(b)
 }
 
 /// Default instance for [`AABB`]s. Returns an [`AABB`] which is [`empty()`].
@@ -652,15 +1881,466 @@ impl Bounded for Point3 {
     }
 }
 
+/// Implementation of [`Bounded`] for references to [`Bounded`] shapes, so that a slice of
+/// references can be used wherever a slice of shapes is expected.
+///
+/// [`Bounded`]: trait.Bounded.html
+///
+impl<T: Bounded + ?Sized> Bounded for &T {
+    fn aabb(&self) -> AABB {
+        (**self).aabb()
+    }
+}
+
+/// Implementation of [`Bounded`] for boxed shapes, including trait objects.
+/// This allows shapes of different types to be stored as `Box<dyn Bounded>`.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{AABB, Bounded};
+/// use bvh::Point3;
+///
+/// let shapes: Vec<Box<dyn Bounded>> = vec![
+///     Box::new(Point3::new(1.0, 2.0, 3.0)),
+///     Box::new(AABB::with_bounds(Point3::new(-1.0, -1.0, -1.0), Point3::ZERO)),
+/// ];
+///
+/// let aabb = shapes.iter().fold(AABB::empty(), |aabb, shape| aabb.join(&shape.aabb()));
+/// assert_eq!(aabb.min, Point3::new(-1.0, -1.0, -1.0));
+/// assert_eq!(aabb.max, Point3::new(1.0, 2.0, 3.0));
+/// ```
+///
+/// [`Bounded`]: trait.Bounded.html
+///
+impl<T: Bounded + ?Sized> Bounded for Box<T> {
+    fn aabb(&self) -> AABB {
+        (**self).aabb()
+    }
+}
+
+/// Implementation of [`Bounded`] for reference counted shapes, including trait objects.
+///
+/// [`Bounded`]: trait.Bounded.html
+///
+impl<T: Bounded + ?Sized> Bounded for Rc<T> {
+    fn aabb(&self) -> AABB {
+        (**self).aabb()
+    }
+}
+
+/// Implementation of [`Bounded`] for atomically reference counted shapes,
+/// including trait objects.
+///
+/// [`Bounded`]: trait.Bounded.html
+///
+#[cfg(target_has_atomic = "ptr")]
+impl<T: Bounded + ?Sized> Bounded for Arc<T> {
+    fn aabb(&self) -> AABB {
+        (**self).aabb()
+    }
+}
+
+/// Implementation of [`SurfacePoint`] for [`AABB`]. The [`AABB`] is treated as a solid,
+/// so points inside of it are their own closest point.
+///
+/// [`AABB`]: struct.AABB.html
+/// [`SurfacePoint`]: trait.SurfacePoint.html
+///
+impl SurfacePoint for AABB {
+    fn closest_point_on(&self, p: &Point3) -> Point3 {
+        p.max(self.min).min(self.max)
+    }
+}
+
+/// Implementation of [`SurfacePoint`] for [`Point3`].
+///
+/// [`SurfacePoint`]: trait.SurfacePoint.html
+/// [`Point3`]: glam::Vec3
+///
+impl SurfacePoint for Point3 {
+    fn closest_point_on(&self, _: &Point3) -> Point3 {
+        *self
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::aabb::{Bounded, AABB};
-    use crate::testbase::{tuple_to_point, tuple_to_vector, tuplevec_large_strategy, TupleVec};
+    use crate::aabb::{Bounded, HalfSpaceClass, OrderedAABB, Side, SurfacePoint, AABB};
+    use crate::axis::Axis;
+    use crate::ray::Ray;
+    use crate::testbase::{
+        tuple_to_point, tuple_to_vector, tuplevec_large_strategy, tuplevec_small_strategy, TupleVec,
+    };
     use crate::EPSILON;
     use crate::{Point3, Vector3};
 
     use float_eq::assert_float_eq;
+    use glam::{EulerRot, Mat4, Quat};
     use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::hash::{Hash, Hasher};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[test]
+    /// Tests whether `sample_surface` returns points on the faces given by their normals,
+    /// and chooses each face with a frequency proportional to its area.
+    fn test_sample_surface_face_frequencies() {
+        let aabb = AABB::with_bounds(Point3::new(-1.0, 0.0, 2.0), Point3::new(0.0, 2.0, 5.0));
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Counts per face, in the order -X, +X, -Y, +Y, -Z, +Z.
+        let samples = 100_000;
+        let mut counts = [0; 6];
+        for _ in 0..samples {
+            let (point, normal) = aabb.sample_surface(&mut rng);
+            assert!(aabb.contains(&point));
+
+            let axis = [Axis::X, Axis::Y, Axis::Z]
+                .iter()
+                .position(|&axis| normal[axis] != 0.0)
+                .unwrap();
+            assert_eq!(normal.length(), 1.0);
+            let face = if normal.max_element() > 0.0 {
+                assert_eq!(point[axis], aabb.max[axis]);
+                2 * axis + 1
+            } else {
+                assert_eq!(point[axis], aabb.min[axis]);
+                2 * axis
+            };
+            counts[face] += 1;
+        }
+
+        // The faces normal to X, Y and Z have areas of 6, 3 and 2.
+        let face_areas = [6.0, 6.0, 3.0, 3.0, 2.0, 2.0];
+        let surface_area = aabb.surface_area();
+        for (&count, &area) in counts.iter().zip(&face_areas) {
+            let frequency = count as f32 / samples as f32;
+            assert_float_eq!(frequency, area / surface_area, abs <= 0.01);
+        }
+    }
+
+    #[test]
+    /// Tests whether the `AABB` of a sphere contains points on its surface in 1000 random
+    /// directions, and touches the sphere along every axis.
+    fn test_from_sphere_contains_surface() {
+        let center = Point3::new(3.0, -2.0, 10.0);
+        let radius = 2.5;
+        let aabb = AABB::from_sphere(&center, radius);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut directions = 0;
+        while directions < 1000 {
+            let direction = Vector3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            // Only keep directions within the unit ball, which are uniformly distributed.
+            let length = direction.length();
+            if !(0.01..=1.0).contains(&length) {
+                continue;
+            }
+            let surface_point = center + direction * (radius / length);
+            assert!(aabb.approx_contains_eps(&surface_point, EPSILON));
+            directions += 1;
+        }
+
+        for &axis in &[Axis::X, Axis::Y, Axis::Z] {
+            let mut offset = Vector3::ZERO;
+            offset[axis] = radius;
+            assert_eq!((center - offset)[axis], aabb.min[axis]);
+            assert_eq!((center + offset)[axis], aabb.max[axis]);
+        }
+    }
+
+    #[test]
+    /// Tests whether `OrderedAABB` treats `-0.0` like `0.0` and all `NaN`s alike, and tells
+    /// apart boxes which differ in any bound.
+    fn test_ordered_aabb() {
+        fn hash(aabb: AABB) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            OrderedAABB(aabb).hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let aabb = AABB::with_bounds(Point3::new(0.0, -1.0, 2.0), Point3::new(3.0, 4.0, 5.0));
+        let negative_zero =
+            AABB::with_bounds(Point3::new(-0.0, -1.0, 2.0), Point3::new(3.0, 4.0, 5.0));
+        assert_eq!(OrderedAABB(aabb), OrderedAABB(negative_zero));
+        assert_eq!(hash(aabb), hash(negative_zero));
+
+        let nan = AABB::with_bounds(Point3::new(0.0, f32::NAN, 2.0), Point3::new(3.0, 4.0, 5.0));
+        let other_nan = AABB::with_bounds(
+            Point3::new(0.0, -f32::from_bits(f32::NAN.to_bits() | 1), 2.0),
+            Point3::new(3.0, 4.0, 5.0),
+        );
+        assert_eq!(OrderedAABB(nan), OrderedAABB(nan));
+        assert_eq!(OrderedAABB(nan), OrderedAABB(other_nan));
+        assert_eq!(hash(nan), hash(other_nan));
+        assert_ne!(OrderedAABB(nan), OrderedAABB(aabb));
+
+        let mut keys = HashSet::new();
+        keys.insert(OrderedAABB(aabb));
+        for i in 0..6 {
+            let mut moved = aabb;
+            let bound = if i < 3 {
+                &mut moved.min
+            } else {
+                &mut moved.max
+            };
+            bound[i % 3] += 0.5;
+            assert_ne!(OrderedAABB(moved), OrderedAABB(aabb));
+            assert!(keys.insert(moved.into()));
+        }
+        assert!(!keys.insert(negative_zero.into()));
+        assert_eq!(AABB::from(OrderedAABB(aabb)).min, aabb.min);
+    }
+
+    #[test]
+    /// Tests `contains_eps_margin` with points just inside and just outside the margin band.
+    fn test_contains_eps_margin() {
+        let aabb = AABB::with_bounds(Point3::new(-1.0, 0.0, 2.0), Point3::new(0.0, 2.0, 5.0));
+        let margin = 0.5;
+        let center = aabb.center();
+        for axis in [Axis::X, Axis::Y, Axis::Z].iter().copied() {
+            for &(bound, sign) in &[(aabb.min, -1.0), (aabb.max, 1.0)] {
+                let at = |distance: f32| {
+                    let mut p = center;
+                    p[axis] = bound[axis] + sign * distance;
+                    p
+                };
+                assert!(aabb.contains_eps_margin(&at(0.0), margin));
+                assert!(aabb.contains_eps_margin(&at(0.49), margin));
+                assert!(aabb.contains_eps_margin(&at(margin), margin));
+                assert!(!aabb.contains_eps_margin(&at(0.51), margin));
+
+                // No margin is the same as `contains`, and a negative one shrinks the box.
+                assert!(aabb.contains_eps_margin(&at(0.0), 0.0));
+                assert!(!aabb.contains_eps_margin(&at(0.01), 0.0));
+                assert!(aabb.contains_eps_margin(&at(-0.3), -0.25));
+                assert!(!aabb.contains_eps_margin(&at(-0.2), -0.25));
+            }
+        }
+    }
+
+    #[test]
+    /// Tests `distance_to_aabb` for adjacent, diagonal and overlapping `AABB`s.
+    fn test_distance_to_aabb() {
+        let aabb = AABB::with_bounds(Point3::ZERO, Point3::ONE);
+
+        // Separated along a single axis, and touching.
+        let right = AABB::with_bounds(Point3::new(3.0, 0.5, 0.5), Point3::new(4.0, 2.0, 2.0));
+        assert_eq!(aabb.distance_to_aabb(&right), 2.0);
+        let touching = AABB::with_bounds(Point3::new(1.0, 0.0, 0.0), Point3::new(2.0, 1.0, 1.0));
+        assert_eq!(aabb.distance_to_aabb(&touching), 0.0);
+
+        // Separated diagonally, the closest points are two corners.
+        let diagonal = AABB::with_bounds(Point3::splat(3.0), Point3::splat(4.0));
+        assert_float_eq!(
+            aabb.distance_to_aabb(&diagonal),
+            12.0f32.sqrt(),
+            abs <= EPSILON
+        );
+        assert_float_eq!(
+            diagonal.distance_to_aabb(&aabb),
+            12.0f32.sqrt(),
+            abs <= EPSILON
+        );
+
+        // Overlapping and nested.
+        let overlapping = AABB::with_bounds(Point3::splat(0.5), Point3::splat(2.0));
+        assert_eq!(aabb.distance_to_aabb(&overlapping), 0.0);
+        let inner = AABB::with_bounds(Point3::splat(0.25), Point3::splat(0.75));
+        assert_eq!(aabb.distance_to_aabb(&inner), 0.0);
+        assert_eq!(inner.distance_to_aabb(&aabb), 0.0);
+    }
+
+    #[test]
+    /// Tests whether an infinite `AABB` contains all points and absorbs other `AABB`s.
+    fn test_infinite() {
+        let infinite = AABB::infinite();
+        let finite = AABB::with_bounds(Point3::new(-1.0, 2.0, 3.0), Point3::new(4.0, 5.0, 6.0));
+        for point in [
+            Point3::ZERO,
+            Point3::splat(f32::MAX),
+            Point3::splat(-f32::MAX),
+        ] {
+            assert!(infinite.contains(&point));
+        }
+        assert!(infinite.contains_aabb(&finite));
+        assert!(infinite.intersects_aabb(&finite));
+
+        for joint in [infinite.join(&finite), finite.join(&infinite)] {
+            assert_eq!(joint.min, infinite.min);
+            assert_eq!(joint.max, infinite.max);
+        }
+        assert_eq!(infinite.surface_area(), f32::INFINITY);
+        assert_eq!(infinite.volume(), f32::INFINITY);
+
+        let ray = Ray::new(Point3::new(1.0, 2.0, 3.0), Vector3::new(0.3, -0.2, 1.0));
+        assert!(ray.intersects_aabb(&infinite));
+    }
+
+    #[test]
+    /// Tests chaining rays through a row of adjacent cells with `exit_face`.
+    fn test_exit_face_through_cells() {
+        let cell = |i: i32| {
+            let min = Point3::new(i as f32, 0.0, 0.0);
+            AABB::with_bounds(min, min + Vector3::ONE)
+        };
+        let direction = Vector3::new(1.0, 0.1, 0.05);
+        let mut ray = Ray::new(Point3::new(0.5, 0.25, 0.5), direction);
+        let mut visited = vec![0];
+        while let Some((t, axis, side)) = cell(*visited.last().unwrap()).exit_face(&ray) {
+            if axis != Axis::X {
+                // The ray left the row through a side wall.
+                assert_eq!((axis, side), (Axis::Y, Side::Max));
+                break;
+            }
+            assert_eq!(side, Side::Max);
+            visited.push(visited.last().unwrap() + 1);
+            ray = Ray::new(ray.origin + ray.direction * t, direction);
+        }
+        // The ray rises by 0.1 per cell, starting at 0.25.
+        assert_eq!(visited, (0..8).collect::<Vec<_>>());
+
+        let miss = Ray::new(Point3::new(0.5, 2.0, 0.5), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(cell(0).exit_face(&miss), None);
+        assert_eq!(AABB::empty().exit_face(&miss), None);
+    }
+
+    /// Asserts that `cells` tile `parent` as a grid of `n[0] * n[1] * n[2]` cells, ordered
+    /// with x varying fastest: every cell starts exactly where its predecessor along each
+    /// axis ends, and the outer cells end exactly at the faces of `parent`.
+    fn assert_tiles(parent: &AABB, cells: &[AABB], n: [usize; 3]) {
+        assert_eq!(cells.len(), n[0] * n[1] * n[2]);
+        let stride = [1, n[0], n[0] * n[1]];
+        for (index, cell) in cells.iter().enumerate() {
+            for axis in 0..3 {
+                let coordinate = index / stride[axis] % n[axis];
+                assert!(cell.min[axis] <= cell.max[axis]);
+                if coordinate == 0 {
+                    assert_eq!(cell.min[axis], parent.min[axis]);
+                } else {
+                    assert_eq!(cell.min[axis], cells[index - stride[axis]].max[axis]);
+                }
+                if coordinate == n[axis] - 1 {
+                    assert_eq!(cell.max[axis], parent.max[axis]);
+                }
+            }
+        }
+
+        let volume = cells.iter().map(AABB::volume).sum::<f32>();
+        assert!((volume - parent.volume()).abs() <= parent.volume() * 1e-4);
+    }
+
+    #[test]
+    /// Tests the entry and exit points of axis aligned and diagonal rays, and of rays
+    /// starting inside of an `AABB`.
+    fn test_intersect_ray_points() {
+        let aabb = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 4.0, 8.0));
+
+        let axis_aligned = Ray::new(Point3::new(1.0, 1.0, 10.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(
+            aabb.intersect_ray_points(&axis_aligned),
+            Some((Point3::new(1.0, 1.0, 8.0), Point3::new(1.0, 1.0, 0.0)))
+        );
+
+        let diagonal = Ray::new(Point3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+        let (entry, exit) = aabb.intersect_ray_points(&diagonal).unwrap();
+        assert!(entry.abs_diff_eq(Point3::new(0.0, 0.0, 0.0), EPSILON));
+        assert!(exit.abs_diff_eq(Point3::new(2.0, 2.0, 2.0), EPSILON));
+
+        let inside = Ray::new(Point3::new(1.0, 2.0, 3.0), Vector3::new(-1.0, 1.0, 0.0));
+        let (entry, exit) = aabb.intersect_ray_points(&inside).unwrap();
+        assert_eq!(entry, inside.origin);
+        assert!(exit.abs_diff_eq(Point3::new(0.0, 3.0, 3.0), EPSILON));
+
+        let miss = Ray::new(Point3::new(3.0, 1.0, 1.0), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(aabb.intersect_ray_points(&miss), None);
+        let behind = Ray::new(Point3::new(1.0, 1.0, 10.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(aabb.intersect_ray_points(&behind), None);
+        assert_eq!(AABB::empty().intersect_ray_points(&axis_aligned), None);
+    }
+
+    proptest! {
+        // Test whether a ray starting inside of an `AABB` enters it at its origin, and leaves
+        // it on its boundary at the exit distance of `Ray::intersection_interval`.
+        #[test]
+        fn test_intersect_ray_points_inside_origin(
+            t in (0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0),
+            direction in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+        ) {
+            prop_assume!(tuple_to_vector(&direction).length() > 0.01);
+            let aabb = AABB::with_bounds(Point3::new(-1.0, -2.0, -3.0), Point3::new(4.0, 5.0, 6.0));
+            let origin = aabb.min + aabb.size() * tuple_to_point(&t);
+            let ray = Ray::new(origin, tuple_to_vector(&direction));
+
+            let (entry, exit) = aabb.intersect_ray_points(&ray).unwrap();
+            assert_eq!(entry, origin);
+            assert!(aabb.contains(&exit));
+            let on_min_face = (exit - aabb.min).abs().min_element() < EPSILON;
+            let on_max_face = (exit - aabb.max).abs().min_element() < EPSILON;
+            assert!(on_min_face || on_max_face);
+
+            let (entry_t, exit_t) = ray.intersection_interval(&aabb).unwrap();
+            assert!(entry_t <= 0.0);
+            assert!(((exit - entry).length() - exit_t).abs() < 1e-3);
+        }
+
+        // Test whether `classify_half_space` agrees with testing all corners of the `AABB`.
+        #[test]
+        fn test_classify_half_space_matches_corners(
+            a in (-20.0f32..20.0, -20.0f32..20.0, -20.0f32..20.0),
+            b in (-20.0f32..20.0, -20.0f32..20.0, -20.0f32..20.0),
+            normal in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+            d in -30.0f32..30.0,
+        ) {
+            let aabb = AABB::empty().grow(&tuple_to_point(&a)).grow(&tuple_to_point(&b));
+            let normal = tuple_to_vector(&normal);
+            let distances = aabb.to_wireframe().0.iter().map(|corner| normal.dot(*corner) + d).collect::<Vec<_>>();
+
+            let expected = if distances.iter().all(|&distance| distance >= 0.0) {
+                HalfSpaceClass::Positive
+            } else if distances.iter().all(|&distance| distance < 0.0) {
+                HalfSpaceClass::Negative
+            } else {
+                HalfSpaceClass::Spanning
+            };
+            assert_eq!(aabb.classify_half_space(&normal, d), expected);
+        }
+    }
+
+    proptest! {
+        // Test whether `is_valid` accepts the bounds of any points, and rejects them once a
+        // bound is inverted or becomes `NaN`.
+        #[test]
+        fn test_is_valid(a: TupleVec, b: TupleVec, axis in 0usize..3) {
+            let a = tuple_to_point(&a);
+            let b = tuple_to_point(&b);
+            let aabb = AABB::empty().grow(&a).grow(&b);
+            assert!(aabb.is_valid());
+            assert!(!aabb.is_empty());
+
+            let mut corrupt = aabb;
+            corrupt.min[axis] = f32::NAN;
+            assert!(!corrupt.is_valid());
+            let mut corrupt = aabb;
+            corrupt.max[axis] = f32::NAN;
+            assert!(!corrupt.is_valid());
+
+            // Inverting a bound makes the `AABB` empty and invalid, unless it is flat.
+            let mut inverted = aabb;
+            inverted.min[axis] = aabb.max[axis];
+            inverted.max[axis] = aabb.min[axis];
+            assert_eq!(inverted.is_valid(), aabb.min[axis] == aabb.max[axis]);
+            assert_eq!(inverted.is_valid(), !inverted.is_empty());
+        }
+    }
 
     proptest! {
         // Test whether an empty `AABB` does not contains anything.
@@ -738,6 +2418,84 @@ mod tests {
             assert!(aabb1_contains_init_five && aabb2_contains_last_five && aabbu_contains_all);
         }
 
+        // Test whether `approx_eq` accepts an `AABB` whose bounds are moved by at most `epsilon`,
+        // and rejects it for smaller epsilons.
+        #[test]
+        fn test_approx_eq(a in tuplevec_small_strategy(), b in tuplevec_small_strategy(),
+                          offset in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0)) {
+            let aabb = AABB::empty().grow(&tuple_to_point(&a)).grow(&tuple_to_point(&b));
+            let offset = Vector3::new(offset.0, offset.1, offset.2);
+            let moved = AABB::with_bounds(aabb.min + offset, aabb.max + offset);
+            let distance = (moved.min - aabb.min)
+                .abs()
+                .max((moved.max - aabb.max).abs())
+                .max_element();
+
+            assert!(aabb.approx_eq(&aabb, 0.0));
+            assert!(aabb.approx_eq(&moved, distance));
+            assert!(moved.approx_eq(&aabb, distance));
+            assert!(!aabb.approx_eq(&moved, distance * 0.5) || distance == 0.0);
+        }
+
+        // Test whether the distance between two `AABB`s is symmetric, and matches the distance
+        // to a point when one of them is degenerate.
+        #[test]
+        fn test_distance_to_aabb_matches_point(a in (tuplevec_small_strategy(), tuplevec_small_strategy()),
+                                               b in tuplevec_small_strategy()) {
+            let aabb = AABB::empty().grow(&tuple_to_point(&a.0)).grow(&tuple_to_point(&a.1));
+            let point = tuple_to_point(&b);
+            let point_aabb = AABB::with_bounds(point, point);
+
+            let distance = aabb.distance_to_aabb(&point_aabb);
+            assert_eq!(distance, point_aabb.distance_to_aabb(&aabb));
+            assert_float_eq!(distance, aabb.distance_squared_to_point(&point).sqrt(), r2nd <= EPSILON);
+        }
+
+        // Test whether two `AABB`s overlap exactly when there is no distance between them,
+        // and whether their joint contains their overlap.
+        #[test]
+        fn test_intersects_aabb(a in (tuplevec_small_strategy(), tuplevec_small_strategy()),
+                                b in (tuplevec_small_strategy(), tuplevec_small_strategy())) {
+            let aabb1 = AABB::empty().grow(&tuple_to_point(&a.0)).grow(&tuple_to_point(&a.1));
+            let aabb2 = AABB::empty().grow(&tuple_to_point(&b.0)).grow(&tuple_to_point(&b.1));
+
+            let intersects = aabb1.intersects_aabb(&aabb2);
+            assert_eq!(intersects, aabb2.intersects_aabb(&aabb1));
+            assert_eq!(intersects, aabb1.distance_to_aabb(&aabb2) == 0.0);
+            assert!(aabb1.intersects_aabb(&aabb1));
+            assert!(!aabb1.intersects_aabb(&AABB::empty()));
+        }
+
+        // Test whether references and smart pointers to a shape report the bounds of the shape.
+        #[test]
+        fn test_bounded_pointers(a in tuplevec_small_strategy(), b in tuplevec_small_strategy()) {
+            let aabb = AABB::empty().grow(&tuple_to_point(&a)).grow(&tuple_to_point(&b));
+            let shapes: [&dyn Bounded; 4] = [
+                &&aabb,
+                &Box::new(aabb),
+                &Rc::new(aabb),
+                &Arc::new(aabb),
+            ];
+            for shape in &shapes {
+                assert_eq!(shape.aabb().min, aabb.min);
+                assert_eq!(shape.aabb().max, aabb.max);
+            }
+        }
+
+        // Test whether the joint of two `AABB`s contains both of them, and whether one of them
+        // contains the joint exactly when it contains the other one.
+        #[test]
+        fn test_join_contains_aabbs(a: (TupleVec, TupleVec), b: (TupleVec, TupleVec)) {
+            let aabb1 = AABB::empty().grow(&tuple_to_point(&a.0)).grow(&tuple_to_point(&a.1));
+            let aabb2 = AABB::empty().grow(&tuple_to_point(&b.0)).grow(&tuple_to_point(&b.1));
+            let joint = aabb1.join(&aabb2);
+
+            assert!(joint.contains_aabb(&aabb1));
+            assert!(joint.contains_aabb(&aabb2));
+            assert!(joint.contains_aabb(&joint));
+            assert_eq!(aabb1.contains_aabb(&joint), aabb1.contains_aabb(&aabb2));
+        }
+
         // Test whether some points relative to the center of an AABB are classified correctly.
         // Currently doesn't test `approx_contains_eps` or `contains` very well due to scaling by 0.9 and 1.1.
         #[test]
@@ -789,6 +2547,30 @@ mod tests {
             assert_float_eq!(area_a, area_b, rmax <= EPSILON);
         }
 
+        // Test whether the aspect ratios of an `AABB` match the ratios of its edges and don't
+        // depend on the order of the edges.
+        #[test]
+        fn test_aspect_ratios(pos in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                              size in (0.1f32..100.0, 0.1f32..100.0, 0.1f32..100.0)) {
+            let min = tuple_to_point(&pos);
+            let size = tuple_to_point(&size);
+            let aabb = AABB::with_bounds(min, min + size);
+            let size = aabb.size();
+
+            let expected = |a: f32, b: f32| if a > b { a / b } else { b / a };
+            assert_eq!(aabb.xy_aspect(), expected(size.x, size.y));
+            assert_eq!(aabb.xz_aspect(), expected(size.x, size.z));
+            assert_eq!(aabb.yz_aspect(), expected(size.y, size.z));
+            assert!(aabb.xy_aspect() >= 1.0 && aabb.xz_aspect() >= 1.0 && aabb.yz_aspect() >= 1.0);
+
+            // A flat box is infinitely thin in the planes containing its zero-length edge.
+            let flat = AABB::with_bounds(min, Point3::new(min.x + size.x, min.y, min.z + size.z));
+            assert_eq!(flat.xy_aspect(), f32::INFINITY);
+            assert_eq!(flat.yz_aspect(), f32::INFINITY);
+            assert!(AABB::with_bounds(min, min).xz_aspect().is_nan());
+            assert!(AABB::empty().xy_aspect().is_nan());
+        }
+
         // Test whether the volume of a nonempty AABB is always positive.
         #[test]
         fn test_volume_always_positive(a in tuplevec_large_strategy(), b in tuplevec_large_strategy()) {
@@ -829,5 +2611,399 @@ mod tests {
             // The AABBs should be the same
             assert!(aabb.contains(&point) == aabb_by_index.contains(&point));
         }
+
+        // Test whether the closest point of an `AABB` lies inside of it and matches `distance_squared_to_point`.
+        #[test]
+        fn test_closest_point_on_aabb(a in tuplevec_small_strategy(), b in tuplevec_small_strategy(), p in tuplevec_small_strategy()) {
+            let aabb = AABB::empty()
+                .grow(&tuple_to_point(&a))
+                .grow(&tuple_to_point(&b));
+            let p = tuple_to_point(&p);
+
+            let closest = aabb.closest_point_on(&p);
+            assert!(aabb.contains(&closest));
+            assert_float_eq!((closest - p).length_squared(), aabb.distance_squared_to_point(&p), rmax <= EPSILON);
+        }
+
+        // Test whether both faces along an axis are flat, lie on the bounds of the `AABB`
+        // and join back to the `AABB`.
+        #[test]
+        fn test_face_aabb(a in tuplevec_small_strategy(), b in tuplevec_small_strategy()) {
+            let aabb = AABB::empty()
+                .grow(&tuple_to_point(&a))
+                .grow(&tuple_to_point(&b));
+
+            for axis in [Axis::X, Axis::Y, Axis::Z] {
+                let min_face = aabb.face_aabb(axis, Side::Min);
+                let max_face = aabb.face_aabb(axis, Side::Max);
+
+                assert_eq!(min_face.size()[axis], 0.0);
+                assert_eq!(max_face.size()[axis], 0.0);
+                assert_eq!(min_face.min, aabb.min);
+                assert_eq!(max_face.max, aabb.max);
+
+                let joint = min_face.join(&max_face);
+                assert_eq!(joint.min, aabb.min);
+                assert_eq!(joint.max, aabb.max);
+            }
+        }
+
+        // Test whether transforming an `AABB` gives the `AABB` of its transformed corners.
+        #[test]
+        fn test_transform(a in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                          b in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                          scale in (-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0),
+                          angles in (-3.2f32..3.2, -3.2f32..3.2, -3.2f32..3.2),
+                          translation in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0)) {
+            let aabb = AABB::empty()
+                .grow(&tuple_to_point(&a))
+                .grow(&tuple_to_point(&b));
+            let m = Mat4::from_scale_rotation_translation(
+                tuple_to_point(&scale),
+                Quat::from_euler(EulerRot::XYZ, angles.0, angles.1, angles.2),
+                tuple_to_point(&translation),
+            );
+
+            let mut expected = AABB::empty();
+            for i in 0..8 {
+                let corner = Point3::new(
+                    if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                    if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                    if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+                );
+                expected.grow_mut(&m.transform_point3(corner));
+            }
+
+            assert!(aabb.transform(&m).relative_eq(&expected, 1e-2));
+        }
+
+        // Test whether the `AABB` of an oriented box contains its corners, and touches
+        // each of its faces with one of them.
+        #[test]
+        fn test_from_obb(center in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                         half_extents in (0.0f32..10.0, 0.0f32..10.0, 0.0f32..10.0),
+                         angles in (-3.2f32..3.2, -3.2f32..3.2, -3.2f32..3.2)) {
+            let center = tuple_to_point(&center);
+            let half_extents = tuple_to_vector(&half_extents);
+            let rotation = Quat::from_euler(EulerRot::XYZ, angles.0, angles.1, angles.2);
+            let aabb = AABB::from_obb(&center, &half_extents, &rotation);
+
+            let mut corners = AABB::empty();
+            for i in 0..8 {
+                let local = Vector3::new(
+                    if i & 1 == 0 { -half_extents.x } else { half_extents.x },
+                    if i & 2 == 0 { -half_extents.y } else { half_extents.y },
+                    if i & 4 == 0 { -half_extents.z } else { half_extents.z },
+                );
+                let corner = center + rotation * local;
+                assert!(aabb.approx_contains_eps(&corner, 1e-4));
+                corners.grow_mut(&corner);
+            }
+            assert!(aabb.relative_eq(&corners, 1e-4));
+        }
+
+        // Test whether a ray from inside an `AABB` leaves it through a point on the face
+        // returned by `exit_face`.
+        #[test]
+        fn test_exit_face_from_inside(t in (0.0f32..=1.0, 0.0f32..=1.0, 0.0f32..=1.0),
+                                      direction in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0)) {
+            let aabb = AABB::with_bounds(Point3::new(-1.0, -2.0, -3.0), Point3::new(4.0, 5.0, 6.0));
+            let origin = aabb.min + aabb.size() * tuple_to_vector(&t);
+            let direction = tuple_to_vector(&direction);
+            prop_assume!(direction.length() > 0.1);
+            let ray = Ray::new(origin, direction);
+
+            let (t, axis, side) = aabb.exit_face(&ray).unwrap();
+            assert!(t >= 0.0);
+            let exit = ray.origin + ray.direction * t;
+            let face = aabb.face_aabb(axis, side);
+            assert!((exit[axis] - face.min[axis]).abs() < 1e-3);
+            assert!(face.approx_contains_eps(&exit, 1e-3));
+        }
+
+        // Test whether the wireframe of an `AABB` has its corners and each of its twelve
+        // edges exactly once, with every edge running along a single axis.
+        #[test]
+        fn test_to_wireframe(a in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                             b in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0)) {
+            let aabb = AABB::empty()
+                .grow(&tuple_to_point(&a))
+                .grow(&tuple_to_point(&b));
+            let (corners, edges) = aabb.to_wireframe();
+
+            for corner in corners.iter() {
+                for axis in 0..3 {
+                    assert!(corner[axis] == aabb.min[axis] || corner[axis] == aabb.max[axis]);
+                }
+            }
+
+            let mut seen = Vec::new();
+            for &[i, j] in edges.iter() {
+                let differing = i ^ j;
+                assert!(differing.is_power_of_two() && differing < 8);
+                let axis = differing.trailing_zeros() as usize;
+                let edge = corners[i] - corners[j];
+                assert_eq!(edge.abs()[axis], aabb.size()[axis]);
+
+                let key = (i.min(j), i.max(j));
+                assert!(!seen.contains(&key));
+                seen.push(key);
+            }
+        }
+
+        // Test whether testing many rays against an `AABB` at once gives the same results as
+        // testing them one by one, including axis aligned rays.
+        #[test]
+        fn test_intersects_rays(a in (-10i32..10, -10i32..10, -10i32..10),
+                                b in (-10i32..10, -10i32..10, -10i32..10),
+                                rays in prop::collection::vec(
+                                    ((-20i32..20, -20i32..20, -20i32..20), (-2i32..2, -2i32..2, -2i32..2)),
+                                    0..64)) {
+            // Integer coordinates put ray origins exactly on the planes of the `AABB` now and then.
+            let to_point = |t: (i32, i32, i32)| Point3::new(t.0 as f32, t.1 as f32, t.2 as f32);
+            let aabb = AABB::empty().grow(&to_point(a)).grow(&to_point(b));
+            let rays = rays
+                .iter()
+                .filter(|(_, direction)| *direction != (0, 0, 0))
+                .map(|&(origin, direction)| Ray::new(to_point(origin) * 0.5, to_point(direction)))
+                .collect::<Vec<_>>();
+
+            let mut hits = vec![false; rays.len()];
+            aabb.intersects_rays(&rays, &mut hits);
+            for (ray, hit) in rays.iter().zip(hits) {
+                assert_eq!(hit, ray.intersects_aabb(&aabb));
+            }
+        }
+
+        // Test whether padding an `AABB` grows exactly its thin axes to the minimum size, and
+        // whether the padded `AABB` still contains the original one.
+        #[test]
+        fn test_pad_to_minimum_size(a in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                                    size in (0.0f32..2.0, 0.0f32..2.0, 0.0f32..2.0),
+                                    min_size in 0.0f32..2.0) {
+            let min = tuple_to_point(&a);
+            let aabb = AABB::with_bounds(min, min + tuple_to_vector(&size));
+            let padded = aabb.pad_to_minimum_size(min_size);
+
+            assert!(padded.contains_aabb(&aabb));
+            for axis in 0..3 {
+                if aabb.size()[axis] < min_size {
+                    assert!((padded.size()[axis] - min_size).abs() < EPSILON * 100.0);
+                    assert!((padded.center()[axis] - aabb.center()[axis]).abs() < EPSILON * 100.0);
+                } else {
+                    assert_eq!(padded.min[axis], aabb.min[axis]);
+                    assert_eq!(padded.max[axis], aabb.max[axis]);
+                }
+            }
+            assert!(AABB::empty().pad_to_minimum_size(min_size).is_empty());
+        }
+
+        // Test whether interpolating two `AABB`s hits both ends exactly, and whether the
+        // results in between lie within the joint bounds of both ends.
+        #[test]
+        fn test_lerp(a in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                     b in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                     c in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                     d in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                     t in 0.0f32..=1.0) {
+            let start = AABB::empty().grow(&tuple_to_point(&a)).grow(&tuple_to_point(&b));
+            let end = AABB::empty().grow(&tuple_to_point(&c)).grow(&tuple_to_point(&d));
+
+            let at_start = start.lerp(&end, 0.0);
+            let at_end = start.lerp(&end, 1.0);
+            assert!(at_start.min == start.min && at_start.max == start.max);
+            assert!(at_end.min == end.min && at_end.max == end.max);
+
+            let between = start.lerp(&end, t);
+            assert!(!between.is_empty());
+            assert!(start.join(&end).approx_contains_aabb_eps(&between, EPSILON * 100.0));
+
+            let kept = AABB::empty().lerp(&end, t);
+            assert!(kept.min == end.min && kept.max == end.max);
+            assert!(AABB::empty().lerp(&AABB::empty(), t).is_empty());
+        }
+
+        // Test whether the swept `AABB` contains the box at every point of the motion.
+        #[test]
+        fn test_swept(a in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                      b in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                      motion in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                      t in 0.0f32..=1.0) {
+            let aabb = AABB::empty().grow(&tuple_to_point(&a)).grow(&tuple_to_point(&b));
+            let motion = tuple_to_point(&motion);
+
+            let moved = aabb.translate(&motion);
+            assert!(moved.size().abs_diff_eq(aabb.size(), EPSILON * 100.0));
+            assert!(moved.center().abs_diff_eq(aabb.center() + motion, EPSILON * 100.0));
+
+            let swept = aabb.swept(&motion);
+            assert!(swept.contains_aabb(&aabb));
+            assert!(swept.contains_aabb(&moved));
+            assert!(swept.approx_contains_aabb_eps(&aabb.translate(&(motion * t)), EPSILON * 100.0));
+            assert!(swept.size().abs_diff_eq(aabb.size() + motion.abs(), EPSILON * 100.0));
+
+            assert!(AABB::empty().swept(&motion).is_empty());
+        }
+
+        // Test whether the octants of an `AABB` tile it exactly.
+        #[test]
+        fn test_subdivide(a in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                          b in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0)) {
+            let aabb = AABB::empty()
+                .grow(&tuple_to_point(&a))
+                .grow(&tuple_to_point(&b));
+            assert_tiles(&aabb, &aabb.subdivide(), [2, 2, 2]);
+        }
+
+        // Test whether the cells of a grid over an `AABB` tile it exactly, and whether the
+        // grid is empty if any of the counts is zero.
+        #[test]
+        fn test_subdivide_along_axes(a in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                                     b in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                                     n in (0usize..7, 0usize..7, 0usize..7)) {
+            let aabb = AABB::empty()
+                .grow(&tuple_to_point(&a))
+                .grow(&tuple_to_point(&b));
+            let cells = aabb.subdivide_along_axes(n.0, n.1, n.2);
+            if n.0 == 0 || n.1 == 0 || n.2 == 0 {
+                assert!(cells.is_empty());
+            } else {
+                assert_tiles(&aabb, &cells, [n.0, n.1, n.2]);
+            }
+        }
+
+        // Test whether `enclosing_transformed` joins the transformed boxes.
+        #[test]
+        fn test_enclosing_transformed(points in prop::collection::vec(
+                                          (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                                          0..16),
+                                      angles in (-3.2f32..3.2, -3.2f32..3.2, -3.2f32..3.2),
+                                      translation in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0)) {
+            let mut boxes = points
+                .chunks(2)
+                .map(|pair| pair.iter().fold(AABB::empty(), |aabb, p| aabb.grow(&tuple_to_point(p))))
+                .collect::<Vec<_>>();
+            boxes.push(AABB::empty());
+            let m = Mat4::from_rotation_translation(
+                Quat::from_euler(EulerRot::XYZ, angles.0, angles.1, angles.2),
+                tuple_to_point(&translation),
+            );
+
+            let expected = boxes
+                .iter()
+                .fold(AABB::empty(), |joint, aabb| joint.join(&aabb.transform(&m)));
+            let enclosing = AABB::enclosing_transformed(&boxes, &m);
+
+            if expected.is_empty() {
+                assert!(enclosing.is_empty());
+            } else {
+                assert!(enclosing.relative_eq(&expected, 1e-4));
+            }
+        }
+
+        // Test whether the halves of a split `AABB` meet at the split position
+        // and join back to the original `AABB`.
+        #[test]
+        fn test_split_along_axis(a in tuplevec_small_strategy(),
+                                 b in tuplevec_small_strategy(),
+                                 t in 0.0f32..=1.0) {
+            let aabb = AABB::empty()
+                .grow(&tuple_to_point(&a))
+                .grow(&tuple_to_point(&b));
+
+            for axis in [Axis::X, Axis::Y, Axis::Z] {
+                let position = (aabb.min[axis] + (aabb.max[axis] - aabb.min[axis]) * t)
+                    .min(aabb.max[axis]);
+                let (lower, upper) = aabb.split_along_axis(axis, position);
+
+                assert_eq!(lower.max[axis], position);
+                assert_eq!(upper.min[axis], position);
+
+                let joint = lower.join(&upper);
+                assert_eq!(joint.min, aabb.min);
+                assert_eq!(joint.max, aabb.max);
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "bench", test))]
+mod bench {
+    use glam::{Mat4, Quat};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use crate::aabb::AABB;
+    use crate::testbase::{create_rays, default_bounds, tuple_to_point, TupleVec};
+    use crate::Point3;
+
+    /// Generates `n` random deterministic `AABB`s and a rotation with a translation.
+    fn gen_boxes_and_transform(n: usize) -> (Vec<AABB>, Mat4) {
+        let mut rng = StdRng::from_seed([0; 32]);
+        let boxes = (0..n)
+            .map(|_| {
+                let a = tuple_to_point(&rng.gen::<TupleVec>());
+                let b = tuple_to_point(&rng.gen::<TupleVec>());
+                AABB::empty().grow(&a).grow(&b)
+            })
+            .collect::<Vec<_>>();
+        let m = Mat4::from_rotation_translation(
+            Quat::from_rotation_y(0.7) * Quat::from_rotation_x(0.3),
+            Point3::new(1.0, 2.0, 3.0),
+        );
+        (boxes, m)
+    }
+
+    /// Benchmark for testing 10,000 rays against one `AABB` with `AABB::intersects_rays`.
+    #[bench]
+    fn bench_intersects_rays(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let rays = create_rays(10_000, &bounds);
+        let aabb = AABB::with_bounds(bounds.min * 0.1, bounds.max * 0.1);
+        let mut hits = vec![false; rays.len()];
+        b.iter(|| ::test::black_box(&aabb).intersects_rays(&rays, &mut hits));
+    }
+
+    /// Benchmark for testing 10,000 rays against one `AABB` with `Ray::intersects_aabb`,
+    /// as a baseline for `bench_intersects_rays`.
+    #[bench]
+    fn bench_intersects_rays_one_by_one(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let rays = create_rays(10_000, &bounds);
+        let aabb = AABB::with_bounds(bounds.min * 0.1, bounds.max * 0.1);
+        let mut hits = vec![false; rays.len()];
+        b.iter(|| {
+            let aabb = ::test::black_box(&aabb);
+            for (hit, ray) in hits.iter_mut().zip(&rays) {
+                *hit = ray.intersects_aabb(aabb);
+            }
+        });
+    }
+
+    /// Benchmark for enclosing 1000 transformed `AABB`s with `AABB::enclosing_transformed`.
+    #[bench]
+    fn bench_enclosing_transformed(b: &mut ::test::Bencher) {
+        let (boxes, m) = gen_boxes_and_transform(1000);
+        b.iter(|| AABB::enclosing_transformed(::test::black_box(&boxes), &m));
+    }
+
+    /// Benchmark for enclosing 1000 transformed `AABB`s by transforming all eight corners.
+    #[bench]
+    fn bench_enclosing_transformed_corners(b: &mut ::test::Bencher) {
+        let (boxes, m) = gen_boxes_and_transform(1000);
+        b.iter(|| {
+            let mut enclosing = AABB::empty();
+            for aabb in ::test::black_box(&boxes) {
+                for i in 0..8 {
+                    let corner = Point3::new(
+                        if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                        if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                        if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+                    );
+                    enclosing.grow_mut(&m.transform_point3(corner));
+                }
+            }
+            enclosing
+        });
     }
 }
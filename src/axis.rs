@@ -2,8 +2,8 @@
 
 #![allow(unused)]
 use crate::{Point3, Vector3};
-use std::fmt::{Display, Formatter, Result};
-use std::ops::{Index, IndexMut};
+use core::fmt::{Display, Formatter, Result};
+use core::ops::{Index, IndexMut};
 
 struct MyType<T>(T);
 
@@ -35,7 +35,21 @@ struct MyType<T>(T);
 /// assert_eq!(position[Axis::X], 1000.0);
 /// # }
 /// ```
-#[derive(Debug, Copy, Clone, PartialEq)]
+///
+/// Axes are ordered `X < Y < Z` and can be used as keys of maps and sets.
+///
+/// ```
+/// use bvh::axis::Axis;
+/// use std::collections::BTreeMap;
+///
+/// let mut costs = BTreeMap::new();
+/// costs.insert(Axis::Z, 3.0);
+/// costs.insert(Axis::X, 1.0);
+///
+/// assert!(Axis::X < Axis::Y && Axis::Y < Axis::Z);
+/// assert_eq!(costs.keys().collect::<Vec<_>>(), [&Axis::X, &Axis::Z]);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Axis {
     /// Index of the X axis.
     X = 0,
@@ -23,6 +23,22 @@ pub trait BHShape: Bounded {
     fn bh_node_index(&self) -> usize;
 }
 
+/// Implementation of [`BHShape`] for boxed shapes, including trait objects.
+/// This allows a hierarchy to be built over shapes of different types stored as
+/// `Box<dyn BHShape>`.
+///
+/// [`BHShape`]: trait.BHShape.html
+///
+impl<T: BHShape + ?Sized> BHShape for Box<T> {
+    fn set_bh_node_index(&mut self, index: usize) {
+        (**self).set_bh_node_index(index);
+    }
+
+    fn bh_node_index(&self) -> usize {
+        (**self).bh_node_index()
+    }
+}
+
 /// This trait defines an acceleration structure with space partitioning.
 /// This structure is used to efficiently compute ray-scene intersections.
 pub trait BoundingHierarchy {
@@ -163,7 +179,7 @@ pub trait BoundingHierarchy {
     /// [`BoundingHierarchy`]: trait.BoundingHierarchy.html
     /// [`AABB`]: ../aabb/struct.AABB.html
     ///
-    fn traverse<'a, Shape: BHShape>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&Shape>;
+    fn traverse<'a, Shape: BHShape>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&'a Shape>;
 
     /// Prints the [`BoundingHierarchy`] in a tree-like visualization.
     ///
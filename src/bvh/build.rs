@@ -0,0 +1,291 @@
+//! Building blocks for [`BVH`] builders, for prototyping custom split heuristics without
+//! reimplementing the machinery of the crate.
+//!
+//! [`BVH`]: ../struct.BVH.html
+//!
+
+use crate::aabb::{Bounded, AABB};
+use crate::axis::Axis;
+use crate::Point3;
+
+/// A node of a [`BVH`] under construction by a bottom-up builder: its bounds, and the
+/// entries of its two children unless it is a leaf.
+///
+/// [`BVH`]: ../struct.BVH.html
+///
+pub(crate) type BottomUpEntry = (AABB, Option<(usize, usize)>);
+
+/// The number of clusters below which approximate agglomerative clustering stops splitting
+/// and clusters exhaustively, called δ by Gu et al.
+const AAC_LEAF_CLUSTERS: usize = 20;
+
+/// The exponent of the cluster count reduction of approximate agglomerative clustering,
+/// called ε by Gu et al.
+const AAC_EPSILON: f32 = 0.1;
+
+/// Reorders `indices` in place, so that the indices of all `shapes` whose [`AABB`] center
+/// lies below `pivot` along `axis` come first, and returns their number. This is the split
+/// point: `indices[..split]` are below `pivot` and `indices[split..]` are not.
+///
+/// The partition is Hoare-style: it swaps misplaced pairs from both ends, so it does not
+/// allocate, but it does not keep the order of the indices on either side. Centers which are
+/// NaN along `axis` go to the upper side.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::AABB;
+/// use bvh::bvh::build::partition;
+/// use bvh::Point3;
+///
+/// let points = (0..10)
+///     .map(|i| Point3::new(i as f32, 0.0, 0.0))
+///     .collect::<Vec<_>>();
+///
+/// // Split the points at the center of their bounds along its largest axis.
+/// let aabb = AABB::from_bounded(&points);
+/// let axis = aabb.largest_axis();
+/// let mut indices = (0..points.len()).collect::<Vec<_>>();
+/// let split = partition(&mut indices, &points, axis, aabb.center()[axis]);
+///
+/// assert_eq!(split, 5);
+/// assert!(indices[..split].iter().all(|&i| i < 5));
+/// assert!(indices[split..].iter().all(|&i| i >= 5));
+/// ```
+///
+/// [`AABB`]: ../../aabb/struct.AABB.html
+///
+pub fn partition<T: Bounded>(indices: &mut [usize], shapes: &[T], axis: Axis, pivot: f32) -> usize {
+    let below = |index: usize| shapes[index].aabb().center()[axis] < pivot;
+
+    let mut left = 0;
+    let mut right = indices.len();
+    loop {
+        while left < right && below(indices[left]) {
+            left += 1;
+        }
+        while left < right && !below(indices[right - 1]) {
+            right -= 1;
+        }
+        if left == right {
+            return left;
+        }
+        // `indices[left]` belongs to the upper side and `indices[right - 1]` to the lower one.
+        indices.swap(left, right - 1);
+        left += 1;
+        right -= 1;
+    }
+}
+
+/// Clusters the leaves in `entries` bottom-up with the approximate agglomerative clustering
+/// of Gu et al., "Efficient BVH Construction via Approximate Agglomerative Clustering" (2013).
+/// The leaves are sorted along a Morton curve and split recursively at its highest differing
+/// bit. The small groups at the bottom are clustered exhaustively, by repeatedly merging
+/// the two clusters whose joint [`AABB`] has the smallest surface area, and only a few of
+/// their clusters are passed up to be merged with those of the neighbouring groups.
+///
+/// Appends every merged cluster to `entries` and returns the entry of the root, or `None`
+/// if there are no leaves.
+///
+/// [`AABB`]: ../../aabb/struct.AABB.html
+///
+pub(crate) fn agglomerative_clustering(entries: &mut Vec<BottomUpEntry>) -> Option<usize> {
+    let centroid_bounds = entries.iter().fold(AABB::empty(), |bounds, entry| {
+        bounds.grow(&entry.0.center())
+    });
+    let mut order = entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| (morton_code(&centroid_bounds, &entry.0.center()), index))
+        .collect::<Vec<_>>();
+    order.sort_unstable();
+
+    let mut clusters = build_clusters(entries, &order, 29);
+    combine_clusters(entries, &mut clusters, 1);
+    clusters.first().copied()
+}
+
+/// Returns the 30 bit Morton code of `p`, which interleaves the bits of its position within
+/// `bounds` quantized to 10 bits per axis.
+fn morton_code(bounds: &AABB, p: &Point3) -> u32 {
+    let size = bounds.size();
+    let mut code = 0;
+    for axis in 0..3 {
+        let relative = if size[axis] > 0.0 {
+            (p[axis] - bounds.min[axis]) / size[axis]
+        } else {
+            0.0
+        };
+        let cell = (relative * 1024.0).clamp(0.0, 1023.0) as u32;
+        code |= spread_bits(cell) << (2 - axis);
+    }
+    code
+}
+
+/// Inserts two zero bits between each of the lowest 10 bits of `x`.
+fn spread_bits(x: u32) -> u32 {
+    let mut x = x & 0x3ff;
+    x = (x | (x << 16)) & 0x0300_00ff;
+    x = (x | (x << 8)) & 0x0300_f00f;
+    x = (x | (x << 4)) & 0x030c_30c3;
+    x = (x | (x << 2)) & 0x0924_9249;
+    x
+}
+
+/// Clusters the leaves of `order`, which is sorted by Morton code and whose codes agree on
+/// all bits above `bit`, and returns the entries of the clusters which are left.
+fn build_clusters(
+    entries: &mut Vec<BottomUpEntry>,
+    order: &[(u32, usize)],
+    bit: i32,
+) -> Vec<usize> {
+    if order.len() < AAC_LEAF_CLUSTERS {
+        let mut clusters = order.iter().map(|&(_, index)| index).collect();
+        combine_clusters(entries, &mut clusters, cluster_count(AAC_LEAF_CLUSTERS));
+        return clusters;
+    }
+
+    // Split at the highest bit in which the codes differ, or in the middle if they are equal.
+    let mut bit = bit;
+    let mut split = order.len() / 2;
+    while bit >= 0 {
+        let mask = 1 << bit;
+        bit -= 1;
+        let upper = order.partition_point(|&(code, _)| code & mask == 0);
+        if upper > 0 && upper < order.len() {
+            split = upper;
+            break;
+        }
+    }
+
+    let mut clusters = build_clusters(entries, &order[..split], bit);
+    clusters.extend(build_clusters(entries, &order[split..], bit));
+    combine_clusters(entries, &mut clusters, cluster_count(order.len()));
+    clusters
+}
+
+/// The number of clusters which a group of `leaf_count` leaves is reduced to before it is
+/// merged with its neighbour.
+fn cluster_count(leaf_count: usize) -> usize {
+    let scale = (AAC_LEAF_CLUSTERS as f32).powf(0.5 + AAC_EPSILON) / 2.0;
+    ((scale * (leaf_count as f32).powf(0.5 - AAC_EPSILON)).ceil() as usize).max(1)
+}
+
+/// Merges the two `clusters` whose joint [`AABB`] has the smallest surface area until only
+/// `count` of them are left. The nearest neighbour of every cluster is cached, and only
+/// recomputed when that neighbour is merged.
+///
+/// [`AABB`]: ../../aabb/struct.AABB.html
+///
+fn combine_clusters(entries: &mut Vec<BottomUpEntry>, clusters: &mut Vec<usize>, count: usize) {
+    fn cost(entries: &[BottomUpEntry], a: usize, b: usize) -> f32 {
+        entries[a].0.join(&entries[b].0).surface_area()
+    }
+
+    /// Returns the cost of merging `clusters[i]` with its nearest neighbour, and the position
+    /// of that neighbour.
+    fn nearest(entries: &[BottomUpEntry], clusters: &[usize], i: usize) -> (f32, usize) {
+        let mut best = (f32::INFINITY, i);
+        for (j, &other) in clusters.iter().enumerate() {
+            let merge_cost = cost(entries, clusters[i], other);
+            // Also take the first neighbour in case every cost is infinite.
+            if j != i && (merge_cost < best.0 || best.1 == i) {
+                best = (merge_cost, j);
+            }
+        }
+        best
+    }
+
+    if clusters.len() <= count {
+        return;
+    }
+    let mut neighbours = (0..clusters.len())
+        .map(|i| nearest(entries, clusters, i))
+        .collect::<Vec<_>>();
+    while clusters.len() > count {
+        let mut i = 0;
+        for (k, neighbour) in neighbours.iter().enumerate() {
+            if neighbour.0 < neighbours[i].0 {
+                i = k;
+            }
+        }
+        let j = neighbours[i].1;
+        // Keep the merged cluster at the smaller position, so that it isn't moved below.
+        let (i, j) = (i.min(j), i.max(j));
+
+        let (child_l, child_r) = (clusters[i], clusters[j]);
+        let aabb = entries[child_l].0.join(&entries[child_r].0);
+        clusters[i] = entries.len();
+        entries.push((aabb, Some((child_l, child_r))));
+        let last = clusters.len() - 1;
+        clusters.swap_remove(j);
+        neighbours.swap_remove(j);
+
+        let mut stale = Vec::new();
+        for (k, neighbour) in neighbours.iter_mut().enumerate() {
+            if neighbour.1 == i || neighbour.1 == j {
+                stale.push(k);
+            } else if neighbour.1 == last {
+                // The last cluster was moved to position `j`.
+                neighbour.1 = j;
+            }
+        }
+        neighbours[i] = nearest(entries, clusters, i);
+        for k in stale {
+            neighbours[k] = nearest(entries, clusters, k);
+        }
+        // The merged cluster may be nearer to others than their current neighbours.
+        for k in 0..clusters.len() {
+            if k != i {
+                let merge_cost = cost(entries, clusters[k], clusters[i]);
+                if merge_cost < neighbours[k].0 {
+                    neighbours[k] = (merge_cost, i);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::axis::Axis;
+    use crate::bvh::build::{partition, spread_bits};
+    use crate::testbase::unit_boxes;
+
+    use proptest::prelude::*;
+
+    proptest! {
+        // Test whether `partition` keeps the indices and splits them at `pivot`.
+        #[test]
+        fn test_partition(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 0..64),
+            axis in 0usize..3,
+            pivot in -120.0f32..120.0,
+        ) {
+            let shapes = unit_boxes(&positions);
+            let axis = [Axis::X, Axis::Y, Axis::Z][axis];
+            let mut indices = (0..shapes.len()).rev().collect::<Vec<_>>();
+
+            let split = partition(&mut indices, &shapes, axis, pivot);
+
+            let center = |index: usize| shapes[index].aabb().center()[axis];
+            assert!(indices[..split].iter().all(|&index| center(index) < pivot));
+            assert!(indices[split..].iter().all(|&index| center(index) >= pivot));
+            let expected = shapes.iter().filter(|shape| shape.aabb().center()[axis] < pivot).count();
+            assert_eq!(split, expected);
+
+            indices.sort_unstable();
+            assert!(indices.iter().copied().eq(0..shapes.len()));
+        }
+
+        // Test whether `spread_bits` moves bit `i` of its input to bit `3 * i`.
+        #[test]
+        fn test_spread_bits(x in 0u32..1024) {
+            let spread = spread_bits(x);
+            for i in 0..10 {
+                assert_eq!((spread >> (3 * i)) & 1, (x >> i) & 1);
+            }
+            assert_eq!(spread & !0x0924_9249, 0);
+        }
+    }
+}
@@ -4,18 +4,28 @@
 //! [`BVHNode`]: struct.BVHNode.html
 //!
 
-use crate::aabb::{Bounded, AABB};
+use crate::aabb::{Bounded, SurfacePoint, AABB};
+use crate::axis::Axis;
 use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
-use crate::bvh::iter::BVHTraverseIterator;
-use crate::ray::Ray;
-use crate::utils::{concatenate_vectors, joint_aabb_of_shapes, Bucket};
-use crate::Point3;
+use crate::bvh::build::{agglomerative_clustering, BottomUpEntry};
+use crate::bvh::config::{BVHConfig, SplitHeuristic};
+use crate::bvh::iter::{BVHDepthFirstIterator, BVHTraverseIndexIterator, BVHTraverseIterator};
+use crate::bvh::traversal_state::TraversalState;
+use crate::frustum::{Containment, Frustum};
+use crate::ray::{Intersectable, Ray, RayCone, RaycastResult};
+use crate::utils::{apply_permutation, joint_aabb_of_shapes, Bucket};
 use crate::EPSILON;
+use crate::{Point3, Vector3};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::error::Error;
 use std::f32;
+use std::fmt;
+use std::ops::{AddAssign, Range};
 
 /// The [`BVHNode`] enum that describes a node in a [`BVH`].
-/// It's either a leaf node and references a shape (by holding its index)
-/// or a regular node that has two child nodes.
+/// It's either a leaf node and references a contiguous range of shapes (by holding the index
+/// of the first one and their count) or a regular node that has two child nodes.
 /// The non-leaf node stores the [`AABB`]s of its children.
 ///
 /// [`AABB`]: ../aabb/struct.AABB.html
@@ -34,8 +44,12 @@ pub enum BVHNode {
         /// The node's depth.
         depth: u32,
 
-        /// The shape contained in this leaf.
+        /// The first shape contained in this leaf.
         shape_index: usize,
+
+        /// The number of shapes contained in this leaf. The leaf contains the shapes
+        /// `shape_index..shape_index + shape_count`.
+        shape_count: usize,
     },
     /// Inner node.
     Node {
@@ -89,16 +103,19 @@ impl PartialEq for BVHNode {
                     parent_index: self_parent_index,
                     depth: self_depth,
                     shape_index: self_shape_index,
+                    shape_count: self_shape_count,
                 },
                 &BVHNode::Leaf {
                     parent_index: other_parent_index,
                     depth: other_depth,
                     shape_index: other_shape_index,
+                    shape_count: other_shape_count,
                 },
             ) => {
                 self_parent_index == other_parent_index
                     && self_depth == other_depth
                     && self_shape_index == other_shape_index
+                    && self_shape_count == other_shape_count
             }
             _ => false,
         }
@@ -189,20 +206,26 @@ impl BVHNode {
     }
 
     /// Gets the `AABB` for a `BVHNode`.
-    /// Returns the shape's `AABB` for leaves, and the joined `AABB` of
+    /// Returns the joined `AABB` of the shapes for leaves, and the joined `AABB` of
     /// the two children's `AABB`s for non-leaves.
-    pub fn get_node_aabb<Shape: BHShape>(&self, shapes: &[Shape]) -> AABB {
+    pub fn get_node_aabb<Shape: Bounded>(&self, shapes: &[Shape]) -> AABB {
         match *self {
             BVHNode::Node {
                 child_l_aabb,
                 child_r_aabb,
                 ..
             } => child_l_aabb.join(&child_r_aabb),
-            BVHNode::Leaf { shape_index, .. } => shapes[shape_index].aabb(),
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => shapes[shape_index..shape_index + shape_count]
+                .iter()
+                .fold(AABB::empty(), |aabb, shape| aabb.join(&shape.aabb())),
         }
     }
 
-    /// Returns the index of the shape contained within the node if is a leaf,
+    /// Returns the index of the first shape contained within the node if is a leaf,
     /// or `None` if it is an interior node.
     pub fn shape_index(&self) -> Option<usize> {
         match *self {
@@ -211,6 +234,19 @@ impl BVHNode {
         }
     }
 
+    /// Returns the range of indices of the shapes contained within the node if is a leaf,
+    /// or `None` if it is an interior node.
+    pub fn shape_range(&self) -> Option<Range<usize>> {
+        match *self {
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => Some(shape_index..shape_index + shape_count),
+            _ => None,
+        }
+    }
+
     /// The build function sometimes needs to add nodes while their data is not available yet.
     /// A dummy cerated by this function serves the purpose of being changed later on.
     fn create_dummy() -> BVHNode {
@@ -218,6 +254,7 @@ impl BVHNode {
             parent_index: 0,
             depth: 0,
             shape_index: 0,
+            shape_count: 0,
         }
     }
 
@@ -232,6 +269,201 @@ impl BVHNode {
         nodes: &mut Vec<BVHNode>,
         parent_index: usize,
         depth: u32,
+    ) -> usize {
+        let first_new_node = nodes.len();
+        let mut shape_order = Vec::with_capacity(indices.len());
+        let node_index = BVHNode::build_with_config(
+            shapes,
+            indices,
+            &mut shape_order,
+            nodes,
+            parent_index,
+            depth,
+            &BVHConfig::default(),
+        );
+
+        // Every leaf holds a single shape, so let the leaves reference the shapes directly.
+        for (node_index, node) in nodes.iter_mut().enumerate().skip(first_new_node) {
+            if let BVHNode::Leaf {
+                ref mut shape_index,
+                ..
+            } = *node
+            {
+                *shape_index = shape_order[*shape_index];
+                // Let the shape know the index of the node that represents it.
+                shapes[*shape_index].set_bh_node_index(node_index);
+            }
+        }
+        node_index
+    }
+
+    /// Builds a [`BVHNode`] recursively as described by `config`.
+    /// Returns the index of the new node in the nodes vector.
+    ///
+    /// The indices of the shapes of every new leaf are appended to `shape_order`, and the leaf
+    /// references them by their position in `shape_order` instead of by their index in
+    /// `shapes`. Neither `shapes` nor their node indices are modified.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config` is invalid, see [`BVHConfig::validate`].
+    ///
+    /// [`BVHConfig::validate`]: struct.BVHConfig.html#method.validate
+    /// [`BVHNode`]: enum.BVHNode.html
+    ///
+    pub fn build_with_config<T: BHShape>(
+        shapes: &mut [T],
+        indices: &[usize],
+        shape_order: &mut Vec<usize>,
+        nodes: &mut Vec<BVHNode>,
+        parent_index: usize,
+        depth: u32,
+        config: &BVHConfig,
+    ) -> usize {
+        config.validate();
+        let first_new_node = nodes.len();
+        let offset = shape_order.len();
+        let mut scratch = BVHBuildScratch {
+            indices: indices.to_vec(),
+            ..Default::default()
+        };
+        let node_index =
+            scratch.build_node(shapes, 0..indices.len(), nodes, parent_index, depth, config);
+
+        shape_order.extend_from_slice(&scratch.indices);
+        for node in &mut nodes[first_new_node..] {
+            if let BVHNode::Leaf {
+                ref mut shape_index,
+                ..
+            } = *node
+            {
+                *shape_index += offset;
+            }
+        }
+        node_index
+    }
+
+    /// Traverses the [`BVH`] recursively and returns all shapes whose [`AABB`] is
+    /// intersected by the given [`Ray`].
+    /// Only kept as a reference for testing [`BVH::traverse`], which does the same
+    /// iteratively.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    /// [`Ray`]: ../ray/struct.Ray.html
+    ///
+    #[cfg(test)]
+    pub(crate) fn traverse_recursive(
+        nodes: &[BVHNode],
+        node_index: usize,
+        ray: &Ray,
+        indices: &mut Vec<usize>,
+    ) {
+        match nodes[node_index] {
+            BVHNode::Node {
+                ref child_l_aabb,
+                child_l_index,
+                ref child_r_aabb,
+                child_r_index,
+                ..
+            } => {
+                if ray.intersects_aabb(child_l_aabb) {
+                    BVHNode::traverse_recursive(nodes, child_l_index, ray, indices);
+                }
+                if ray.intersects_aabb(child_r_aabb) {
+                    BVHNode::traverse_recursive(nodes, child_r_index, ray, indices);
+                }
+            }
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => {
+                indices.extend(shape_index..shape_index + shape_count);
+            }
+        }
+    }
+}
+
+/// The error returned by [`BVH::build_checked`] when the [`AABB`] of a shape is not finite
+/// or not [`AABB::is_valid`].
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`AABB::is_valid`]: ../aabb/struct.AABB.html#method.is_valid
+/// [`BVH::build_checked`]: struct.BVH.html#method.build_checked
+///
+#[derive(Debug, Copy, Clone)]
+pub struct InvalidBoundsError {
+    /// The index of the first invalid shape.
+    pub shape_index: usize,
+    /// The invalid [`AABB`] of the shape.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb: AABB,
+}
+
+impl fmt::Display for InvalidBoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "shape {} has invalid bounds {}",
+            self.shape_index, self.aabb
+        )
+    }
+}
+
+impl Error for InvalidBoundsError {}
+
+/// Buffers used while building a [`BVH`]. Keeping them around between builds with
+/// [`BVH::rebuild_with_scratch`] avoids allocating them again for every build.
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::rebuild_with_scratch`]: struct.BVH.html#method.rebuild_with_scratch
+///
+#[derive(Debug, Clone, Default)]
+pub struct BVHBuildScratch {
+    /// The indices of the shapes. Every node owns a contiguous range of them,
+    /// which is partitioned in place between its children.
+    indices: Vec<usize>,
+
+    /// The `Bucket` of every shape of the node which is split using SAH.
+    bucket_numbers: Vec<usize>,
+
+    /// The `Bucket`s of the node which is split using SAH.
+    buckets: Vec<Bucket>,
+
+    /// The position of every `Bucket` in the partitioned indices.
+    bucket_offsets: Vec<usize>,
+
+    /// Temporary storage for partitioning the indices of a node.
+    partitioned_indices: Vec<usize>,
+
+    /// The centroids of the shapes of the node which is split at the median.
+    centroids: Vec<(f32, usize)>,
+}
+
+impl BVHBuildScratch {
+    /// Creates empty buffers. They grow to the required sizes during the first build.
+    pub fn new() -> BVHBuildScratch {
+        Default::default()
+    }
+
+    /// Builds a [`BVHNode`] recursively for the shapes `indices[range]`, as described
+    /// by `config`. Returns the index of the new node in the nodes vector.
+    /// Leaves reference their shapes by their positions in `indices`.
+    ///
+    /// [`BVHNode`]: enum.BVHNode.html
+    ///
+    fn build_node<T: BHShape>(
+        &mut self,
+        shapes: &[T],
+        range: Range<usize>,
+        nodes: &mut Vec<BVHNode>,
+        parent_index: usize,
+        depth: u32,
+        config: &BVHConfig,
     ) -> usize {
         // Helper function to accumulate the AABB joint and the centroids AABB
         fn grow_convex_hull(convex_hull: (AABB, AABB), shape_aabb: &AABB) -> (AABB, AABB) {
@@ -245,22 +477,20 @@ impl BVHNode {
         }
 
         let mut convex_hull = Default::default();
-        for index in indices {
+        for index in &self.indices[range.clone()] {
             convex_hull = grow_convex_hull(convex_hull, &shapes[*index].aabb());
         }
         let (aabb_bounds, centroid_bounds) = convex_hull;
 
-        // If there is only one element left, don't split anymore
-        if indices.len() == 1 {
-            let shape_index = indices[0];
+        // If the remaining elements fit into a single leaf, don't split anymore
+        if range.len() <= config.max_leaf_size.max(1) {
             let node_index = nodes.len();
             nodes.push(BVHNode::Leaf {
                 parent_index,
                 depth,
-                shape_index,
+                shape_index: range.start,
+                shape_count: range.len(),
             });
-            // Let the shape know the index of the node that represents it.
-            shapes[shape_index].set_bh_node_index(node_index);
             return node_index;
         }
 
@@ -273,79 +503,76 @@ impl BVHNode {
         let split_axis = centroid_bounds.largest_axis();
         let split_axis_size = centroid_bounds.max[split_axis] - centroid_bounds.min[split_axis];
 
-        // The following `if` partitions `indices` for recursively calling `BVH::build`.
-        let (child_l_index, child_l_aabb, child_r_index, child_r_aabb) = if split_axis_size
-            < EPSILON
-        {
+        // The following `if` partitions `indices[range]` at `split` for the recursive calls.
+        let (split, child_l_aabb, child_r_aabb) = if split_axis_size < EPSILON {
             // In this branch the shapes lie too close together so that splitting them in a
             // sensible way is not possible. Instead we just split the list of shapes in half.
-            let (child_l_indices, child_r_indices) = indices.split_at(indices.len() / 2);
-            let child_l_aabb = joint_aabb_of_shapes(child_l_indices, shapes);
-            let child_r_aabb = joint_aabb_of_shapes(child_r_indices, shapes);
-
-            // Proceed recursively.
-            let child_l_index =
-                BVHNode::build(shapes, child_l_indices, nodes, node_index, depth + 1);
-            let child_r_index =
-                BVHNode::build(shapes, child_r_indices, nodes, node_index, depth + 1);
-            (child_l_index, child_l_aabb, child_r_index, child_r_aabb)
+            let split = range.start + range.len() / 2;
+            let child_l_aabb = joint_aabb_of_shapes(&self.indices[range.start..split], shapes);
+            let child_r_aabb = joint_aabb_of_shapes(&self.indices[split..range.end], shapes);
+            (split, child_l_aabb, child_r_aabb)
         } else {
-            // Create six `Bucket`s, and six index assignment vector.
-            const NUM_BUCKETS: usize = 6;
-            let mut buckets = [Bucket::empty(); NUM_BUCKETS];
-            let mut bucket_assignments: [Vec<usize>; NUM_BUCKETS] = Default::default();
-
             // In this branch the `split_axis_size` is large enough to perform meaningful splits.
-            // We start by assigning the shapes to `Bucket`s.
-            for idx in indices {
-                let shape = &shapes[*idx];
-                let shape_aabb = shape.aabb();
-                let shape_center = shape_aabb.center();
-
-                // Get the relative position of the shape centroid `[0.0..1.0]`.
-                let bucket_num_relative =
-                    (shape_center[split_axis] - centroid_bounds.min[split_axis]) / split_axis_size;
-
-                // Convert that to the actual `Bucket` number.
-                let bucket_num = (bucket_num_relative * (NUM_BUCKETS as f32 - 0.01)) as usize;
-
-                // Extend the selected `Bucket` and add the index to the actual bucket.
-                buckets[bucket_num].add_aabb(&shape_aabb);
-                bucket_assignments[bucket_num].push(*idx);
-            }
-
-            // Compute the costs for each configuration and select the best configuration.
-            let mut min_bucket = 0;
-            let mut min_cost = f32::INFINITY;
-            let mut child_l_aabb = AABB::empty();
-            let mut child_r_aabb = AABB::empty();
-            for i in 0..(NUM_BUCKETS - 1) {
-                let (l_buckets, r_buckets) = buckets.split_at(i + 1);
-                let child_l = l_buckets.iter().fold(Bucket::empty(), Bucket::join_bucket);
-                let child_r = r_buckets.iter().fold(Bucket::empty(), Bucket::join_bucket);
-
-                let cost = (child_l.size as f32 * child_l.aabb.surface_area()
-                    + child_r.size as f32 * child_r.aabb.surface_area())
-                    / aabb_bounds.surface_area();
-                if cost < min_cost {
-                    min_bucket = i;
-                    min_cost = cost;
-                    child_l_aabb = child_l.aabb;
-                    child_r_aabb = child_r.aabb;
-                }
-            }
-
-            // Join together all index buckets.
-            let (l_assignments, r_assignments) = bucket_assignments.split_at_mut(min_bucket + 1);
-            let child_l_indices = concatenate_vectors(l_assignments);
-            let child_r_indices = concatenate_vectors(r_assignments);
-
-            // Proceed recursively.
-            let child_l_index =
-                BVHNode::build(shapes, &child_l_indices, nodes, node_index, depth + 1);
-            let child_r_index =
-                BVHNode::build(shapes, &child_r_indices, nodes, node_index, depth + 1);
-            (child_l_index, child_l_aabb, child_r_index, child_r_aabb)
+            match config.heuristic {
+                SplitHeuristic::Sah => self.split_sah(
+                    shapes,
+                    range.clone(),
+                    split_axis,
+                    &centroid_bounds,
+                    &aabb_bounds,
+                    config.sah_bins,
+                ),
+                SplitHeuristic::Median => self.split_median(shapes, range.clone(), split_axis),
+            }
+        };
+
+        // Move the split if it would leave a child with fewer than `min_leaf_size` shapes.
+        let min_leaf_size = config.min_leaf_size.max(1);
+        let (split, child_l_aabb, child_r_aabb) =
+            if split - range.start < min_leaf_size || range.end - split < min_leaf_size {
+                let split = split.clamp(range.start + min_leaf_size, range.end - min_leaf_size);
+                let child_l_aabb = joint_aabb_of_shapes(&self.indices[range.start..split], shapes);
+                let child_r_aabb = joint_aabb_of_shapes(&self.indices[split..range.end], shapes);
+                (split, child_l_aabb, child_r_aabb)
+            } else {
+                (split, child_l_aabb, child_r_aabb)
+            };
+
+        // Proceed recursively.
+        let child_l_index = self.build_node(
+            shapes,
+            range.start..split,
+            nodes,
+            node_index,
+            depth + 1,
+            config,
+        );
+        let child_r_index = self.build_node(
+            shapes,
+            split..range.end,
+            nodes,
+            node_index,
+            depth + 1,
+            config,
+        );
+
+        // Pad the shapes before joining them, so that the bounds of the children stay nested.
+        let (child_l_aabb, child_r_aabb) = if config.min_aabb_size > 0.0 {
+            let padded_aabb = |indices: &[usize]| {
+                indices.iter().fold(AABB::empty(), |aabb, &index| {
+                    aabb.join(
+                        &shapes[index]
+                            .aabb()
+                            .pad_to_minimum_size(config.min_aabb_size),
+                    )
+                })
+            };
+            (
+                padded_aabb(&self.indices[range.start..split]),
+                padded_aabb(&self.indices[split..range.end]),
+            )
+        } else {
+            (child_l_aabb, child_r_aabb)
         };
 
         // Construct the actual data structure and replace the dummy node.
@@ -363,41 +590,178 @@ impl BVHNode {
         node_index
     }
 
-    /// Traverses the [`BVH`] recursively and returns all shapes whose [`AABB`] is
-    /// intersected by the given [`Ray`].
+    /// Partitions `indices[range]` using binned SAH along `split_axis`.
+    /// Returns the position of the split and the joint [`AABB`] of each child.
     ///
     /// [`AABB`]: ../aabb/struct.AABB.html
-    /// [`BVH`]: struct.BVH.html
-    /// [`Ray`]: ../ray/struct.Ray.html
     ///
-    pub fn traverse_recursive(
-        nodes: &[BVHNode],
-        node_index: usize,
-        ray: &Ray,
-        indices: &mut Vec<usize>,
-    ) {
-        match nodes[node_index] {
-            BVHNode::Node {
-                ref child_l_aabb,
-                child_l_index,
-                ref child_r_aabb,
-                child_r_index,
-                ..
-            } => {
-                if ray.intersects_aabb(child_l_aabb) {
-                    BVHNode::traverse_recursive(nodes, child_l_index, ray, indices);
-                }
-                if ray.intersects_aabb(child_r_aabb) {
-                    BVHNode::traverse_recursive(nodes, child_r_index, ray, indices);
-                }
-            }
-            BVHNode::Leaf { shape_index, .. } => {
-                indices.push(shape_index);
+    fn split_sah<T: BHShape>(
+        &mut self,
+        shapes: &[T],
+        range: Range<usize>,
+        split_axis: Axis,
+        centroid_bounds: &AABB,
+        aabb_bounds: &AABB,
+        num_buckets: usize,
+    ) -> (usize, AABB, AABB) {
+        let split_axis_size = centroid_bounds.max[split_axis] - centroid_bounds.min[split_axis];
+
+        // Create the `Bucket`s.
+        self.buckets.clear();
+        self.buckets.resize(num_buckets, Bucket::empty());
+        self.bucket_numbers.clear();
+
+        // We start by assigning the shapes to `Bucket`s.
+        for idx in &self.indices[range.clone()] {
+            let shape = &shapes[*idx];
+            let shape_aabb = shape.aabb();
+            let shape_center = shape_aabb.center();
+
+            // Get the relative position of the shape centroid `[0.0..1.0]`.
+            let bucket_num_relative =
+                (shape_center[split_axis] - centroid_bounds.min[split_axis]) / split_axis_size;
+
+            // Convert that to the actual `Bucket` number.
+            let bucket_num = (bucket_num_relative * (num_buckets as f32 - 0.01)) as usize;
+
+            // Extend the selected `Bucket` and remember the assignment.
+            self.buckets[bucket_num].add_aabb(&shape_aabb);
+            self.bucket_numbers.push(bucket_num);
+        }
+
+        // Compute the costs for each configuration and select the best configuration.
+        let mut min_bucket = 0;
+        let mut min_cost = f32::INFINITY;
+        let mut child_l_aabb = AABB::empty();
+        let mut child_r_aabb = AABB::empty();
+        for i in 0..(num_buckets - 1) {
+            let (l_buckets, r_buckets) = self.buckets.split_at(i + 1);
+            let child_l = l_buckets.iter().fold(Bucket::empty(), Bucket::join_bucket);
+            let child_r = r_buckets.iter().fold(Bucket::empty(), Bucket::join_bucket);
+
+            let cost = (child_l.size as f32 * child_l.aabb.surface_area()
+                + child_r.size as f32 * child_r.aabb.surface_area())
+                / aabb_bounds.surface_area();
+            if cost < min_cost {
+                min_bucket = i;
+                min_cost = cost;
+                child_l_aabb = child_l.aabb;
+                child_r_aabb = child_r.aabb;
             }
         }
+
+        // Sort the indices by `Bucket`, keeping their order within each `Bucket`.
+        self.bucket_offsets.clear();
+        let mut offset = 0;
+        for bucket in &self.buckets {
+            self.bucket_offsets.push(offset);
+            offset += bucket.size;
+        }
+        self.partitioned_indices.clear();
+        self.partitioned_indices.resize(range.len(), 0);
+        for (&idx, &bucket_num) in self.indices[range.clone()].iter().zip(&self.bucket_numbers) {
+            self.partitioned_indices[self.bucket_offsets[bucket_num]] = idx;
+            self.bucket_offsets[bucket_num] += 1;
+        }
+        self.indices[range.clone()].copy_from_slice(&self.partitioned_indices);
+
+        // The left child gets all `Bucket`s up to and including `min_bucket`.
+        let split = range.start + self.bucket_offsets[min_bucket];
+        (split, child_l_aabb, child_r_aabb)
+    }
+
+    /// Partitions `indices[range]` into two halves at the median of the shape centroids
+    /// along `split_axis`.
+    /// Returns the position of the split and the joint [`AABB`] of each child.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn split_median<T: BHShape>(
+        &mut self,
+        shapes: &[T],
+        range: Range<usize>,
+        split_axis: Axis,
+    ) -> (usize, AABB, AABB) {
+        self.centroids.clear();
+        self.centroids.extend(
+            self.indices[range.clone()]
+                .iter()
+                .map(|&index| (shapes[index].aabb().center()[split_axis], index)),
+        );
+        let median = self.centroids.len() / 2;
+        self.centroids
+            .select_nth_unstable_by(median, |a, b| a.0.total_cmp(&b.0));
+
+        for (index, &(_, shape_index)) in
+            self.indices[range.clone()].iter_mut().zip(&self.centroids)
+        {
+            *index = shape_index;
+        }
+
+        let split = range.start + median;
+        let child_l_aabb = joint_aabb_of_shapes(&self.indices[range.start..split], shapes);
+        let child_r_aabb = joint_aabb_of_shapes(&self.indices[split..range.end], shapes);
+        (split, child_l_aabb, child_r_aabb)
+    }
+}
+
+/// Counts the work done by one traversal of a [`BVH`], see [`BVH::traversal_stats`] and
+/// [`BVH::traverse_instrumented`].
+/// Unlike wall-clock time, these counts do not depend on the machine, which makes them
+/// suitable for comparing different ways to build a [`BVH`]. Stats of several rays can be
+/// summed up with `+=`.
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::traversal_stats`]: struct.BVH.html#method.traversal_stats
+/// [`BVH::traverse_instrumented`]: struct.BVH.html#method.traverse_instrumented
+///
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TraversalStats {
+    /// The number of inner nodes which were reached. Both children of each of them are
+    /// tested, so this is half of `aabb_tests`.
+    pub nodes_visited: usize,
+
+    /// The number of ray-[`AABB`] tests.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb_tests: usize,
+
+    /// The number of leaves which were reached.
+    pub leaf_tests: usize,
+
+    /// The number of shapes which were returned as candidates.
+    pub candidates: usize,
+}
+
+impl AddAssign for TraversalStats {
+    fn add_assign(&mut self, other: TraversalStats) {
+        self.nodes_visited += other.nodes_visited;
+        self.aabb_tests += other.aabb_tests;
+        self.leaf_tests += other.leaf_tests;
+        self.candidates += other.candidates;
     }
 }
 
+/// The order in which the children of a node are visited by the ordered traversals of a
+/// [`BVH`], see [`BVH::traverse_ordered_into`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::traverse_ordered_into`]: struct.BVH.html#method.traverse_ordered_into
+///
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraversalOrder {
+    /// Always visit the left child first, like [`BVH::traverse`].
+    ///
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    ///
+    LeftFirst,
+
+    /// Visit the child which the ray enters first before the other one. Closest hit queries
+    /// then find near hits early, which lets them skip far subtrees more often.
+    NearFirst,
+}
+
 /// The [`BVH`] data structure. Contains the list of [`BVHNode`]s.
 ///
 /// [`BVH`]: struct.BVH.html
@@ -406,9 +770,18 @@ impl BVHNode {
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
 pub struct BVH {
-    /// The list of nodes of the [`BVH`].
+    /// The list of nodes of the [`BVH`]. The root is the node `0`.
+    ///
+    /// The index of a node in this list is its id, which can key a side table of per-node
+    /// data, see [`BVH::fold_nodes`]. The ids are assigned by [`BVH::build`] and its variants.
+    /// [`BVH::optimize`] keeps them, but may move subtrees to other nodes, while
+    /// [`BVH::reorder_dfs`] renumbers all nodes.
     ///
     /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    /// [`BVH::fold_nodes`]: struct.BVH.html#method.fold_nodes
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    /// [`BVH::reorder_dfs`]: struct.BVH.html#method.reorder_dfs
     ///
     pub nodes: Vec<BVHNode>,
 }
@@ -419,368 +792,6465 @@ impl BVH {
     /// [`BVH`]: struct.BVH.html
     ///
     pub fn build<Shape: BHShape>(shapes: &mut [Shape]) -> BVH {
-        let indices = (0..shapes.len()).collect::<Vec<usize>>();
-        let expected_node_count = shapes.len() * 2;
-        let mut nodes = Vec::with_capacity(expected_node_count);
-        BVHNode::build(shapes, &indices, &mut nodes, 0, 0);
-        BVH { nodes }
+        BVH::build_with_config(shapes, &BVHConfig::default())
     }
 
-    /// Traverses the [`BVH`].
-    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    /// Creates a new [`BVH`] from the `shapes` slice, as described by `config`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{BVHConfig, SplitHeuristic, BVH};
+    /// use bvh::Point3;
+    /// # struct Sphere { position: Point3, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let config = BVHConfig {
+    ///     heuristic: SplitHeuristic::Median,
+    ///     ..Default::default()
+    /// };
+    /// let bvh = BVH::build_with_config(&mut spheres, &config);
+    /// # bvh.assert_consistent(&spheres);
+    /// ```
+    ///
+    /// If `config.max_leaf_size` is larger than `1`, `shapes` are reordered, so that the shapes
+    /// of every leaf lie next to each other.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.sah_bins` is less than `2`, `config.max_leaf_size` is `0`, or
+    /// `config.min_leaf_size` is larger than `(config.max_leaf_size + 1) / 2`. Also panics if
+    /// the [`AABB`] of any shape is not finite or not [`AABB::is_valid`], e.g. because it
+    /// contains a `NaN`.
+    /// [`BVH::build_checked`] returns an error instead, and [`BVH::build_skipping_invalid`]
+    /// leaves such shapes out.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::is_valid`]: ../aabb/struct.AABB.html#method.is_valid
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_checked`]: struct.BVH.html#method.build_checked
+    /// [`BVH::build_skipping_invalid`]: struct.BVH.html#method.build_skipping_invalid
+    ///
+    pub fn build_with_config<Shape: BHShape>(shapes: &mut [Shape], config: &BVHConfig) -> BVH {
+        let mut bvh = BVH { nodes: Vec::new() };
+        bvh.rebuild_with_scratch(shapes, config, &mut BVHBuildScratch::new());
+        bvh
+    }
+
+    /// Creates a new [`BVH`] from the `shapes` slice like [`BVH::build`], but computes the
+    /// bounds stored in the nodes with `merge_fn` instead of [`AABB::join`].
+    /// The bounds of a leaf are the [`AABB`]s of its shapes merged from left to right, and the
+    /// bounds of an inner node are the merged bounds of its two children. The tree itself is
+    /// split as usual, only the stored bounds are affected.
     ///
+    /// This allows tighter bounds for geometry whose extent is known better than the joint
+    /// [`AABB`] of its parts suggests. The merged bounds must still contain all shapes of the
+    /// subtree, or the traversal may miss them. Passing [`AABB::join`] gives the same [`BVH`]
+    /// as [`BVH::build`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::join`]: ../aabb/struct.AABB.html#method.join
     /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    ///
+    pub fn build_with_merge<Shape, F>(shapes: &mut [Shape], merge_fn: F) -> BVH
+    where
+        Shape: BHShape,
+        F: Fn(&AABB, &AABB) -> AABB,
+    {
+        let mut bvh = BVH::build(shapes);
+        if !bvh.nodes.is_empty() {
+            bvh.merge_subtree(0, shapes, &merge_fn);
+        }
+        bvh
+    }
+
+    /// Creates a new [`BVH`] from a slice of shapes which don't implement [`BHShape`], such as
+    /// boxed [`Bounded`] trait objects. This lets shapes of different types share one [`BVH`]
+    /// without wrapping them in an enum.
+    ///
+    /// The shapes are neither reordered nor mutated, and the [`BVH`] refers to them by their
+    /// index in `shapes`. Use [`BVH::traverse_dyn`] with the same slice to find them again.
+    /// [`AABB`] implements [`Bounded`] too, so precomputed boxes whose shapes live elsewhere
+    /// can be passed as they are, and the leaves then refer to the indices of the boxes.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// struct Sphere {
+    ///     center: Point3,
+    ///     radius: f32,
+    /// }
+    ///
+    /// impl Bounded for Sphere {
+    ///     fn aabb(&self) -> AABB {
+    ///         let half_size = Vector3::splat(self.radius);
+    ///         AABB::with_bounds(self.center - half_size, self.center + half_size)
+    ///     }
+    /// }
+    ///
+    /// let shapes: Vec<Box<dyn Bounded>> = vec![
+    ///     Box::new(Sphere { center: Point3::new(0.0, 0.0, 5.0), radius: 1.0 }),
+    ///     Box::new(AABB::with_bounds(Point3::new(-1.0, -1.0, 9.0), Point3::new(1.0, 1.0, 10.0))),
+    ///     Box::new(Point3::new(10.0, 0.0, 0.0)),
+    /// ];
+    /// let bvh = BVH::build_dyn(&shapes);
+    ///
+    /// let ray = Ray::new(Point3::ZERO, Vector3::new(0.0, 0.0, 1.0));
+    /// let mut hits = bvh.traverse_dyn(&ray, &shapes);
+    /// hits.sort_unstable();
+    /// assert_eq!(hits, vec![0, 1]);
+    /// ```
+    ///
     /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BHShape`]: ../bounding_hierarchy/trait.BHShape.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`Bounded`]: ../aabb/trait.Bounded.html
+    /// [`BVH::traverse_dyn`]: struct.BVH.html#method.traverse_dyn
     ///
-    pub fn traverse<'a, Shape: Bounded>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&Shape> {
-        let mut indices = Vec::new();
-        BVHNode::traverse_recursive(&self.nodes, 0, ray, &mut indices);
-        indices
+    pub fn build_dyn<Shape: Bounded>(shapes: &[Shape]) -> BVH {
+        let mut proxies = shapes
             .iter()
-            .map(|index| &shapes[*index])
-            .collect::<Vec<_>>()
+            .map(|shape| DynShape {
+                aabb: shape.aabb(),
+                node_index: 0,
+            })
+            .collect::<Vec<_>>();
+        // The default config keeps one shape per leaf, so the proxies are never reordered
+        // and their indices stay the indices into `shapes`.
+        BVH::build(&mut proxies)
     }
 
-    /// Creates a [`BVHTraverseIterator`] to traverse the [`BVH`].
-    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    /// Creates a new [`BVH`] from precomputed bounding boxes, e.g. read from a serialized scene
+    /// or computed on the GPU, where no shapes are at hand to call [`Bounded::aabb`] on.
+    /// Every pair of `aabbs` holds the id of a shape and its [`AABB`].
+    ///
+    /// Every leaf holds one shape and refers to it by its id instead of an index, so the index
+    /// queries like [`BVH::query_aabb`] or [`BVH::traverse_into`] return the ids. The ids don't
+    /// have to be dense, but methods which take a slice of shapes index it with them. For
+    /// boxes without ids, use [`BVH::build_from_aabb_slice`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
     ///
+    /// let aabbs = (0..10)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         (100 + i, AABB::with_bounds(min, min + Point3::ONE))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_from_aabbs(&aabbs);
+    ///
+    /// let ray = Ray::new(Point3::new(6.5, -10.0, 0.5), Vector3::new(0.0, 1.0, 0.0));
+    /// let mut hits = Vec::new();
+    /// bvh.traverse_into(&ray, &mut hits);
+    /// assert_eq!(hits, vec![103]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Bounded::aabb`]: ../aabb/trait.Bounded.html#tymethod.aabb
     /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_from_aabb_slice`]: struct.BVH.html#method.build_from_aabb_slice
+    /// [`BVH::query_aabb`]: struct.BVH.html#method.query_aabb
+    /// [`BVH::traverse_into`]: struct.BVH.html#method.traverse_into
+    ///
+    pub fn build_from_aabbs(aabbs: &[(usize, AABB)]) -> BVH {
+        let mut proxies = aabbs
+            .iter()
+            .map(|&(_, aabb)| DynShape {
+                aabb,
+                node_index: 0,
+            })
+            .collect::<Vec<_>>();
+        // The default config keeps one shape per leaf and the proxies in the order of `aabbs`.
+        let mut bvh = BVH::build(&mut proxies);
+        for node in bvh.nodes.iter_mut() {
+            if let BVHNode::Leaf {
+                ref mut shape_index,
+                ..
+            } = *node
+            {
+                *shape_index = aabbs[*shape_index].0;
+            }
+        }
+        bvh
+    }
+
+    /// Creates a new [`BVH`] from a slice of precomputed bounding boxes, whose shapes live
+    /// elsewhere. The leaves refer to the boxes by their index in `boxes`, so the results of
+    /// index queries like [`BVH::query_aabb`] or [`BVH::traverse_into`] index `boxes`, and
+    /// `boxes` can be passed as the shapes to methods like [`BVH::traverse_dyn`].
+    ///
+    /// This is [`BVH::build_dyn`] for a slice of [`AABB`]s. Use [`BVH::build_from_aabbs`] to
+    /// give the boxes ids instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_from_aabb_slice(&boxes);
+    ///
+    /// let ray = Ray::new(Point3::new(6.5, -10.0, 0.5), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(bvh.traverse_dyn(&ray, &boxes), vec![3]);
+    /// ```
+    ///
     /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_dyn`]: struct.BVH.html#method.build_dyn
+    /// [`BVH::build_from_aabbs`]: struct.BVH.html#method.build_from_aabbs
+    /// [`BVH::query_aabb`]: struct.BVH.html#method.query_aabb
+    /// [`BVH::traverse_dyn`]: struct.BVH.html#method.traverse_dyn
+    /// [`BVH::traverse_into`]: struct.BVH.html#method.traverse_into
     ///
-    pub fn traverse_iterator<'a, Shape: Bounded>(
-        &'a self,
-        ray: &'a Ray,
-        shapes: &'a [Shape],
-    ) -> BVHTraverseIterator<Shape> {
-        BVHTraverseIterator::new(self, ray, shapes)
+    pub fn build_from_aabb_slice(boxes: &[AABB]) -> BVH {
+        BVH::build_dyn(boxes)
     }
 
-    /// Prints the [`BVH`] in a tree-like visualization.
+    /// Creates a new [`BVH`] from a slice of shapes which are already sorted spatially, e.g.
+    /// along a Morton curve. Instead of partitioning the shapes, adjacent leaves are paired
+    /// into inner nodes, and adjacent inner nodes are paired level by level until one root is
+    /// left. This is much cheaper than [`BVH::build`], and the tree is balanced.
+    ///
+    /// The quality of the [`BVH`] depends entirely on the order of `sorted`: shapes are only
+    /// ever grouped with their neighbours in the slice. A spatially coherent order gives a
+    /// [`BVH`] which is close to a top-down build, while a random order gives nodes which
+    /// span most of the scene and make traversals slow.
+    ///
+    /// Like [`BVH::build_dyn`], the shapes are neither reordered nor mutated, and the
+    /// [`BVH`] refers to them by their index in `sorted`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// // The boxes are sorted along the x axis.
+    /// let boxes = (0..16)
+    ///     .map(|i| {
+    ///         let x = i as f32 * 2.0;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, 0.0), Point3::new(x + 1.0, 1.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_bottom_up(&boxes);
+    ///
+    /// let ray = Ray::new(Point3::new(4.5, 0.5, -1.0), Vector3::new(0.0, 0.0, 1.0));
+    /// assert_eq!(bvh.traverse_dyn(&ray, &boxes), vec![2]);
+    /// ```
     ///
     /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    /// [`BVH::build_dyn`]: struct.BVH.html#method.build_dyn
     ///
-    pub fn pretty_print(&self) {
-        let nodes = &self.nodes;
-        fn print_node(nodes: &[BVHNode], node_index: usize) {
-            match nodes[node_index] {
-                BVHNode::Node {
-                    child_l_index,
-                    child_r_index,
+    pub fn build_bottom_up<Shape: Bounded>(sorted: &[Shape]) -> BVH {
+        // The first `sorted.len()` entries are the leaves, every further entry pairs two
+        // earlier ones.
+        let mut entries = sorted
+            .iter()
+            .map(|shape| (shape.aabb(), None))
+            .collect::<Vec<BottomUpEntry>>();
+        let mut level = (0..sorted.len()).collect::<Vec<_>>();
+        while level.len() > 1 {
+            let mut next_level = Vec::with_capacity(level.len().div_ceil(2));
+            for pair in level.chunks(2) {
+                if let [child_l, child_r] = *pair {
+                    let aabb = entries[child_l].0.join(&entries[child_r].0);
+                    next_level.push(entries.len());
+                    entries.push((aabb, Some((child_l, child_r))));
+                } else {
+                    // An odd one out is paired on the next level.
+                    next_level.push(pair[0]);
+                }
+            }
+            level = next_level;
+        }
+
+        let mut bvh = BVH {
+            nodes: Vec::with_capacity(entries.len()),
+        };
+        if let Some(&root) = level.first() {
+            bvh.emit_bottom_up(&entries, root, 0, 0);
+        }
+        bvh
+    }
+
+    /// Creates a new [`BVH`] from the `shapes` slice bottom-up, by repeatedly merging the two
+    /// subtrees whose joint [`AABB`] has the smallest surface area. Unlike the top-down
+    /// [`BVH::build`], which decides every split by looking at all shapes below a node at
+    /// once, this groups shapes with their actual neighbours first, which gives better trees
+    /// for strongly clustered scenes, at the cost of a slower build.
+    ///
+    /// Merging exactly the best pair of the whole scene in every step is quadratic in the
+    /// number of shapes. Instead, the approximate agglomerative clustering of Gu et al. only
+    /// looks for the best pairs among the shapes which are close along a Morton curve, and then
+    /// among the clusters which are left over from neighbouring groups.
+    ///
+    /// Every leaf holds a single shape, so the shapes are not reordered, but like with
+    /// [`BVH::build`] they are told the index of their leaf.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    /// # struct Sphere { position: Point3, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// // Two tight clusters far apart.
+    /// let mut spheres = (0..100)
+    ///     .map(|i| {
+    ///         let x = (i % 2) as f32 * 1000.0 + (i / 2) as f32 * 0.1;
+    ///         Sphere { position: Point3::new(x, 0.0, 0.0), node_index: 0 }
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_agglomerative(&mut spheres);
+    /// bvh.assert_consistent(&spheres);
+    ///
+    /// let ray = Ray::new(Point3::new(1000.0, 0.0, -10.0), Vector3::new(0.0, 0.0, 1.0));
+    /// assert!(bvh.traverse(&ray, &spheres).iter().all(|sphere| sphere.position.x >= 1000.0));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`AABB`] of any shape is not finite or not [`AABB::is_valid`], e.g.
+    /// because it contains a `NaN`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::is_valid`]: ../aabb/struct.AABB.html#method.is_valid
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    ///
+    pub fn build_agglomerative<Shape: BHShape>(shapes: &mut [Shape]) -> BVH {
+        let mut entries = Vec::with_capacity(shapes.len() * 2);
+        for (index, shape) in shapes.iter().enumerate() {
+            let aabb = shape.aabb();
+            assert!(
+                is_buildable(&aabb),
+                "Shape {} has invalid bounds {}.",
+                index,
+                aabb
+            );
+            entries.push((aabb, None));
+        }
+
+        let mut bvh = BVH {
+            nodes: Vec::with_capacity(entries.len()),
+        };
+        if let Some(root) = agglomerative_clustering(&mut entries) {
+            bvh.emit_bottom_up(&entries, root, 0, 0);
+        }
+
+        // Let the shapes know the index of the node that represents them.
+        for (node_index, node) in bvh.nodes.iter().enumerate() {
+            if let Some(shape_index) = node.shape_index() {
+                shapes[shape_index].set_bh_node_index(node_index);
+            }
+        }
+        bvh
+    }
+
+    /// Appends the nodes of the subtree of [`BVH::build_bottom_up`] or
+    /// [`BVH::build_agglomerative`] below `entry` in depth-first order. Returns the index of
+    /// the subtree's root node.
+    ///
+    /// [`BVH::build_agglomerative`]: struct.BVH.html#method.build_agglomerative
+    /// [`BVH::build_bottom_up`]: struct.BVH.html#method.build_bottom_up
+    ///
+    fn emit_bottom_up(
+        &mut self,
+        entries: &[BottomUpEntry],
+        entry: usize,
+        parent_index: usize,
+        depth: u32,
+    ) -> usize {
+        let node_index = self.nodes.len();
+        match entries[entry].1 {
+            Some((child_l, child_r)) => {
+                self.nodes.push(BVHNode::Node {
+                    parent_index,
                     depth,
-                    child_l_aabb,
-                    child_r_aabb,
+                    child_l_index: 0,
+                    child_l_aabb: entries[child_l].0,
+                    child_r_index: 0,
+                    child_r_aabb: entries[child_r].0,
+                });
+                let child_l_index = self.emit_bottom_up(entries, child_l, node_index, depth + 1);
+                let child_r_index = self.emit_bottom_up(entries, child_r, node_index, depth + 1);
+                if let BVHNode::Node {
+                    child_l_index: ref mut l,
+                    child_r_index: ref mut r,
                     ..
-                } => {
-                    let padding: String = " ".repeat(depth as usize);
-                    println!("{}child_l {}", padding, child_l_aabb);
-                    print_node(nodes, child_l_index);
-                    println!("{}child_r {}", padding, child_r_aabb);
-                    print_node(nodes, child_r_index);
+                } = self.nodes[node_index]
+                {
+                    *l = child_l_index;
+                    *r = child_r_index;
                 }
-                BVHNode::Leaf {
-                    shape_index, depth, ..
-                } => {
-                    let padding: String = " ".repeat(depth as usize);
-                    println!("{}shape\t{:?}", padding, shape_index);
+            }
+            None => self.nodes.push(BVHNode::Leaf {
+                parent_index,
+                depth,
+                shape_index: entry,
+                shape_count: 1,
+            }),
+        }
+        node_index
+    }
+
+    /// Applies `transform` to each of the `old_shapes` the [`BVH`] was built from, e.g. to move
+    /// objects between frames, and builds a new [`BVH`] over the transformed shapes.
+    /// Returns the new [`BVH`] together with the transformed shapes.
+    ///
+    /// This is equivalent to [`BVH::build_dyn`] on the transformed shapes: the new [`BVH`]
+    /// refers to them by their index, which is the index of the shape they were created from.
+    /// The old [`BVH`] is currently only checked against `old_shapes` in debug builds.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let x = i as f32;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, 0.0), Point3::new(x + 0.5, 1.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // Move all boxes up by 10.
+    /// let offset = Vector3::new(0.0, 10.0, 0.0);
+    /// let (moved_bvh, moved) =
+    ///     bvh.map_shapes(&boxes, |aabb| AABB::with_bounds(aabb.min + offset, aabb.max + offset));
+    ///
+    /// let ray = Ray::new(Point3::new(3.25, 10.5, -1.0), Vector3::new(0.0, 0.0, 1.0));
+    /// assert_eq!(moved_bvh.traverse_dyn(&ray, &moved), vec![3]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics in debug builds if the [`BVH`] refers to shapes beyond the end of `old_shapes`.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_dyn`]: struct.BVH.html#method.build_dyn
+    ///
+    pub fn map_shapes<T, U, F>(&self, old_shapes: &[T], transform: F) -> (BVH, Vec<U>)
+    where
+        T: Bounded,
+        U: Bounded,
+        F: FnMut(&T) -> U,
+    {
+        debug_assert!(
+            self.nodes
+                .iter()
+                .filter_map(BVHNode::shape_range)
+                .all(|shape_range| shape_range.end <= old_shapes.len()),
+            "The BVH was not built from this slice of shapes."
+        );
+        let new_shapes = old_shapes.iter().map(transform).collect::<Vec<_>>();
+        let bvh = BVH::build_dyn(&new_shapes);
+        (bvh, new_shapes)
+    }
+
+    /// Combines two independently built [`BVH`]s under a new root, e.g. to add a group of
+    /// objects to a scene without rebuilding it. The shapes of `bvh_b` must follow the shapes
+    /// of `bvh_a` in one slice, starting at `offset_b`, usually the number of shapes of `bvh_a`.
+    /// `shapes` is that slice. It gives the bounds of a [`BVH`] which consists of a single leaf.
+    ///
+    /// The nodes of `bvh_a` follow the new root, and the nodes of `bvh_b` follow those, so
+    /// the node indices stored in the shapes of `bvh_a` grow by `1`, and those of `bvh_b` by
+    /// `1 + bvh_a.nodes.len()`. Update them before calling [`BVH::optimize`].
+    ///
+    /// If one of the [`BVH`]s is empty, the other one is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let x = i as f32 * 2.0;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, 0.0), Point3::new(x + 1.0, 1.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh_a = BVH::build_dyn(&boxes[..6]);
+    /// let bvh_b = BVH::build_dyn(&boxes[6..]);
+    /// let bvh = BVH::merge(bvh_a, bvh_b, 6, &boxes);
+    ///
+    /// let ray = Ray::new(Point3::new(16.5, 0.5, -1.0), Vector3::new(0.0, 0.0, 1.0));
+    /// assert_eq!(bvh.traverse_dyn(&ray, &boxes), vec![8]);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    ///
+    pub fn merge<Shape: Bounded>(bvh_a: BVH, bvh_b: BVH, offset_b: usize, shapes: &[Shape]) -> BVH {
+        if bvh_b.nodes.is_empty() {
+            return bvh_a;
+        }
+        if bvh_a.nodes.is_empty() {
+            let mut bvh = bvh_b;
+            for node in bvh.nodes.iter_mut() {
+                if let BVHNode::Leaf {
+                    ref mut shape_index,
+                    ..
+                } = *node
+                {
+                    *shape_index += offset_b;
                 }
             }
+            return bvh;
         }
-        print_node(nodes, 0);
+
+        let node_offset_b = 1 + bvh_a.nodes.len();
+        let mut nodes = Vec::with_capacity(node_offset_b + bvh_b.nodes.len());
+        nodes.push(BVHNode::Node {
+            parent_index: 0,
+            depth: 0,
+            child_l_index: 1,
+            child_l_aabb: bvh_a.root_aabb_of(shapes),
+            child_r_index: node_offset_b,
+            child_r_aabb: bvh_b.root_aabb_of(&shapes[offset_b..]),
+        });
+        for (bvh, node_offset, shape_offset) in [(&bvh_a, 1, 0), (&bvh_b, node_offset_b, offset_b)]
+        {
+            for (index, node) in bvh.nodes.iter().enumerate() {
+                let mut node = *node;
+                match node {
+                    BVHNode::Node {
+                        ref mut parent_index,
+                        ref mut depth,
+                        ref mut child_l_index,
+                        ref mut child_r_index,
+                        ..
+                    } => {
+                        *parent_index += node_offset;
+                        *depth += 1;
+                        *child_l_index += node_offset;
+                        *child_r_index += node_offset;
+                    }
+                    BVHNode::Leaf {
+                        ref mut parent_index,
+                        ref mut depth,
+                        ref mut shape_index,
+                        ..
+                    } => {
+                        *parent_index += node_offset;
+                        *depth += 1;
+                        *shape_index += shape_offset;
+                    }
+                }
+                // The old roots now hang below the new root.
+                if index == 0 {
+                    *node.parent_mut() = 0;
+                }
+                nodes.push(node);
+            }
+        }
+        BVH { nodes }
     }
 
-    /// Verifies that the node at index `node_index` lies inside `expected_outer_aabb`,
-    /// its parent index is equal to `expected_parent_index`, its depth is equal to
-    /// `expected_depth`. Increares `node_count` by the number of visited nodes.
-    fn is_consistent_subtree<Shape: BHShape>(
-        &self,
-        node_index: usize,
-        expected_parent_index: usize,
-        expected_outer_aabb: &AABB,
-        expected_depth: u32,
-        node_count: &mut usize,
-        shapes: &[Shape],
-    ) -> bool {
-        *node_count += 1;
+    /// Returns the joint [`AABB`] of the children of the root, or [`AABB::infinite`] if the
+    /// root is a leaf.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::infinite`]: ../aabb/struct.AABB.html#method.infinite
+    ///
+    pub(crate) fn root_aabb(&self) -> AABB {
+        match self.nodes[0] {
+            BVHNode::Node {
+                ref child_l_aabb,
+                ref child_r_aabb,
+                ..
+            } => child_l_aabb.join(child_r_aabb),
+            BVHNode::Leaf { .. } => AABB::infinite(),
+        }
+    }
+
+    /// Recomputes the bounds stored in the subtree below `node_index` with `merge_fn`.
+    /// Returns the merged bounds of the whole subtree.
+    fn merge_subtree<Shape, F>(&mut self, node_index: usize, shapes: &[Shape], merge_fn: &F) -> AABB
+    where
+        Shape: BHShape,
+        F: Fn(&AABB, &AABB) -> AABB,
+    {
         match self.nodes[node_index] {
             BVHNode::Node {
-                parent_index,
-                depth,
                 child_l_index,
-                child_l_aabb,
                 child_r_index,
-                child_r_aabb,
+                ..
             } => {
-                let correct_parent_index = expected_parent_index == parent_index;
-                let correct_depth = expected_depth == depth;
-                let left_aabb_in_parent =
-                    expected_outer_aabb.approx_contains_aabb_eps(&child_l_aabb, EPSILON);
-                let right_aabb_in_parent =
-                    expected_outer_aabb.approx_contains_aabb_eps(&child_r_aabb, EPSILON);
-                let left_subtree_consistent = self.is_consistent_subtree(
-                    child_l_index,
-                    node_index,
-                    &child_l_aabb,
-                    expected_depth + 1,
-                    node_count,
-                    shapes,
-                );
-                let right_subtree_consistent = self.is_consistent_subtree(
-                    child_r_index,
-                    node_index,
-                    &child_r_aabb,
-                    expected_depth + 1,
-                    node_count,
-                    shapes,
-                );
-
-                correct_parent_index
-                    && correct_depth
-                    && left_aabb_in_parent
-                    && right_aabb_in_parent
-                    && left_subtree_consistent
-                    && right_subtree_consistent
+                let child_l_aabb = self.merge_subtree(child_l_index, shapes, merge_fn);
+                let child_r_aabb = self.merge_subtree(child_r_index, shapes, merge_fn);
+                *self.nodes[node_index].child_l_aabb_mut() = child_l_aabb;
+                *self.nodes[node_index].child_r_aabb_mut() = child_r_aabb;
+                merge_fn(&child_l_aabb, &child_r_aabb)
             }
             BVHNode::Leaf {
-                parent_index,
-                depth,
                 shape_index,
-            } => {
-                let correct_parent_index = expected_parent_index == parent_index;
-                let correct_depth = expected_depth == depth;
-                let shape_aabb = shapes[shape_index].aabb();
-                let shape_aabb_in_parent =
-                    expected_outer_aabb.approx_contains_aabb_eps(&shape_aabb, EPSILON);
+                shape_count,
+                ..
+            } => shapes[shape_index..shape_index + shape_count]
+                .iter()
+                .map(|shape| shape.aabb())
+                .reduce(|aabb, shape_aabb| merge_fn(&aabb, &shape_aabb))
+                .unwrap_or_else(AABB::empty),
+        }
+    }
 
-                correct_parent_index && correct_depth && shape_aabb_in_parent
-            }
+    /// Rebuilds the [`BVH`] from the `shapes` slice, reusing the memory of its nodes.
+    /// The result is identical to [`BVH::build`].
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    ///
+    pub fn rebuild<Shape: BHShape>(&mut self, shapes: &mut [Shape]) {
+        self.rebuild_with_scratch(shapes, &BVHConfig::default(), &mut BVHBuildScratch::new());
+    }
+
+    /// Rebuilds the [`BVH`] from the `shapes` slice as described by `config`, reusing the
+    /// memory of its nodes and the buffers in `scratch`. The result is identical to
+    /// [`BVH::build_with_config`].
+    ///
+    /// Once the buffers are large enough, which usually is the case after the first build,
+    /// rebuilding a [`BVH`] of the same size does not allocate.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{BVHBuildScratch, BVHConfig, BVH};
+    /// use bvh::Point3;
+    /// # struct Sphere { position: Point3, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let config = BVHConfig::default();
+    /// let mut scratch = BVHBuildScratch::new();
+    /// let mut bvh = BVH::build(&mut spheres);
+    ///
+    /// for frame in 0..10 {
+    ///     for sphere in spheres.iter_mut() {
+    ///         sphere.position.y = frame as f32;
+    ///     }
+    ///     bvh.rebuild_with_scratch(&mut spheres, &config, &mut scratch);
+    /// }
+    /// # bvh.assert_consistent(&spheres);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.sah_bins` is less than `2`, `config.max_leaf_size` is `0`, or
+    /// `config.min_leaf_size` is larger than `(config.max_leaf_size + 1) / 2`. Also panics if
+    /// the [`AABB`] of any shape is not finite or not [`AABB::is_valid`], e.g. because it
+    /// contains a `NaN`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::is_valid`]: ../aabb/struct.AABB.html#method.is_valid
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_with_config`]: struct.BVH.html#method.build_with_config
+    ///
+    pub fn rebuild_with_scratch<Shape: BHShape>(
+        &mut self,
+        shapes: &mut [Shape],
+        config: &BVHConfig,
+        scratch: &mut BVHBuildScratch,
+    ) {
+        // Invalid bounds would silently corrupt the splits of every node above the shape.
+        for (index, shape) in shapes.iter().enumerate() {
+            let aabb = shape.aabb();
+            assert!(
+                is_buildable(&aabb),
+                "Shape {} has invalid bounds {}.",
+                index,
+                aabb
+            );
         }
+        scratch.indices.clear();
+        scratch.indices.extend(0..shapes.len());
+        self.build_from_indices(shapes, shapes.len(), config, scratch);
     }
 
-    /// Checks if all children of a node have the correct parent index, and that there is no
-    /// detached subtree. Also checks if the `AABB` hierarchy is consistent.
-    pub fn is_consistent<Shape: BHShape>(&self, shapes: &[Shape]) -> bool {
-        // The root node of the bvh is not bounded by anything.
-        let space = AABB {
-            min: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
-            max: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
-        };
+    /// Creates a new [`BVH`] from the `shapes` slice like [`BVH::build_with_config`], but
+    /// returns an [`InvalidBoundsError`] for the first shape whose [`AABB`] is not finite or
+    /// not [`AABB::is_valid`], e.g. because a vertex of a mesh is `NaN` or infinite.
+    /// This is the strict variant: nothing is built and `shapes` are left untouched if any
+    /// shape is invalid. [`BVH::build_skipping_invalid`] is the lenient one.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::{BVHConfig, BVH};
+    /// use bvh::Point3;
+    ///
+    /// # struct Shape { aabb: AABB, node_index: usize }
+    /// # impl bvh::aabb::Bounded for Shape {
+    /// #     fn aabb(&self) -> AABB { self.aabb }
+    /// # }
+    /// # impl bvh::bounding_hierarchy::BHShape for Shape {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut shapes = [0.0, f32::NAN, 2.0]
+    ///     .iter()
+    ///     .map(|&x| {
+    ///         let min = Point3::new(x, 0.0, 0.0);
+    ///         Shape { aabb: AABB::with_bounds(min, min + Point3::ONE), node_index: 0 }
+    ///     })
+    ///     .collect::<Vec<_>>();
+    ///
+    /// let error = BVH::build_checked(&mut shapes, &BVHConfig::default()).unwrap_err();
+    /// assert_eq!(error.shape_index, 1);
+    ///
+    /// shapes.remove(1);
+    /// assert!(BVH::build_checked(&mut shapes, &BVHConfig::default()).is_ok());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config` is invalid, like [`BVH::build_with_config`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::is_valid`]: ../aabb/struct.AABB.html#method.is_valid
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_skipping_invalid`]: struct.BVH.html#method.build_skipping_invalid
+    /// [`BVH::build_with_config`]: struct.BVH.html#method.build_with_config
+    /// [`InvalidBoundsError`]: struct.InvalidBoundsError.html
+    ///
+    pub fn build_checked<Shape: BHShape>(
+        shapes: &mut [Shape],
+        config: &BVHConfig,
+    ) -> Result<BVH, InvalidBoundsError> {
+        for (shape_index, shape) in shapes.iter().enumerate() {
+            let aabb = shape.aabb();
+            if !is_buildable(&aabb) {
+                return Err(InvalidBoundsError { shape_index, aabb });
+            }
+        }
+        Ok(BVH::build_with_config(shapes, config))
+    }
 
-        // The counter for all nodes.
-        let mut node_count = 0;
-        let subtree_consistent =
-            self.is_consistent_subtree(0, 0, &space, 0, &mut node_count, shapes);
+    /// Creates a new [`BVH`] from the `shapes` slice like [`BVH::build_with_config`], but
+    /// leaves out every shape whose [`AABB`] is not finite or not [`AABB::is_valid`], and
+    /// returns the positions of the skipped shapes in `shapes` after the build. This is
+    /// the lenient variant of [`BVH::build_checked`].
+    ///
+    /// The skipped shapes are not part of the [`BVH`], so no query returns them, and their
+    /// node indices are left untouched. If `config.max_leaf_size` is larger than `1`, the
+    /// shapes are reordered as usual and the skipped ones are moved to the end of `shapes`.
+    /// Otherwise all shapes stay where they are.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::{BVHConfig, BVH};
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    /// # struct Shape { aabb: AABB, node_index: usize }
+    /// # impl bvh::aabb::Bounded for Shape {
+    /// #     fn aabb(&self) -> AABB { self.aabb }
+    /// # }
+    /// # impl bvh::bounding_hierarchy::BHShape for Shape {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut shapes = [0.0, f32::INFINITY, 2.0, f32::NAN, 4.0]
+    ///     .iter()
+    ///     .map(|&x| {
+    ///         let min = Point3::new(x, 0.0, 0.0);
+    ///         Shape { aabb: AABB::with_bounds(min, min + Point3::ONE), node_index: 0 }
+    ///     })
+    ///     .collect::<Vec<_>>();
+    ///
+    /// let (bvh, skipped) = BVH::build_skipping_invalid(&mut shapes, &BVHConfig::default());
+    /// assert_eq!(skipped, vec![1, 3]);
+    ///
+    /// let ray = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vector3::new(1.0, 0.0, 0.0));
+    /// let mut hits = Vec::new();
+    /// bvh.traverse_into(&ray, &mut hits);
+    /// hits.sort_unstable();
+    /// assert_eq!(hits, vec![0, 2, 4]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config` is invalid, like [`BVH::build_with_config`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::is_valid`]: ../aabb/struct.AABB.html#method.is_valid
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_checked`]: struct.BVH.html#method.build_checked
+    /// [`BVH::build_with_config`]: struct.BVH.html#method.build_with_config
+    ///
+    pub fn build_skipping_invalid<Shape: BHShape>(
+        shapes: &mut [Shape],
+        config: &BVHConfig,
+    ) -> (BVH, Vec<usize>) {
+        let mut scratch = BVHBuildScratch::new();
+        let (valid, skipped): (Vec<usize>, Vec<usize>) =
+            (0..shapes.len()).partition(|&index| is_buildable(&shapes[index].aabb()));
+        let valid_count = valid.len();
+        scratch.indices = valid;
+        scratch.indices.extend(&skipped);
 
-        // Check if all nodes have been counted from the root node.
-        // If this is false, it means we have a detached subtree.
-        let is_connected = node_count == self.nodes.len();
-        subtree_consistent && is_connected
+        let mut bvh = BVH { nodes: Vec::new() };
+        bvh.build_from_indices(shapes, valid_count, config, &mut scratch);
+        let skipped = if config.max_leaf_size == 1 {
+            skipped
+        } else {
+            (valid_count..shapes.len()).collect()
+        };
+        (bvh, skipped)
     }
 
-    /// Assert version of `is_consistent_subtree`.
-    fn assert_consistent_subtree<Shape: BHShape>(
-        &self,
-        node_index: usize,
-        expected_parent_index: usize,
-        expected_outer_aabb: &AABB,
-        expected_depth: u32,
-        node_count: &mut usize,
-        shapes: &[Shape],
+    /// Builds the nodes of the [`BVH`] for the shapes `scratch.indices[..shape_count]`, and
+    /// keeps the shapes of `scratch.indices[shape_count..]` out of it. `scratch.indices` must
+    /// be a permutation of the indices of `shapes`.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    fn build_from_indices<Shape: BHShape>(
+        &mut self,
+        shapes: &mut [Shape],
+        shape_count: usize,
+        config: &BVHConfig,
+        scratch: &mut BVHBuildScratch,
     ) {
-        *node_count += 1;
-        let node = &self.nodes[node_index];
+        config.validate();
+        let expected_node_count = shape_count * 2;
+        self.nodes.clear();
+        self.nodes.reserve(expected_node_count);
+        scratch.build_node(shapes, 0..shape_count, &mut self.nodes, 0, 0, config);
 
-        let parent = node.parent();
-        assert_eq!(
-            expected_parent_index, parent,
-            "Wrong parent index. Expected: {}; Actual: {}",
-            expected_parent_index, parent
-        );
-        let depth = node.depth();
-        assert_eq!(
-            expected_depth, depth,
-            "Wrong depth. Expected: {}; Actual: {}",
-            expected_depth, depth
-        );
+        if config.max_leaf_size == 1 {
+            // Every leaf holds a single shape, so the shapes can stay where they are.
+            for node in self.nodes.iter_mut() {
+                if let BVHNode::Leaf {
+                    ref mut shape_index,
+                    ..
+                } = *node
+                {
+                    *shape_index = scratch.indices[*shape_index];
+                }
+            }
+        } else {
+            // Move the shapes of every leaf next to each other.
+            apply_permutation(shapes, &mut scratch.indices);
+        }
 
-        match *node {
-            BVHNode::Node {
-                child_l_index,
-                child_l_aabb,
-                child_r_index,
-                child_r_aabb,
-                ..
-            } => {
-                assert!(
-                    expected_outer_aabb.approx_contains_aabb_eps(&child_l_aabb, EPSILON),
-                    "Left child lies outside the expected bounds.
-                         \tBounds: {}
-                         \tLeft child: {}",
-                    expected_outer_aabb,
-                    child_l_aabb
-                );
-                assert!(
-                    expected_outer_aabb.approx_contains_aabb_eps(&child_r_aabb, EPSILON),
-                    "Right child lies outside the expected bounds.
-                         \tBounds: {}
-                         \tRight child: {}",
-                    expected_outer_aabb,
-                    child_r_aabb
-                );
-                self.assert_consistent_subtree(
+        // Let the shapes know the index of the node that represents them.
+        for (node_index, node) in self.nodes.iter().enumerate() {
+            if let Some(shape_range) = node.shape_range() {
+                for shape in &mut shapes[shape_range] {
+                    shape.set_bh_node_index(node_index);
+                }
+            }
+        }
+    }
+
+    /// Rebuilds only the part of the [`BVH`] around `region`, e.g. after some shapes inside
+    /// of it moved. This is more thorough than refitting the [`AABB`]s of the moved shapes'
+    /// ancestors, and much cheaper than rebuilding the whole [`BVH`] when `region` is small.
+    ///
+    /// The rebuilt subtree is found by descending from the root as long as `region` overlaps
+    /// the [`AABB`] of only one child. All shapes below that node are partitioned from
+    /// scratch, using their current [`AABB`]s and the default [`BVHConfig`]. The new subtree
+    /// replaces the old one in place: its root keeps the old root's index, its other nodes
+    /// reuse the indices of the old subtree first and are appended to [`BVH::nodes`] when
+    /// the new subtree has more nodes. Finally, the [`AABB`]s which the ancestors store for
+    /// their children are refitted bottom up to the bounds of the new subtree.
+    ///
+    /// Shapes which moved must have been inside of `region` before as well as after moving,
+    /// and shapes outside of it must not have moved. Like [`BVH::build_dyn`], this method does
+    /// not update node indices stored in `shapes`, and the rebuilt leaves hold one shape each,
+    /// referenced by its index in `shapes`. Don't call [`BVH::optimize`] afterwards.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let mut boxes = (0..100)
+    ///     .map(|i| {
+    ///         let x = i as f32;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, 0.0), Point3::new(x + 0.5, 1.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let mut bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // Swap two boxes on the left, then repair the tree around them.
+    /// boxes.swap(2, 7);
+    /// let region = AABB::with_bounds(Point3::new(1.5, 0.0, 0.0), Point3::new(8.0, 1.0, 1.0));
+    /// bvh.rebuild_region(&boxes, &region);
+    ///
+    /// let ray = Ray::new(Point3::new(7.25, 0.5, -1.0), Vector3::new(0.0, 0.0, 1.0));
+    /// let hits = bvh.traverse(&ray, &boxes);
+    /// assert_eq!(hits.len(), 1);
+    /// assert_eq!(hits[0].min.x, 7.0);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_dyn`]: struct.BVH.html#method.build_dyn
+    /// [`BVH::nodes`]: struct.BVH.html#structfield.nodes
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    /// [`BVHConfig`]: struct.BVHConfig.html
+    ///
+    pub fn rebuild_region<T: Bounded>(&mut self, shapes: &[T], region: &AABB) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        // Find the smallest subtree which contains all leaves overlapping `region`.
+        let mut subtree_root = 0;
+        while let BVHNode::Node {
+            child_l_index,
+            ref child_l_aabb,
+            child_r_index,
+            ref child_r_aabb,
+            ..
+        } = self.nodes[subtree_root]
+        {
+            match (
+                region.intersects_aabb(child_l_aabb),
+                region.intersects_aabb(child_r_aabb),
+            ) {
+                (true, true) => break,
+                (true, false) => subtree_root = child_l_index,
+                (false, true) => subtree_root = child_r_index,
+                (false, false) => return,
+            }
+        }
+
+        // Collect the old nodes of the subtree, root first, and the shapes below them.
+        let mut slots = Vec::new();
+        let mut shape_indices = Vec::new();
+        let mut stack = vec![subtree_root];
+        while let Some(node_index) = stack.pop() {
+            slots.push(node_index);
+            match self.nodes[node_index] {
+                BVHNode::Node {
                     child_l_index,
-                    node_index,
-                    &child_l_aabb,
-                    expected_depth + 1,
-                    node_count,
-                    shapes,
-                );
-                self.assert_consistent_subtree(
                     child_r_index,
-                    node_index,
-                    &child_r_aabb,
-                    expected_depth + 1,
-                    node_count,
+                    ..
+                } => {
+                    stack.push(child_r_index);
+                    stack.push(child_l_index);
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => shape_indices.extend(shape_index..shape_index + shape_count),
+            }
+        }
+        if shape_indices.is_empty() {
+            return;
+        }
+
+        let proxies = shape_indices
+            .iter()
+            .map(|&shape_index| DynShape {
+                aabb: shapes[shape_index].aabb(),
+                node_index: 0,
+            })
+            .collect::<Vec<_>>();
+        let mut scratch = BVHBuildScratch {
+            indices: (0..proxies.len()).collect(),
+            ..Default::default()
+        };
+        let root_node = self.nodes[subtree_root];
+        let mut subtree = Vec::with_capacity(proxies.len() * 2);
+        scratch.build_node(
+            &proxies,
+            0..proxies.len(),
+            &mut subtree,
+            root_node.parent(),
+            root_node.depth(),
+            &BVHConfig::default(),
+        );
+
+        // Every old leaf holds at least one shape, so the new subtree is never smaller.
+        while slots.len() < subtree.len() {
+            slots.push(self.nodes.len());
+            self.nodes.push(BVHNode::create_dummy());
+        }
+        for (local_index, mut node) in subtree.into_iter().enumerate() {
+            if local_index != 0 {
+                *node.parent_mut() = slots[node.parent()];
+            }
+            match node {
+                BVHNode::Node {
+                    ref mut child_l_index,
+                    ref mut child_r_index,
+                    ..
+                } => {
+                    *child_l_index = slots[*child_l_index];
+                    *child_r_index = slots[*child_r_index];
+                }
+                BVHNode::Leaf {
+                    ref mut shape_index,
+                    ..
+                } => *shape_index = shape_indices[scratch.indices[*shape_index]],
+            }
+            self.nodes[slots[local_index]] = node;
+        }
+
+        // Refit the ancestors to the new bounds.
+        let mut node_index = subtree_root;
+        while node_index != 0 {
+            let aabb = match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_aabb,
+                    child_r_aabb,
+                    ..
+                } => child_l_aabb.join(&child_r_aabb),
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => shapes[shape_index..shape_index + shape_count]
+                    .iter()
+                    .fold(AABB::empty(), |aabb, shape| aabb.join(&shape.aabb())),
+            };
+            let parent_index = self.nodes[node_index].parent();
+            let parent = &mut self.nodes[parent_index];
+            if parent.child_l() == node_index {
+                *parent.child_l_aabb_mut() = aabb;
+            } else {
+                *parent.child_r_aabb_mut() = aabb;
+            }
+            node_index = parent_index;
+        }
+    }
+
+    /// Renumbers the nodes of the [`BVH`] in depth-first order, so that the left child of
+    /// every node directly follows it, and every subtree occupies a contiguous range of nodes.
+    /// Updates the node indices stored in `shapes` accordingly.
+    ///
+    /// [`BVH::build`] already creates the nodes in this order, but [`BVH::optimize`] rotates
+    /// nodes without moving them in memory. Reordering restores the locality of the nodes,
+    /// which speeds up traversal.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    ///
+    pub fn reorder_dfs<Shape: BHShape>(&mut self, shapes: &mut [Shape]) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        // Collect the old node indices in depth-first order.
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            order.push(node_index);
+            if let BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } = self.nodes[node_index]
+            {
+                stack.push(child_r_index);
+                stack.push(child_l_index);
+            }
+        }
+
+        let mut new_indices = vec![0; self.nodes.len()];
+        for (new_index, &old_index) in order.iter().enumerate() {
+            new_indices[old_index] = new_index;
+        }
+
+        let mut nodes = Vec::with_capacity(order.len());
+        for (new_index, &old_index) in order.iter().enumerate() {
+            let mut node = self.nodes[old_index];
+            match node {
+                BVHNode::Node {
+                    ref mut parent_index,
+                    ref mut child_l_index,
+                    ref mut child_r_index,
+                    ..
+                } => {
+                    *parent_index = new_indices[*parent_index];
+                    *child_l_index = new_indices[*child_l_index];
+                    *child_r_index = new_indices[*child_r_index];
+                }
+                BVHNode::Leaf {
+                    ref mut parent_index,
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    *parent_index = new_indices[*parent_index];
+                    for shape in &mut shapes[shape_index..shape_index + shape_count] {
+                        shape.set_bh_node_index(new_index);
+                    }
+                }
+            }
+            nodes.push(node);
+        }
+        self.nodes = nodes;
+    }
+
+    /// Removes the leaves whose shapes are degenerate, e.g. zero-area triangles of an imported
+    /// mesh. A leaf is removed if the surface area of the joint [`AABB`] of its shapes is below
+    /// `min_surface_area`, and an inner node which loses one child is replaced by the other.
+    /// The remaining nodes are renumbered in depth-first order, their bounds are recomputed,
+    /// and the node indices stored in their shapes are updated.
+    ///
+    /// Returns the indices of the removed shapes. They stay in `shapes`, but the [`BVH`] no
+    /// longer refers to them, so they must not be passed to [`BVH::optimize`] as updated.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    /// # struct Sphere { position: Point3, radius: f32, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         let half_size = Point3::splat(self.radius);
+    /// #         AABB::with_bounds(self.position - half_size, self.position + half_size)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// // Every third sphere has a radius of zero.
+    /// let mut spheres = (0..9)
+    ///     .map(|i| Sphere {
+    ///         position: Point3::new(i as f32, 0.0, 0.0),
+    ///         radius: if i % 3 == 0 { 0.0 } else { 0.5 },
+    ///         node_index: 0,
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let mut bvh = BVH::build(&mut spheres);
+    ///
+    /// let mut removed = bvh.prune(&mut spheres, 1e-6);
+    /// removed.sort_unstable();
+    /// assert_eq!(removed, vec![0, 3, 6]);
+    /// assert_eq!(bvh.nodes.len(), 11);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    ///
+    pub fn prune<Shape: BHShape>(
+        &mut self,
+        shapes: &mut [Shape],
+        min_surface_area: f32,
+    ) -> Vec<usize> {
+        let mut removed = Vec::new();
+        let kept = self.fold_nodes(
+            |shape_range| {
+                let aabb = shapes[shape_range.clone()]
+                    .iter()
+                    .fold(AABB::empty(), |aabb, shape| aabb.join(&shape.aabb()));
+                if aabb.surface_area() < min_surface_area {
+                    removed.extend(shape_range);
+                    false
+                } else {
+                    true
+                }
+            },
+            |kept_l, kept_r| *kept_l || *kept_r,
+        );
+        if removed.is_empty() {
+            return removed;
+        }
+
+        let old_nodes = std::mem::take(&mut self.nodes);
+        if kept[0] {
+            self.nodes.reserve(old_nodes.len());
+            self.push_pruned_subtree(&old_nodes, &kept, shapes, 0, 0, 0);
+        }
+        removed
+    }
+
+    /// Appends the kept nodes of the subtree below `node_index` in `old_nodes` for
+    /// [`BVH::prune`], skipping over inner nodes with a single kept child. Returns the index
+    /// and the joint [`AABB`] of the subtree's root node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::prune`]: struct.BVH.html#method.prune
+    ///
+    fn push_pruned_subtree<Shape: BHShape>(
+        &mut self,
+        old_nodes: &[BVHNode],
+        kept: &[bool],
+        shapes: &mut [Shape],
+        node_index: usize,
+        parent_index: usize,
+        depth: u32,
+    ) -> (usize, AABB) {
+        let new_index = self.nodes.len();
+        match old_nodes[node_index] {
+            BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } => match (kept[child_l_index], kept[child_r_index]) {
+                (true, true) => {
+                    self.nodes.push(old_nodes[node_index]);
+                    let (new_l_index, child_l_aabb) = self.push_pruned_subtree(
+                        old_nodes,
+                        kept,
+                        shapes,
+                        child_l_index,
+                        new_index,
+                        depth + 1,
+                    );
+                    let (new_r_index, child_r_aabb) = self.push_pruned_subtree(
+                        old_nodes,
+                        kept,
+                        shapes,
+                        child_r_index,
+                        new_index,
+                        depth + 1,
+                    );
+                    self.nodes[new_index] = BVHNode::Node {
+                        parent_index,
+                        depth,
+                        child_l_index: new_l_index,
+                        child_l_aabb,
+                        child_r_index: new_r_index,
+                        child_r_aabb,
+                    };
+                    (new_index, child_l_aabb.join(&child_r_aabb))
+                }
+                (true, false) => self.push_pruned_subtree(
+                    old_nodes,
+                    kept,
                     shapes,
-                );
+                    child_l_index,
+                    parent_index,
+                    depth,
+                ),
+                (false, _) => self.push_pruned_subtree(
+                    old_nodes,
+                    kept,
+                    shapes,
+                    child_r_index,
+                    parent_index,
+                    depth,
+                ),
+            },
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => {
+                self.nodes.push(BVHNode::Leaf {
+                    parent_index,
+                    depth,
+                    shape_index,
+                    shape_count,
+                });
+                let mut aabb = AABB::empty();
+                for shape in &mut shapes[shape_index..shape_index + shape_count] {
+                    shape.set_bh_node_index(new_index);
+                    aabb.join_mut(&shape.aabb());
+                }
+                (new_index, aabb)
             }
-            BVHNode::Leaf { shape_index, .. } => {
-                let shape_aabb = shapes[shape_index].aabb();
-                assert!(
-                    expected_outer_aabb.approx_contains_aabb_eps(&shape_aabb, EPSILON),
-                    "Shape's AABB lies outside the expected bounds.\n\tBounds: {}\n\tShape: {}",
-                    expected_outer_aabb,
-                    shape_aabb
-                );
+        }
+    }
+
+    /// Computes a value for every node of the [`BVH`] from the bottom up, e.g. to annotate
+    /// subtrees with the materials they contain. `leaf` computes the value of a leaf from the
+    /// range of its shapes, and `node` combines the values of the two children of an inner
+    /// node. Returns the values indexed by node id, i.e. the value of `self.nodes[i]` is at
+    /// index `i`.
+    ///
+    /// The values are not updated when the [`BVH`] changes. Compute them again after
+    /// rebuilding, optimizing or reordering it.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// let boxes = (0..8)
+    ///     .map(|i| {
+    ///         let x = i as f32;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, 0.0), Point3::new(x + 0.5, 1.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// // Boxes on the left use material 0, boxes on the right material 1.
+    /// let materials = [0, 0, 0, 0, 1, 1, 1, 1];
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // A bit mask of the materials in each subtree.
+    /// let masks = bvh.fold_nodes(
+    ///     |shape_range| shape_range.fold(0u32, |mask, i| mask | 1 << materials[i]),
+    ///     |mask_l, mask_r| mask_l | mask_r,
+    /// );
+    /// assert_eq!(masks.len(), bvh.nodes.len());
+    /// assert_eq!(masks[0], 0b11);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn fold_nodes<P, L, N>(&self, mut leaf: L, mut node: N) -> Vec<P>
+    where
+        L: FnMut(Range<usize>) -> P,
+        N: FnMut(&P, &P) -> P,
+    {
+        if self.nodes.is_empty() {
+            return Vec::new();
+        }
+        let mut values = self.nodes.iter().map(|_| None).collect::<Vec<_>>();
+
+        // The flag tells whether the children of the node were already visited.
+        let mut stack = vec![(0, false)];
+        while let Some((node_index, children_done)) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } => {
+                    if children_done {
+                        let value = match (&values[child_l_index], &values[child_r_index]) {
+                            (Some(value_l), Some(value_r)) => node(value_l, value_r),
+                            _ => unreachable!("Children are visited before their parent."),
+                        };
+                        values[node_index] = Some(value);
+                    } else {
+                        stack.push((node_index, true));
+                        stack.push((child_r_index, false));
+                        stack.push((child_l_index, false));
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => values[node_index] = Some(leaf(shape_index..shape_index + shape_count)),
+            }
+        }
+
+        values
+            .into_iter()
+            .map(|value| value.expect("Every node is reachable from the root."))
+            .collect()
+    }
+
+    /// Recomputes the bounds of every node from the `shapes` after they moved or deformed,
+    /// e.g. every frame of a cloth simulation, and returns the new bounds of the whole
+    /// [`BVH`], so that callers can tell whether the scene grew. The structure of the tree is
+    /// left untouched, so this only takes one pass over the nodes, but the tree gets worse
+    /// for queries the further the shapes move from where they were when it was built.
+    ///
+    /// `shapes` must be the slice the [`BVH`] was built from, mutated in place. The bounds are
+    /// tight joins of the shapes, like those of [`BVH::optimize`], so
+    /// [`BVHConfig::min_aabb_size`] is not applied again. An empty [`BVH`] returns an empty
+    /// [`AABB`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let mut boxes = (0..10)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let mut bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // Lift every box by 5 units.
+    /// for aabb in boxes.iter_mut() {
+    ///     *aabb = aabb.translate(&Vector3::new(0.0, 5.0, 0.0));
+    /// }
+    /// let bounds = bvh.refit(&boxes);
+    /// assert_eq!(bounds.min, Point3::new(0.0, 5.0, 0.0));
+    /// assert_eq!(bounds.max, Point3::new(19.0, 6.0, 1.0));
+    ///
+    /// let ray = Ray::new(Point3::new(4.5, 5.5, -10.0), Vector3::new(0.0, 0.0, 1.0));
+    /// assert_eq!(bvh.traverse_dyn(&ray, &boxes), vec![2]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    /// [`BVHConfig::min_aabb_size`]: struct.BVHConfig.html#structfield.min_aabb_size
+    ///
+    pub fn refit<Shape: Bounded>(&mut self, shapes: &[Shape]) -> AABB {
+        let bounds = self.fold_nodes(
+            |shape_range| AABB::from_bounded(&shapes[shape_range]),
+            |aabb_l, aabb_r| aabb_l.join(aabb_r),
+        );
+        for node in self.nodes.iter_mut() {
+            if let BVHNode::Node {
+                child_l_index,
+                ref mut child_l_aabb,
+                child_r_index,
+                ref mut child_r_aabb,
+                ..
+            } = *node
+            {
+                *child_l_aabb = bounds[child_l_index];
+                *child_r_aabb = bounds[child_r_index];
+            }
+        }
+        bounds.first().copied().unwrap_or_else(AABB::empty)
+    }
+
+    /// Traverses the [`BVH`].
+    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    /// The shapes are returned in depth-first order, left children first.
+    /// All shapes of a leaf are returned as soon as the joint [`AABB`] of the leaf is hit.
+    ///
+    /// The traversal is iterative, so arbitrarily deep trees cannot overflow the call stack.
+    /// It is shared with [`BVH::traverse_into`], which returns the indices of the same shapes
+    /// instead of references. `shapes` must be the slice the [`BVH`] was built from, which is
+    /// checked in debug builds.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    /// # struct Sphere { position: Point3, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32 * 3.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build(&mut spheres);
+    /// let ray = Ray::new(Point3::new(30.0, 0.0, -10.0), Vector3::new(0.0, 0.0, 1.0));
+    ///
+    /// // The shapes can be used directly...
+    /// for sphere in bvh.traverse(&ray, &spheres) {
+    ///     assert_eq!(sphere.position.x, 30.0);
+    /// }
+    ///
+    /// // ...while indices have to be looked up in the right slice first.
+    /// let mut indices = Vec::new();
+    /// bvh.traverse_into(&ray, &mut indices);
+    /// for index in indices {
+    ///     assert_eq!(spheres[index].position.x, 30.0);
+    /// }
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse_into`]: struct.BVH.html#method.traverse_into
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn traverse<'a, Shape: Bounded>(
+        &'a self,
+        ray: &Ray,
+        shapes: &'a [Shape],
+    ) -> Vec<&'a Shape> {
+        let mut hits = Vec::new();
+        self.traverse_leaves(ray, |shape_range| {
+            debug_assert!(
+                shape_range.end <= shapes.len(),
+                "The BVH was not built from this slice of shapes."
+            );
+            hits.extend(&shapes[shape_range]);
+        });
+        hits
+    }
+
+    /// Traverses the [`BVH`] like [`BVH::traverse`], but writes the indices of the candidate
+    /// shapes into `out` instead of returning them. `out` is cleared first.
+    ///
+    /// Neither `out` nor the traversal itself allocate once `out` is large enough, unless
+    /// the [`BVH`] is extremely deep. This makes it suited for hot loops reusing one buffer
+    /// per thread.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    ///
+    pub fn traverse_into(&self, ray: &Ray, out: &mut Vec<usize>) {
+        out.clear();
+        self.traverse_leaves(ray, |shape_range| out.extend(shape_range));
+    }
+
+    /// Traverses the [`BVH`] like [`BVH::traverse_into`], but writes the indices of the
+    /// candidate shapes into the fixed buffer `out`, e.g. a stack array or a slice of mapped
+    /// memory. Returns the number of candidates written to the front of `out`.
+    ///
+    /// If `out` is too small, the traversal still completes and returns `Err` with the total
+    /// number of candidates, so the caller can grow its buffer and try again. `out` then holds
+    /// the first `out.len()` of them, in the same order as [`BVH::traverse`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    /// let ray = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vector3::new(1.0, 0.0, 0.0));
+    ///
+    /// let mut out = [0; 16];
+    /// assert_eq!(bvh.traverse_into_slice(&ray, &mut out), Ok(10));
+    ///
+    /// let mut small = [0; 4];
+    /// assert_eq!(bvh.traverse_into_slice(&ray, &mut small), Err(10));
+    /// assert_eq!(small, out[..4]);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    /// [`BVH::traverse_into`]: struct.BVH.html#method.traverse_into
+    ///
+    pub fn traverse_into_slice(&self, ray: &Ray, out: &mut [usize]) -> Result<usize, usize> {
+        let mut count = 0;
+        self.traverse_leaves(ray, |shape_range| {
+            for shape_index in shape_range {
+                if let Some(slot) = out.get_mut(count) {
+                    *slot = shape_index;
+                }
+                count += 1;
             }
+        });
+
+        if count <= out.len() {
+            Ok(count)
+        } else {
+            Err(count)
+        }
+    }
+
+    /// Calls `visit` with the shape range of every leaf hit by `ray`, in depth-first order,
+    /// left children first. This drives the same traversal as [`BVH::traverse_iter`], and is
+    /// used by [`BVH::traverse`], [`BVH::traverse_into`] and [`BVH::traverse_into_slice`].
+    ///
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    /// [`BVH::traverse_into`]: struct.BVH.html#method.traverse_into
+    /// [`BVH::traverse_into_slice`]: struct.BVH.html#method.traverse_into_slice
+    /// [`BVH::traverse_iter`]: struct.BVH.html#method.traverse_iter
+    ///
+    fn traverse_leaves<F: FnMut(Range<usize>)>(&self, ray: &Ray, mut visit: F) {
+        let mut leaves = BVHTraverseIndexIterator::new(self, ray);
+        while let Some(shape_range) = leaves.next_leaf() {
+            visit(shape_range);
+        }
+    }
+
+    /// Traverses a [`BVH`] created by [`BVH::build_dyn`] and returns the indices of the
+    /// candidate shapes in `shapes`, which must be the slice the [`BVH`] was built from.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build_dyn`]: struct.BVH.html#method.build_dyn
+    ///
+    pub fn traverse_dyn<Shape: Bounded>(&self, ray: &Ray, shapes: &[Shape]) -> Vec<usize> {
+        let mut hits = Vec::new();
+        self.traverse_into(ray, &mut hits);
+        debug_assert!(
+            hits.iter().all(|&index| index < shapes.len()),
+            "The BVH was not built from this slice of shapes."
+        );
+        hits
+    }
+
+    /// Traverses the [`BVH`] like [`BVH::traverse`], but instead of the candidates
+    /// returns how many [`AABB`]s were tested, how many leaves were reached,
+    /// and how many candidates were found.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::{TraversalStats, BVH};
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// struct Sphere {
+    ///     position: Point3,
+    ///     node_index: usize,
+    /// }
+    ///
+    /// impl Bounded for Sphere {
+    ///     fn aabb(&self) -> AABB {
+    ///         let half_size = Vector3::new(1.0, 1.0, 1.0);
+    ///         AABB::with_bounds(self.position - half_size, self.position + half_size)
+    ///     }
+    /// }
+    ///
+    /// impl BHShape for Sphere {
+    ///     fn set_bh_node_index(&mut self, index: usize) {
+    ///         self.node_index = index;
+    ///     }
+    ///
+    ///     fn bh_node_index(&self) -> usize {
+    ///         self.node_index
+    ///     }
+    /// }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32 * 3.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build(&mut spheres);
+    ///
+    /// // Sum up the stats of a few rays.
+    /// let mut stats = TraversalStats::default();
+    /// for i in 0..10 {
+    ///     let ray = Ray::new(Point3::new(i as f32 * 30.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    ///     stats += bvh.traversal_stats(&ray, &spheres);
+    /// }
+    /// assert_eq!(stats.candidates, 10);
+    /// assert!(stats.aabb_tests > stats.leaf_tests);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    ///
+    pub fn traversal_stats<Shape: Bounded>(&self, ray: &Ray, shapes: &[Shape]) -> TraversalStats {
+        self.traverse_counted(ray, |shape_range| {
+            debug_assert!(
+                shape_range.end <= shapes.len(),
+                "The BVH was not built from this slice of shapes."
+            );
+        })
+    }
+
+    /// Traverses the [`BVH`] like [`BVH::traverse`], and returns the candidates together with
+    /// the [`TraversalStats`] of the traversal. This shows how well the [`BVH`] fits the rays
+    /// without an external profiler. The counting happens in a separate copy of the
+    /// traversal, so [`BVH::traverse`] and the other queries don't pay for it.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..16)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// let ray = Ray::new(Point3::new(4.5, -10.0, 0.5), Vector3::new(0.0, 1.0, 0.0));
+    /// let (hits, stats) = bvh.traverse_instrumented(&ray, &boxes);
+    /// assert_eq!(hits.len(), 1);
+    /// assert_eq!(stats.candidates, 1);
+    /// assert_eq!(stats.aabb_tests, 2 * stats.nodes_visited);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    /// [`TraversalStats`]: struct.TraversalStats.html
+    ///
+    pub fn traverse_instrumented<'a, Shape: Bounded>(
+        &'a self,
+        ray: &Ray,
+        shapes: &'a [Shape],
+    ) -> (Vec<&'a Shape>, TraversalStats) {
+        let mut hits = Vec::new();
+        let stats = self.traverse_counted(ray, |shape_range| hits.extend(&shapes[shape_range]));
+        (hits, stats)
+    }
+
+    /// Calls `visit` with the shape range of every leaf hit by `ray`, in the same order as
+    /// [`BVH::traverse_leaves`], and counts the work done on the way.
+    ///
+    /// [`BVH::traverse_leaves`]: struct.BVH.html#method.traverse_leaves
+    ///
+    fn traverse_counted<F: FnMut(Range<usize>)>(&self, ray: &Ray, mut visit: F) -> TraversalStats {
+        let mut stats = TraversalStats::default();
+        if self.nodes.is_empty() {
+            return stats;
+        }
+
+        let mut stack = Vec::with_capacity(32);
+        stack.push(0);
+        while let Some(node_index) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    stats.nodes_visited += 1;
+                    stats.aabb_tests += 2;
+                    // Push the right child first, so that the left subtree is visited first.
+                    if ray.intersects_aabb(child_r_aabb) {
+                        stack.push(child_r_index);
+                    }
+                    if ray.intersects_aabb(child_l_aabb) {
+                        stack.push(child_l_index);
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    stats.leaf_tests += 1;
+                    stats.candidates += shape_count;
+                    visit(shape_index..shape_index + shape_count);
+                }
+            }
+        }
+        stats
+    }
+
+    /// Traverses the [`BVH`] like [`BVH::traverse_into`], but visits the children of every
+    /// node in `order`. With [`TraversalOrder::NearFirst`] the leaves come roughly from front
+    /// to back, so a caller looking for a near hit among the candidates can often stop early.
+    /// `out` is cleared first.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::{TraversalOrder, BVH};
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // Looking down the row of boxes from its far end.
+    /// let ray = Ray::new(Point3::new(30.0, 0.5, 0.5), Vector3::new(-1.0, 0.0, 0.0));
+    /// let mut out = Vec::new();
+    /// bvh.traverse_ordered_into(&ray, TraversalOrder::NearFirst, &mut out);
+    /// assert_eq!(out, (0..10).rev().collect::<Vec<_>>());
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse_into`]: struct.BVH.html#method.traverse_into
+    /// [`TraversalOrder::NearFirst`]: enum.TraversalOrder.html#variant.NearFirst
+    ///
+    pub fn traverse_ordered_into(&self, ray: &Ray, order: TraversalOrder, out: &mut Vec<usize>) {
+        out.clear();
+        self.traverse_ordered_counted(ray, order, |shape_range| {
+            out.extend(shape_range);
+            f32::INFINITY
+        });
+    }
+
+    /// Calls `visit` with the shape range of every leaf hit by `ray`, visiting the children of
+    /// every node in `order`, and counts the work done on the way. `visit` returns the
+    /// distance beyond which no more leaves are needed, e.g. the nearest hit so far, and
+    /// subtrees entered beyond it are skipped.
+    ///
+    fn traverse_ordered_counted<F>(
+        &self,
+        ray: &Ray,
+        order: TraversalOrder,
+        mut visit: F,
+    ) -> TraversalStats
+    where
+        F: FnMut(Range<usize>) -> f32,
+    {
+        let mut stats = TraversalStats::default();
+        if self.nodes.is_empty() {
+            return stats;
+        }
+
+        let mut t_max = f32::INFINITY;
+        let mut stack = Vec::with_capacity(32);
+        stack.push((0, 0.0));
+        while let Some((node_index, entry)) = stack.pop() {
+            // `t_max` may have shrunk since this node was pushed.
+            if entry > t_max {
+                continue;
+            }
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    stats.nodes_visited += 1;
+                    stats.aabb_tests += 2;
+                    let entry = |aabb| {
+                        ray.intersection_interval(aabb)
+                            .map(|(entry, _)| entry.max(0.0))
+                    };
+                    let entry_l = entry(child_l_aabb);
+                    let entry_r = entry(child_r_aabb);
+                    let mut children = [(child_r_index, entry_r), (child_l_index, entry_l)];
+
+                    // The child pushed last is visited first.
+                    if order == TraversalOrder::NearFirst {
+                        if let (Some(entry_l), Some(entry_r)) = (entry_l, entry_r) {
+                            if entry_r < entry_l {
+                                children.swap(0, 1);
+                            }
+                        }
+                    }
+                    for &(child_index, child_entry) in children.iter() {
+                        if let Some(child_entry) = child_entry {
+                            if child_entry <= t_max {
+                                stack.push((child_index, child_entry));
+                            }
+                        }
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    stats.leaf_tests += 1;
+                    stats.candidates += shape_count;
+                    t_max = t_max.min(visit(shape_index..shape_index + shape_count));
+                }
+            }
+        }
+        stats
+    }
+
+    /// Traverses the [`BVH`] once for every ray in `rays`, distributing the rays over the
+    /// rayon thread pool.
+    /// Returns one subset of `shapes` per ray, in the order of `rays`, each being the same
+    /// as what [`BVH::traverse`] would return for that ray.
+    ///
+    /// Only available with the `rayon` feature.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    ///
+    #[cfg(feature = "rayon")]
+    pub fn par_traverse<'a, Shape: Bounded + Sync>(
+        &'a self,
+        rays: &[Ray],
+        shapes: &'a [Shape],
+    ) -> Vec<Vec<&'a Shape>> {
+        use rayon::prelude::*;
+
+        rays.par_iter()
+            .map(|ray| self.traverse(ray, shapes))
+            .collect()
+    }
+
+    /// Traverses the [`BVH`] once for every ray in `rays`, distributing the rays over the
+    /// rayon thread pool.
+    /// Returns the indices of the candidate shapes per ray, in the order of `rays`, each being
+    /// the same as what [`BVH::traverse_into`] would write for that ray.
+    ///
+    /// Each rayon job reuses one [`TraversalState`] for all of its rays, so that only the
+    /// returned `Vec`s are allocated per ray.
+    ///
+    /// Only available with the `rayon` feature.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..100)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// let rays = (0..100)
+    ///     .map(|i| {
+    ///         let origin = Point3::new(i as f32 * 2.0 + 0.5, -10.0, 0.5);
+    ///         Ray::new(origin, Vector3::new(0.0, 1.0, 0.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let hits = bvh.traverse_batch(&rays);
+    /// assert!(hits.iter().enumerate().all(|(i, hit)| *hit == vec![i]));
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse_into`]: struct.BVH.html#method.traverse_into
+    /// [`TraversalState`]: struct.TraversalState.html
+    ///
+    #[cfg(feature = "rayon")]
+    pub fn traverse_batch(&self, rays: &[Ray]) -> Vec<Vec<usize>> {
+        use rayon::prelude::*;
+
+        rays.par_iter()
+            .map_init(TraversalState::new, |state, ray| {
+                let mut out = Vec::new();
+                state.traverse(self, ray, &mut out);
+                out
+            })
+            .collect()
+    }
+
+    /// Finds the nearest of the `shapes` hit by each ray in `rays`, distributing the rays
+    /// over the rayon thread pool.
+    /// Returns the index of the shape and the distance of the hit per ray, in the order of
+    /// `rays`, each being the same as what [`BVH::traverse_nearest`] would return for that ray.
+    ///
+    /// Each rayon job reuses one [`TraversalState`] for all of its rays, so that no ray
+    /// allocates.
+    ///
+    /// Only available with the `rayon` feature.
+    ///
+    /// [`BVH::traverse_nearest`]: struct.BVH.html#method.traverse_nearest
+    /// [`TraversalState`]: struct.TraversalState.html
+    ///
+    #[cfg(feature = "rayon")]
+    pub fn nearest_batch<Shape, F>(
+        &self,
+        rays: &[Ray],
+        shapes: &[Shape],
+        intersect: F,
+    ) -> Vec<Option<(usize, f32)>>
+    where
+        Shape: Sync,
+        F: Fn(&Shape, &Ray) -> Option<f32> + Sync,
+    {
+        use rayon::prelude::*;
+
+        rays.par_iter()
+            .map_init(TraversalState::new, |state, ray| {
+                state.traverse_nearest(self, ray, shapes, &intersect)
+            })
+            .collect()
+    }
+
+    /// Creates a [`BVHTraverseIterator`] to traverse the [`BVH`].
+    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn traverse_iterator<'a, Shape: Bounded>(
+        &'a self,
+        ray: &'a Ray,
+        shapes: &'a [Shape],
+    ) -> BVHTraverseIterator<'a, Shape> {
+        BVHTraverseIterator::new(self, ray, shapes)
+    }
+
+    /// Creates a [`BVHTraverseIndexIterator`], which lazily returns the indices of the shapes
+    /// [`BVH::traverse`] would return, in the same order.
+    ///
+    /// Since the [`BVH`] is only traversed as far as the iterator is advanced, this is
+    /// cheaper than [`BVH::traverse`] when only the first few candidates are needed.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    /// # struct Sphere { position: Point3, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build(&mut spheres);
+    /// let ray = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    ///
+    /// // Stop at the first candidate which passes some more expensive test.
+    /// let first = bvh.traverse_iter(&ray).find(|&index| spheres[index].position.x > 50.0);
+    /// assert!(first.is_some());
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    /// [`BVHTraverseIndexIterator`]: struct.BVHTraverseIndexIterator.html
+    ///
+    pub fn traverse_iter<'a>(&'a self, ray: &'a Ray) -> BVHTraverseIndexIterator<'a> {
+        BVHTraverseIndexIterator::new(self, ray)
+    }
+
+    /// Creates a [`BVHDepthFirstIterator`], which lazily returns a [`BVHNodeView`] of every
+    /// node in depth-first pre-order, left children first.
+    ///
+    /// [`BVH::build`] and [`BVH::reorder_dfs`] lay the nodes out in exactly this order, so the
+    /// returned indices are then `0..nodes.len()` and the views can be written to a flat buffer
+    /// as they come. [`BVH::optimize`] moves nodes around without renumbering them.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    /// # struct Sphere { position: Point3, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut spheres = (0..10)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build(&mut spheres);
+    ///
+    /// let mut views = bvh.depth_first_iter();
+    /// let root = views.next().unwrap();
+    /// assert_eq!(root.parent_index, None);
+    /// assert_eq!(root.aabb.min, Point3::new(-1.0, -1.0, -1.0));
+    /// assert!(views.all(|view| view.depth > 0));
+    /// assert!(bvh.depth_first_iter().map(|view| view.index).eq(0..bvh.nodes.len()));
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::build`]: struct.BVH.html#method.build
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    /// [`BVH::reorder_dfs`]: struct.BVH.html#method.reorder_dfs
+    /// [`BVHDepthFirstIterator`]: struct.BVHDepthFirstIterator.html
+    /// [`BVHNodeView`]: struct.BVHNodeView.html
+    ///
+    pub fn depth_first_iter(&self) -> BVHDepthFirstIterator<'_> {
+        BVHDepthFirstIterator::new(self)
+    }
+
+    /// Tests whether any of the `shapes` is hit by `ray` at a distance of at most `t_max`.
+    /// Unlike [`BVH::traverse`] this does not collect all candidates, but returns `true`
+    /// as soon as the first intersection is confirmed, which makes it well suited for
+    /// shadow rays.
+    ///
+    /// Hits at a distance of `0.0` occlude, e.g. a shape whose [`AABB`] contains the origin of
+    /// `ray`. [`BVH::is_occluded_by`] ignores those, so that a shadow ray starting on a surface
+    /// is not blocked by it.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    /// [`BVH::is_occluded_by`]: struct.BVH.html#method.is_occluded_by
+    ///
+    pub fn is_occluded<Shape: Bounded + Intersectable>(
+        &self,
+        ray: &Ray,
+        t_max: f32,
+        shapes: &[Shape],
+    ) -> bool {
+        self.any_occluder(ray, t_max, shapes, |shape, ray| {
+            shape
+                .intersect(ray)
+                .is_some_and(|distance| distance <= t_max)
+        })
+    }
+
+    /// Tests whether any of the `shapes` is hit by `ray` at a distance in `(0, t_max]`,
+    /// as reported by `intersect`.
+    ///
+    /// A hit at exactly `t_max` occludes, so a shadow ray towards a light can use the distance
+    /// to the light as `t_max` if the light itself is not one of the `shapes`. Hits at a
+    /// distance of `0.0` don't occlude, so a shadow ray starting on a surface is not blocked by
+    /// that surface.
+    ///
+    /// The nodes are visited in no particular order, and subtrees which `ray` enters beyond
+    /// `t_max` are skipped. The traversal returns as soon as the first occluding hit is found.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::{Intersectable, Ray};
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let walls = vec![
+    ///     AABB::with_bounds(Point3::new(5.0, -2.0, -2.0), Point3::new(5.5, 2.0, 2.0)),
+    ///     AABB::with_bounds(Point3::new(5.0, 3.0, -2.0), Point3::new(5.5, 5.0, 2.0)),
+    /// ];
+    /// let bvh = BVH::build_dyn(&walls);
+    ///
+    /// let point = Point3::new(0.0, 0.0, 0.0);
+    /// let light = Point3::new(10.0, 0.0, 0.0);
+    /// let ray = Ray::new(point, light - point);
+    /// let t_max = (light - point).length();
+    /// assert!(bvh.is_occluded_by(&ray, t_max, &walls, |wall, ray| wall.intersect(ray)));
+    ///
+    /// // Through the gap between the walls.
+    /// let light = Point3::new(10.0, 5.0, 0.0);
+    /// let ray = Ray::new(point, light - point);
+    /// let t_max = (light - point).length();
+    /// assert!(!bvh.is_occluded_by(&ray, t_max, &walls, |wall, ray| wall.intersect(ray)));
+    /// ```
+    ///
+    pub fn is_occluded_by<Shape, F>(
+        &self,
+        ray: &Ray,
+        t_max: f32,
+        shapes: &[Shape],
+        mut intersect: F,
+    ) -> bool
+    where
+        F: FnMut(&Shape, &Ray) -> Option<f32>,
+    {
+        self.any_occluder(ray, t_max, shapes, |shape, ray| {
+            intersect(shape, ray).is_some_and(|distance| distance > 0.0 && distance <= t_max)
+        })
+    }
+
+    /// Returns whether `occludes` holds for any of the `shapes` in a leaf which `ray` enters
+    /// before `t_max`. Shared by [`BVH::is_occluded`] and [`BVH::is_occluded_by`], which only
+    /// differ in which hits occlude.
+    ///
+    /// [`BVH::is_occluded`]: struct.BVH.html#method.is_occluded
+    /// [`BVH::is_occluded_by`]: struct.BVH.html#method.is_occluded_by
+    ///
+    fn any_occluder<Shape, F>(
+        &self,
+        ray: &Ray,
+        t_max: f32,
+        shapes: &[Shape],
+        mut occludes: F,
+    ) -> bool
+    where
+        F: FnMut(&Shape, &Ray) -> bool,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    for &(child_aabb, child_index) in
+                        &[(child_l_aabb, child_l_index), (child_r_aabb, child_r_index)]
+                    {
+                        if let Some((entry, _)) = ray.intersection_interval(child_aabb) {
+                            if entry <= t_max {
+                                stack.push(child_index);
+                            }
+                        }
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    if shapes[shape_index..shape_index + shape_count]
+                        .iter()
+                        .any(|shape| occludes(shape, ray))
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Tests whether the [`AABB`] of any of the `shapes` is hit by `ray` before `t_max`, i.e.
+    /// whether the [`AABB`] overlaps the part of `ray` from its origin to the distance `t_max`.
+    /// An [`AABB`] which contains the origin counts as hit.
+    ///
+    /// This is a conservative shadow test for shapes which only implement [`Bounded`]: a
+    /// `false` result proves that nothing lies between the origin and `t_max`, e.g. to skip the
+    /// exact test of [`BVH::is_occluded`]. Like it, this does not collect any candidates,
+    /// skips subtrees which `ray` enters beyond `t_max` and returns on the first hit.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let walls = vec![
+    ///     AABB::with_bounds(Point3::new(5.0, -2.0, -2.0), Point3::new(5.5, 2.0, 2.0)),
+    ///     AABB::with_bounds(Point3::new(5.0, 3.0, -2.0), Point3::new(5.5, 5.0, 2.0)),
+    /// ];
+    /// let bvh = BVH::build_dyn(&walls);
+    ///
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// assert!(bvh.traverse_shadow(&ray, 10.0, &walls));
+    /// // The light is in front of the walls.
+    /// assert!(!bvh.traverse_shadow(&ray, 4.0, &walls));
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::is_occluded`]: struct.BVH.html#method.is_occluded
+    /// [`Bounded`]: ../aabb/trait.Bounded.html
+    ///
+    pub fn traverse_shadow<Shape: Bounded>(&self, ray: &Ray, t_max: f32, shapes: &[Shape]) -> bool {
+        if self.nodes.is_empty() {
+            return false;
+        }
+
+        // `intersection_interval` only reports boxes whose exit lies in front of the origin.
+        let hit_before_t_max = |aabb: &AABB| match ray.intersection_interval(aabb) {
+            Some((entry, _)) => entry <= t_max,
+            None => false,
+        };
+
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    if hit_before_t_max(child_r_aabb) {
+                        stack.push(child_r_index);
+                    }
+                    if hit_before_t_max(child_l_aabb) {
+                        stack.push(child_l_index);
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    let leaf_shapes = &shapes[shape_index..shape_index + shape_count];
+                    if leaf_shapes
+                        .iter()
+                        .any(|shape| hit_before_t_max(&shape.aabb()))
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    /// Tests whether any of the `shapes` is hit by the line segment from `a` to `b`, as
+    /// reported by `intersect` for a [`Ray`] from `a` towards `b`. This answers visibility
+    /// queries like "can `a` see `b`" without setting up the [`Ray`] and its length by hand.
+    ///
+    /// Only hits strictly between the endpoints occlude. Hits within a relative tolerance of
+    /// [`EPSILON`] of either endpoint are ignored, so the surfaces `a` and `b` lie on don't
+    /// occlude the segment, even if rounding puts their hits slightly inside of it. Subtrees
+    /// which are entered beyond `b` are skipped, and the traversal stops at the first occluding
+    /// hit, just like [`BVH::is_occluded_by`]. A segment of length zero is never occluded.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Intersectable;
+    /// use bvh::Point3;
+    ///
+    /// let boxes = vec![
+    ///     AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0)),
+    ///     AABB::with_bounds(Point3::new(9.0, 0.0, 0.0), Point3::new(10.0, 1.0, 1.0)),
+    /// ];
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // From the face of the first box to the face of the second one.
+    /// let a = Point3::new(1.0, 0.5, 0.5);
+    /// let b = Point3::new(9.0, 0.5, 0.5);
+    /// assert!(!bvh.segment_occluded(&a, &b, &boxes, |aabb, ray| aabb.intersect(ray)));
+    ///
+    /// // Through the second box.
+    /// let b = Point3::new(11.0, 0.5, 0.5);
+    /// assert!(bvh.segment_occluded(&a, &b, &boxes, |aabb, ray| aabb.intersect(ray)));
+    /// ```
+    ///
+    /// [`BVH::is_occluded_by`]: struct.BVH.html#method.is_occluded_by
+    /// [`EPSILON`]: ../constant.EPSILON.html
+    /// [`Ray`]: ../ray/struct.Ray.html
+    ///
+    pub fn segment_occluded<T, F>(
+        &self,
+        a: &Point3,
+        b: &Point3,
+        shapes: &[T],
+        mut intersect: F,
+    ) -> bool
+    where
+        F: FnMut(&T, &Ray) -> Option<f32>,
+    {
+        let length = (*b - *a).length();
+        if length <= 0.0 {
+            return false;
+        }
+
+        let tolerance = EPSILON * length;
+        let ray = Ray::new(*a, *b - *a);
+        self.is_occluded_by(&ray, length, shapes, |shape, ray| {
+            intersect(shape, ray)
+                .filter(|&distance| distance > tolerance && distance < length - tolerance)
+        })
+    }
+
+    /// Finds the nearest of the `shapes` which is hit by `ray`.
+    /// Returns the index of the shape and the distance of the hit, or `None` if `ray`
+    /// misses all of them. Subtrees which `ray` enters behind the nearest hit found so far
+    /// are skipped.
+    ///
+    pub fn nearest_hit<Shape: Bounded + Intersectable>(
+        &self,
+        ray: &Ray,
+        shapes: &[Shape],
+    ) -> Option<(usize, f32)> {
+        self.nearest_hit_counted(ray, shapes, TraversalOrder::NearFirst)
+            .0
+    }
+
+    /// Finds the nearest hit like [`BVH::nearest_hit`], visiting the children of every node
+    /// in `order`, and counts the work done on the way.
+    ///
+    /// [`BVH::nearest_hit`]: struct.BVH.html#method.nearest_hit
+    ///
+    fn nearest_hit_counted<Shape: Bounded + Intersectable>(
+        &self,
+        ray: &Ray,
+        shapes: &[Shape],
+        order: TraversalOrder,
+    ) -> (Option<(usize, f32)>, TraversalStats) {
+        let mut best = None;
+        let mut best_distance = f32::INFINITY;
+        let stats = self.traverse_ordered_counted(ray, order, |shape_range| {
+            for shape_index in shape_range {
+                if let Some(distance) = shapes[shape_index].intersect(ray) {
+                    if distance < best_distance {
+                        best_distance = distance;
+                        best = Some(shape_index);
+                    }
+                }
+            }
+            best_distance
+        });
+        (best.map(|shape_index| (shape_index, best_distance)), stats)
+    }
+
+    /// Finds all `shapes` which are hit by `ray`, e.g. to composite transparent surfaces or
+    /// to evaluate CSG along the ray. Returns the index of every hit shape with its
+    /// [`RaycastResult`], sorted from the nearest to the farthest hit, with ties broken by
+    /// the shape index.
+    ///
+    /// Unlike [`BVH::nearest_hit`], no subtree can be skipped for lying behind a hit, but
+    /// subtrees which `ray` misses are still skipped. Every shape is reported at most once:
+    /// should a shape be referenced by several leaves, its duplicate hits are removed by index.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let panes = (0..5)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, -1.0, -1.0);
+    ///         AABB::with_bounds(min, min + Point3::new(0.1, 2.0, 2.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&panes);
+    ///
+    /// // Looking down the row of panes from its far end.
+    /// let ray = Ray::new(Point3::new(20.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0));
+    /// let hits = bvh.traverse_all_sorted(&ray, &panes);
+    /// let order = hits.iter().map(|&(index, _)| index).collect::<Vec<_>>();
+    /// assert_eq!(order, vec![4, 3, 2, 1, 0]);
+    /// assert!((hits[0].1.t().unwrap() - 11.9).abs() < 1e-5);
+    /// assert!(hits.iter().all(|&(_, result)| result.is_hit()));
+    /// ```
+    ///
+    /// [`BVH::nearest_hit`]: struct.BVH.html#method.nearest_hit
+    /// [`RaycastResult`]: ../ray/enum.RaycastResult.html
+    ///
+    pub fn traverse_all_sorted<T: Bounded + Intersectable>(
+        &self,
+        ray: &Ray,
+        shapes: &[T],
+    ) -> Vec<(usize, RaycastResult)> {
+        let mut hits = Vec::new();
+        self.traverse_leaves(ray, |shape_range| {
+            for shape_index in shape_range {
+                if let Some(t) = shapes[shape_index].intersect(ray) {
+                    hits.push((shape_index, RaycastResult::Hit(t)));
+                }
+            }
+        });
+
+        hits.sort_unstable_by_key(|&(shape_index, _)| shape_index);
+        hits.dedup_by_key(|&mut (shape_index, _)| shape_index);
+        hits.sort_by_key(|&(shape_index, result)| (result, shape_index));
+        hits
+    }
+
+    /// Finds all `shapes` which are hit by `ray` at a distance in `[t_min, t_max]`, e.g. from a
+    /// small `t_min` to avoid self-intersections of secondary rays up to the distance of a
+    /// light. Returns the index and the distance of every hit, in the order in which
+    /// [`BVH::traverse`] returns the candidates.
+    ///
+    /// Subtrees whose [`AABB`] `ray` only overlaps before `t_min` or after `t_max` are
+    /// skipped, and the hits reported by [`Intersectable`] outside of the interval are
+    /// rejected. With `t_min = 0.0` and `t_max = f32::INFINITY` this finds every hit of the
+    /// candidates of [`BVH::traverse`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let panes = (0..5)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, -1.0, -1.0);
+    ///         AABB::with_bounds(min, min + Point3::new(0.1, 2.0, 2.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&panes);
+    ///
+    /// // Starting on the first pane, which must not hit itself.
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// let mut hits = bvh.traverse_clamped(&ray, 1e-3, 6.0, &panes);
+    /// hits.sort_by_key(|&(index, _)| index);
+    /// assert_eq!(hits, vec![(1, 2.0), (2, 4.0), (3, 6.0)]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::traverse`]: struct.BVH.html#method.traverse
+    /// [`Intersectable`]: ../ray/trait.Intersectable.html
+    ///
+    pub fn traverse_clamped<Shape: Bounded + Intersectable>(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        shapes: &[Shape],
+    ) -> Vec<(usize, f32)> {
+        let mut hits = Vec::new();
+        self.traverse_predicate_with(
+            |aabb| match ray.intersection_interval(aabb) {
+                Some((entry, exit)) => entry <= t_max && exit >= t_min,
+                None => false,
+            },
+            |shape_index| {
+                if let Some(distance) = shapes[shape_index].intersect(ray) {
+                    if distance >= t_min && distance <= t_max {
+                        hits.push((shape_index, distance));
+                    }
+                }
+            },
+        );
+        hits
+    }
+
+    /// Finds the nearest of the `shapes` which is hit by the axis of `cone`, see
+    /// [`BVH::nearest_hit`]. Additionally returns the radius of the footprint of `cone`
+    /// at the hit distance, which can be used for texture filtering or level of detail.
+    ///
+    /// [`BVH::nearest_hit`]: struct.BVH.html#method.nearest_hit
+    ///
+    pub fn nearest_hit_cone<Shape: Bounded + Intersectable>(
+        &self,
+        cone: &RayCone,
+        shapes: &[Shape],
+    ) -> Option<(usize, f32, f32)> {
+        self.nearest_hit(&cone.ray, shapes)
+            .map(|(shape_index, distance)| (shape_index, distance, cone.radius_at(distance)))
+    }
+
+    /// Finds the nearest of the `shapes` hit by `ray`, as reported by `intersect`.
+    /// Returns the index of the shape and the distance of the hit, or `None` if `intersect`
+    /// returns `None` for all candidate shapes.
+    ///
+    /// Unlike [`BVH::nearest_hit`], the shapes don't need to implement [`Intersectable`],
+    /// and the nodes are visited best-first: a priority queue always continues with the node
+    /// whose [`AABB`] the ray enters first. The traversal ends as soon as that entry is farther
+    /// away than the best hit, so on dense scenes `intersect` is called for few shapes beyond
+    /// the nearest ones.
+    ///
+    /// `intersect` must return non-negative distances along `ray`, which lie inside the
+    /// [`AABB`] of the shape. If several shapes are hit at exactly the nearest distance,
+    /// the one with the smallest index is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    /// # struct Sphere { position: Point3, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32 * 3.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build(&mut spheres);
+    ///
+    /// // Intersect the unit spheres analytically.
+    /// let intersect = |sphere: &Sphere, ray: &Ray| {
+    ///     let offset = ray.origin - sphere.position;
+    ///     let b = offset.dot(ray.direction);
+    ///     let discriminant = b * b - offset.length_squared() + 1.0;
+    ///     (discriminant >= 0.0).then(|| -b - discriminant.sqrt()).filter(|&t| t >= 0.0)
+    /// };
+    ///
+    /// let ray = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+    /// assert_eq!(bvh.traverse_nearest(&ray, &spheres, intersect), Some((0, 9.0)));
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::nearest_hit`]: struct.BVH.html#method.nearest_hit
+    /// [`Intersectable`]: ../ray/trait.Intersectable.html
+    ///
+    pub fn traverse_nearest<Shape, F>(
+        &self,
+        ray: &Ray,
+        shapes: &[Shape],
+        intersect: F,
+    ) -> Option<(usize, f32)>
+    where
+        F: FnMut(&Shape, &Ray) -> Option<f32>,
+    {
+        TraversalState::new().traverse_nearest(self, ray, shapes, intersect)
+    }
+
+    /// Finds the point on any of the `shapes` which is closest to `p`.
+    /// Returns the index of the closest shape, the closest point on it and its distance to `p`,
+    /// or `None` if the [`BVH`] is empty.
+    /// Subtrees whose [`AABB`] is farther away than the best point found so far are skipped.
+    ///
+    /// This is [`BVH::closest`] for shapes which implement [`SurfacePoint`], such as [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::closest`]: struct.BVH.html#method.closest
+    /// [`SurfacePoint`]: ../aabb/trait.SurfacePoint.html
+    ///
+    pub fn nearest_point<Shape: Bounded + SurfacePoint>(
+        &self,
+        p: &Point3,
+        shapes: &[Shape],
+    ) -> Option<(usize, Point3, f32)> {
+        self.closest(p, shapes, |shape, p| {
+            let closest = shape.closest_point_on(p);
+            (closest, (closest - *p).length())
+        })
+    }
+
+    /// Finds the point on any of the `shapes` which is closest to `p`, like
+    /// [`BVH::nearest_point`], but computes the closest point on a shape and its distance to
+    /// `p` with `closest_point`. This allows exact distances to shapes which don't implement
+    /// [`SurfacePoint`]. The distance must never be smaller than the distance of the shape's
+    /// [`AABB`], or closer shapes in skipped subtrees may be missed.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// struct Sphere {
+    ///     center: Point3,
+    ///     radius: f32,
+    /// }
+    ///
+    /// impl Bounded for Sphere {
+    ///     fn aabb(&self) -> AABB {
+    ///         let half_size = Vector3::splat(self.radius);
+    ///         AABB::with_bounds(self.center - half_size, self.center + half_size)
+    ///     }
+    /// }
+    ///
+    /// let spheres = vec![
+    ///     Sphere { center: Point3::new(0.0, 0.0, 0.0), radius: 10.0 },
+    ///     Sphere { center: Point3::new(12.0, 12.0, 0.0), radius: 1.0 },
+    /// ];
+    /// let bvh = BVH::build_dyn(&spheres);
+    ///
+    /// // The point lies inside the `AABB` of the large sphere, but closer to the small one.
+    /// let p = Point3::new(9.5, 9.5, 0.0);
+    /// let (index, _, distance) = bvh
+    ///     .closest(&p, &spheres, |sphere, p| {
+    ///         let offset = *p - sphere.center;
+    ///         let distance = (offset.length() - sphere.radius).max(0.0);
+    ///         (*p - offset.normalize() * distance, distance)
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(index, 1);
+    /// assert!((distance - (12.5f32.sqrt() - 1.0)).abs() < 1e-5);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::nearest_point`]: struct.BVH.html#method.nearest_point
+    /// [`SurfacePoint`]: ../aabb/trait.SurfacePoint.html
+    ///
+    pub fn closest<Shape, F>(
+        &self,
+        p: &Point3,
+        shapes: &[Shape],
+        mut closest_point: F,
+    ) -> Option<(usize, Point3, f32)>
+    where
+        Shape: Bounded,
+        F: FnMut(&Shape, &Point3) -> (Point3, f32),
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<(usize, Point3)> = None;
+        let mut best_distance = f32::INFINITY;
+        let mut stack = vec![(0, 0.0)];
+        while let Some((node_index, distance_squared)) = stack.pop() {
+            // The best distance may have shrunk since this node was pushed.
+            if distance_squared >= best_distance * best_distance {
+                continue;
+            }
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    let distance_l = child_l_aabb.distance_squared_to_point(p);
+                    let distance_r = child_r_aabb.distance_squared_to_point(p);
+                    let mut children = [(child_l_index, distance_l), (child_r_index, distance_r)];
+
+                    // Push the farther child first, so that the nearer one is visited first.
+                    if distance_l < distance_r {
+                        children.swap(0, 1);
+                    }
+                    for &(child_index, child_distance) in children.iter() {
+                        if child_distance < best_distance * best_distance {
+                            stack.push((child_index, child_distance));
+                        }
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    let leaf_shapes = shapes[shape_index..shape_index + shape_count].iter();
+                    for (index, shape) in (shape_index..).zip(leaf_shapes) {
+                        let (closest, distance) = closest_point(shape, p);
+                        if distance < best_distance {
+                            best_distance = distance;
+                            best = Some((index, closest));
+                        }
+                    }
+                }
+            }
+        }
+
+        best.map(|(shape_index, closest)| (shape_index, closest, best_distance))
+    }
+
+    /// Finds the `k` shapes whose [`AABB`]s are closest to `p`, and no farther away than
+    /// `max_distance`. Returns their indices and distances, sorted by ascending distance.
+    /// Shapes at the same distance are sorted by index. Fewer than `k` shapes are returned if
+    /// the [`BVH`] holds fewer shapes within `max_distance`.
+    ///
+    /// The subtrees are visited nearest first, and a subtree is skipped once it is farther
+    /// away than the `k`-th best shape found so far. Use [`BVH::nearest_k_by`] to rank the
+    /// shapes by their exact distance instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let x = i as f32 * 2.0;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, 0.0), Point3::new(x + 1.0, 1.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// let nearest = bvh.nearest_k(&Point3::new(4.5, 0.5, 0.5), &boxes, 3, f32::INFINITY);
+    /// assert_eq!(nearest, vec![(2, 0.0), (1, 1.5), (3, 1.5)]);
+    ///
+    /// let within_one = bvh.nearest_k(&Point3::new(4.5, 0.5, 0.5), &boxes, 3, 1.0);
+    /// assert_eq!(within_one, vec![(2, 0.0)]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::nearest_k_by`]: struct.BVH.html#method.nearest_k_by
+    ///
+    pub fn nearest_k<Shape: Bounded>(
+        &self,
+        p: &Point3,
+        shapes: &[Shape],
+        k: usize,
+        max_distance: f32,
+    ) -> Vec<(usize, f32)> {
+        self.nearest_k_by(p, shapes, k, max_distance, |shape, p| {
+            shape.aabb().distance_squared_to_point(p).sqrt()
+        })
+    }
+
+    /// Finds the `k` nearest shapes like [`BVH::nearest_k`], but computes the distance of a
+    /// shape to `p` with `distance`, e.g. the exact distance to a triangle. The distance must
+    /// never be smaller than the distance of the shape's [`AABB`], or closer shapes in skipped
+    /// subtrees may be missed.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::nearest_k`]: struct.BVH.html#method.nearest_k
+    ///
+    pub fn nearest_k_by<Shape, F>(
+        &self,
+        p: &Point3,
+        shapes: &[Shape],
+        k: usize,
+        max_distance: f32,
+        mut distance: F,
+    ) -> Vec<(usize, f32)>
+    where
+        Shape: Bounded,
+        F: FnMut(&Shape, &Point3) -> f32,
+    {
+        if self.nodes.is_empty() || k == 0 {
+            return Vec::new();
+        }
+
+        // Distances are non-negative, so their bit patterns sort like the distances.
+        // The candidates form a max-heap, whose top is the `k`-th best so far.
+        let mut candidates: BinaryHeap<(u32, usize)> = BinaryHeap::with_capacity(k + 1);
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0.0f32.to_bits(), 0)));
+        while let Some(Reverse((node_distance, node_index))) = queue.pop() {
+            let node_distance = f32::from_bits(node_distance);
+            let kth_distance = match candidates.peek() {
+                Some(&(kth, _)) if candidates.len() == k => f32::from_bits(kth),
+                _ => max_distance,
+            };
+            // All remaining nodes are at least as far away as this one.
+            if node_distance > kth_distance {
+                break;
+            }
+
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    for &(child_aabb, child_index) in
+                        &[(child_l_aabb, child_l_index), (child_r_aabb, child_r_index)]
+                    {
+                        let child_distance = child_aabb.distance_squared_to_point(p).sqrt();
+                        if child_distance <= kth_distance {
+                            queue.push(Reverse((child_distance.to_bits(), child_index)));
+                        }
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    let leaf_shapes = shapes[shape_index..shape_index + shape_count].iter();
+                    for (index, shape) in (shape_index..).zip(leaf_shapes) {
+                        let shape_distance = distance(shape, p);
+                        if shape_distance <= max_distance {
+                            candidates.push((shape_distance.max(0.0).to_bits(), index));
+                            if candidates.len() > k {
+                                candidates.pop();
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        candidates
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(distance, index)| (index, f32::from_bits(distance)))
+            .collect()
+    }
+
+    /// Returns the indices of the shapes of all leaves whose bounds satisfy `predicate`.
+    /// This is the traversal behind the box, point and swept box queries, and the extension
+    /// point for any other query volume: `predicate` only has to decide whether a node's
+    /// [`AABB`] may touch the volume.
+    ///
+    /// `predicate` must be conservative: it may return `true` for bounds which the volume
+    /// misses, but must never return `false` for bounds which contain the [`AABB`] of a shape
+    /// it should find, since the whole subtree below such a node is skipped. All shapes of a
+    /// visited leaf are returned, so an exact query tests the shapes again. The shape of a
+    /// [`BVH`] with a single leaf is always returned. The shapes are returned in depth-first
+    /// order, left children first.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // Everything on the positive side of the plane x = 11.
+    /// let mut found = bvh.traverse_predicate(|aabb| aabb.max.x >= 11.0);
+    /// found.sort_unstable();
+    /// assert_eq!(found, vec![5, 6, 7, 8, 9]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn traverse_predicate<P: FnMut(&AABB) -> bool>(&self, predicate: P) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.traverse_predicate_with(predicate, |shape_index| out.push(shape_index));
+        out
+    }
+
+    /// Finds the shapes like [`BVH::traverse_predicate`], but calls `visit` with the index of
+    /// each of them instead of collecting them. Use a [`TraversalState`] to reuse the traversal
+    /// stack across queries.
+    ///
+    /// [`BVH::traverse_predicate`]: struct.BVH.html#method.traverse_predicate
+    /// [`TraversalState`]: struct.TraversalState.html
+    ///
+    pub fn traverse_predicate_with<P, F>(&self, predicate: P, visit: F)
+    where
+        P: FnMut(&AABB) -> bool,
+        F: FnMut(usize),
+    {
+        TraversalState::new().traverse_predicate_with(self, predicate, visit);
+    }
+
+    /// Returns the indices of the shapes whose leaves overlap `query`, in depth-first order.
+    /// Only subtrees whose bounds overlap `query` are visited. Like
+    /// [`AABB::intersects_aabb`], bounds which only touch `query` count as overlapping.
+    ///
+    /// The leaves are tested with the bounds stored in the [`BVH`], so all shapes of a
+    /// leaf are returned if its joint bounds overlap `query`, and the shape of a [`BVH`]
+    /// with a single leaf is always returned.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let x = i as f32 * 2.0;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, 0.0), Point3::new(x + 1.0, 1.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// let query = AABB::with_bounds(Point3::new(3.0, 0.5, 0.5), Point3::new(6.5, 2.0, 2.0));
+    /// let mut overlapping = bvh.query_aabb(&query);
+    /// overlapping.sort_unstable();
+    /// assert_eq!(overlapping, vec![1, 2, 3]);
+    /// ```
+    ///
+    /// [`AABB::intersects_aabb`]: ../aabb/struct.AABB.html#method.intersects_aabb
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn query_aabb(&self, query: &AABB) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.query_aabb_with(query, |shape_index| out.push(shape_index));
+        out
+    }
+
+    /// Finds the shapes like [`BVH::query_aabb`], but writes their indices into `out`
+    /// instead of returning them. `out` is cleared first.
+    ///
+    /// [`BVH::query_aabb`]: struct.BVH.html#method.query_aabb
+    ///
+    pub fn query_aabb_into(&self, query: &AABB, out: &mut Vec<usize>) {
+        out.clear();
+        self.query_aabb_with(query, |shape_index| out.push(shape_index));
+    }
+
+    /// Finds the shapes like [`BVH::query_aabb`], but calls `visit` with the index of each
+    /// of them instead of collecting them.
+    ///
+    /// [`BVH::query_aabb`]: struct.BVH.html#method.query_aabb
+    ///
+    pub fn query_aabb_with<F: FnMut(usize)>(&self, query: &AABB, visit: F) {
+        self.traverse_predicate_with(|aabb| query.intersects_aabb(aabb), visit);
+    }
+
+    /// Returns the indices of the shapes whose leaves are touched by `aabb` while it moves
+    /// by `displacement`, e.g. for the broadphase of continuous collision detection. Like
+    /// [`BVH::query_aabb`], bounds which only touch count as overlapping, all shapes of a
+    /// touched leaf are returned, and the shape of a [`BVH`] with a single leaf is always
+    /// returned.
+    ///
+    /// Instead of testing against the union of the start and end positions, which covers a
+    /// lot of empty space for long diagonal motions, each node is tested for a time during
+    /// the motion at which it overlaps the moving box. This is a slab test of the
+    /// `displacement` against the node's bounds grown by the size of `aabb`. With a zero
+    /// `displacement`, the result is exactly that of [`BVH::query_aabb`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = vec![
+    ///     AABB::with_bounds(Point3::new(4.0, 0.0, 0.0), Point3::new(5.0, 1.0, 1.0)),
+    ///     AABB::with_bounds(Point3::new(8.0, 8.0, 0.0), Point3::new(9.0, 9.0, 1.0)),
+    /// ];
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // Moving diagonally, the unit box passes the first box above its corner, but hits the
+    /// // second box. The union of the start and end positions would contain both of them.
+    /// let start = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+    /// let hits = bvh.query_moving_aabb(&start, &Vector3::new(10.0, 10.0, 0.0));
+    /// assert_eq!(hits, vec![1]);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::query_aabb`]: struct.BVH.html#method.query_aabb
+    ///
+    pub fn query_moving_aabb(&self, aabb: &AABB, displacement: &Vector3) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.query_moving_aabb_into(aabb, displacement, &mut out);
+        out
+    }
+
+    /// Finds the shapes like [`BVH::query_moving_aabb`], but writes their indices into `out`
+    /// instead of returning them. `out` is cleared first.
+    ///
+    /// [`BVH::query_moving_aabb`]: struct.BVH.html#method.query_moving_aabb
+    ///
+    pub fn query_moving_aabb_into(
+        &self,
+        aabb: &AABB,
+        displacement: &Vector3,
+        out: &mut Vec<usize>,
+    ) {
+        out.clear();
+        let swept = aabb.swept(displacement);
+        self.traverse_predicate_with(
+            |bounds| {
+                swept.intersects_aabb(bounds) && moving_aabb_touches(aabb, displacement, bounds)
+            },
+            |shape_index| out.push(shape_index),
+        );
+    }
+
+    /// Returns the indices of the `shapes` whose [`AABB`]s contain the point `p`, e.g. for
+    /// picking or trigger zones. Only subtrees whose bounds contain `p` are visited.
+    /// Like [`AABB::contains`], points on the boundary of an [`AABB`] are contained.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// // Nested boxes, which all contain the origin, and one box next to them.
+    /// let mut boxes = (1..=5)
+    ///     .map(|i| AABB::with_bounds(Point3::splat(-i as f32), Point3::splat(i as f32)))
+    ///     .collect::<Vec<_>>();
+    /// boxes.push(AABB::with_bounds(Point3::splat(10.0), Point3::splat(11.0)));
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// let mut containing = bvh.query_point(&boxes, &Point3::ZERO);
+    /// containing.sort_unstable();
+    /// assert_eq!(containing, vec![0, 1, 2, 3, 4]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::contains`]: ../aabb/struct.AABB.html#method.contains
+    ///
+    pub fn query_point<Shape: Bounded>(&self, shapes: &[Shape], p: &Point3) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.query_point_into(shapes, p, &mut out);
+        out
+    }
+
+    /// Finds the shapes like [`BVH::query_point`], but writes their indices into `out`
+    /// instead of returning them. `out` is cleared first.
+    ///
+    /// [`BVH::query_point`]: struct.BVH.html#method.query_point
+    ///
+    pub fn query_point_into<Shape: Bounded>(
+        &self,
+        shapes: &[Shape],
+        p: &Point3,
+        out: &mut Vec<usize>,
+    ) {
+        out.clear();
+        self.traverse_predicate_with(
+            |aabb| aabb.contains(p),
+            |shape_index| {
+                if shapes[shape_index].aabb().contains(p) {
+                    out.push(shape_index);
+                }
+            },
+        );
+    }
+
+    /// Returns the indices of the `shapes` whose [`AABB`]s contain `point`, e.g. for picking
+    /// the objects under the cursor. This is the same query as [`BVH::query_point`], under
+    /// the name used by picking code: subtrees whose bounds don't contain `point` are skipped
+    /// entirely, and points on the boundary of an [`AABB`] are contained.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// // Two overlapping windows and one window elsewhere on the screen.
+    /// let windows = vec![
+    ///     AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(4.0, 3.0, 0.0)),
+    ///     AABB::with_bounds(Point3::new(2.0, 1.0, 0.0), Point3::new(6.0, 5.0, 0.0)),
+    ///     AABB::with_bounds(Point3::new(8.0, 0.0, 0.0), Point3::new(9.0, 1.0, 0.0)),
+    /// ];
+    /// let bvh = BVH::build_dyn(&windows);
+    ///
+    /// let mut picked = bvh.find_containing(&windows, &Point3::new(3.0, 2.0, 0.0));
+    /// picked.sort_unstable();
+    /// assert_eq!(picked, vec![0, 1]);
+    /// assert!(bvh.find_containing(&windows, &Point3::new(7.0, 0.5, 0.0)).is_empty());
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::query_point`]: struct.BVH.html#method.query_point
+    ///
+    pub fn find_containing<Shape: Bounded>(&self, shapes: &[Shape], point: &Point3) -> Vec<usize> {
+        self.query_point(shapes, point)
+    }
+
+    /// Finds the shapes like [`BVH::find_containing`], but writes their indices into `out`
+    /// instead of returning them. `out` is cleared first.
+    ///
+    /// [`BVH::find_containing`]: struct.BVH.html#method.find_containing
+    ///
+    pub fn find_containing_into<Shape: Bounded>(
+        &self,
+        shapes: &[Shape],
+        point: &Point3,
+        out: &mut Vec<usize>,
+    ) {
+        self.query_point_into(shapes, point, out);
+    }
+
+    /// Returns the indices of the `shapes` whose [`AABB`]s may overlap `frustum`, e.g. for
+    /// visibility culling. Only subtrees whose bounds may overlap `frustum` are visited, and
+    /// subtrees whose bounds lie completely inside it are accepted without further tests.
+    /// Shapes are tested like in [`Frustum::intersects_aabb`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::frustum::Frustum;
+    /// use bvh::{Point3, Vector3};
+    /// use glam::Mat4;
+    ///
+    /// // A row of boxes along the x axis.
+    /// let boxes = (-10..10)
+    ///     .map(|i| {
+    ///         let x = i as f32 * 2.0;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, -1.0), Point3::new(x + 1.0, 1.0, 0.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // A camera at `z = 10`, looking along -z.
+    /// let view = Mat4::look_at_rh(Point3::new(0.0, 0.0, 10.0), Point3::ZERO, Vector3::Y);
+    /// let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+    /// let frustum = Frustum::from_view_projection(&(projection * view));
+    ///
+    /// // At `z = -1`, the camera sees `-11 <= x <= 11`, which overlaps the boxes from
+    /// // `x = -12` to `x = 10`.
+    /// let mut visible = bvh.query_frustum(&boxes, &frustum);
+    /// visible.sort_unstable();
+    /// assert_eq!(visible, (4..16).collect::<Vec<_>>());
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Frustum::intersects_aabb`]: ../frustum/struct.Frustum.html#method.intersects_aabb
+    ///
+    pub fn query_frustum<Shape: Bounded>(&self, shapes: &[Shape], frustum: &Frustum) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.query_frustum_into(shapes, frustum, &mut out);
+        out
+    }
+
+    /// Finds the shapes like [`BVH::query_frustum`], but writes their indices into `out`
+    /// instead of returning them. `out` is cleared first.
+    ///
+    /// [`BVH::query_frustum`]: struct.BVH.html#method.query_frustum
+    ///
+    pub fn query_frustum_into<Shape: Bounded>(
+        &self,
+        shapes: &[Shape],
+        frustum: &Frustum,
+        out: &mut Vec<usize>,
+    ) {
+        out.clear();
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        // The flag tells whether the bounds of the node are known to lie inside `frustum`.
+        let mut stack = vec![(0, false)];
+        while let Some((node_index, inside)) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    // Push the right child first, so that the left subtree is visited first.
+                    for &(child_aabb, child_index) in
+                        &[(child_r_aabb, child_r_index), (child_l_aabb, child_l_index)]
+                    {
+                        if inside {
+                            stack.push((child_index, true));
+                            continue;
+                        }
+                        match frustum.classify_aabb(child_aabb) {
+                            Containment::Inside => stack.push((child_index, true)),
+                            Containment::Intersecting => stack.push((child_index, false)),
+                            Containment::Outside => {}
+                        }
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    let shape_range = shape_index..shape_index + shape_count;
+                    if inside {
+                        out.extend(shape_range);
+                    } else {
+                        out.extend(
+                            shape_range
+                                .filter(|&index| frustum.intersects_aabb(&shapes[index].aabb())),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the indices of the `shapes` whose [`AABB`]s lie entirely inside `query`,
+    /// in depth-first order.
+    ///
+    /// Subtrees whose bounds don't overlap `query` are skipped, and subtrees whose bounds lie
+    /// inside `query` are reported without testing their shapes. Only the shapes of leaves
+    /// crossing the border of `query` are tested individually.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    /// # struct Sphere { position: Point3, node_index: usize }
+    /// # impl Bounded for Sphere {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+    /// #     }
+    /// # }
+    /// # impl BHShape for Sphere {
+    /// #     fn set_bh_node_index(&mut self, index: usize) { self.node_index = index; }
+    /// #     fn bh_node_index(&self) -> usize { self.node_index }
+    /// # }
+    ///
+    /// let mut spheres = (0..10)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32 * 3.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build(&mut spheres);
+    ///
+    /// // Contains the second and third sphere, and only parts of the first and fourth.
+    /// let query = AABB::with_bounds(Point3::new(1.5, -5.0, -5.0), Point3::new(7.5, 5.0, 5.0));
+    /// let mut contained = bvh.leaves_contained_in(&spheres, &query);
+    /// contained.sort_unstable();
+    /// assert_eq!(contained, vec![1, 2]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn leaves_contained_in<Shape: Bounded>(
+        &self,
+        shapes: &[Shape],
+        query: &AABB,
+    ) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.leaves_contained_in_into(shapes, query, &mut out);
+        out
+    }
+
+    /// Finds the shapes like [`BVH::leaves_contained_in`], but writes their indices into `out`
+    /// instead of returning them. `out` is cleared first.
+    ///
+    /// [`BVH::leaves_contained_in`]: struct.BVH.html#method.leaves_contained_in
+    ///
+    pub fn leaves_contained_in_into<Shape: Bounded>(
+        &self,
+        shapes: &[Shape],
+        query: &AABB,
+        out: &mut Vec<usize>,
+    ) {
+        out.clear();
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        // The flag tells whether the bounds of the node are known to lie inside `query`.
+        let mut stack = vec![(0, false)];
+        while let Some((node_index, contained)) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    // Push the right child first, so that the left subtree is visited first.
+                    for &(child_aabb, child_index) in
+                        &[(child_r_aabb, child_r_index), (child_l_aabb, child_l_index)]
+                    {
+                        if contained || query.contains_aabb(child_aabb) {
+                            stack.push((child_index, true));
+                        } else if query.intersects_aabb(child_aabb) {
+                            stack.push((child_index, false));
+                        }
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    let shape_range = shape_index..shape_index + shape_count;
+                    if contained {
+                        out.extend(shape_range);
+                    } else {
+                        out.extend(
+                            shape_range.filter(|&index| query.contains_aabb(&shapes[index].aabb())),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns every pair of `shapes` whose [`AABB`]s overlap, e.g. for the broadphase of a
+    /// collision detection. Each pair `(i, j)` is reported exactly once with `i < j`, shapes
+    /// are never paired with themselves, and the pairs are sorted. Like
+    /// [`AABB::intersects_aabb`], [`AABB`]s which only touch count as overlapping.
+    ///
+    /// The [`BVH`] is descended simultaneously against itself, so only pairs of subtrees
+    /// whose bounds overlap are visited instead of all `n²` pairs of shapes.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// // A row of boxes which overlap their direct neighbours.
+    /// let boxes = (0..5)
+    ///     .map(|i| {
+    ///         let x = i as f32;
+    ///         AABB::with_bounds(Point3::new(x, 0.0, 0.0), Point3::new(x + 1.5, 1.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// assert_eq!(bvh.overlapping_pairs(&boxes), vec![(0, 1), (1, 2), (2, 3), (3, 4)]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::intersects_aabb`]: ../aabb/struct.AABB.html#method.intersects_aabb
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn overlapping_pairs<Shape: Bounded>(&self, shapes: &[Shape]) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        self.for_each_overlapping_pair(shapes, |i, j| pairs.push((i, j)));
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Finds the pairs of shapes like [`BVH::overlapping_pairs`], but calls `visit` with
+    /// each of them instead of collecting them. The pairs are not sorted, but still `i < j`
+    /// holds for every pair `(i, j)`. Does not allocate apart from the traversal stacks.
+    ///
+    /// [`BVH::overlapping_pairs`]: struct.BVH.html#method.overlapping_pairs
+    ///
+    pub fn for_each_overlapping_pair<Shape: Bounded, F: FnMut(usize, usize)>(
+        &self,
+        shapes: &[Shape],
+        mut visit: F,
+    ) {
+        let mut visit_pair = |i: usize, j: usize| {
+            if shapes[i].aabb().intersects_aabb(&shapes[j].aabb()) {
+                visit(i.min(j), i.max(j));
+            }
+        };
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        // First find overlaps inside of subtrees, which yields the pairs of sibling subtrees
+        // whose bounds overlap. Then find the overlaps between those.
+        let mut subtrees = vec![0];
+        let mut subtree_pairs = Vec::new();
+        while let Some(node_index) = subtrees.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => {
+                    subtrees.push(child_r_index);
+                    subtrees.push(child_l_index);
+                    if child_l_aabb.intersects_aabb(&child_r_aabb) {
+                        subtree_pairs.push((
+                            child_l_index,
+                            child_l_aabb,
+                            child_r_index,
+                            child_r_aabb,
+                        ));
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    for i in shape_index..shape_index + shape_count {
+                        for j in i + 1..shape_index + shape_count {
+                            visit_pair(i, j);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.descend_overlapping_pairs(self, subtree_pairs, |range_a, range_b| {
+            for i in range_a {
+                for j in range_b.clone() {
+                    visit_pair(i, j);
+                }
+            }
+        });
+    }
+
+    /// Returns every pair of a shape of this [`BVH`] and a shape of `other` whose [`AABB`]s
+    /// overlap, e.g. for colliding dynamic objects with static level geometry. In each pair
+    /// `(i, j)`, `i` is the index in `shapes` and `j` the index in `other_shapes`. The pairs
+    /// are sorted. Like [`AABB::intersects_aabb`], [`AABB`]s which only touch count as
+    /// overlapping.
+    ///
+    /// Both trees are descended simultaneously, always splitting the larger of two subtrees.
+    /// Therefore trees of very different sizes are handled well, e.g. a single shape is
+    /// tested against a large [`BVH`] in logarithmic time.
+    ///
+    /// This is the counterpart of [`BVH::overlapping_pairs`], which finds the overlaps
+    /// inside of a single [`BVH`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// // A floor of ten tiles, and two falling boxes of which one touches the floor.
+    /// let tiles = (0..10)
+    ///     .map(|i| {
+    ///         let x = i as f32;
+    ///         AABB::with_bounds(Point3::new(x, -1.0, 0.0), Point3::new(x + 1.0, 0.0, 1.0))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let boxes = vec![
+    ///     AABB::with_bounds(Point3::new(2.5, -0.1, 0.0), Point3::new(3.5, 0.9, 1.0)),
+    ///     AABB::with_bounds(Point3::new(6.5, 2.0, 0.0), Point3::new(7.5, 3.0, 1.0)),
+    /// ];
+    /// let level = BVH::build_dyn(&tiles);
+    /// let dynamic = BVH::build_dyn(&boxes);
+    ///
+    /// let pairs = dynamic.overlapping_pairs_with(&boxes, &level, &tiles);
+    /// assert_eq!(pairs, vec![(0, 2), (0, 3)]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::intersects_aabb`]: ../aabb/struct.AABB.html#method.intersects_aabb
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH::overlapping_pairs`]: struct.BVH.html#method.overlapping_pairs
+    ///
+    pub fn overlapping_pairs_with<Shape: Bounded, OtherShape: Bounded>(
+        &self,
+        shapes: &[Shape],
+        other: &BVH,
+        other_shapes: &[OtherShape],
+    ) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        self.for_each_overlapping_pair_with(shapes, other, other_shapes, |i, j| {
+            pairs.push((i, j));
+        });
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// Finds the pairs of shapes like [`BVH::overlapping_pairs_with`], but calls `visit`
+    /// with each of them instead of collecting them, e.g. to pass them straight on to the
+    /// contact generation. The pairs are not sorted.
+    ///
+    /// [`BVH::overlapping_pairs_with`]: struct.BVH.html#method.overlapping_pairs_with
+    ///
+    pub fn for_each_overlapping_pair_with<Shape, OtherShape, F>(
+        &self,
+        shapes: &[Shape],
+        other: &BVH,
+        other_shapes: &[OtherShape],
+        mut visit: F,
+    ) where
+        Shape: Bounded,
+        OtherShape: Bounded,
+        F: FnMut(usize, usize),
+    {
+        if self.nodes.is_empty() || other.nodes.is_empty() {
+            return;
+        }
+        let aabb = self.root_aabb_of(shapes);
+        let other_aabb = other.root_aabb_of(other_shapes);
+        if !aabb.intersects_aabb(&other_aabb) {
+            return;
+        }
+
+        let root_pair = vec![(0, aabb, 0, other_aabb)];
+        self.descend_overlapping_pairs(other, root_pair, |range, other_range| {
+            for i in range {
+                let shape_aabb = shapes[i].aabb();
+                for j in other_range.clone() {
+                    if shape_aabb.intersects_aabb(&other_shapes[j].aabb()) {
+                        visit(i, j);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Returns the joint [`AABB`] of the root's children, or of the root's `shapes` if the
+    /// root is a leaf.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn root_aabb_of<Shape: Bounded>(&self, shapes: &[Shape]) -> AABB {
+        match self.nodes[0] {
+            BVHNode::Node {
+                ref child_l_aabb,
+                ref child_r_aabb,
+                ..
+            } => child_l_aabb.join(child_r_aabb),
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => shapes[shape_index..shape_index + shape_count]
+                .iter()
+                .fold(AABB::empty(), |aabb, shape| aabb.join(&shape.aabb())),
+        }
+    }
+
+    /// Descends the pairs of subtrees in `pairs`, whose bounds overlap, until both subtrees
+    /// of a pair are leaves, and calls `visit` with the shape ranges of these leaves. The
+    /// first subtree of each pair belongs to this [`BVH`], the second one to `other`. Each
+    /// entry holds the index and the [`AABB`] of both subtrees.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    ///
+    fn descend_overlapping_pairs<F: FnMut(Range<usize>, Range<usize>)>(
+        &self,
+        other: &BVH,
+        mut pairs: Vec<(usize, AABB, usize, AABB)>,
+        mut visit: F,
+    ) {
+        while let Some((a_index, a_aabb, b_index, b_aabb)) = pairs.pop() {
+            let (node_a, node_b) = (&self.nodes[a_index], &other.nodes[b_index]);
+
+            // Descend into the larger subtree, so that both shrink at a similar rate.
+            let descend_a = match (node_a, node_b) {
+                (BVHNode::Node { .. }, BVHNode::Node { .. }) => {
+                    a_aabb.surface_area() >= b_aabb.surface_area()
+                }
+                (BVHNode::Node { .. }, BVHNode::Leaf { .. }) => true,
+                (BVHNode::Leaf { .. }, BVHNode::Node { .. }) => false,
+                (BVHNode::Leaf { .. }, BVHNode::Leaf { .. }) => {
+                    visit(node_a.shape_range().unwrap(), node_b.shape_range().unwrap());
+                    continue;
+                }
+            };
+
+            let (node, fixed_aabb) = if descend_a {
+                (node_a, b_aabb)
+            } else {
+                (node_b, a_aabb)
+            };
+            for (child_index, child_aabb) in [
+                (node.child_l(), node.child_l_aabb()),
+                (node.child_r(), node.child_r_aabb()),
+            ] {
+                if !child_aabb.intersects_aabb(&fixed_aabb) {
+                    continue;
+                }
+                if descend_a {
+                    pairs.push((child_index, child_aabb, b_index, b_aabb));
+                } else {
+                    pairs.push((a_index, a_aabb, child_index, child_aabb));
+                }
+            }
+        }
+    }
+
+    /// Returns the depth of the leaf which holds the shape at `shape_index`, where the root
+    /// has depth `0`, or `None` if no leaf holds it, e.g. because `shape_index` is out of range.
+    ///
+    /// Shapes at extreme depths make every ray towards them walk a long chain of nodes, which
+    /// points to pathological input. This is a diagnostic which looks at every node, so it
+    /// takes `O(n)` time.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::Point3;
+    ///
+    /// let boxes = (0..4)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    ///
+    /// assert_eq!(bvh.depth_at_shape(0), Some(2));
+    /// assert_eq!(bvh.depth_at_shape(4), None);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn depth_at_shape(&self, shape_index: usize) -> Option<usize> {
+        self.nodes
+            .iter()
+            .find(|node| matches!(node.shape_range(), Some(range) if range.contains(&shape_index)))
+            .map(|node| node.depth() as usize)
+    }
+
+    /// Prints the [`BVH`] in a tree-like visualization.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub fn pretty_print(&self) {
+        let nodes = &self.nodes;
+        fn print_node(nodes: &[BVHNode], node_index: usize) {
+            match nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    depth,
+                    child_l_aabb,
+                    child_r_aabb,
+                    ..
+                } => {
+                    let padding: String = " ".repeat(depth as usize);
+                    println!("{}child_l {}", padding, child_l_aabb);
+                    print_node(nodes, child_l_index);
+                    println!("{}child_r {}", padding, child_r_aabb);
+                    print_node(nodes, child_r_index);
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    depth,
+                    ..
+                } => {
+                    let padding: String = " ".repeat(depth as usize);
+                    for index in shape_index..shape_index + shape_count {
+                        println!("{}shape\t{:?}", padding, index);
+                    }
+                }
+            }
+        }
+        print_node(nodes, 0);
+    }
+
+    /// Verifies that the node at index `node_index` lies inside `expected_outer_aabb`,
+    /// its parent index is equal to `expected_parent_index`, its depth is equal to
+    /// `expected_depth`. Increares `node_count` by the number of visited nodes.
+    fn is_consistent_subtree<Shape: BHShape>(
+        &self,
+        node_index: usize,
+        expected_parent_index: usize,
+        expected_outer_aabb: &AABB,
+        expected_depth: u32,
+        node_count: &mut usize,
+        shapes: &[Shape],
+    ) -> bool {
+        *node_count += 1;
+        match self.nodes[node_index] {
+            BVHNode::Node {
+                parent_index,
+                depth,
+                child_l_index,
+                child_l_aabb,
+                child_r_index,
+                child_r_aabb,
+            } => {
+                let correct_parent_index = expected_parent_index == parent_index;
+                let correct_depth = expected_depth == depth;
+                let left_aabb_in_parent =
+                    expected_outer_aabb.approx_contains_aabb_eps(&child_l_aabb, EPSILON);
+                let right_aabb_in_parent =
+                    expected_outer_aabb.approx_contains_aabb_eps(&child_r_aabb, EPSILON);
+                let left_subtree_consistent = self.is_consistent_subtree(
+                    child_l_index,
+                    node_index,
+                    &child_l_aabb,
+                    expected_depth + 1,
+                    node_count,
+                    shapes,
+                );
+                let right_subtree_consistent = self.is_consistent_subtree(
+                    child_r_index,
+                    node_index,
+                    &child_r_aabb,
+                    expected_depth + 1,
+                    node_count,
+                    shapes,
+                );
+
+                correct_parent_index
+                    && correct_depth
+                    && left_aabb_in_parent
+                    && right_aabb_in_parent
+                    && left_subtree_consistent
+                    && right_subtree_consistent
+            }
+            BVHNode::Leaf {
+                parent_index,
+                depth,
+                shape_index,
+                shape_count,
+            } => {
+                let correct_parent_index = expected_parent_index == parent_index;
+                let correct_depth = expected_depth == depth;
+                let shape_aabbs_in_parent = shapes[shape_index..shape_index + shape_count]
+                    .iter()
+                    .all(|shape| {
+                        expected_outer_aabb.approx_contains_aabb_eps(&shape.aabb(), EPSILON)
+                    });
+
+                correct_parent_index && correct_depth && shape_aabbs_in_parent
+            }
+        }
+    }
+
+    /// Checks if all children of a node have the correct parent index, and that there is no
+    /// detached subtree. Also checks if the `AABB` hierarchy is consistent.
+    pub fn is_consistent<Shape: BHShape>(&self, shapes: &[Shape]) -> bool {
+        // The root node of the bvh is not bounded by anything.
+        let space = AABB {
+            min: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            max: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        };
+
+        // The counter for all nodes.
+        let mut node_count = 0;
+        let subtree_consistent =
+            self.is_consistent_subtree(0, 0, &space, 0, &mut node_count, shapes);
+
+        // Check if all nodes have been counted from the root node.
+        // If this is false, it means we have a detached subtree.
+        let is_connected = node_count == self.nodes.len();
+        subtree_consistent && is_connected
+    }
+
+    /// Assert version of `is_consistent_subtree`.
+    fn assert_consistent_subtree<Shape: BHShape>(
+        &self,
+        node_index: usize,
+        expected_parent_index: usize,
+        expected_outer_aabb: &AABB,
+        expected_depth: u32,
+        node_count: &mut usize,
+        shapes: &[Shape],
+    ) {
+        *node_count += 1;
+        let node = &self.nodes[node_index];
+
+        let parent = node.parent();
+        assert_eq!(
+            expected_parent_index, parent,
+            "Wrong parent index. Expected: {}; Actual: {}",
+            expected_parent_index, parent
+        );
+        let depth = node.depth();
+        assert_eq!(
+            expected_depth, depth,
+            "Wrong depth. Expected: {}; Actual: {}",
+            expected_depth, depth
+        );
+
+        match *node {
+            BVHNode::Node {
+                child_l_index,
+                child_l_aabb,
+                child_r_index,
+                child_r_aabb,
+                ..
+            } => {
+                assert!(
+                    expected_outer_aabb.approx_contains_aabb_eps(&child_l_aabb, EPSILON),
+                    "Left child lies outside the expected bounds.
+                         \tBounds: {}
+                         \tLeft child: {}",
+                    expected_outer_aabb,
+                    child_l_aabb
+                );
+                assert!(
+                    expected_outer_aabb.approx_contains_aabb_eps(&child_r_aabb, EPSILON),
+                    "Right child lies outside the expected bounds.
+                         \tBounds: {}
+                         \tRight child: {}",
+                    expected_outer_aabb,
+                    child_r_aabb
+                );
+                self.assert_consistent_subtree(
+                    child_l_index,
+                    node_index,
+                    &child_l_aabb,
+                    expected_depth + 1,
+                    node_count,
+                    shapes,
+                );
+                self.assert_consistent_subtree(
+                    child_r_index,
+                    node_index,
+                    &child_r_aabb,
+                    expected_depth + 1,
+                    node_count,
+                    shapes,
+                );
+            }
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => {
+                for shape in &shapes[shape_index..shape_index + shape_count] {
+                    let shape_aabb = shape.aabb();
+                    assert!(
+                        expected_outer_aabb.approx_contains_aabb_eps(&shape_aabb, EPSILON),
+                        "Shape's AABB lies outside the expected bounds.\n\tBounds: {}\n\tShape: {}",
+                        expected_outer_aabb,
+                        shape_aabb
+                    );
+                }
+            }
+        }
+    }
+
+    /// Assert version of `is_consistent`.
+    pub fn assert_consistent<Shape: BHShape>(&self, shapes: &[Shape]) {
+        // The root node of the bvh is not bounded by anything.
+        let space = AABB {
+            min: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            max: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+        };
+
+        // The counter for all nodes.
+        let mut node_count = 0;
+        self.assert_consistent_subtree(0, 0, &space, 0, &mut node_count, shapes);
+
+        // Check if all nodes have been counted from the root node.
+        // If this is false, it means we have a detached subtree.
+        assert_eq!(node_count, self.nodes.len(), "Detached subtree");
+    }
+
+    /// Check that the `AABB`s in the `BVH` are tight, which means, that parent `AABB`s are not
+    /// larger than they should be. This function checks, whether the children of node `node_index`
+    /// lie inside `outer_aabb`.
+    #[allow(clippy::only_used_in_recursion)]
+    pub fn assert_tight_subtree<Shape: BHShape>(
+        &self,
+        node_index: usize,
+        outer_aabb: &AABB,
+        shapes: &[Shape],
+    ) {
+        if let BVHNode::Node {
+            child_l_index,
+            child_l_aabb,
+            child_r_index,
+            child_r_aabb,
+            ..
+        } = self.nodes[node_index]
+        {
+            let joint_aabb = child_l_aabb.join(&child_r_aabb);
+            assert!(joint_aabb.relative_eq(outer_aabb, EPSILON));
+            self.assert_tight_subtree(child_l_index, &child_l_aabb, shapes);
+            self.assert_tight_subtree(child_r_index, &child_r_aabb, shapes);
+        }
+    }
+
+    /// Check that the `AABB`s in the `BVH` are tight, which means, that parent `AABB`s are not
+    /// larger than they should be.
+    pub fn assert_tight<Shape: BHShape>(&self, shapes: &[Shape]) {
+        // When starting to check whether the `BVH` is tight, we cannot provide a minimum
+        // outer `AABB`, therefore we compute the correct one in this instance.
+        if let BVHNode::Node {
+            child_l_aabb,
+            child_r_aabb,
+            ..
+        } = self.nodes[0]
+        {
+            let joint_aabb = child_l_aabb.join(&child_r_aabb);
+            self.assert_tight_subtree(0, &joint_aabb, shapes);
+        }
+    }
+}
+
+/// Returns whether a shape with the bounds `aabb` can be built into a [`BVH`] without
+/// corrupting it. Besides [`AABB::is_valid`] bounds this requires finite ones, because the
+/// center of an infinite [`AABB`] may be infinite or `NaN`, which breaks the splits.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`AABB::is_valid`]: ../aabb/struct.AABB.html#method.is_valid
+/// [`BVH`]: struct.BVH.html
+///
+fn is_buildable(aabb: &AABB) -> bool {
+    aabb.is_valid() && aabb.min.is_finite() && aabb.max.is_finite()
+}
+
+/// Returns whether `aabb` touches `other` at some time `t` in `[0, 1]` while it moves to
+/// `aabb + t * displacement`. Per axis, the times at which the projections overlap form an
+/// interval, and the boxes touch if the intervals of all three axes have a common time.
+fn moving_aabb_touches(aabb: &AABB, displacement: &Vector3, other: &AABB) -> bool {
+    let mut entry = 0.0f32;
+    let mut exit = 1.0f32;
+    for axis in 0..3 {
+        // The projections overlap while `aabb.min + t * d <= other.max` and
+        // `aabb.max + t * d >= other.min`.
+        let d = displacement[axis];
+        let to_touch = other.min[axis] - aabb.max[axis];
+        let to_leave = other.max[axis] - aabb.min[axis];
+        if d == 0.0 {
+            if to_touch > 0.0 || to_leave < 0.0 {
+                return false;
+            }
+            continue;
+        }
+        let (t0, t1) = if d > 0.0 {
+            (to_touch / d, to_leave / d)
+        } else {
+            (to_leave / d, to_touch / d)
+        };
+        entry = entry.max(t0);
+        exit = exit.min(t1);
+    }
+    entry <= exit
+}
+
+/// Stands in for a shape of [`BVH::build_dyn`], which can't store its node index itself.
+///
+/// [`BVH::build_dyn`]: struct.BVH.html#method.build_dyn
+///
+struct DynShape {
+    aabb: AABB,
+    node_index: usize,
+}
+
+impl Bounded for DynShape {
+    fn aabb(&self) -> AABB {
+        self.aabb
+    }
+}
+
+impl BHShape for DynShape {
+    fn set_bh_node_index(&mut self, index: usize) {
+        self.node_index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+        self.node_index
+    }
+}
+
+impl BoundingHierarchy for BVH {
+    fn build<Shape: BHShape>(shapes: &mut [Shape]) -> BVH {
+        BVH::build(shapes)
+    }
+
+    fn traverse<'a, Shape: Bounded>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&'a Shape> {
+        self.traverse(ray, shapes)
+    }
+
+    fn pretty_print(&self) {
+        self.pretty_print();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::moving_aabb_touches;
+    use crate::aabb::{Bounded, SurfacePoint, AABB};
+    use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
+    use crate::bvh::{BVHBuildScratch, BVHConfig, BVHNode, SplitHeuristic, TraversalOrder, BVH};
+    use crate::frustum::Frustum;
+    use crate::ray::{Intersectable, IntersectableShape, Ray, RayCone, RaycastResult};
+    use crate::testbase::{
+        build_some_bh, build_with_max_leaf_size, create_n_cubes, default_bounds, next_point3,
+        randomly_transform_scene, ray_toward_random_shape, traverse_some_bh, tuple_to_point,
+        tuple_to_vector, tuplevec_grid_strategy, unit_boxes, Triangle, UnitBox,
+    };
+    use crate::{Point3, Vector3, EPSILON};
+
+    use glam::Mat4;
+    use proptest::prelude::*;
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_bvh() {
+        build_some_bh::<BVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given as a BVH.
+    fn test_traverse_bvh() {
+        traverse_some_bh::<BVH>();
+    }
+
+    #[test]
+    /// Verify contents of the bounding hierarchy for a fixed scene structure
+    fn test_bvh_shape_indices() {
+        use std::collections::HashSet;
+
+        let (all_shapes, bh) = build_some_bh::<BVH>();
+
+        // It should find all shape indices.
+        let expected_shapes: HashSet<_> = (0..all_shapes.len()).collect();
+        let mut found_shapes = HashSet::new();
+
+        for node in bh.nodes.iter() {
+            match *node {
+                BVHNode::Node { .. } => {
+                    assert_eq!(node.shape_index(), None);
+                }
+                BVHNode::Leaf { .. } => {
+                    found_shapes.insert(
+                        node.shape_index()
+                            .expect("getting a shape index from a leaf node"),
+                    );
+                }
+            }
+        }
+
+        assert_eq!(expected_shapes, found_shapes);
+    }
+
+    #[test]
+    /// Tests whether `is_occluded` only reports hits which lie within `t_max`.
+    fn test_is_occluded() {
+        let (shapes, bvh) = build_some_bh::<BVH>();
+
+        // The closest box is entered at x = -10.5.
+        let ray = Ray::new(Point3::new(-20.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(!bvh.is_occluded(&ray, 9.0, &shapes));
+        assert!(bvh.is_occluded(&ray, 9.5, &shapes));
+        assert!(bvh.is_occluded(&ray, f32::INFINITY, &shapes));
+
+        // This ray passes above all boxes.
+        let ray = Ray::new(Point3::new(-20.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert!(!bvh.is_occluded(&ray, f32::INFINITY, &shapes));
+    }
+
+    #[test]
+    /// Tests whether a shape containing the origin of the ray occludes for `is_occluded`, but
+    /// not for `is_occluded_by`, which ignores hits at a distance of `0.0`.
+    fn test_is_occluded_origin_inside_shape() {
+        let (shapes, bvh) = build_some_bh::<BVH>();
+        let intersect = |shape: &UnitBox, ray: &Ray| shape.intersect(ray);
+
+        // The ray starts inside of the box at x = 0 and leaves all boxes behind.
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        assert_eq!(shapes[10].intersect(&ray), Some(0.0));
+        assert!(bvh.is_occluded(&ray, 0.25, &shapes));
+        assert!(bvh.is_occluded(&ray, f32::INFINITY, &shapes));
+        assert!(!bvh.is_occluded_by(&ray, f32::INFINITY, &shapes, intersect));
+
+        assert!(!BVH { nodes: Vec::new() }.is_occluded(&ray, f32::INFINITY, &shapes));
+    }
+
+    #[test]
+    /// Tests `is_occluded_by` with a wall between two points, and a box exactly at `t_max`.
+    fn test_is_occluded_by_wall() {
+        let intersect = |shape: &AABB, ray: &Ray| shape.intersect(ray);
+        let from = Point3::new(0.0, 0.0, 0.0);
+        let to = Point3::new(10.0, 0.0, 0.0);
+        let ray = Ray::new(from, to - from);
+        let t_max = (to - from).length();
+
+        // Some boxes beside the line between the two points.
+        let mut shapes = (0..20)
+            .map(|i| {
+                let min = Point3::new(i as f32, 1.0 + (i % 3) as f32, -1.0);
+                AABB::with_bounds(min, min + Vector3::splat(0.5))
+            })
+            .collect::<Vec<_>>();
+        let bvh = BVH::build_dyn(&shapes);
+        assert!(!bvh.is_occluded_by(&ray, t_max, &shapes, intersect));
+
+        // A wall between the points.
+        shapes.push(AABB::with_bounds(
+            Point3::new(4.0, -5.0, -5.0),
+            Point3::new(4.5, 5.0, 5.0),
+        ));
+        let bvh = BVH::build_dyn(&shapes);
+        assert!(bvh.is_occluded_by(&ray, t_max, &shapes, intersect));
+        assert!(!bvh.is_occluded_by(&ray, 3.9, &shapes, intersect));
+
+        // Removing the wall clears the line again.
+        shapes.pop();
+        let bvh = BVH::build_dyn(&shapes);
+        assert!(!bvh.is_occluded_by(&ray, t_max, &shapes, intersect));
+
+        // A box entered exactly at `t_max` occludes, while a hit at the origin doesn't.
+        shapes.push(AABB::with_bounds(
+            to - Vector3::new(0.0, 0.5, 0.5),
+            to + Vector3::new(1.0, 0.5, 0.5),
+        ));
+        let bvh = BVH::build_dyn(&shapes);
+        assert!(bvh.is_occluded_by(&ray, t_max, &shapes, intersect));
+        assert!(!bvh.is_occluded_by(&ray, t_max - 0.01, &shapes, intersect));
+        let at_origin = |shape: &AABB, ray: &Ray| shape.intersect(ray).map(|_| 0.0);
+        assert!(!bvh.is_occluded_by(&ray, t_max, &shapes, at_origin));
+
+        assert!(!BVH { nodes: Vec::new() }.is_occluded_by(&ray, t_max, &shapes, intersect));
+    }
+
+    #[test]
+    /// Tests `segment_occluded` with endpoints on the faces, edges and corners of boxes.
+    fn test_segment_occluded_endpoints_on_faces() {
+        let intersect = |shape: &AABB, ray: &Ray| shape.intersect(ray);
+        let from = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+        let to = AABB::with_bounds(Point3::new(7.0, 3.0, 2.0), Point3::new(8.0, 4.0, 3.0));
+        let wall = AABB::with_bounds(Point3::new(4.0, -5.0, -5.0), Point3::new(4.5, 0.0, 5.0));
+        let shapes = vec![from, to, wall];
+        let bvh = BVH::build_dyn(&shapes);
+
+        // Between the faces which look at each other, the points of the faces and the corners.
+        let pairs = [
+            (Point3::new(1.0, 0.5, 0.5), Point3::new(7.0, 3.5, 2.5)),
+            (Point3::new(0.3, 1.0, 0.7), Point3::new(7.2, 3.0, 2.9)),
+            (Point3::new(1.0, 1.0, 1.0), Point3::new(7.0, 3.0, 2.0)),
+            (Point3::new(1.0, 0.1, 0.9), Point3::new(7.9, 3.0, 2.1)),
+        ];
+        for &(a, b) in &pairs {
+            assert!(!bvh.segment_occluded(&a, &b, &shapes, intersect));
+            assert!(!bvh.segment_occluded(&b, &a, &shapes, intersect));
+        }
+
+        // A solid box which the segment starts on and passes through is hit at distance `0.0`,
+        // so it doesn't occlude either.
+        let a = Point3::new(0.0, 0.5, 0.5);
+        assert!(!bvh.segment_occluded(&a, &Point3::new(7.0, 3.5, 2.5), &shapes, intersect));
+        // Ending on the far face of the target box.
+        let b = Point3::new(8.0, 3.5, 2.5);
+        assert!(bvh.segment_occluded(&Point3::new(1.0, 0.5, 0.5), &b, &shapes, intersect));
+
+        // Blocked by the wall, and just ending on it.
+        let a = Point3::new(1.0, -0.5, 0.5);
+        assert!(bvh.segment_occluded(&a, &Point3::new(7.0, -0.5, 0.5), &shapes, intersect));
+        assert!(!bvh.segment_occluded(&a, &Point3::new(4.0, -0.5, 0.5), &shapes, intersect));
+
+        // Degenerate segments are never occluded.
+        let inside = Point3::new(4.2, -1.0, 0.0);
+        assert!(!bvh.segment_occluded(&inside, &inside, &shapes, intersect));
+    }
+
+    #[test]
+    /// Tests whether `nearest_hit_cone` finds the nearest box and the footprint of the cone there.
+    fn test_nearest_hit_cone() {
+        let (shapes, bvh) = build_some_bh::<BVH>();
+
+        // The closest box is entered at x = -10.5.
+        let cone = RayCone::new(
+            Point3::new(-20.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            0.1,
+        );
+        let (shape_index, distance, radius) = bvh.nearest_hit_cone(&cone, &shapes).unwrap();
+        assert_eq!(shapes[shape_index].pos.x, -10.0);
+        assert_eq!(distance, 9.5);
+        assert_eq!(radius, cone.radius_at(9.5));
+
+        // This cone passes above all boxes.
+        let cone = RayCone::new(
+            Point3::new(-20.0, 1.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            0.1,
+        );
+        assert!(bvh.nearest_hit_cone(&cone, &shapes).is_none());
+    }
+
+    #[test]
+    /// Tests whether `build_with_merge` stores the merged bounds in the nodes.
+    fn test_build_with_merge() {
+        let mut shapes = create_n_cubes(50, &default_bounds());
+        let bvh = BVH::build(&mut shapes);
+
+        // Merging with `AABB::join` is the same as the default build.
+        let mut joined_shapes = create_n_cubes(50, &default_bounds());
+        let joined = BVH::build_with_merge(&mut joined_shapes, AABB::join);
+        assert_eq!(format!("{:?}", joined.nodes), format!("{:?}", bvh.nodes));
+
+        // A merge which pads the joint bounds stores bounds containing the joint ones.
+        let pad = |a: &AABB, b: &AABB| {
+            let aabb = a.join(b);
+            AABB::with_bounds(aabb.min - Vector3::ONE, aabb.max + Vector3::ONE)
+        };
+        let mut padded_shapes = create_n_cubes(50, &default_bounds());
+        let padded = BVH::build_with_merge(&mut padded_shapes, pad);
+        assert_eq!(padded.nodes.len(), bvh.nodes.len());
+        for (node, padded_node) in bvh.nodes.iter().zip(&padded.nodes) {
+            if let BVHNode::Node { .. } = node {
+                assert!(padded_node
+                    .child_l_aabb()
+                    .contains_aabb(&node.child_l_aabb()));
+                assert!(padded_node
+                    .child_r_aabb()
+                    .contains_aabb(&node.child_r_aabb()));
+            }
+        }
+        padded.assert_consistent(&padded_shapes);
+    }
+
+    #[test]
+    /// Tests whether a `BVH` of boxed shapes of mixed types finds the same shapes
+    /// as a `BVH` of the concrete shapes.
+    fn test_build_dyn_mixed_shapes() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut triangles = create_n_cubes(50, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        // Alternate between the triangles and their bare `AABB`s.
+        let shapes = triangles
+            .iter()
+            .enumerate()
+            .map(|(index, triangle)| -> Box<dyn Bounded> {
+                if index % 2 == 0 {
+                    Box::new(Triangle::new(triangle.a, triangle.b, triangle.c))
+                } else {
+                    Box::new(triangle.aabb())
+                }
+            })
+            .collect::<Vec<_>>();
+        let dyn_bvh = BVH::build_dyn(&shapes);
+        assert_eq!(dyn_bvh.nodes, bvh.nodes);
+
+        let mut expected = Vec::new();
+        let mut hit_count = 0;
+        for x in -10..10 {
+            for y in -10..10 {
+                let origin = Point3::new(x as f32, y as f32, -20.0);
+                let ray = Ray::new(origin, Vector3::new(0.0, 0.0, 1.0));
+                bvh.traverse_into(&ray, &mut expected);
+                assert_eq!(dyn_bvh.traverse_dyn(&ray, &shapes), expected);
+                hit_count += expected.len();
+            }
+        }
+        assert!(hit_count > 0);
+    }
+
+    #[test]
+    /// Tests whether a `BVH` built bottom-up from sorted shapes is balanced, stores tight
+    /// bounds and finds the same shapes as a brute force search.
+    fn test_build_bottom_up() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut triangles = create_n_cubes(25, &bounds);
+        triangles.sort_by(|a, b| a.aabb().center().x.total_cmp(&b.aabb().center().x));
+        let bvh = BVH::build_bottom_up(&triangles);
+
+        // Every shape is in exactly one leaf, and each node's bounds are those of its subtree.
+        let mut leaf_count = vec![0; triangles.len()];
+        let mut stack = vec![(0, AABB::empty(), 0)];
+        let mut max_depth = 0;
+        while let Some((node_index, _, depth)) = stack.pop() {
+            assert_eq!(bvh.nodes[node_index].depth(), depth);
+            max_depth = max_depth.max(depth);
+            match bvh.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => {
+                    for (child_index, child_aabb) in
+                        [(child_l_index, child_l_aabb), (child_r_index, child_r_aabb)]
+                    {
+                        assert_eq!(bvh.nodes[child_index].parent(), node_index);
+                        let joint = bvh.nodes[child_index]
+                            .shape_range()
+                            .map(|range| {
+                                range.fold(AABB::empty(), |aabb, i| aabb.join(&triangles[i].aabb()))
+                            })
+                            .unwrap_or_else(|| {
+                                bvh.nodes[child_index]
+                                    .child_l_aabb()
+                                    .join(&bvh.nodes[child_index].child_r_aabb())
+                            });
+                        assert_eq!(joint.min, child_aabb.min);
+                        assert_eq!(joint.max, child_aabb.max);
+                        stack.push((child_index, child_aabb, depth + 1));
+                    }
+                }
+                BVHNode::Leaf { shape_index, .. } => leaf_count[shape_index] += 1,
+            }
+        }
+        assert!(leaf_count.iter().all(|&count| count == 1));
+        let expected_depth = (triangles.len() as f32).log2().ceil() as u32;
+        assert_eq!(max_depth, expected_depth);
+
+        let mut hit_count = 0;
+        for x in -10..10 {
+            for y in -10..10 {
+                let origin = Point3::new(x as f32 + 0.5, y as f32 + 0.5, -20.0);
+                let ray = Ray::new(origin, Vector3::new(0.0, 0.0, 1.0));
+                let expected = (0..triangles.len())
+                    .filter(|&i| ray.intersects_aabb(&triangles[i].aabb()))
+                    .collect::<Vec<_>>();
+                let mut hits = bvh.traverse_dyn(&ray, &triangles);
+                hits.sort_unstable();
+                assert_eq!(hits, expected);
+                hit_count += hits.len();
+            }
+        }
+        assert!(hit_count > 0);
+
+        assert!(BVH::build_bottom_up::<AABB>(&[]).nodes.is_empty());
+        let single = BVH::build_bottom_up(&triangles[..1]);
+        assert_eq!(single.nodes.len(), 1);
+    }
+
+    #[test]
+    /// Tests whether `build_agglomerative` builds consistent and tight trees with one shape
+    /// per leaf for uniformly spread and for clustered shapes, and whether they find the same
+    /// shapes as a brute force search.
+    fn test_build_agglomerative() {
+        let bounds = AABB::with_bounds(Point3::splat(-100.0), Point3::splat(100.0));
+        let cluster_bounds = AABB::with_bounds(Point3::splat(-2.0), Point3::splat(2.0));
+        let mut seed = 0;
+        let centers = (0..5)
+            .map(|_| next_point3(&mut seed, &bounds))
+            .collect::<Vec<_>>();
+        let uniform = (0..500)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect::<Vec<_>>();
+        let clustered = (0..500)
+            .map(|id| {
+                let center = centers[id as usize % centers.len()];
+                UnitBox::new(id, center + next_point3(&mut seed, &cluster_bounds))
+            })
+            .collect::<Vec<_>>();
+
+        for mut shapes in [uniform, clustered] {
+            let positions = shapes.iter().map(|shape| shape.pos).collect::<Vec<_>>();
+            let bvh = BVH::build_agglomerative(&mut shapes);
+            bvh.assert_consistent(&shapes);
+            bvh.assert_tight(&shapes);
+            assert_eq!(bvh.nodes.len(), 2 * shapes.len() - 1);
+            let positions_after = shapes.iter().map(|shape| shape.pos).collect::<Vec<_>>();
+            assert_eq!(positions_after, positions);
+
+            let mut hit_count = 0;
+            for _ in 0..100 {
+                let origin = next_point3(&mut seed, &bounds);
+                let target = shapes[hit_count % shapes.len()].pos;
+                let ray = Ray::new(origin, target - origin);
+                let expected = (0..shapes.len())
+                    .filter(|&i| ray.intersects_aabb(&shapes[i].aabb()))
+                    .collect::<Vec<_>>();
+                let mut hits = Vec::new();
+                bvh.traverse_into(&ray, &mut hits);
+                hits.sort_unstable();
+                assert_eq!(hits, expected);
+                hit_count += hits.len();
+            }
+        }
+
+        assert!(BVH::build_agglomerative::<UnitBox>(&mut [])
+            .nodes
+            .is_empty());
+        let mut single = vec![UnitBox::new(0, Point3::ZERO)];
+        assert_eq!(BVH::build_agglomerative(&mut single).nodes.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Shape 1 has invalid bounds")]
+    /// Tests whether `build_agglomerative` rejects infinite bounds like the top-down builds.
+    fn test_build_agglomerative_with_infinite_shape() {
+        let mut shapes = (0..3)
+            .map(|id| UnitBox::new(id, Point3::new(id as f32, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+        shapes[1].pos.z = f32::NEG_INFINITY;
+        BVH::build_agglomerative(&mut shapes);
+    }
+
+    #[test]
+    /// Tests whether `nearest_k_by` ranks the shapes by the given distance.
+    fn test_nearest_k_by_center_distance() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let triangles = create_n_cubes(50, &bounds);
+        let bvh = BVH::build_dyn(&triangles);
+        let p = Point3::new(1.0, -2.0, 3.0);
+        let center_distance = |triangle: &Triangle, p: &Point3| {
+            ((triangle.a + triangle.b + triangle.c) / 3.0 - *p).length()
+        };
+
+        let mut expected = triangles
+            .iter()
+            .enumerate()
+            .map(|(index, triangle)| (index, center_distance(triangle, &p)))
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        expected.truncate(10);
+        assert_eq!(
+            bvh.nearest_k_by(&p, &triangles, 10, f32::INFINITY, center_distance),
+            expected
+        );
+    }
+
+    #[test]
+    /// Tests whether `query_point` returns all of many nested boxes around a point.
+    fn test_query_point_nested_boxes() {
+        let p = Point3::new(0.25, -0.5, 1.0);
+        let mut boxes = (1..=200)
+            .map(|i| {
+                let half_size = Vector3::splat(i as f32 * 0.1);
+                AABB::with_bounds(p - half_size, p + half_size * 2.0)
+            })
+            .collect::<Vec<_>>();
+        // A box which doesn't contain the point, and one which contains it on its boundary.
+        boxes.push(AABB::with_bounds(p + Vector3::X, p + Vector3::splat(2.0)));
+        boxes.push(AABB::with_bounds(p - Vector3::ONE, p));
+        let bvh = BVH::build_dyn(&boxes);
+
+        let mut found = bvh.query_point(&boxes, &p);
+        found.sort_unstable();
+        let mut expected = (0..200).collect::<Vec<_>>();
+        expected.push(201);
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    /// Tests whether `prune` removes exactly the degenerate shapes and leaves a consistent,
+    /// tight `BVH` behind.
+    fn test_prune() {
+        for max_leaf_size in [1, 4] {
+            let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+            let mut triangles = create_n_cubes(20, &bounds);
+            // Collapse every fifth triangle into a point.
+            for (index, triangle) in triangles.iter_mut().enumerate() {
+                if index % 5 == 0 {
+                    *triangle = Triangle::new(triangle.a, triangle.a, triangle.a);
+                }
+            }
+            let config = BVHConfig {
+                max_leaf_size,
+                ..Default::default()
+            };
+            let mut bvh = BVH::build_with_config(&mut triangles, &config);
+
+            let mut removed = bvh.prune(&mut triangles, 1e-6);
+            removed.sort_unstable();
+            let mut kept = (0..triangles.len())
+                .filter(|index| removed.binary_search(index).is_err())
+                .collect::<Vec<_>>();
+            assert!(removed
+                .iter()
+                .all(|&index| triangles[index].aabb().surface_area() == 0.0));
+            if max_leaf_size == 1 {
+                assert_eq!(removed.len(), triangles.len() / 5);
+            }
+
+            bvh.assert_consistent(&triangles);
+            bvh.assert_tight(&triangles);
+            let mut in_leaves = bvh
+                .nodes
+                .iter()
+                .filter_map(BVHNode::shape_range)
+                .flatten()
+                .collect::<Vec<_>>();
+            in_leaves.sort_unstable();
+            assert_eq!(in_leaves, kept);
+            kept.retain(|&index| {
+                bvh.nodes[triangles[index].bh_node_index()]
+                    .shape_range()
+                    .is_some_and(|range| range.contains(&index))
+            });
+            assert_eq!(kept, in_leaves);
+
+            // Pruning everything leaves an empty `BVH`.
+            assert_eq!(
+                bvh.prune(&mut triangles, f32::INFINITY).len(),
+                in_leaves.len()
+            );
+            assert!(bvh.nodes.is_empty());
+        }
+    }
+
+    #[test]
+    /// Tests whether `fold_nodes` computes the value of every node from its children.
+    fn test_fold_nodes() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut triangles = create_n_cubes(20, &bounds);
+        let config = BVHConfig {
+            max_leaf_size: 3,
+            ..Default::default()
+        };
+        let bvh = BVH::build_with_config(&mut triangles, &config);
+
+        // The number of shapes and the joint `AABB` of every subtree.
+        let values = bvh.fold_nodes(
+            |shape_range| {
+                let aabb = triangles[shape_range.clone()]
+                    .iter()
+                    .fold(AABB::empty(), |aabb, triangle| aabb.join(&triangle.aabb()));
+                (shape_range.len(), aabb)
+            },
+            |(count_l, aabb_l), (count_r, aabb_r)| (count_l + count_r, aabb_l.join(aabb_r)),
+        );
+        assert_eq!(values.len(), bvh.nodes.len());
+        assert_eq!(values[0].0, triangles.len());
+        for node in bvh.nodes.iter() {
+            if let BVHNode::Node {
+                child_l_index,
+                child_l_aabb,
+                child_r_index,
+                child_r_aabb,
+                ..
+            } = *node
+            {
+                assert!(values[child_l_index].1.relative_eq(&child_l_aabb, EPSILON));
+                assert!(values[child_r_index].1.relative_eq(&child_r_aabb, EPSILON));
+            }
+        }
+
+        let empty = BVH { nodes: Vec::new() };
+        assert!(empty.fold_nodes(|_| 0, |a, b| a + b).is_empty());
+    }
+
+    #[test]
+    /// Tests whether merging two `BVH`s gives a consistent `BVH` which finds the shapes of
+    /// both.
+    fn test_merge() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let mut triangles = create_n_cubes(20, &bounds);
+        let split = 100;
+        let bvh_a = BVH::build(&mut triangles[..split]);
+        let bvh_b = BVH::build(&mut triangles[split..]);
+        let node_offset_b = 1 + bvh_a.nodes.len();
+        let bvh = BVH::merge(bvh_a, bvh_b, split, &triangles);
+
+        for (index, triangle) in triangles.iter_mut().enumerate() {
+            let node_offset = if index < split { 1 } else { node_offset_b };
+            triangle.set_bh_node_index(triangle.bh_node_index() + node_offset);
+        }
+        bvh.assert_consistent(&triangles);
+        bvh.assert_tight(&triangles);
+        for (index, triangle) in triangles.iter().enumerate() {
+            assert_eq!(
+                bvh.nodes[triangle.bh_node_index()].shape_index(),
+                Some(index)
+            );
+        }
+
+        let mut hit_count = 0;
+        for x in -10..10 {
+            for y in -10..10 {
+                let origin = Point3::new(x as f32 + 0.5, y as f32 + 0.5, -20.0);
+                let ray = Ray::new(origin, Vector3::new(0.0, 0.0, 1.0));
+                let mut expected = (0..triangles.len())
+                    .filter(|&i| ray.intersects_aabb(&triangles[i].aabb()))
+                    .collect::<Vec<_>>();
+                let mut hits = bvh.traverse_dyn(&ray, &triangles);
+                hits.sort_unstable();
+                expected.sort_unstable();
+                assert_eq!(hits, expected);
+                hit_count += hits.len();
+            }
+        }
+        assert!(hit_count > 0);
+
+        // A single leaf keeps the bounds of its shape, an empty `BVH` adds nothing.
+        let single = BVH::build(&mut triangles[..1]);
+        let merged = BVH::merge(
+            single.clone(),
+            BVH::build(&mut triangles[1..3]),
+            1,
+            &triangles,
+        );
+        let ray = Ray::new(Point3::splat(100.0), Vector3::new(0.0, 0.0, 1.0));
+        assert!(merged.traverse_dyn(&ray, &triangles).is_empty());
+        let empty = BVH { nodes: Vec::new() };
+        assert_eq!(
+            BVH::merge(empty.clone(), single.clone(), 5, &triangles).nodes[0].shape_index(),
+            Some(5)
+        );
+        assert_eq!(
+            BVH::merge(single, empty, 5, &triangles).nodes[0].shape_index(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    /// Tests whether a `BVH` merged from a single leaf and a larger `BVH`, on either side,
+    /// bounds the leaf tightly, so that queries and later insertions behave like in a `BVH`
+    /// built over all shapes.
+    fn test_merge_single_leaf_then_insert() {
+        for single_first in [true, false] {
+            let mut boxes = (0..50)
+                .map(|id| UnitBox::new(id, Point3::new(id as f32 * 3.0, 0.0, 0.0)))
+                .collect::<Vec<_>>();
+            let mut bvh = if single_first {
+                BVH::merge(
+                    BVH::build_dyn(&boxes[..1]),
+                    BVH::build_dyn(&boxes[1..]),
+                    1,
+                    &boxes,
+                )
+            } else {
+                BVH::merge(
+                    BVH::build_dyn(&boxes[..49]),
+                    BVH::build_dyn(&boxes[49..]),
+                    49,
+                    &boxes,
+                )
+            };
+            let root_aabb = bvh.nodes[0].get_node_aabb(&boxes);
+            assert!(root_aabb.approx_eq(&AABB::from_bounded(&boxes), EPSILON));
+
+            // Boxes inserted next to the single leaf must not see infinite bounds.
+            for i in 0..50 {
+                let id = boxes.len() as i32;
+                let offset = if single_first { -3.0 } else { 150.0 };
+                let pos = Point3::new(offset + i as f32 * 0.25, 2.0, 0.0);
+                boxes.push(UnitBox::new(id, pos));
+                assert_eq!(bvh.insert(&boxes), boxes.len() - 1);
+            }
+            let root_aabb = bvh.nodes[0].get_node_aabb(&boxes);
+            assert!(root_aabb.surface_area().is_finite());
+            assert!(root_aabb.approx_eq(&AABB::from_bounded(&boxes), EPSILON));
+
+            for x in -2..52 {
+                let center = Point3::new(x as f32 * 3.0, 1.0, 0.0);
+                let query = AABB::with_bounds(center - Point3::ONE, center + Point3::ONE);
+                let mut expected = (0..boxes.len())
+                    .filter(|&i| boxes[i].aabb().intersects_aabb(&query))
+                    .collect::<Vec<_>>();
+                let mut found = bvh.query_aabb(&query);
+                found.sort_unstable();
+                expected.sort_unstable();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    /// Tests whether `map_shapes` gives the same `BVH` as building one over the transformed
+    /// shapes, and keeps their indices.
+    fn test_map_shapes() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let triangles = create_n_cubes(20, &bounds);
+        let bvh = BVH::build_dyn(&triangles);
+
+        let offset = Vector3::new(3.0, -2.0, 1.0);
+        let (moved_bvh, moved) = bvh.map_shapes(&triangles, |triangle| {
+            Triangle::new(
+                triangle.a + offset,
+                triangle.b + offset,
+                triangle.c + offset,
+            )
+        });
+        assert_eq!(moved.len(), triangles.len());
+        for (triangle, moved_triangle) in triangles.iter().zip(moved.iter()) {
+            assert_eq!(triangle.a + offset, moved_triangle.a);
+        }
+        assert_eq!(moved_bvh.nodes, BVH::build_dyn(&moved).nodes);
+
+        let mut hit_count = 0;
+        for x in -10..10 {
+            for y in -10..10 {
+                let origin = Point3::new(x as f32 + 0.5, y as f32 + 0.5, -20.0);
+                let ray = Ray::new(origin, Vector3::new(0.0, 0.0, 1.0));
+                let moved_ray = Ray::new(origin + offset, Vector3::new(0.0, 0.0, 1.0));
+                let hits = bvh.traverse_dyn(&ray, &triangles);
+                assert_eq!(hits, moved_bvh.traverse_dyn(&moved_ray, &moved));
+                hit_count += hits.len();
+            }
+        }
+        assert!(hit_count > 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "The BVH was not built from this slice of shapes.")]
+    /// Tests whether traversing with a slice shorter than the one used to build the `BVH`
+    /// is caught in debug builds.
+    fn test_traverse_wrong_shapes() {
+        let (shapes, bvh) = build_some_bh::<BVH>();
+        let ray = Ray::new(Point3::new(-20.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        bvh.traverse(&ray, &shapes[..shapes.len() / 2]);
+    }
+
+    #[test]
+    /// Tests whether `min_aabb_size` pads the bounds of flat triangles, so that rays running
+    /// in their plane find them.
+    fn test_build_with_min_aabb_size() {
+        // Axis-aligned triangles in the planes z = 0, 1, ..., 9.
+        let mut triangles = (0..10)
+            .map(|z| {
+                let z = z as f32;
+                Triangle::new(
+                    Point3::new(0.0, 0.0, z),
+                    Point3::new(1.0, 0.0, z),
+                    Point3::new(0.0, 1.0, z),
+                )
+            })
+            .collect::<Vec<_>>();
+        let config = BVHConfig {
+            min_aabb_size: 0.1,
+            ..Default::default()
+        };
+        let bvh = BVH::build_with_config(&mut triangles, &config);
+        bvh.assert_consistent(&triangles);
+
+        for node in &bvh.nodes {
+            if let BVHNode::Node {
+                ref child_l_aabb,
+                ref child_r_aabb,
+                ..
+            } = *node
+            {
+                assert!(child_l_aabb.size().min_element() >= 0.1 - EPSILON);
+                assert!(child_r_aabb.size().min_element() >= 0.1 - EPSILON);
+            }
+        }
+
+        // A ray within the plane z = 3, which is parallel to all triangles.
+        let ray = Ray::new(Point3::new(-1.0, 0.25, 3.0), Vector3::new(1.0, 0.0, 0.0));
+        let hits = bvh.traverse(&ray, &triangles);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].a.z, 3.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "must split into leaves of min_leaf_size")]
+    /// Tests whether a `min_leaf_size` which cannot be reached by splitting is rejected.
+    fn test_build_with_unreachable_min_leaf_size() {
+        let mut shapes = create_n_cubes(2, &default_bounds());
+        let config = BVHConfig {
+            max_leaf_size: 4,
+            min_leaf_size: 3,
+            ..Default::default()
+        };
+        BVH::build_with_config(&mut shapes, &config);
+    }
+
+    #[test]
+    #[should_panic(expected = "must split into leaves of min_leaf_size")]
+    /// Tests whether `BVHNode::build_with_config` rejects a `min_leaf_size` which cannot be
+    /// reached by splitting, before it splits any node.
+    fn test_build_node_with_unreachable_min_leaf_size() {
+        let mut shapes = create_n_cubes(2, &default_bounds());
+        let indices = (0..shapes.len()).collect::<Vec<_>>();
+        let config = BVHConfig {
+            max_leaf_size: 2,
+            min_leaf_size: 3,
+            ..Default::default()
+        };
+        BVHNode::build_with_config(
+            &mut shapes,
+            &indices,
+            &mut Vec::new(),
+            &mut Vec::new(),
+            0,
+            0,
+            &config,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Shape 3 has invalid bounds")]
+    /// Tests whether a shape with a `NaN` position is rejected before it corrupts the build.
+    fn test_build_with_nan_shape() {
+        let mut shapes = (0..5)
+            .map(|id| UnitBox::new(id, Point3::new(id as f32, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+        shapes[3].pos.y = f32::NAN;
+        BVH::build(&mut shapes);
+    }
+
+    #[test]
+    #[should_panic(expected = "Shape 2 has invalid bounds")]
+    /// Tests whether a shape with infinite bounds is rejected like one with `NaN` bounds.
+    fn test_build_with_infinite_shape() {
+        let mut shapes = (0..5)
+            .map(|id| UnitBox::new(id, Point3::new(id as f32, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+        shapes[2].pos.x = f32::INFINITY;
+        BVH::build(&mut shapes);
+    }
+
+    #[test]
+    /// Tests whether a `BVH` built from precomputed `AABB`s and sparse ids finds the ids of
+    /// the same boxes as a `BVH` built from the boxes themselves.
+    fn test_build_from_aabbs() {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let aabbs = (0..500)
+            .map(|i| {
+                let min = next_point3(&mut seed, &bounds);
+                (
+                    7 * i + 3,
+                    AABB::with_bounds(min, min + Point3::new(1.0, 2.0, 0.5)),
+                )
+            })
+            .collect::<Vec<_>>();
+        let boxes = aabbs.iter().map(|&(_, aabb)| aabb).collect::<Vec<_>>();
+        let bvh = BVH::build_from_aabbs(&aabbs);
+        let reference = BVH::build_dyn(&boxes);
+
+        let mut ids = bvh
+            .nodes
+            .iter()
+            .filter_map(|node| node.shape_index())
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        assert!(ids.iter().copied().eq(aabbs.iter().map(|&(id, _)| id)));
+
+        let mut hits = Vec::new();
+        for _ in 0..100 {
+            let origin = next_point3(&mut seed, &bounds);
+            let target = next_point3(&mut seed, &bounds);
+            let ray = Ray::new(origin, target - origin);
+            bvh.traverse_into(&ray, &mut hits);
+            let expected = reference
+                .traverse_dyn(&ray, &boxes)
+                .iter()
+                .map(|&index| aabbs[index].0)
+                .collect::<Vec<_>>();
+            assert_eq!(hits, expected);
+        }
+    }
+
+    #[test]
+    /// Tests whether a `BVH` built from a slice of `AABB`s refers to every box by its index,
+    /// and finds exactly the boxes which overlap a query.
+    fn test_build_from_aabb_slice() {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let boxes = (0..500)
+            .map(|_| {
+                let min = next_point3(&mut seed, &bounds);
+                AABB::with_bounds(min, min + Point3::new(1.0, 2.0, 0.5))
+            })
+            .collect::<Vec<_>>();
+        let bvh = BVH::build_from_aabb_slice(&boxes);
+
+        let mut indices = bvh
+            .nodes
+            .iter()
+            .filter_map(|node| node.shape_index())
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        assert!(indices.iter().copied().eq(0..boxes.len()));
+
+        for _ in 0..100 {
+            let center = next_point3(&mut seed, &bounds);
+            let query = AABB::with_bounds(center - Point3::splat(5.0), center + Point3::splat(5.0));
+            let mut found = bvh.query_aabb(&query);
+            found.sort_unstable();
+            let expected = (0..boxes.len())
+                .filter(|&i| boxes[i].intersects_aabb(&query))
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+        }
+        let single = BVH::build_from_aabb_slice(&boxes[..1]);
+        assert_eq!(single.nodes[0].shape_index(), Some(0));
+    }
+
+    #[test]
+    /// Tests whether `depth_at_shape` returns the depth of the leaf of every shape, also for
+    /// leaves with several shapes, and `None` for indices past the end.
+    fn test_depth_at_shape() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(50, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        for (index, triangle) in triangles.iter().enumerate() {
+            let leaf = &bvh.nodes[triangle.bh_node_index()];
+            assert_eq!(bvh.depth_at_shape(index), Some(leaf.depth() as usize));
+        }
+        assert_eq!(bvh.depth_at_shape(triangles.len()), None);
+
+        let config = BVHConfig {
+            max_leaf_size: 4,
+            ..Default::default()
+        };
+        let bvh = BVH::build_with_config(&mut triangles, &config);
+        for node in &bvh.nodes {
+            for index in node.shape_range().unwrap_or(0..0) {
+                assert_eq!(bvh.depth_at_shape(index), Some(node.depth() as usize));
+            }
+        }
+        assert_eq!(BVH { nodes: Vec::new() }.depth_at_shape(0), None);
+    }
+
+    #[test]
+    /// Tests whether `build_checked` reports the first invalid shape, and whether
+    /// `build_skipping_invalid` leaves out exactly the invalid shapes.
+    fn test_build_checked_and_skipping_invalid() {
+        for &max_leaf_size in &[1, 3] {
+            let config = BVHConfig {
+                max_leaf_size,
+                ..Default::default()
+            };
+            let mut shapes = (0..20)
+                .map(|id| UnitBox::new(id, Point3::new(id as f32 * 2.0, 0.0, 0.0)))
+                .collect::<Vec<_>>();
+            shapes[4].pos.y = f32::NAN;
+            shapes[11].pos.z = f32::INFINITY;
+            shapes[17].pos.x = f32::NEG_INFINITY;
+
+            let error = BVH::build_checked(&mut shapes, &config).unwrap_err();
+            assert_eq!(error.shape_index, 4);
+
+            let (bvh, skipped) = BVH::build_skipping_invalid(&mut shapes, &config);
+            let mut skipped_ids = skipped
+                .iter()
+                .map(|&index| shapes[index].id)
+                .collect::<Vec<_>>();
+            skipped_ids.sort_unstable();
+            assert_eq!(skipped_ids, vec![4, 11, 17]);
+
+            let everything = AABB::with_bounds(Point3::splat(-100.0), Point3::splat(100.0));
+            let mut found = bvh
+                .query_aabb(&everything)
+                .iter()
+                .map(|&index| shapes[index].id)
+                .collect::<Vec<_>>();
+            found.sort_unstable();
+            let expected = (0..20)
+                .filter(|id| ![4, 11, 17].contains(id))
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+
+            if max_leaf_size > 1 {
+                assert_eq!(skipped, vec![17, 18, 19]);
+                bvh.assert_consistent(&shapes[..17]);
+                bvh.assert_tight(&shapes[..17]);
+            }
+        }
+    }
+
+    #[test]
+    /// Tests whether a `BVH` can be built over boxed trait objects of different shape types,
+    /// and traversed with them directly.
+    fn test_boxed_trait_object_shapes() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let triangles = create_n_cubes(20, &bounds);
+        let mut shapes = triangles
+            .iter()
+            .enumerate()
+            .map(|(index, triangle)| -> Box<dyn BHShape> {
+                if index % 2 == 0 {
+                    Box::new(Triangle::new(triangle.a, triangle.b, triangle.c))
+                } else {
+                    Box::new(UnitBox::new(index as i32, triangle.a))
+                }
+            })
+            .collect::<Vec<_>>();
+        let bvh = BVH::build(&mut shapes);
+        bvh.assert_consistent(&shapes);
+
+        // A `BVH` of `Box<dyn Bounded>` built with `build_dyn` finds the same shapes.
+        let bounded = shapes
+            .iter()
+            .map(|shape| -> Box<dyn Bounded> { Box::new(shape.aabb()) })
+            .collect::<Vec<_>>();
+        let dyn_bvh = BVH::build_dyn(&bounded);
+
+        let mut hit_count = 0;
+        for x in -10..10 {
+            let ray = Ray::new(
+                Point3::new(x as f32, 0.0, -20.0),
+                Vector3::new(0.0, 0.0, 1.0),
+            );
+            let hits = bvh.traverse(&ray, &shapes);
+            let dyn_hits = dyn_bvh.traverse(&ray, &bounded);
+            assert_eq!(hits.len(), dyn_hits.len());
+            for (hit, dyn_hit) in hits.iter().zip(&dyn_hits) {
+                assert!(hit.aabb().approx_eq(&dyn_hit.aabb(), 0.0));
+            }
+            hit_count += hits.len();
+        }
+        assert!(hit_count > 0);
+    }
+
+    #[test]
+    /// Tests whether `traverse_nearest` intersects far fewer shapes than there are candidates
+    /// in a dense scene.
+    fn test_traverse_nearest_culls_far_shapes() {
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::splat(5.0));
+        let mut triangles = create_n_cubes(2000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        let intersect = |triangle: &Triangle, ray: &Ray| {
+            let distance = ray
+                .intersects_triangle(&triangle.a, &triangle.b, &triangle.c)
+                .distance;
+            Some(distance).filter(|distance| distance.is_finite())
+        };
+        let mut candidates = 0;
+        let mut calls = 0;
+        let mut hits = 0;
+        for y in -5..5 {
+            let ray = Ray::new(
+                Point3::new(-30.0, y as f32, 0.5),
+                Vector3::new(1.0, 0.0, 0.0),
+            );
+            let expected = bvh
+                .traverse(&ray, &triangles)
+                .iter()
+                .inspect(|_| candidates += 1)
+                .filter_map(|triangle| intersect(triangle, &ray))
+                .reduce(f32::min);
+            let nearest = bvh.traverse_nearest(&ray, &triangles, |triangle, ray| {
+                calls += 1;
+                intersect(triangle, ray)
+            });
+            assert_eq!(nearest.map(|(_, distance)| distance), expected);
+            hits += nearest.is_some() as usize;
+        }
+        assert!(hits > 0);
+        assert!(
+            calls * 4 < candidates,
+            "{} calls for {} candidates",
+            calls,
+            candidates
+        );
+    }
+
+    #[test]
+    /// Tests whether the closest hit queries work on boxed `IntersectableShape` trait objects
+    /// of different types.
+    fn test_nearest_hit_boxed_trait_objects() {
+        let shapes = (0..50)
+            .map(|i| -> Box<dyn IntersectableShape> {
+                let position = Point3::new(i as f32 * 2.0, (i % 3) as f32, 0.0);
+                if i % 2 == 0 {
+                    Box::new(UnitBox::new(i, position))
+                } else {
+                    Box::new(AABB::with_bounds(position, position + Vector3::ONE))
+                }
+            })
+            .collect::<Vec<_>>();
+        let bvh = BVH::build_dyn(&shapes);
+
+        for x in -2..100 {
+            let ray = Ray::new(
+                Point3::new(x as f32, 0.5, -10.0),
+                Vector3::new(0.0, 0.0, 1.0),
+            );
+            let expected = shapes
+                .iter()
+                .enumerate()
+                .filter_map(|(index, shape)| shape.intersect(&ray).map(|t| (index, t)))
+                .reduce(|best, hit| if hit.1 < best.1 { hit } else { best });
+            let nearest = bvh.nearest_hit(&ray, &shapes);
+            assert_eq!(nearest.map(|(_, t)| t), expected.map(|(_, t)| t));
+            let found = bvh.traverse_nearest(&ray, &shapes, |shape, ray| shape.intersect(ray));
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    /// Tests whether `traverse_into` reuses the buffer it is given.
+    fn test_traverse_into_reuses_buffer() {
+        let (shapes, bvh) = build_some_bh::<BVH>();
+        let mut out = Vec::with_capacity(shapes.len());
+        let capacity = out.capacity();
+
+        let ray = Ray::new(Point3::new(-20.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        bvh.traverse_into(&ray, &mut out);
+        assert!(!out.is_empty());
+
+        // This ray passes above all boxes, so the previous results must be cleared.
+        let ray = Ray::new(Point3::new(-20.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        bvh.traverse_into(&ray, &mut out);
+        assert!(out.is_empty());
+        assert_eq!(out.capacity(), capacity);
+    }
+
+    #[test]
+    /// Tests whether `traverse_into` and the iterators handle trees deeper than their
+    /// fixed size stack.
+    fn test_traverse_into_deep_bvh() {
+        // A chain of inner nodes, each with a leaf as its right child.
+        let depth = 100;
+        let aabb = AABB::with_bounds(Point3::splat(-1.0), Point3::splat(1.0));
+        let mut nodes = Vec::new();
+        for level in 0..depth {
+            nodes.push(BVHNode::Node {
+                parent_index: level.max(1) * 2 - 2,
+                depth: level as u32,
+                child_l_aabb: aabb,
+                child_l_index: level * 2 + 2,
+                child_r_aabb: aabb,
+                child_r_index: level * 2 + 1,
+            });
+            nodes.push(BVHNode::Leaf {
+                parent_index: level * 2,
+                depth: level as u32 + 1,
+                shape_index: level,
+                shape_count: 1,
+            });
+        }
+        nodes.push(BVHNode::Leaf {
+            parent_index: depth * 2 - 2,
+            depth: depth as u32,
+            shape_index: depth,
+            shape_count: 1,
+        });
+        let bvh = BVH { nodes };
+        let shapes = (0..=depth as i32)
+            .map(|id| UnitBox::new(id, Point3::ZERO))
+            .collect::<Vec<_>>();
+
+        let ray = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let expected = bvh
+            .traverse(&ray, &shapes)
+            .iter()
+            .map(|shape| shape.id)
+            .collect::<Vec<_>>();
+        let mut out = Vec::new();
+        bvh.traverse_into(&ray, &mut out);
+        let found = out
+            .iter()
+            .map(|&index| shapes[index].id)
+            .collect::<Vec<_>>();
+        assert_eq!(found.len(), shapes.len());
+        assert_eq!(expected, found);
+
+        // The iterators share the traversal, and must not run out of stack either.
+        assert!(bvh.traverse_iter(&ray).eq(out.iter().copied()));
+        assert_eq!(bvh.traverse_iterator(&ray, &shapes).count(), shapes.len());
+    }
+
+    proptest! {
+        // Test whether `traverse_into` and `traverse_into_slice` yield the same shapes, in the
+        // same order, as `traverse`, with one or several shapes per leaf, and whether a slice
+        // which is too small receives a prefix of them.
+        #[test]
+        fn test_traverse_into_matches_traverse(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            aim in ray_toward_random_shape(200.0, 0.0),
+            max_leaf_size in 1usize..5,
+            slice_len in 0usize..70,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let ray = aim.ray(&shapes);
+
+            let expected = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            let mut out = Vec::new();
+            bvh.traverse_into(&ray, &mut out);
+            let found = out.iter().map(|&index| shapes[index].id).collect::<Vec<_>>();
+            assert_eq!(expected, found);
+
+            let mut slice = vec![usize::MAX; slice_len];
+            match bvh.traverse_into_slice(&ray, &mut slice) {
+                Ok(count) => {
+                    assert!(count <= slice_len);
+                    assert_eq!(&slice[..count], &out[..]);
+                }
+                Err(needed) => {
+                    assert!(needed > slice_len);
+                    assert_eq!(needed, out.len());
+                    assert_eq!(&slice[..], &out[..slice_len]);
+                }
+            }
+        }
+
+        // Test whether `traverse_iter` yields the shapes of `traverse` in the same order, also
+        // when the iterator is dropped early, and whether `traverse_iterator` agrees.
+        #[test]
+        fn test_traverse_iter_matches_traverse(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            aim in ray_toward_random_shape(200.0, 0.0),
+            max_leaf_size in 1usize..5,
+            take in 0usize..8,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let ray = aim.ray(&shapes);
+
+            let expected = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            let found = bvh.traverse_iter(&ray).map(|index| shapes[index].id).collect::<Vec<_>>();
+            assert_eq!(&expected, &found);
+
+            let prefix = bvh.traverse_iter(&ray).take(take).map(|index| shapes[index].id).collect::<Vec<_>>();
+            assert_eq!(&expected[..take.min(expected.len())], &prefix[..]);
+
+            let iterated = bvh.traverse_iterator(&ray, &shapes).map(|shape| shape.id).collect::<Vec<_>>();
+            assert_eq!(expected, iterated);
+        }
+    }
+
+    proptest! {
+        // Test whether `query_aabb` finds every shape overlapping the query box without
+        // duplicates, and exactly those with one shape per leaf.
+        #[test]
+        fn test_query_aabb_matches_brute_force(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 2..64),
+            a in (-120.0f32..120.0, -120.0f32..120.0, -120.0f32..120.0),
+            b in (-120.0f32..120.0, -120.0f32..120.0, -120.0f32..120.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let query = AABB::empty()
+                .grow(&Point3::new(a.0, a.1, a.2))
+                .grow(&Point3::new(b.0, b.1, b.2));
+
+            let expected = (0..shapes.len())
+                .filter(|&index| query.intersects_aabb(&shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            let mut found = bvh.query_aabb(&query);
+            found.sort_unstable();
+            let mut unique = found.clone();
+            unique.dedup();
+            assert_eq!(&found, &unique);
+            assert!(expected.iter().all(|index| found.binary_search(index).is_ok()));
+            if max_leaf_size == 1 {
+                assert_eq!(&expected, &found);
+            }
+
+            let mut out = vec![usize::MAX];
+            bvh.query_aabb_into(&query, &mut out);
+            assert_eq!(out, bvh.query_aabb(&query));
+        }
+    }
+
+    proptest! {
+        // Test `traverse_predicate` with a custom query volume: the slab between two parallel
+        // planes `offset <= normal . x <= offset + thickness`.
+        #[test]
+        fn test_traverse_predicate_slab(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 2..64),
+            normal in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+            offset in -150.0f32..150.0,
+            thickness in 0.0f32..50.0,
+        ) {
+            let normal = tuple_to_point(&normal);
+            prop_assume!(normal.length() > 0.1);
+            let normal = normal.normalize();
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build(&mut shapes);
+
+            // The interval of `normal . x` over the corners of `aabb` overlaps the slab.
+            let in_slab = |aabb: &AABB| {
+                let center = normal.dot(aabb.center());
+                let radius = normal.abs().dot(aabb.size()) / 2.0;
+                center + radius >= offset && center - radius <= offset + thickness
+            };
+
+            let expected = (0..shapes.len())
+                .filter(|&index| in_slab(&shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            let candidates = bvh.traverse_predicate(in_slab);
+            let mut found = candidates
+                .iter()
+                .copied()
+                .filter(|&index| in_slab(&shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            found.sort_unstable();
+            assert_eq!(expected, found);
+
+            let mut visited = Vec::new();
+            bvh.traverse_predicate_with(in_slab, |index| visited.push(index));
+            assert_eq!(visited, candidates);
+
+            // Accepting every node visits every shape once.
+            let mut all = bvh.traverse_predicate(|_| true);
+            all.sort_unstable();
+            assert!(all.iter().copied().eq(0..shapes.len()));
+        }
+    }
+
+    /// Finds all pairs of overlapping `AABB`s of `shapes` by testing every pair.
+    fn overlapping_pairs_brute_force<Shape: Bounded>(shapes: &[Shape]) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..shapes.len() {
+            for j in i + 1..shapes.len() {
+                if shapes[i].aabb().intersects_aabb(&shapes[j].aabb()) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    #[test]
+    /// Tests `overlapping_pairs` against the brute force on a few thousand shapes.
+    fn test_overlapping_pairs_many_shapes() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let triangles = create_n_cubes(250, &bounds);
+        let bvh = BVH::build_dyn(&triangles);
+
+        let expected = overlapping_pairs_brute_force(&triangles);
+        assert!(expected.len() > triangles.len());
+        assert_eq!(bvh.overlapping_pairs(&triangles), expected);
+    }
+
+    #[test]
+    /// Tests `overlapping_pairs_with` for a single shape against a large `BVH`, in both
+    /// directions.
+    fn test_overlapping_pairs_with_single_shape() {
+        let bounds = AABB::with_bounds(Point3::splat(-10.0), Point3::splat(10.0));
+        let triangles = create_n_cubes(1000, &bounds);
+        let bvh = BVH::build_dyn(&triangles);
+        let probe = vec![AABB::with_bounds(Point3::splat(-1.0), Point3::splat(1.0))];
+        let probe_bvh = BVH::build_dyn(&probe);
+
+        let expected = (0..triangles.len())
+            .filter(|&j| probe[0].intersects_aabb(&triangles[j].aabb()))
+            .map(|j| (0, j))
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(
+            probe_bvh.overlapping_pairs_with(&probe, &bvh, &triangles),
+            expected
+        );
+
+        let swapped = expected.iter().map(|&(i, j)| (j, i)).collect::<Vec<_>>();
+        assert_eq!(
+            bvh.overlapping_pairs_with(&triangles, &probe_bvh, &probe),
+            swapped
+        );
+    }
+
+    proptest! {
+        // Test whether `overlapping_pairs` finds exactly the overlapping pairs, each once,
+        // with one or several shapes per leaf.
+        #[test]
+        fn test_overlapping_pairs_matches_brute_force(
+            positions in prop::collection::vec((-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0), 1..200),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+
+            let expected = overlapping_pairs_brute_force(&shapes);
+            assert_eq!(bvh.overlapping_pairs(&shapes), expected);
+
+            let mut visited = 0;
+            bvh.for_each_overlapping_pair(&shapes, |i, j| {
+                assert!(i < j);
+                visited += 1;
+            });
+            assert_eq!(visited, expected.len());
+        }
+
+        // Test whether `overlapping_pairs_with` finds exactly the overlapping pairs of
+        // the cross product of two sets of shapes.
+        #[test]
+        fn test_overlapping_pairs_with_matches_brute_force(
+            positions in prop::collection::vec((-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0), 1..200),
+            other_positions in prop::collection::vec((-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0), 1..20),
+            max_leaf_size in 1usize..5,
+        ) {
+            let config = BVHConfig {
+                max_leaf_size,
+                ..Default::default()
+            };
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build_with_config(&mut shapes, &config);
+            let mut other_shapes = unit_boxes(&other_positions);
+            let other = BVH::build_with_config(&mut other_shapes, &config);
+
+            let mut expected = Vec::new();
+            for (i, shape) in shapes.iter().enumerate() {
+                for (j, other_shape) in other_shapes.iter().enumerate() {
+                    if shape.aabb().intersects_aabb(&other_shape.aabb()) {
+                        expected.push((i, j));
+                    }
+                }
+            }
+            assert_eq!(bvh.overlapping_pairs_with(&shapes, &other, &other_shapes), expected);
+
+            let mut swapped = other.overlapping_pairs_with(&other_shapes, &bvh, &shapes);
+            swapped.iter_mut().for_each(|pair| *pair = (pair.1, pair.0));
+            swapped.sort_unstable();
+            assert_eq!(swapped, expected);
+
+            let mut visited = Vec::new();
+            bvh.for_each_overlapping_pair_with(&shapes, &other, &other_shapes, |i, j| {
+                visited.push((i, j));
+            });
+            visited.sort_unstable();
+            assert_eq!(visited, expected);
+        }
+    }
+
+    #[test]
+    /// Tests that a box moving past another one is not reported, while one clipping its
+    /// corner is, although both pass through the union of their start and end positions.
+    fn test_query_moving_aabb_pass_and_clip() {
+        let shapes = [
+            AABB::with_bounds(Point3::new(4.0, 0.0, 0.0), Point3::new(5.0, 1.0, 1.0)),
+            AABB::with_bounds(Point3::new(20.0, -20.0, 0.0), Point3::new(21.0, -19.0, 1.0)),
+        ];
+        let bvh = BVH::build_dyn(&shapes);
+        let start = AABB::with_bounds(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 1.0, 1.0));
+
+        // Passes above the corner at (4, 1): at x = 3, the box already is at y = 3.
+        let passing = Vector3::new(10.0, 10.0, 0.0);
+        assert!(bvh.query_moving_aabb(&start, &passing).is_empty());
+        assert!(!moving_aabb_touches(&start, &passing, &shapes[0]));
+
+        // Reaches x = 3 at y = 0.9, so it clips the corner of the obstacle.
+        let clipping = Vector3::new(10.0, 3.0, 0.0);
+        assert_eq!(bvh.query_moving_aabb(&start, &clipping), vec![0]);
+
+        // Stops just before the obstacle.
+        let short = Vector3::new(2.9, 0.0, 0.0);
+        assert!(bvh.query_moving_aabb(&start, &short).is_empty());
+    }
+
+    proptest! {
+        // Test whether `query_moving_aabb` finds exactly the shapes which the moving box
+        // touches at one of many sampled times, and whether it reduces to `query_aabb`
+        // without a displacement.
+        #[test]
+        fn test_query_moving_aabb_matches_sampling(
+            positions in prop::collection::vec((-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0), 1..64),
+            start in (-15.0f32..15.0, -15.0f32..15.0, -15.0f32..15.0),
+            size in (0.0f32..3.0, 0.0f32..3.0, 0.0f32..3.0),
+            displacement in (-20.0f32..20.0, -20.0f32..20.0, -20.0f32..20.0),
+        ) {
+            let shapes = unit_boxes(&positions);
+            let bvh = BVH::build_dyn(&shapes);
+            let start = Point3::new(start.0, start.1, start.2);
+            let aabb = AABB::with_bounds(start, start + Vector3::new(size.0, size.1, size.2));
+            let displacement = Vector3::new(displacement.0, displacement.1, displacement.2);
+
+            let mut found = bvh.query_moving_aabb(&aabb, &displacement);
+            found.sort_unstable();
+            let expected = (0..shapes.len())
+                .filter(|&index| moving_aabb_touches(&aabb, &displacement, &shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            if shapes.len() > 1 {
+                // A single leaf is always reported.
+                assert_eq!(&found, &expected);
+            }
+
+            // Every overlap at a sampled time must be found. Shrinking the shapes a bit keeps
+            // rounding errors out of the comparison.
+            for step in 0..=100 {
+                let offset = displacement * (step as f32 / 100.0);
+                let moved = AABB::with_bounds(aabb.min + offset, aabb.max + offset);
+                for (index, shape) in shapes.iter().enumerate() {
+                    let shrunk = AABB::with_bounds(
+                        shape.aabb().min + Vector3::splat(1e-3),
+                        shape.aabb().max - Vector3::splat(1e-3),
+                    );
+                    if moved.intersects_aabb(&shrunk) {
+                        assert!(found.contains(&index));
+                    }
+                }
+            }
+
+            let resting = bvh.query_moving_aabb(&aabb, &Vector3::ZERO);
+            assert_eq!(resting, bvh.query_aabb(&aabb));
+        }
+
+        // Test whether `rebuild_region` repairs the `BVH` after shapes moved inside of the
+        // region, so that `AABB` queries find the shapes at their new positions, and whether
+        // the nodes stay linked correctly.
+        #[test]
+        fn test_rebuild_region_matches_brute_force(
+            positions in prop::collection::vec((-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0), 1..64),
+            offsets in prop::collection::vec((0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0), 64),
+            center in (-10.0f32..10.0, -10.0f32..10.0, -10.0f32..10.0),
+            half_size in 1.0f32..8.0,
+            query in ((-12.0f32..12.0, -12.0f32..12.0, -12.0f32..12.0), 0.0f32..6.0),
+            max_leaf_size in 1usize..4,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let mut bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+
+            // Move every shape which lies inside of the region to another place inside of it.
+            let center = Point3::new(center.0, center.1, center.2);
+            let region = AABB::with_bounds(center - Vector3::splat(half_size), center + Vector3::splat(half_size));
+            let free = AABB::with_bounds(region.min + Vector3::splat(0.5), region.max - Vector3::splat(0.5));
+            for (shape, offset) in shapes.iter_mut().zip(&offsets) {
+                if free.contains(&shape.pos) {
+                    shape.pos = free.min + free.size() * Vector3::new(offset.0, offset.1, offset.2);
+                }
+            }
+            bvh.rebuild_region(&shapes, &region);
+
+            // Every shape is referenced by exactly one leaf, and children know their parents.
+            let mut referenced = vec![0; shapes.len()];
+            for (node_index, node) in bvh.nodes.iter().enumerate() {
+                match *node {
+                    BVHNode::Node { child_l_index, child_r_index, depth, .. } => {
+                        for child_index in [child_l_index, child_r_index] {
+                            assert_eq!(bvh.nodes[child_index].parent(), node_index);
+                            assert_eq!(bvh.nodes[child_index].depth(), depth + 1);
+                        }
+                    }
+                    BVHNode::Leaf { shape_index, shape_count, .. } => {
+                        for count in &mut referenced[shape_index..shape_index + shape_count] {
+                            *count += 1;
+                        }
+                    }
+                }
+            }
+            assert!(referenced.iter().all(|&count| count == 1));
+
+            let query_center = Point3::new((query.0).0, (query.0).1, (query.0).2);
+            let query = AABB::with_bounds(query_center - Vector3::splat(query.1), query_center + Vector3::splat(query.1));
+            let expected = (0..shapes.len())
+                .filter(|&index| query.intersects_aabb(&shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            let mut found = bvh.query_aabb(&query);
+            found.sort_unstable();
+            assert!(expected.iter().all(|index| found.binary_search(index).is_ok()));
+        }
+    }
+
+    proptest! {
+        // Test whether `nearest_k` finds the same shapes as sorting all shapes by the
+        // distance of their `AABB`s.
+        #[test]
+        fn test_nearest_k_matches_brute_force(
+            positions in prop::collection::vec(tuplevec_grid_strategy(20), 1..60),
+            p in (-25i32..25, -25i32..25, -25i32..25),
+            k in 0usize..80,
+            max_distance in prop_oneof![Just(f32::INFINITY), 0.0f32..20.0],
+            max_leaf_size in 1usize..5,
+        ) {
+            // Integer coordinates produce many ties, which must be broken by index.
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let p = Point3::new(p.0 as f32, p.1 as f32, p.2 as f32);
+
+            let mut expected = (0..shapes.len())
+                .map(|index| (index, shapes[index].aabb().distance_squared_to_point(&p).sqrt()))
+                .filter(|&(_, distance)| distance <= max_distance)
+                .collect::<Vec<_>>();
+            expected.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+            expected.truncate(k);
+            assert_eq!(bvh.nearest_k(&p, &shapes, k, max_distance), expected);
+        }
+    }
+
+    proptest! {
+        // Test whether `query_point` and `find_containing` find exactly the shapes whose
+        // `AABB`s contain the point, including points on their boundaries.
+        #[test]
+        fn test_query_point_matches_brute_force(
+            positions in prop::collection::vec(tuplevec_grid_strategy(5), 1..100),
+            p in (-12i32..12, -12i32..12, -12i32..12),
+            max_leaf_size in 1usize..5,
+        ) {
+            // Boxes and the point on a half-unit grid, so that the point often lies on a face.
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let p = Point3::new(p.0 as f32, p.1 as f32, p.2 as f32) * 0.5;
+
+            let expected = (0..shapes.len())
+                .filter(|&index| shapes[index].aabb().contains(&p))
+                .collect::<Vec<_>>();
+            let mut found = bvh.query_point(&shapes, &p);
+            found.sort_unstable();
+            assert_eq!(expected, found);
+
+            let mut out = vec![usize::MAX];
+            bvh.find_containing_into(&shapes, &p, &mut out);
+            assert_eq!(out, bvh.find_containing(&shapes, &p));
+            assert_eq!(out, bvh.query_point(&shapes, &p));
+        }
+    }
+
+    proptest! {
+        // Test whether `query_frustum` finds exactly the shapes which a brute force search
+        // finds.
+        #[test]
+        fn test_query_frustum_matches_brute_force(
+            positions in prop::collection::vec((-50.0f32..50.0, -50.0f32..50.0, -50.0f32..50.0), 1..100),
+            eye in (-60.0f32..60.0, -60.0f32..60.0, -60.0f32..60.0),
+            fov in 0.2f32..2.5,
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let eye = Point3::new(eye.0, eye.1, eye.2);
+            prop_assume!(eye.length() > 1.0);
+            let view = Mat4::look_at_rh(eye, Point3::ZERO, Vector3::Y);
+            let projection = Mat4::perspective_rh(fov, 1.5, 0.5, 80.0);
+            let frustum = Frustum::from_view_projection(&(projection * view));
+
+            let expected = (0..shapes.len())
+                .filter(|&index| frustum.intersects_aabb(&shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            let mut found = bvh.query_frustum(&shapes, &frustum);
+            found.sort_unstable();
+            assert_eq!(expected, found);
+        }
+    }
+
+    proptest! {
+        // Test whether `leaves_contained_in` finds exactly the shapes inside the query box,
+        // with one or several shapes per leaf.
+        #[test]
+        fn test_leaves_contained_in_matches_brute_force(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            a in (-120.0f32..120.0, -120.0f32..120.0, -120.0f32..120.0),
+            b in (-120.0f32..120.0, -120.0f32..120.0, -120.0f32..120.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let query = AABB::empty()
+                .grow(&Point3::new(a.0, a.1, a.2))
+                .grow(&Point3::new(b.0, b.1, b.2));
+
+            let mut expected = (0..shapes.len())
+                .filter(|&index| query.contains_aabb(&shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            let mut found = bvh.leaves_contained_in(&shapes, &query);
+            expected.sort_unstable();
+            found.sort_unstable();
+            assert_eq!(expected, found);
+
+            // The whole scene is contained in its own bounds.
+            let bounds = shapes.iter().fold(AABB::empty(), |aabb, shape| aabb.join(&shape.aabb()));
+            assert_eq!(bvh.leaves_contained_in(&shapes, &bounds).len(), shapes.len());
+        }
+    }
+
+    proptest! {
+        // Test whether `traversal_stats` counts as many candidates as `traverse` returns,
+        // and whether the numbers of tests are consistent with the structure of the `BVH`.
+        // `traverse_instrumented` must return the same stats and the shapes of `traverse`.
+        #[test]
+        fn test_traversal_stats_match_traverse(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            aim in ray_toward_random_shape(200.0, 0.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build(&mut shapes);
+            let ray = aim.ray(&shapes);
+
+            let stats = bvh.traversal_stats(&ray, &shapes);
+            assert_eq!(stats.candidates, bvh.traverse(&ray, &shapes).len());
+            assert_eq!(stats.aabb_tests, 2 * stats.nodes_visited);
+
+            let (hits, instrumented) = bvh.traverse_instrumented(&ray, &shapes);
+            assert_eq!(instrumented, stats);
+            let expected = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            assert_eq!(hits.iter().map(|shape| shape.id).collect::<Vec<_>>(), expected);
+            assert!(stats.leaf_tests <= stats.aabb_tests + 1);
+            assert!(stats.aabb_tests < 2 * shapes.len());
+            if shapes.len() > 1 {
+                assert!(stats.aabb_tests >= 2);
+            }
+        }
+    }
+
+    proptest! {
+        // Test whether `nearest_hit` finds the same distance as a brute force search.
+        #[test]
+        fn test_nearest_hit_matches_brute_force(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            aim in ray_toward_random_shape(200.0, 2.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build(&mut shapes);
+
+            let ray = aim.ray(&shapes);
+
+            let expected = shapes
+                .iter()
+                .filter_map(|shape| shape.intersect(&ray))
+                .fold(None, |best: Option<f32>, t| Some(best.map_or(t, |best| best.min(t))));
+            let found = bvh.nearest_hit(&ray, &shapes);
+
+            assert_eq!(found.map(|(_, distance)| distance), expected);
+            if let Some((shape_index, distance)) = found {
+                assert_eq!(shapes[shape_index].intersect(&ray), Some(distance));
+            }
+        }
+    }
+
+    proptest! {
+        // Test whether `traverse_all_sorted` finds every hit of a brute force search, once and
+        // sorted by distance.
+        #[test]
+        fn test_traverse_all_sorted_matches_brute_force(
+            positions in prop::collection::vec(tuplevec_grid_strategy(8), 1..64),
+            aim in ray_toward_random_shape(20.0, 0.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let ray = aim.ray(&shapes);
+
+            let mut expected = shapes
+                .iter()
+                .enumerate()
+                .filter_map(|(index, shape)| shape.intersect(&ray).map(|t| (index, RaycastResult::Hit(t))))
+                .collect::<Vec<_>>();
+            expected.sort_by_key(|&(index, result)| (result, index));
+            let found = bvh.traverse_all_sorted(&ray, &shapes);
+            assert_eq!(found, expected);
+            assert!(!found.is_empty());
+        }
+    }
+
+    #[test]
+    /// Tests whether `traverse_clamped` skips a box before `t_min`, keeps one straddling
+    /// `t_min` and returns nothing beyond `t_max`.
+    fn test_traverse_clamped_interval() {
+        // Unit boxes centered at x = 0, 3, .., 27, put into leaves of several boxes.
+        let mut boxes = (0..10)
+            .map(|id| UnitBox::new(id, Point3::new(id as f32 * 3.0, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+        let config = BVHConfig {
+            max_leaf_size: 3,
+            ..Default::default()
+        };
+        let bvh = BVH::build_with_config(&mut boxes, &config);
+        let ray = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vector3::X);
+        let ids = |hits: Vec<(usize, f32)>| {
+            let mut ids = hits
+                .iter()
+                .map(|&(index, _)| boxes[index].id)
+                .collect::<Vec<_>>();
+            ids.sort_unstable();
+            ids
+        };
+
+        // Box 0 spans [9.5, 10.5] and box 1 spans [12.5, 13.5] along the ray. Box 1 straddles
+        // `t_min`, so its subtree is visited, but its hit at 12.5 lies before `t_min`. Box 3 is
+        // entered at 18.5, beyond `t_max`.
+        let hits = bvh.traverse_clamped(&ray, 13.0, 17.0, &boxes);
+        assert_eq!(ids(hits.clone()), vec![2]);
+        assert!(hits
+            .iter()
+            .all(|&(_, distance)| (13.0..=17.0).contains(&distance)));
+
+        // A box containing the origin is hit at `0.0`, which a small `t_min` rejects.
+        let ray = Ray::new(Point3::new(3.0, 0.0, 0.0), Vector3::X);
+        assert_eq!(
+            ids(bvh.traverse_clamped(&ray, 0.0, 4.0, &boxes)),
+            vec![1, 2]
+        );
+        assert_eq!(ids(bvh.traverse_clamped(&ray, 1e-3, 4.0, &boxes)), vec![2]);
+
+        // Box 4 is entered at 8.5, beyond `t_max`, and an empty interval finds nothing.
+        let hits = bvh.traverse_clamped(&ray, 1e-3, 8.0, &boxes);
+        assert_eq!(ids(hits), vec![2, 3]);
+        assert!(bvh.traverse_clamped(&ray, 5.0, 4.0, &boxes).is_empty());
+    }
+
+    proptest! {
+        // Test whether `traverse_clamped` with the default interval finds the hits of the
+        // candidates of `traverse_into`, and with any interval only the hits inside of it.
+        #[test]
+        fn test_traverse_clamped_matches_traverse(
+            positions in prop::collection::vec((-8.0f32..8.0, -8.0f32..8.0, -8.0f32..8.0), 1..64),
+            origin in (-12.0f32..12.0, -12.0f32..12.0, -12.0f32..12.0),
+            direction in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+            t_min in 0.0f32..10.0,
+            length in 0.0f32..10.0,
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let ray = Ray::new(tuple_to_point(&origin), tuple_to_point(&direction));
+
+            let mut candidates = Vec::new();
+            bvh.traverse_into(&ray, &mut candidates);
+            let expected = candidates
+                .iter()
+                .filter_map(|&index| shapes[index].intersect(&ray).map(|distance| (index, distance)))
+                .collect::<Vec<_>>();
+            assert_eq!(bvh.traverse_clamped(&ray, 0.0, f32::INFINITY, &shapes), expected);
+
+            let t_max = t_min + length;
+            let clamped = expected
+                .iter()
+                .copied()
+                .filter(|&(_, distance)| distance >= t_min && distance <= t_max)
+                .collect::<Vec<_>>();
+            assert_eq!(bvh.traverse_clamped(&ray, t_min, t_max, &shapes), clamped);
+        }
+    }
+
+    #[test]
+    /// Tests whether visiting the nearer child first finds the same closest hit as visiting
+    /// the left child first, but visits far fewer nodes once a hit is found.
+    fn test_nearest_hit_near_first_visits_fewer_nodes() {
+        let mut boxes = (0..64)
+            .map(|id| UnitBox::new(id, Point3::new(id as f32 * 2.0, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+        let bvh = BVH::build(&mut boxes);
+
+        // The boxes at small x end up in the left subtrees, so looking from beyond the last
+        // box visits the far boxes first when ignoring the order of the children.
+        let ray = Ray::new(Point3::new(200.0, 0.0, 0.0), -Vector3::X);
+        let (left_first, left_first_stats) =
+            bvh.nearest_hit_counted(&ray, &boxes, TraversalOrder::LeftFirst);
+        let (near_first, near_first_stats) =
+            bvh.nearest_hit_counted(&ray, &boxes, TraversalOrder::NearFirst);
+
+        assert_eq!(left_first, near_first);
+        assert_eq!(boxes[near_first.unwrap().0].id, 63);
+        assert_eq!(bvh.nearest_hit(&ray, &boxes), near_first);
+        assert!(near_first_stats.nodes_visited * 4 < left_first_stats.nodes_visited);
+        assert!(near_first_stats.leaf_tests < left_first_stats.leaf_tests);
+
+        // Looking the other way, the left child is the near one anyway.
+        let ray = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vector3::X);
+        let (_, left_first_stats) =
+            bvh.nearest_hit_counted(&ray, &boxes, TraversalOrder::LeftFirst);
+        let (_, near_first_stats) =
+            bvh.nearest_hit_counted(&ray, &boxes, TraversalOrder::NearFirst);
+        assert_eq!(near_first_stats, left_first_stats);
+    }
+
+    proptest! {
+        // Test whether `traverse_ordered_into` finds the candidates of `traverse_into` in
+        // either order.
+        #[test]
+        fn test_traverse_ordered_into_matches_traverse_into(
+            positions in prop::collection::vec((-8.0f32..8.0, -8.0f32..8.0, -8.0f32..8.0), 1..64),
+            origin in (-12.0f32..12.0, -12.0f32..12.0, -12.0f32..12.0),
+            direction in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let ray = Ray::new(tuple_to_point(&origin), tuple_to_point(&direction));
+
+            let mut expected = Vec::new();
+            bvh.traverse_into(&ray, &mut expected);
+            let mut out = Vec::new();
+            bvh.traverse_ordered_into(&ray, TraversalOrder::LeftFirst, &mut out);
+            assert_eq!(out, expected);
+
+            bvh.traverse_ordered_into(&ray, TraversalOrder::NearFirst, &mut out);
+            out.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(out, expected);
+        }
+    }
+
+    proptest! {
+        // Test whether a refitted `BVH` still finds every shape hit by a ray after the shapes
+        // moved, and reports their joint bounds.
+        #[test]
+        fn test_refit_after_moving_shapes(
+            positions in prop::collection::vec((-8.0f32..8.0, -8.0f32..8.0, -8.0f32..8.0), 1..64),
+            offsets in prop::collection::vec((-4.0f32..4.0, -4.0f32..4.0, -4.0f32..4.0), 64),
+            origin in (-12.0f32..12.0, -12.0f32..12.0, -12.0f32..12.0),
+            direction in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let mut bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+
+            for (shape, offset) in shapes.iter_mut().zip(&offsets) {
+                shape.pos += tuple_to_vector(offset);
+            }
+            let bounds = bvh.refit(&shapes);
+            let expected_bounds = AABB::from_bounded(&shapes);
+            assert_eq!((bounds.min, bounds.max), (expected_bounds.min, expected_bounds.max));
+            bvh.assert_consistent(&shapes);
+            bvh.assert_tight(&shapes);
+
+            // Every shape hit by the ray is still a candidate after it moved.
+            let ray = Ray::new(tuple_to_point(&origin), tuple_to_point(&direction));
+            let candidates = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            for shape in shapes.iter().filter(|shape| ray.intersects_aabb(&shape.aabb())) {
+                assert!(candidates.contains(&shape.id));
+            }
+        }
+    }
+
+    proptest! {
+        // Test whether `traverse_shadow` agrees with testing the `AABB` of every shape.
+        #[test]
+        fn test_traverse_shadow_matches_brute_force(
+            positions in prop::collection::vec((-8.0f32..8.0, -8.0f32..8.0, -8.0f32..8.0), 1..64),
+            origin in (-12.0f32..12.0, -12.0f32..12.0, -12.0f32..12.0),
+            direction in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+            t_max in 0.0f32..20.0,
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let ray = Ray::new(tuple_to_point(&origin), tuple_to_point(&direction));
+
+            let expected = shapes.iter().any(|shape| {
+                matches!(ray.intersection_interval(&shape.aabb()), Some((entry, _)) if entry <= t_max)
+            });
+            assert_eq!(bvh.traverse_shadow(&ray, t_max, &shapes), expected);
+        }
+    }
+
+    proptest! {
+        // Test whether `traverse_nearest` finds the same shape as a brute force search, which
+        // keeps the smallest index of equally near shapes. Positions on an integer grid make
+        // ties likely.
+        #[test]
+        fn test_traverse_nearest_matches_brute_force(
+            positions in prop::collection::vec(tuplevec_grid_strategy(8), 1..64),
+            aim in ray_toward_random_shape(20.0, 1.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+
+            let ray = aim.ray(&shapes);
+
+            let mut expected = None;
+            let mut best_distance = f32::INFINITY;
+            for (index, shape) in shapes.iter().enumerate() {
+                if let Some(distance) = shape.intersect(&ray) {
+                    if distance < best_distance {
+                        best_distance = distance;
+                        expected = Some((index, distance));
+                    }
+                }
+            }
+            let found = bvh.traverse_nearest(&ray, &shapes, |shape, ray| shape.intersect(ray));
+            assert_eq!(found, expected);
+        }
+    }
+
+    proptest! {
+        // Test whether `nearest_point` finds the same distance as a brute force search.
+        #[test]
+        fn test_nearest_point_matches_brute_force(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            p in (-200.0f32..200.0, -200.0f32..200.0, -200.0f32..200.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build(&mut shapes);
+            let p = Point3::new(p.0, p.1, p.2);
+
+            let expected = shapes
+                .iter()
+                .map(|shape| shape.aabb().distance_squared_to_point(&p).sqrt())
+                .fold(f32::INFINITY, f32::min);
+            let (shape_index, closest, distance) = bvh.nearest_point(&p, &shapes).unwrap();
+
+            assert_eq!(distance, expected);
+            assert_eq!(closest, shapes[shape_index].closest_point_on(&p));
+        }
+    }
+
+    /// The closest point on the surface of the sphere inscribed into `shape`, and its distance.
+    fn closest_point_on_inscribed_sphere(shape: &UnitBox, p: &Point3) -> (Point3, f32) {
+        let offset = *p - shape.pos;
+        let distance = (offset.length() - 0.5).max(0.0);
+        (*p - offset.normalize_or_zero() * distance, distance)
+    }
+
+    proptest! {
+        // Test whether `closest` finds the same distance as a brute force search, for shapes
+        // which are smaller than their `AABB`s.
+        #[test]
+        fn test_closest_matches_brute_force(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            p in (-200.0f32..200.0, -200.0f32..200.0, -200.0f32..200.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let p = Point3::new(p.0, p.1, p.2);
+
+            let expected = shapes
+                .iter()
+                .map(|shape| closest_point_on_inscribed_sphere(shape, &p).1)
+                .fold(f32::INFINITY, f32::min);
+            let (shape_index, closest, distance) = bvh
+                .closest(&p, &shapes, closest_point_on_inscribed_sphere)
+                .unwrap();
+
+            assert_eq!(distance, expected);
+            assert_eq!(closest, closest_point_on_inscribed_sphere(&shapes[shape_index], &p).0);
         }
     }
 
-    /// Assert version of `is_consistent`.
-    pub fn assert_consistent<Shape: BHShape>(&self, shapes: &[Shape]) {
-        // The root node of the bvh is not bounded by anything.
-        let space = AABB {
-            min: Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
-            max: Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+    #[test]
+    /// Tests whether `closest` keeps searching when the nearest `AABB` doesn't hold the nearest
+    /// point, which catches pruning by `AABB` distance alone.
+    fn test_closest_nearest_aabb_is_not_nearest_shape() {
+        // The point lies inside the `AABB` of the first box, but far from its inscribed sphere,
+        // near the corner of the `AABB`. The sphere of the second box is closer.
+        let mut shapes = vec![
+            UnitBox::new(0, Point3::ZERO),
+            UnitBox::new(1, Point3::new(1.0, 1.0, 0.5)),
+        ];
+        let bvh = BVH::build(&mut shapes);
+        let p = Point3::splat(0.49);
+        assert_eq!(shapes[0].aabb().distance_squared_to_point(&p), 0.0);
+
+        let (shape_index, _, distance) = bvh
+            .closest(&p, &shapes, closest_point_on_inscribed_sphere)
+            .unwrap();
+        assert_eq!(shapes[shape_index].id, 1);
+        assert!(distance < closest_point_on_inscribed_sphere(&shapes[0], &p).1);
+    }
+
+    proptest! {
+        // Test whether the iterative `traverse` returns the same shapes, in the same order,
+        // as the recursive reference implementation.
+        #[test]
+        fn test_traverse_matches_traverse_recursive(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            origin in (-200.0f32..200.0, -200.0f32..200.0, -200.0f32..200.0),
+            target in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build(&mut shapes);
+            let origin = Point3::new(origin.0, origin.1, origin.2);
+            let target = Point3::new(target.0, target.1, target.2);
+            let ray = Ray::new(origin, target - origin);
+
+            let mut expected = Vec::new();
+            BVHNode::traverse_recursive(&bvh.nodes, 0, &ray, &mut expected);
+            let ids = bvh
+                .traverse(&ray, &shapes)
+                .iter()
+                .map(|shape| shape.id as usize)
+                .collect::<Vec<_>>();
+
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[test]
+    /// Tests whether building with the default `BVHConfig` gives the same tree as `BVH::build`.
+    fn test_build_with_default_config() {
+        let (mut shapes, bvh) = build_some_bh::<BVH>();
+        let configured = BVH::build_with_config(&mut shapes, &BVHConfig::default());
+        assert_eq!(bvh.nodes, configured.nodes);
+    }
+
+    #[test]
+    /// Tests whether rebuilding a `BVH` of the same size reuses all buffers.
+    fn test_rebuild_reuses_buffers() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100, &bounds);
+        let config = BVHConfig::default();
+        let mut scratch = BVHBuildScratch::new();
+        let mut bvh = BVH::build(&mut triangles);
+        bvh.rebuild_with_scratch(&mut triangles, &config, &mut scratch);
+
+        let nodes_ptr = bvh.nodes.as_ptr();
+        let nodes_capacity = bvh.nodes.capacity();
+        let scratch_capacities = |scratch: &BVHBuildScratch| {
+            (
+                scratch.indices.capacity(),
+                scratch.bucket_numbers.capacity(),
+                scratch.buckets.capacity(),
+                scratch.bucket_offsets.capacity(),
+                scratch.partitioned_indices.capacity(),
+            )
         };
+        let capacities = scratch_capacities(&scratch);
 
-        // The counter for all nodes.
-        let mut node_count = 0;
-        self.assert_consistent_subtree(0, 0, &space, 0, &mut node_count, shapes);
+        let mut seed = 0;
+        randomly_transform_scene(&mut triangles, 600, &bounds, None, &mut seed);
+        bvh.rebuild_with_scratch(&mut triangles, &config, &mut scratch);
+        assert_eq!(bvh.nodes.as_ptr(), nodes_ptr);
+        assert_eq!(bvh.nodes.capacity(), nodes_capacity);
+        assert_eq!(scratch_capacities(&scratch), capacities);
 
-        // Check if all nodes have been counted from the root node.
-        // If this is false, it means we have a detached subtree.
-        assert_eq!(node_count, self.nodes.len(), "Detached subtree");
+        let expected = BVH::build(&mut triangles);
+        assert_eq!(format!("{:?}", bvh.nodes), format!("{:?}", expected.nodes));
     }
 
-    /// Check that the `AABB`s in the `BVH` are tight, which means, that parent `AABB`s are not
-    /// larger than they should be. This function checks, whether the children of node `node_index`
-    /// lie inside `outer_aabb`.
-    pub fn assert_tight_subtree<Shape: BHShape>(
-        &self,
-        node_index: usize,
-        outer_aabb: &AABB,
-        shapes: &[Shape],
-    ) {
-        if let BVHNode::Node {
-            child_l_index,
-            child_l_aabb,
-            child_r_index,
-            child_r_aabb,
-            ..
-        } = self.nodes[node_index]
-        {
-            let joint_aabb = child_l_aabb.join(&child_r_aabb);
-            assert!(joint_aabb.relative_eq(outer_aabb, EPSILON));
-            self.assert_tight_subtree(child_l_index, &child_l_aabb, shapes);
-            self.assert_tight_subtree(child_r_index, &child_r_aabb, shapes);
+    proptest! {
+        // Test whether trees built with other configurations are consistent and tight,
+        // and whether they find the same shapes as a brute force search.
+        #[test]
+        fn test_build_with_config(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            median: bool,
+            sah_bins in 2usize..16,
+            origin in (-200.0f32..200.0, -200.0f32..200.0, -200.0f32..200.0),
+            target in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let config = BVHConfig {
+                heuristic: if median { SplitHeuristic::Median } else { SplitHeuristic::Sah },
+                sah_bins,
+                max_leaf_size: 1,
+                min_leaf_size: 1,
+                min_aabb_size: 0.0,
+            };
+            let bvh = BVH::build_with_config(&mut shapes, &config);
+            bvh.assert_consistent(&shapes);
+            bvh.assert_tight(&shapes);
+
+            let origin = Point3::new(origin.0, origin.1, origin.2);
+            let target = Point3::new(target.0, target.1, target.2);
+            let ray = Ray::new(origin, target - origin);
+
+            let mut ids = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            ids.sort_unstable();
+            let expected = shapes
+                .iter()
+                .filter(|shape| ray.intersects_aabb(&shape.aabb()))
+                .map(|shape| shape.id)
+                .collect::<Vec<_>>();
+            if shapes.len() > 1 {
+                assert_eq!(ids, expected);
+            }
         }
-    }
 
-    /// Check that the `AABB`s in the `BVH` are tight, which means, that parent `AABB`s are not
-    /// larger than they should be.
-    pub fn assert_tight<Shape: BHShape>(&self, shapes: &[Shape]) {
-        // When starting to check whether the `BVH` is tight, we cannot provide a minimum
-        // outer `AABB`, therefore we compute the correct one in this instance.
-        if let BVHNode::Node {
-            child_l_aabb,
-            child_r_aabb,
-            ..
-        } = self.nodes[0]
-        {
-            let joint_aabb = child_l_aabb.join(&child_r_aabb);
-            self.assert_tight_subtree(0, &joint_aabb, shapes);
+        #[test]
+        /// Tests whether rebuilding a `BVH` with reused buffers gives the same tree, and the
+        /// same order of shapes, as building a new one.
+        fn test_rebuild_matches_build(
+            old_positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            median: bool,
+            max_leaf_size in 1usize..4,
+        ) {
+            let config = BVHConfig {
+                heuristic: if median { SplitHeuristic::Median } else { SplitHeuristic::Sah },
+                max_leaf_size,
+                ..Default::default()
+            };
+            let mut scratch = BVHBuildScratch::new();
+            let mut old_shapes = unit_boxes(&old_positions);
+            let mut bvh = BVH::build_with_config(&mut old_shapes, &config);
+            bvh.rebuild_with_scratch(&mut old_shapes, &config, &mut scratch);
+
+            let mut shapes = unit_boxes(&positions);
+            let mut expected_shapes = unit_boxes(&positions);
+            bvh.rebuild_with_scratch(&mut shapes, &config, &mut scratch);
+            let expected = BVH::build_with_config(&mut expected_shapes, &config);
+
+            assert_eq!(format!("{:?}", bvh.nodes), format!("{:?}", expected.nodes));
+            let ids = |shapes: &[UnitBox]| {
+                shapes
+                    .iter()
+                    .map(|shape| (shape.id, shape.bh_node_index()))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(ids(&shapes), ids(&expected_shapes));
+        }
+
+        #[test]
+        /// Tests that leaves hold between `min_leaf_size` and `max_leaf_size` shapes, that every
+        /// shape knows its leaf, and that all structures derived from the `BVH` return all
+        /// shapes of a hit leaf.
+        fn test_build_with_max_leaf_size(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            max_leaf_size in 1usize..8,
+            min_leaf_size in any::<prop::sample::Index>(),
+            origin in (-200.0f32..200.0, -200.0f32..200.0, -200.0f32..200.0),
+            target in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let min_leaf_size = 1 + min_leaf_size.index(max_leaf_size.div_ceil(2));
+            let config = BVHConfig {
+                max_leaf_size,
+                min_leaf_size,
+                ..Default::default()
+            };
+            let bvh = BVH::build_with_config(&mut shapes, &config);
+            bvh.assert_consistent(&shapes);
+            bvh.assert_tight(&shapes);
+            assert!(bvh.nodes.len() < 2 * shapes.len());
+
+            // Every shape lies in exactly one leaf.
+            let mut leaf_of_shape = vec![None; shapes.len()];
+            for (node_index, node) in bvh.nodes.iter().enumerate() {
+                if let Some(shape_range) = node.shape_range() {
+                    assert!(shape_range.len() >= min_leaf_size.min(shapes.len()));
+                    assert!(shape_range.len() <= max_leaf_size);
+                    for shape_index in shape_range {
+                        assert_eq!(leaf_of_shape[shape_index], None);
+                        leaf_of_shape[shape_index] = Some(node_index);
+                    }
+                }
+            }
+            for (shape, leaf) in shapes.iter().zip(leaf_of_shape) {
+                assert_eq!(Some(shape.bh_node_index()), leaf);
+            }
+
+            let origin = Point3::new(origin.0, origin.1, origin.2);
+            let target = Point3::new(target.0, target.1, target.2);
+            let ray = Ray::new(origin, target - origin);
+            let sorted_ids = |hits: Vec<&UnitBox>| {
+                let mut ids = hits.iter().map(|shape| shape.id).collect::<Vec<_>>();
+                ids.sort_unstable();
+                ids
+            };
+
+            let ids = sorted_ids(bvh.traverse(&ray, &shapes));
+            let expected = sorted_ids(
+                shapes
+                    .iter()
+                    .filter(|shape| ray.intersects_aabb(&shape.aabb()))
+                    .collect(),
+            );
+            assert!(expected.iter().all(|id| ids.contains(id)));
+            assert_eq!(sorted_ids(bvh.traverse_iterator(&ray, &shapes).collect()), ids);
+            assert_eq!(sorted_ids(bvh.to_bvh4().traverse(&ray, &shapes)), ids);
+            assert_eq!(sorted_ids(bvh.compact().unwrap().traverse(&ray, &shapes)), ids);
+
+            // The flat nodes test the `AABB` of every shape.
+            assert_eq!(sorted_ids(bvh.flatten().traverse(&ray, &shapes)), expected);
         }
     }
-}
 
-impl BoundingHierarchy for BVH {
-    fn build<Shape: BHShape>(shapes: &mut [Shape]) -> BVH {
-        BVH::build(shapes)
+    /// Asserts that every inner node of `bvh` is directly followed by its left child.
+    fn assert_dfs_order(bvh: &BVH) {
+        for (node_index, node) in bvh.nodes.iter().enumerate() {
+            if let BVHNode::Node { child_l_index, .. } = *node {
+                assert_eq!(child_l_index, node_index + 1);
+            }
+        }
     }
 
-    fn traverse<'a, Shape: Bounded>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&Shape> {
-        self.traverse(ray, shapes)
+    proptest! {
+        // Test whether `reorder_dfs` restores the depth-first order of an optimized `BVH`
+        // without changing the result of any traversal.
+        #[test]
+        fn test_reorder_dfs(seed: u64, rays in prop::collection::vec(0u64..1000, 8)) {
+            let bounds = default_bounds();
+            let mut triangles = create_n_cubes(50, &bounds);
+            let mut bvh = BVH::build(&mut triangles);
+            assert_dfs_order(&bvh);
+
+            let mut seed = seed;
+            let updated = randomly_transform_scene(&mut triangles, 300, &bounds, Some(10.0), &mut seed);
+            bvh.optimize(&updated, &triangles);
+
+            let rays = rays
+                .iter()
+                .map(|&ray_seed| {
+                    let mut ray_seed = ray_seed;
+                    let origin = next_point3(&mut ray_seed, &bounds);
+                    let target = next_point3(&mut ray_seed, &bounds);
+                    Ray::new(origin, target - origin)
+                })
+                .collect::<Vec<_>>();
+            let before = rays
+                .iter()
+                .map(|ray| bvh.traverse(ray, &triangles).iter().map(|t| t.a).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+
+            bvh.reorder_dfs(&mut triangles);
+            assert_dfs_order(&bvh);
+            bvh.assert_consistent(&triangles);
+            bvh.assert_tight(&triangles);
+
+            let after = rays
+                .iter()
+                .map(|ray| bvh.traverse(ray, &triangles).iter().map(|t| t.a).collect::<Vec<_>>())
+                .collect::<Vec<_>>();
+            assert_eq!(before, after);
+        }
     }
 
-    fn pretty_print(&self) {
-        self.pretty_print();
+    /// Returns `(index, parent_index, depth)` of every node of `bvh` in depth-first pre-order.
+    fn pre_order_with_stack(bvh: &BVH) -> Vec<(usize, Option<usize>, u32)> {
+        let mut order = Vec::new();
+        let mut stack = vec![(0, None, 0)];
+        while let Some((node_index, parent_index, depth)) = stack.pop() {
+            order.push((node_index, parent_index, depth));
+            if let BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } = bvh.nodes[node_index]
+            {
+                stack.push((child_r_index, Some(node_index), depth + 1));
+                stack.push((child_l_index, Some(node_index), depth + 1));
+            }
+        }
+        order
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::bvh::{BVHNode, BVH};
-    use crate::testbase::{build_some_bh, traverse_some_bh};
+    proptest! {
+        // Test whether `depth_first_iter` visits the nodes in pre-order with the bounds stored
+        // in their parents, also after `optimize` scattered the nodes.
+        #[test]
+        fn test_depth_first_iter(seed: u64, n in 1usize..60) {
+            let bounds = default_bounds();
+            let mut triangles = create_n_cubes(n, &bounds);
+            let mut bvh = BVH::build(&mut triangles);
+            assert!(bvh.depth_first_iter().map(|view| view.index).eq(0..bvh.nodes.len()));
+
+            let mut seed = seed;
+            let updated = randomly_transform_scene(&mut triangles, 6 * n, &bounds, Some(10.0), &mut seed);
+            bvh.optimize(&updated, &triangles);
+
+            let views = bvh.depth_first_iter().collect::<Vec<_>>();
+            let expected = pre_order_with_stack(&bvh);
+            assert_eq!(views.len(), expected.len());
+            for (view, &(index, parent_index, depth)) in views.iter().zip(expected.iter()) {
+                assert_eq!((view.index, view.parent_index, view.depth), (index, parent_index, depth));
+                let aabb = match parent_index {
+                    None => bvh.root_aabb(),
+                    Some(parent_index) if bvh.nodes[parent_index].child_l() == index => {
+                        bvh.nodes[parent_index].child_l_aabb()
+                    }
+                    Some(parent_index) => bvh.nodes[parent_index].child_r_aabb(),
+                };
+                assert_eq!((view.aabb.min, view.aabb.max), (aabb.min, aabb.max));
+            }
+
+            bvh.reorder_dfs(&mut triangles);
+            assert!(bvh.depth_first_iter().map(|view| view.index).eq(0..bvh.nodes.len()));
+        }
+    }
 
     #[test]
-    /// Tests whether the building procedure succeeds in not failing.
-    fn test_build_bvh() {
-        build_some_bh::<BVH>();
+    /// Tests traversing a degenerate scene of 100,000 identical boxes.
+    fn test_traverse_identical_aabbs() {
+        let mut shapes = (0..100_000)
+            .map(|id| UnitBox::new(id, Point3::ZERO))
+            .collect::<Vec<_>>();
+        let bvh = BVH::build(&mut shapes);
+
+        let ray = Ray::new(Point3::new(-10.0, 0.0, 0.0), Vector3::X);
+        assert_eq!(bvh.traverse(&ray, &shapes).len(), shapes.len());
+
+        let ray = Ray::new(Point3::new(-10.0, 2.0, 0.0), Vector3::X);
+        assert!(bvh.traverse(&ray, &shapes).is_empty());
     }
 
     #[test]
-    /// Runs some primitive tests for intersections of a ray with a fixed scene given as a BVH.
-    fn test_traverse_bvh() {
-        traverse_some_bh::<BVH>();
+    #[cfg(feature = "rayon")]
+    /// Tests whether `par_traverse` returns the same shapes per ray as `traverse`.
+    fn test_par_traverse_matches_traverse() {
+        let (shapes, bvh) = build_some_bh::<BVH>();
+        let rays = (-12..12)
+            .map(|x| Ray::new(Point3::new(x as f32 * 0.5, -20.0, 0.0), Vector3::Y))
+            .collect::<Vec<_>>();
+
+        let hits = bvh.par_traverse(&rays, &shapes);
+        assert_eq!(hits.len(), rays.len());
+        for (ray, hit) in rays.iter().zip(hits) {
+            let expected = bvh.traverse(ray, &shapes);
+            let ids = hit.iter().map(|shape| shape.id).collect::<Vec<_>>();
+            let expected_ids = expected.iter().map(|shape| shape.id).collect::<Vec<_>>();
+            assert_eq!(ids, expected_ids);
+        }
     }
 
     #[test]
-    /// Verify contents of the bounding hierarchy for a fixed scene structure
-    fn test_bvh_shape_indices() {
-        use std::collections::HashSet;
+    #[cfg(feature = "rayon")]
+    /// Tests whether `traverse_batch` and `nearest_batch` return the results of
+    /// `traverse_into` and `traverse_nearest` in the order of the rays.
+    fn test_batches_match_single_ray_queries() {
+        let mut boxes = (0..300)
+            .map(|id| UnitBox::new(id, Point3::new((id % 17) as f32, (id / 17) as f32, 0.0)))
+            .collect::<Vec<_>>();
+        let config = BVHConfig {
+            max_leaf_size: 3,
+            ..Default::default()
+        };
+        let bvh = BVH::build_with_config(&mut boxes, &config);
 
-        let (all_shapes, bh) = build_some_bh::<BVH>();
+        let bounds = AABB::with_bounds(Point3::splat(-5.0), Point3::new(22.0, 22.0, 5.0));
+        let mut seed = 0;
+        let rays = (0..2000)
+            .map(|_| {
+                let origin = next_point3(&mut seed, &bounds);
+                let target = next_point3(&mut seed, &bounds);
+                Ray::new(origin, target - origin)
+            })
+            .collect::<Vec<_>>();
 
-        // It should find all shape indices.
-        let expected_shapes: HashSet<_> = (0..all_shapes.len()).collect();
-        let mut found_shapes = HashSet::new();
+        let candidates = bvh.traverse_batch(&rays);
+        let nearest = bvh.nearest_batch(&rays, &boxes, |shape, ray| shape.intersect(ray));
+        assert_eq!(candidates.len(), rays.len());
+        assert_eq!(nearest.len(), rays.len());
 
-        for node in bh.nodes.iter() {
-            match *node {
-                BVHNode::Node { .. } => {
-                    assert_eq!(node.shape_index(), None);
-                }
-                BVHNode::Leaf { .. } => {
-                    found_shapes.insert(
-                        node.shape_index()
-                            .expect("getting a shape index from a leaf node"),
-                    );
-                }
-            }
+        let mut expected = Vec::new();
+        for (i, ray) in rays.iter().enumerate() {
+            bvh.traverse_into(ray, &mut expected);
+            assert_eq!(candidates[i], expected);
+            let expected = bvh.traverse_nearest(ray, &boxes, |shape, ray| shape.intersect(ray));
+            assert_eq!(nearest[i], expected);
         }
-
-        assert_eq!(expected_shapes, found_shapes);
+        assert!(nearest.iter().any(Option::is_some));
+        assert!(BVH { nodes: Vec::new() }
+            .traverse_batch(&rays)
+            .iter()
+            .all(Vec::is_empty));
     }
 }
 
 #[cfg(all(feature = "bench", test))]
 mod bench {
-    use crate::bvh::BVH;
+    use crate::aabb::{Bounded, AABB};
+    use crate::bvh::{BVHBuildScratch, BVHConfig, BVH};
+    use crate::ray::Ray;
     use crate::testbase::{
-        build_1200_triangles_bh, build_120k_triangles_bh, build_12k_triangles_bh,
-        intersect_1200_triangles_bh, intersect_120k_triangles_bh, intersect_12k_triangles_bh,
-        intersect_bh, load_sponza_scene,
+        build_1200_triangles_bh, build_120k_triangles_bh, build_12k_triangles_bh, create_n_cubes,
+        create_rays, default_bounds, intersect_1200_triangles_bh, intersect_120k_triangles_bh,
+        intersect_12k_triangles_bh, intersect_bh, load_sponza_scene, next_point3, Triangle,
+        UnitBox,
     };
+    use crate::Point3;
 
     #[bench]
     /// Benchmark the construction of a `BVH` with 1,200 triangles.
@@ -800,6 +7270,114 @@ mod bench {
         build_120k_triangles_bh::<BVH>(b);
     }
 
+    #[bench]
+    /// Benchmark rebuilding a `BVH` with 120,000 triangles, reusing its buffers.
+    fn bench_rebuild_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let config = BVHConfig::default();
+        let mut scratch = BVHBuildScratch::new();
+        let mut bvh = BVH::build(&mut triangles);
+        b.iter(|| {
+            bvh.rebuild_with_scratch(&mut triangles, &config, &mut scratch);
+        });
+    }
+
+    #[bench]
+    /// Benchmark the bottom-up construction of a `BVH` with 120,000 triangles which are
+    /// sorted along the x axis.
+    fn bench_build_bottom_up_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        triangles.sort_by(|a, b| a.aabb().center().x.total_cmp(&b.aabb().center().x));
+        b.iter(|| {
+            BVH::build_bottom_up(&triangles);
+        });
+    }
+
+    /// Creates 120,000 triangles in 12 tight clusters spread over the default bounds.
+    fn create_clustered_120k_triangles() -> Vec<Triangle> {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        (0..12)
+            .flat_map(|_| {
+                let center = next_point3(&mut seed, &bounds);
+                let size = Point3::splat(1_000.0);
+                create_n_cubes(
+                    10_000 / 12,
+                    &AABB::with_bounds(center - size, center + size),
+                )
+            })
+            .collect()
+    }
+
+    #[bench]
+    /// Benchmark the agglomerative construction of a `BVH` with 120,000 triangles.
+    fn bench_build_agglomerative_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        b.iter(|| {
+            BVH::build_agglomerative(&mut triangles);
+        });
+    }
+
+    #[bench]
+    /// Benchmark the construction of a `BVH` with 120,000 clustered triangles.
+    fn bench_build_clustered_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let mut triangles = create_clustered_120k_triangles();
+        b.iter(|| {
+            BVH::build(&mut triangles);
+        });
+    }
+
+    #[bench]
+    /// Benchmark the agglomerative construction of a `BVH` with 120,000 clustered triangles.
+    fn bench_build_agglomerative_clustered_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let mut triangles = create_clustered_120k_triangles();
+        b.iter(|| {
+            BVH::build_agglomerative(&mut triangles);
+        });
+    }
+
+    /// Creates 10,000 unit boxes at random positions in a cube with an edge length of 40,
+    /// so that each of them overlaps a few others.
+    fn create_10k_random_boxes() -> Vec<UnitBox> {
+        let bounds = AABB::with_bounds(Point3::splat(-20.0), Point3::splat(20.0));
+        let mut seed = 0;
+        (0..10_000)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect()
+    }
+
+    #[bench]
+    /// Benchmark finding the overlapping pairs of 10,000 random boxes using a `BVH`.
+    fn bench_overlapping_pairs_10k_boxes_bvh(b: &mut ::test::Bencher) {
+        let boxes = create_10k_random_boxes();
+        let bvh = BVH::build_dyn(&boxes);
+        b.iter(|| bvh.overlapping_pairs(&boxes));
+    }
+
+    #[bench]
+    /// Benchmark finding the overlapping pairs of 10,000 random boxes by testing all pairs,
+    /// as a baseline for `bench_overlapping_pairs_10k_boxes_bvh`.
+    fn bench_overlapping_pairs_10k_boxes_brute_force(b: &mut ::test::Bencher) {
+        let boxes = create_10k_random_boxes()
+            .iter()
+            .map(|shape| shape.aabb())
+            .collect::<Vec<_>>();
+        b.iter(|| {
+            let mut pairs = Vec::new();
+            for i in 0..boxes.len() {
+                for j in i + 1..boxes.len() {
+                    if boxes[i].intersects_aabb(&boxes[j]) {
+                        pairs.push((i, j));
+                    }
+                }
+            }
+            pairs
+        });
+    }
+
     #[bench]
     /// Benchmark the construction of a `BVH` for the Sponza scene.
     fn bench_build_sponza_bvh(b: &mut ::test::Bencher) {
@@ -834,4 +7412,186 @@ mod bench {
         let bvh = BVH::build(&mut triangles);
         intersect_bh(&bvh, &triangles, &bounds, b)
     }
+
+    #[bench]
+    /// Benchmark the traversal of a `BVH` with up to four triangles per leaf with the Sponza scene.
+    fn bench_intersect_sponza_bvh_max_leaf_size_4(b: &mut ::test::Bencher) {
+        let (mut triangles, bounds) = load_sponza_scene();
+        let config = BVHConfig {
+            max_leaf_size: 4,
+            ..Default::default()
+        };
+        let bvh = BVH::build_with_config(&mut triangles, &config);
+        intersect_bh(&bvh, &triangles, &bounds, b)
+    }
+
+    #[bench]
+    /// Benchmark traversing a batch of rays through the Sponza scene one after another.
+    fn bench_traverse_batch_sponza_bvh(b: &mut ::test::Bencher) {
+        let (mut triangles, bounds) = load_sponza_scene();
+        let bvh = BVH::build(&mut triangles);
+        let rays = create_rays(4096, &bounds);
+        b.iter(|| {
+            rays.iter()
+                .map(|ray| bvh.traverse(ray, &triangles))
+                .collect::<Vec<_>>()
+        });
+    }
+
+    #[bench]
+    /// Benchmark traversing a batch of rays through the Sponza scene one after another,
+    /// reusing one buffer for the results.
+    fn bench_traverse_into_batch_sponza_bvh(b: &mut ::test::Bencher) {
+        let (mut triangles, bounds) = load_sponza_scene();
+        let bvh = BVH::build(&mut triangles);
+        let rays = create_rays(4096, &bounds);
+        let mut out = Vec::new();
+        b.iter(|| {
+            let mut candidates = 0;
+            for ray in &rays {
+                bvh.traverse_into(ray, &mut out);
+                candidates += out.len();
+            }
+            candidates
+        });
+    }
+
+    #[bench]
+    /// Benchmark traversing a batch of rays through 120k triangles, writing the candidates of
+    /// every ray into the same fixed buffer.
+    fn bench_traverse_into_slice_batch_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let rays = create_rays(4096, &bounds);
+        let mut out = vec![0; triangles.len()];
+        b.iter(|| {
+            let mut candidates = 0;
+            for ray in &rays {
+                candidates += bvh.traverse_into_slice(ray, &mut out).unwrap();
+            }
+            candidates
+        });
+    }
+
+    /// Intersects `triangle` with `ray`, for the closest hit benchmarks.
+    fn intersect_triangle(triangle: &Triangle, ray: &Ray) -> Option<f32> {
+        let distance = ray
+            .intersects_triangle(&triangle.a, &triangle.b, &triangle.c)
+            .distance;
+        Some(distance).filter(|distance| distance.is_finite())
+    }
+
+    #[bench]
+    /// Benchmark finding the closest hit of a batch of rays in the Sponza scene by intersecting
+    /// all candidates of `traverse`.
+    fn bench_closest_hit_traverse_sponza_bvh(b: &mut ::test::Bencher) {
+        let (mut triangles, bounds) = load_sponza_scene();
+        let bvh = BVH::build(&mut triangles);
+        let rays = create_rays(4096, &bounds);
+        b.iter(|| {
+            rays.iter()
+                .map(|ray| {
+                    bvh.traverse(ray, &triangles)
+                        .iter()
+                        .filter_map(|triangle| intersect_triangle(triangle, ray))
+                        .fold(f32::INFINITY, f32::min)
+                })
+                .sum::<f32>()
+        });
+    }
+
+    #[bench]
+    /// Benchmark finding the closest hit of a batch of rays in the Sponza scene
+    /// with `traverse_nearest`.
+    fn bench_closest_hit_traverse_nearest_sponza_bvh(b: &mut ::test::Bencher) {
+        let (mut triangles, bounds) = load_sponza_scene();
+        let bvh = BVH::build(&mut triangles);
+        let rays = create_rays(4096, &bounds);
+        b.iter(|| {
+            rays.iter()
+                .filter_map(|ray| bvh.traverse_nearest(ray, &triangles, intersect_triangle))
+                .map(|(_, distance)| distance)
+                .sum::<f32>()
+        });
+    }
+
+    /// Benchmark `par_traverse` on the Sponza scene using a pool of `threads` threads.
+    #[cfg(feature = "rayon")]
+    fn par_traverse_batch_sponza_bvh(threads: usize, b: &mut ::test::Bencher) {
+        let (mut triangles, bounds) = load_sponza_scene();
+        let bvh = BVH::build(&mut triangles);
+        let rays = create_rays(4096, &bounds);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        b.iter(|| pool.install(|| bvh.par_traverse(&rays, &triangles)));
+    }
+
+    #[bench]
+    #[cfg(feature = "rayon")]
+    /// Benchmark traversing a batch of rays through the Sponza scene on 4 threads.
+    fn bench_par_traverse_batch_sponza_bvh_4_threads(b: &mut ::test::Bencher) {
+        par_traverse_batch_sponza_bvh(4, b);
+    }
+
+    #[bench]
+    #[cfg(feature = "rayon")]
+    /// Benchmark traversing a batch of rays through the Sponza scene on 8 threads.
+    fn bench_par_traverse_batch_sponza_bvh_8_threads(b: &mut ::test::Bencher) {
+        par_traverse_batch_sponza_bvh(8, b);
+    }
+
+    /// Creates 12k triangles, their `BVH` and 1M rays through them, for comparing the batch
+    /// queries with a loop over the single ray queries.
+    fn million_rays_scene() -> (Vec<Triangle>, BVH, Vec<Ray>) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(1_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        let rays = create_rays(1_000_000, &bounds);
+        (triangles, bvh, rays)
+    }
+
+    #[bench]
+    /// Benchmark traversing 1M rays one after another, reusing one buffer for the results.
+    fn bench_traverse_into_loop_1m_rays(b: &mut ::test::Bencher) {
+        let (_, bvh, rays) = million_rays_scene();
+        let mut out = Vec::new();
+        b.iter(|| {
+            let mut candidates = 0;
+            for ray in &rays {
+                bvh.traverse_into(ray, &mut out);
+                candidates += out.len();
+            }
+            candidates
+        });
+    }
+
+    #[bench]
+    #[cfg(feature = "rayon")]
+    /// Benchmark traversing 1M rays with `traverse_batch`.
+    fn bench_traverse_batch_1m_rays(b: &mut ::test::Bencher) {
+        let (_, bvh, rays) = million_rays_scene();
+        b.iter(|| bvh.traverse_batch(&rays));
+    }
+
+    #[bench]
+    /// Benchmark finding the closest hit of 1M rays one after another.
+    fn bench_traverse_nearest_loop_1m_rays(b: &mut ::test::Bencher) {
+        let (triangles, bvh, rays) = million_rays_scene();
+        b.iter(|| {
+            rays.iter()
+                .map(|ray| bvh.traverse_nearest(ray, &triangles, intersect_triangle))
+                .collect::<Vec<_>>()
+        });
+    }
+
+    #[bench]
+    #[cfg(feature = "rayon")]
+    /// Benchmark finding the closest hit of 1M rays with `nearest_batch`.
+    fn bench_nearest_batch_1m_rays(b: &mut ::test::Bencher) {
+        let (triangles, bvh, rays) = million_rays_scene();
+        b.iter(|| bvh.nearest_batch(&rays, &triangles, intersect_triangle));
+    }
 }
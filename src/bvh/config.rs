@@ -0,0 +1,155 @@
+//! This module defines [`BVHConfig`], which controls how a [`BVH`] is built.
+//!
+//! [`BVH`]: struct.BVH.html
+//! [`BVHConfig`]: struct.BVHConfig.html
+//!
+
+/// The heuristic which decides how the shapes of a node are split into its two children
+/// when building a [`BVH`].
+///
+/// [`BVH`]: struct.BVH.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SplitHeuristic {
+    /// Bins the shapes along the largest axis of their centroids and picks the split between
+    /// two bins with the lowest surface area heuristic (SAH) cost.
+    /// Builds more slowly than [`Median`], but yields trees which are faster to traverse.
+    ///
+    /// [`Median`]: enum.SplitHeuristic.html#variant.Median
+    ///
+    Sah,
+
+    /// Splits the shapes into two halves at the median of their centroids along the largest
+    /// axis. Builds quickly and always yields a balanced tree, but the children of a node
+    /// may overlap a lot, which makes traversal slower than with [`Sah`].
+    ///
+    /// [`Sah`]: enum.SplitHeuristic.html#variant.Sah
+    ///
+    Median,
+}
+
+/// Configuration of the construction of a [`BVH`], see [`BVH::build_with_config`].
+/// The [`Default`] configuration is the one used by [`BVH::build`].
+///
+/// # Examples
+/// ```
+/// use bvh::bvh::{BVHConfig, SplitHeuristic};
+///
+/// let config = BVHConfig {
+///     sah_bins: 12,
+///     ..Default::default()
+/// };
+/// assert_eq!(config.heuristic, SplitHeuristic::Sah);
+/// ```
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::build`]: struct.BVH.html#method.build
+/// [`BVH::build_with_config`]: struct.BVH.html#method.build_with_config
+/// [`Default`]: https://doc.rust-lang.org/std/default/trait.Default.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BVHConfig {
+    /// The heuristic used to split the shapes of each node. Defaults to [`SplitHeuristic::Sah`].
+    ///
+    /// [`SplitHeuristic::Sah`]: enum.SplitHeuristic.html#variant.Sah
+    ///
+    pub heuristic: SplitHeuristic,
+
+    /// The number of bins the shapes of a node are sorted into by [`SplitHeuristic::Sah`].
+    /// Only split positions between bins are considered, so more bins give a better tree
+    /// and faster queries, at the cost of evaluating more splits per node during the build.
+    /// Must be at least `2`. Defaults to `6`.
+    ///
+    /// [`SplitHeuristic::Sah`]: enum.SplitHeuristic.html#variant.Sah
+    ///
+    pub sah_bins: usize,
+
+    /// The maximum number of shapes per leaf. Nodes with at most this many shapes are not
+    /// split any further, and nodes with more shapes are always split. Larger leaves mean
+    /// fewer nodes and less traversal overhead for scenes with many tiny shapes, but every
+    /// shape of a hit leaf is returned by a traversal.
+    /// Must be at least `1`. Defaults to `1`.
+    ///
+    /// With a value larger than `1`, [`BVH::build_with_config`] reorders the shapes so that the
+    /// shapes of every leaf lie next to each other.
+    ///
+    /// [`BVH::build_with_config`]: struct.BVH.html#method.build_with_config
+    ///
+    pub max_leaf_size: usize,
+
+    /// The minimum number of shapes per leaf. Splits which would leave a child with fewer
+    /// shapes are moved until both children have enough, so that e.g. every leaf of a GPU
+    /// [`BVH`] is filled well. Only a [`BVH`] of fewer shapes in total has a smaller leaf.
+    /// Since every node with more than `max_leaf_size` shapes is split, it must be possible
+    /// to split `max_leaf_size + 1` shapes into two leaves, so this must be at most
+    /// `(max_leaf_size + 1) / 2`. Defaults to `1`.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    ///
+    pub min_leaf_size: usize,
+
+    /// The minimum size of the [`AABB`]s of the shapes along every axis. [`AABB`]s which are
+    /// thinner along some axis, e.g. those of axis-aligned triangles, are grown symmetrically
+    /// to this size with [`AABB::pad_to_minimum_size`] before they are joined into the bounds
+    /// of the nodes, so that rays parallel to them don't produce `NaN`s in the slab tests.
+    /// Defaults to `0.0`, which disables the padding.
+    ///
+    /// Only the construction pads the bounds. They are no longer tight, so don't combine this
+    /// with [`BVH::assert_tight`], and bounds recomputed by [`BVH::optimize`] are not padded.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::pad_to_minimum_size`]: ../aabb/struct.AABB.html#method.pad_to_minimum_size
+    /// [`BVH::assert_tight`]: struct.BVH.html#method.assert_tight
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    ///
+    pub min_aabb_size: f32,
+}
+
+impl BVHConfig {
+    /// Checks that a [`BVH`] can be built with this configuration. Every build which takes a
+    /// [`BVHConfig`] calls this before it splits any node.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::bvh::BVHConfig;
+    ///
+    /// let config = BVHConfig {
+    ///     max_leaf_size: 4,
+    ///     min_leaf_size: 2,
+    ///     ..Default::default()
+    /// };
+    /// config.validate();
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sah_bins` is less than `2`, `max_leaf_size` is `0`, or `min_leaf_size` is
+    /// larger than `(max_leaf_size + 1) / 2`.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVHConfig`]: struct.BVHConfig.html
+    ///
+    pub fn validate(&self) {
+        assert!(self.sah_bins >= 2, "SAH needs at least two bins to split.");
+        assert!(
+            self.max_leaf_size >= 1,
+            "Leaves must be able to hold at least one shape."
+        );
+        assert!(
+            2 * self.min_leaf_size <= self.max_leaf_size + 1,
+            "Nodes with more than max_leaf_size shapes must split into leaves of min_leaf_size."
+        );
+    }
+}
+
+impl Default for BVHConfig {
+    fn default() -> BVHConfig {
+        BVHConfig {
+            heuristic: SplitHeuristic::Sah,
+            sah_bins: 6,
+            max_leaf_size: 1,
+            min_leaf_size: 1,
+            min_aabb_size: 0.0,
+        }
+    }
+}
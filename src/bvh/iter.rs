@@ -1,106 +1,145 @@
-use crate::aabb::Bounded;
+use crate::aabb::{Bounded, AABB};
 use crate::bvh::{BVHNode, BVH};
 use crate::ray::Ray;
-
-/// Iterator to traverse a [`BVH`] without memory allocations
+use std::ops::Range;
+
+/// Size of the fixed traversal stack of [`BVHTraverseIndexIterator`]. Deeper trees spill
+/// over to the heap.
+///
+/// [`BVHTraverseIndexIterator`]: struct.BVHTraverseIndexIterator.html
+///
+const STACK_SIZE: usize = 64;
+
+/// Iterator over the indices of the shapes whose leaves are hit by a [`Ray`], created by
+/// [`BVH::traverse_iter`].
+///
+/// The [`BVH`] is traversed lazily, in depth-first order with left children first,
+/// so the indices come in the same order as the shapes of [`BVH::traverse`].
+/// The traversal stack lives in the iterator, so it does not allocate unless the [`BVH`]
+/// is extremely deep, and dropping it early is free.
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::traverse`]: struct.BVH.html#method.traverse
+/// [`BVH::traverse_iter`]: struct.BVH.html#method.traverse_iter
+/// [`Ray`]: ../ray/struct.Ray.html
+///
 #[allow(clippy::upper_case_acronyms)]
-pub struct BVHTraverseIterator<'a, Shape: Bounded> {
+pub struct BVHTraverseIndexIterator<'a> {
     /// Reference to the BVH to traverse
     bvh: &'a BVH,
     /// Reference to the input ray
     ray: &'a Ray,
-    /// Reference to the input shapes array
-    shapes: &'a [Shape],
-    /// Traversal stack. 4 billion items seems enough?
-    stack: [usize; 32],
-    /// Position of the iterator in bvh.nodes
-    node_index: usize,
+    /// Traversal stack of the nodes which still have to be visited
+    stack: [usize; STACK_SIZE],
     /// Size of the traversal stack
     stack_size: usize,
-    /// Whether or not we have a valid node (or leaf)
-    has_node: bool,
+    /// Nodes pushed while the traversal stack was full
+    spilled: Vec<usize>,
+    /// The shapes of the last visited leaf which have not been returned yet
+    leaf_shapes: Range<usize>,
 }
 
-impl<'a, Shape: Bounded> BVHTraverseIterator<'a, Shape> {
-    /// Creates a new `BVHTraverseIterator`
-    pub fn new(bvh: &'a BVH, ray: &'a Ray, shapes: &'a [Shape]) -> Self {
-        BVHTraverseIterator {
+impl<'a> BVHTraverseIndexIterator<'a> {
+    /// Creates a new `BVHTraverseIndexIterator`
+    pub fn new(bvh: &'a BVH, ray: &'a Ray) -> Self {
+        BVHTraverseIndexIterator {
             bvh,
             ray,
-            shapes,
-            stack: [0; 32],
-            node_index: 0,
-            stack_size: 0,
-            has_node: true,
+            stack: [0; STACK_SIZE],
+            stack_size: if bvh.nodes.is_empty() { 0 } else { 1 },
+            spilled: Vec::new(),
+            leaf_shapes: 0..0,
         }
     }
 
-    /// Test if stack is empty.
-    fn is_stack_empty(&self) -> bool {
-        self.stack_size == 0
-    }
-
-    /// Push node onto stack.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `stack[stack_size]` is out of bounds.
+    /// Push node onto the stack, or onto the heap if the stack is full.
     fn stack_push(&mut self, node: usize) {
-        self.stack[self.stack_size] = node;
-        self.stack_size += 1;
+        if self.stack_size < STACK_SIZE && self.spilled.is_empty() {
+            self.stack[self.stack_size] = node;
+            self.stack_size += 1;
+        } else {
+            self.spilled.push(node);
+        }
     }
 
-    /// Pop the stack and return the node.
-    ///
-    /// # Panics
-    ///
-    /// Panics if `stack_size` underflows.
-    fn stack_pop(&mut self) -> usize {
-        self.stack_size -= 1;
-        self.stack[self.stack_size]
+    /// Pop the node pushed last, if any.
+    fn stack_pop(&mut self) -> Option<usize> {
+        if let Some(node) = self.spilled.pop() {
+            Some(node)
+        } else if self.stack_size > 0 {
+            self.stack_size -= 1;
+            Some(self.stack[self.stack_size])
+        } else {
+            None
+        }
     }
 
-    /// Attempt to move to the left node child of the current node.
-    /// If it is a leaf, or the ray does not intersect the node `AABB`, `has_node` will become false.
-    fn move_left(&mut self) {
-        match self.bvh.nodes[self.node_index] {
-            BVHNode::Node {
-                child_l_index,
-                ref child_l_aabb,
-                ..
-            } => {
-                if self.ray.intersects_aabb(child_l_aabb) {
-                    self.node_index = child_l_index;
-                    self.has_node = true;
-                } else {
-                    self.has_node = false;
+    /// Continues the traversal up to the next leaf hit by the ray, and returns its shapes.
+    /// Returns `None` once the traversal is complete.
+    pub(crate) fn next_leaf(&mut self) -> Option<Range<usize>> {
+        while let Some(node_index) = self.stack_pop() {
+            match self.bvh.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    // Push the right child first, so that the left subtree is visited first.
+                    if self.ray.intersects_aabb(child_r_aabb) {
+                        self.stack_push(child_r_index);
+                    }
+                    if self.ray.intersects_aabb(child_l_aabb) {
+                        self.stack_push(child_l_index);
+                    }
                 }
-            }
-            BVHNode::Leaf { .. } => {
-                self.has_node = false;
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => return Some(shape_index..shape_index + shape_count),
             }
         }
+        None
     }
+}
 
-    /// Attempt to move to the right node child of the current node.
-    /// If it is a leaf, or the ray does not intersect the node `AABB`, `has_node` will become false.
-    fn move_right(&mut self) {
-        match self.bvh.nodes[self.node_index] {
-            BVHNode::Node {
-                child_r_index,
-                ref child_r_aabb,
-                ..
-            } => {
-                if self.ray.intersects_aabb(child_r_aabb) {
-                    self.node_index = child_r_index;
-                    self.has_node = true;
-                } else {
-                    self.has_node = false;
-                }
-            }
-            BVHNode::Leaf { .. } => {
-                self.has_node = false;
+impl<'a> Iterator for BVHTraverseIndexIterator<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            // Return the remaining shapes of the last visited leaf first.
+            if let Some(shape_index) = self.leaf_shapes.next() {
+                return Some(shape_index);
             }
+            self.leaf_shapes = self.next_leaf()?;
+        }
+    }
+}
+
+/// Iterator to traverse a [`BVH`] without memory allocations, created by
+/// [`BVH::traverse_iterator`]. It returns the shapes of [`BVHTraverseIndexIterator`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::traverse_iterator`]: struct.BVH.html#method.traverse_iterator
+/// [`BVHTraverseIndexIterator`]: struct.BVHTraverseIndexIterator.html
+///
+#[allow(clippy::upper_case_acronyms)]
+pub struct BVHTraverseIterator<'a, Shape: Bounded> {
+    /// Iterator over the indices of the shapes
+    indices: BVHTraverseIndexIterator<'a>,
+    /// Reference to the input shapes array
+    shapes: &'a [Shape],
+}
+
+impl<'a, Shape: Bounded> BVHTraverseIterator<'a, Shape> {
+    /// Creates a new `BVHTraverseIterator`
+    pub fn new(bvh: &'a BVH, ray: &'a Ray, shapes: &'a [Shape]) -> Self {
+        BVHTraverseIterator {
+            indices: BVHTraverseIndexIterator::new(bvh, ray),
+            shapes,
         }
     }
 }
@@ -109,36 +148,121 @@ impl<'a, Shape: Bounded> Iterator for BVHTraverseIterator<'a, Shape> {
     type Item = &'a Shape;
 
     fn next(&mut self) -> Option<&'a Shape> {
-        loop {
-            if self.is_stack_empty() && !self.has_node {
-                // Completed traversal.
-                break;
-            }
-            if self.has_node {
-                // If we have any node, save it and attempt to move to its left child.
-                self.stack_push(self.node_index);
-                self.move_left();
+        self.indices
+            .next()
+            .map(|shape_index| &self.shapes[shape_index])
+    }
+}
+
+/// A node of a [`BVH`] as seen by [`BVHDepthFirstIterator`].
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVHDepthFirstIterator`]: struct.BVHDepthFirstIterator.html
+///
+#[derive(Debug, Copy, Clone)]
+#[allow(clippy::upper_case_acronyms)]
+pub struct BVHNodeView {
+    /// The index of the node in [`BVH::nodes`].
+    ///
+    /// [`BVH::nodes`]: struct.BVH.html#structfield.nodes
+    ///
+    pub index: usize,
+
+    /// The index of the node's parent, or `None` for the root.
+    pub parent_index: Option<usize>,
+
+    /// The depth of the node, `0` for the root.
+    pub depth: u32,
+
+    /// The bounds of the node, as stored in its parent. The root's bounds are the joint
+    /// [`AABB`] of its children, or [`AABB::infinite`] if the root is a leaf.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`AABB::infinite`]: ../aabb/struct.AABB.html#method.infinite
+    ///
+    pub aabb: AABB,
+}
+
+/// Iterator over the nodes of a [`BVH`] in depth-first pre-order with left children first,
+/// created by [`BVH::depth_first_iter`].
+///
+/// The iterator walks the tree through the parent indices of the nodes, so it keeps no
+/// traversal stack and never allocates.
+///
+/// [`BVH`]: struct.BVH.html
+/// [`BVH::depth_first_iter`]: struct.BVH.html#method.depth_first_iter
+///
+#[allow(clippy::upper_case_acronyms)]
+pub struct BVHDepthFirstIterator<'a> {
+    /// Reference to the BVH to traverse
+    bvh: &'a BVH,
+    /// The node to return next, and its depth
+    next: Option<(usize, u32)>,
+}
+
+impl<'a> BVHDepthFirstIterator<'a> {
+    /// Creates a new `BVHDepthFirstIterator`
+    pub fn new(bvh: &'a BVH) -> Self {
+        BVHDepthFirstIterator {
+            bvh,
+            next: if bvh.nodes.is_empty() {
+                None
             } else {
-                // Go back up the stack and see if a node or leaf was pushed.
-                self.node_index = self.stack_pop();
-                match self.bvh.nodes[self.node_index] {
-                    BVHNode::Node { .. } => {
-                        // If a node was pushed, now attempt to move to its right child.
-                        self.move_right();
-                    }
-                    BVHNode::Leaf { shape_index, .. } => {
-                        // We previously pushed a leaf node. This is the "visit" of the in-order traverse.
-                        // Next time we call `next()` we try to pop the stack again.
-                        self.has_node = false;
-                        return Some(&self.shapes[shape_index]);
-                    }
-                }
+                Some((0, 0))
+            },
+        }
+    }
+
+    /// Returns the node which follows the subtree of `node_index` in pre-order, and its depth.
+    fn next_sibling(&self, mut node_index: usize, mut depth: u32) -> Option<(usize, u32)> {
+        // Climb up until `node_index` is a left child, and continue with its right sibling.
+        while node_index != 0 {
+            let parent_index = self.bvh.nodes[node_index].parent();
+            let parent = &self.bvh.nodes[parent_index];
+            if parent.child_l() == node_index {
+                return Some((parent.child_r(), depth));
             }
+            node_index = parent_index;
+            depth -= 1;
         }
         None
     }
 }
 
+impl<'a> Iterator for BVHDepthFirstIterator<'a> {
+    type Item = BVHNodeView;
+
+    fn next(&mut self) -> Option<BVHNodeView> {
+        let (index, depth) = self.next?;
+        let nodes = &self.bvh.nodes;
+
+        let (parent_index, aabb) = if index == 0 {
+            (None, self.bvh.root_aabb())
+        } else {
+            let parent_index = nodes[index].parent();
+            let parent = &nodes[parent_index];
+            let aabb = if parent.child_l() == index {
+                parent.child_l_aabb()
+            } else {
+                parent.child_r_aabb()
+            };
+            (Some(parent_index), aabb)
+        };
+
+        self.next = match nodes[index] {
+            BVHNode::Node { child_l_index, .. } => Some((child_l_index, depth + 1)),
+            BVHNode::Leaf { .. } => self.next_sibling(index, depth),
+        };
+
+        Some(BVHNodeView {
+            index,
+            parent_index,
+            depth,
+            aabb,
+        })
+    }
+}
+
 // Copy of part of the BH testing in testbase.
 // TODO: Once iterators are part of the BoundingHierarchy trait we can move all this to testbase.
 #[cfg(test)]
@@ -300,4 +424,41 @@ mod bench {
             }
         });
     }
+
+    #[bench]
+    /// Benchmark taking the first four candidates of 128 rays in the Sponza scene from
+    /// the `Vec` returned by `traverse`, which traverses the whole `BVH` first.
+    fn bench_first_4_128rays_sponza_vec(b: &mut ::test::Bencher) {
+        let (mut triangles, bounds) = load_sponza_scene();
+        let bvh = BVH::build(&mut triangles);
+
+        let mut seed = 0;
+        b.iter(|| {
+            for _ in 0..128 {
+                let ray = create_ray(&mut seed, &bounds);
+                for triangle in bvh.traverse(&ray, &triangles).iter().take(4) {
+                    ray.intersects_triangle(&triangle.a, &triangle.b, &triangle.c);
+                }
+            }
+        });
+    }
+
+    #[bench]
+    /// Benchmark taking the first four candidates of 128 rays in the Sponza scene from
+    /// `traverse_iter`, which stops traversing after them.
+    fn bench_first_4_128rays_sponza_traverse_iter(b: &mut ::test::Bencher) {
+        let (mut triangles, bounds) = load_sponza_scene();
+        let bvh = BVH::build(&mut triangles);
+
+        let mut seed = 0;
+        b.iter(|| {
+            for _ in 0..128 {
+                let ray = create_ray(&mut seed, &bounds);
+                for index in bvh.traverse_iter(&ray).take(4) {
+                    let triangle = &triangles[index];
+                    ray.intersects_triangle(&triangle.a, &triangle.b, &triangle.c);
+                }
+            }
+        });
+    }
 }
@@ -3,10 +3,14 @@
 //! [`BVH`]: struct.BVH.html
 //!
 
+pub mod build;
 mod bvh_impl;
+mod config;
 mod iter;
 mod optimization;
+mod traversal_state;
 
 pub use self::bvh_impl::*;
+pub use self::config::*;
 pub use self::iter::*;
-pub use self::optimization::*;
+pub use self::traversal_state::*;
@@ -1,4 +1,5 @@
-//! This module defines the optimization function for the [`BVH`].
+//! This module defines the optimization function for the [`BVH`], and the insertion of
+//! single shapes into an existing [`BVH`].
 //! By passing the indices of shapes that have changed, the function determines possible
 //! tree rotations and optimizes the BVH using a SAH.
 //! Based on http://www.cs.utah.edu/~thiago/papers/rotations.pdf
@@ -6,7 +7,7 @@
 //! [`BVH`]: struct.BVH.html
 //!
 
-use crate::aabb::AABB;
+use crate::aabb::{Bounded, AABB};
 use crate::bounding_hierarchy::BHShape;
 use crate::bvh::*;
 
@@ -150,6 +151,539 @@ impl BVH {
         }
     }
 
+    /// Inserts the last of the `shapes` into the `BVH` without rebuilding it, e.g. after
+    /// spawning an object by pushing it onto `shapes`, and returns its index in `shapes`.
+    /// The indices of all other shapes stay the same.
+    ///
+    /// The new leaf becomes the sibling of the node which increases the surface areas of the
+    /// tree the least, found by descending from the root like the dynamic AABB tree of Box2D.
+    /// This keeps the tree in good shape over many insertions in random order, but it is
+    /// never rebalanced, so after inserting many shapes along a line or after a large part of
+    /// the scene has changed, [`BVH::rebuild`] gives better traversal performance.
+    ///
+    /// Like [`BVH::build_dyn`], this only needs the [`AABB`]s of the shapes and does not update
+    /// node indices stored in `shapes`. The last of the `shapes` must not be part of the `BVH`
+    /// yet.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let mut boxes = Vec::new();
+    /// let mut bvh = BVH { nodes: Vec::new() };
+    /// for i in 0..10 {
+    ///     let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///     boxes.push(AABB::with_bounds(min, min + Point3::ONE));
+    ///     assert_eq!(bvh.insert(&boxes), i);
+    /// }
+    ///
+    /// let ray = Ray::new(Point3::new(6.5, -10.0, 0.5), Vector3::new(0.0, 1.0, 0.0));
+    /// assert_eq!(bvh.traverse_dyn(&ray, &boxes), vec![3]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shapes` is empty.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::build_dyn`]: struct.BVH.html#method.build_dyn
+    /// [`BVH::rebuild`]: struct.BVH.html#method.rebuild
+    ///
+    pub fn insert<Shape: Bounded>(&mut self, shapes: &[Shape]) -> usize {
+        assert!(
+            !shapes.is_empty(),
+            "The inserted shape must be the last of the shapes."
+        );
+        let shape_index = shapes.len() - 1;
+        let leaf_aabb = shapes[shape_index].aabb();
+
+        if self.nodes.is_empty() {
+            self.nodes.push(BVHNode::Leaf {
+                parent_index: 0,
+                depth: 0,
+                shape_index,
+                shape_count: 1,
+            });
+            return shape_index;
+        }
+
+        // Descend to the best sibling of the new leaf.
+        let mut sibling_index = 0;
+        let mut sibling_aabb = self.nodes[0].get_node_aabb(shapes);
+        while let BVHNode::Node {
+            child_l_aabb,
+            child_l_index,
+            child_r_aabb,
+            child_r_index,
+            ..
+        } = self.nodes[sibling_index]
+        {
+            let combined_area = sibling_aabb.join(&leaf_aabb).surface_area();
+            // Pairing the new leaf with this node adds a parent with the combined area.
+            // Pairing it with any descendant instead grows this node, and also its child.
+            let sibling_cost = 2.0 * combined_area;
+            let inherited_cost = 2.0 * (combined_area - sibling_aabb.surface_area());
+            let child_cost = |child_index: usize, child_aabb: &AABB| {
+                let area = child_aabb.join(&leaf_aabb).surface_area();
+                let growth = match self.nodes[child_index] {
+                    BVHNode::Leaf { .. } => area,
+                    BVHNode::Node { .. } => area - child_aabb.surface_area(),
+                };
+                growth + inherited_cost
+            };
+            let cost_l = child_cost(child_l_index, &child_l_aabb);
+            let cost_r = child_cost(child_r_index, &child_r_aabb);
+
+            if sibling_cost <= cost_l && sibling_cost <= cost_r {
+                break;
+            } else if cost_l <= cost_r {
+                sibling_index = child_l_index;
+                sibling_aabb = child_l_aabb;
+            } else {
+                sibling_index = child_r_index;
+                sibling_aabb = child_r_aabb;
+            }
+        }
+
+        // Put a new parent of the sibling and the new leaf where the sibling was.
+        let grandparent_index = self.nodes[sibling_index].parent();
+        let depth = self.nodes[sibling_index].depth();
+        let (parent_index, sibling_index) = if sibling_index == 0 {
+            // The root has to stay at index 0, so the old root is moved to the end instead.
+            let moved_index = self.nodes.len();
+            let old_root = self.nodes[0];
+            self.nodes.push(old_root);
+            if let BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } = old_root
+            {
+                *self.nodes[child_l_index].parent_mut() = moved_index;
+                *self.nodes[child_r_index].parent_mut() = moved_index;
+            }
+            (0, moved_index)
+        } else {
+            let parent_index = self.nodes.len();
+            match self.nodes[grandparent_index] {
+                BVHNode::Node {
+                    ref mut child_l_index,
+                    ref mut child_r_index,
+                    ..
+                } => {
+                    if *child_l_index == sibling_index {
+                        *child_l_index = parent_index;
+                    } else {
+                        *child_r_index = parent_index;
+                    }
+                }
+                // Assuming that our BVH is correct, the parent cannot be a leaf.
+                _ => unreachable!(),
+            }
+            (parent_index, sibling_index)
+        };
+        let leaf_index = self.nodes.len() + usize::from(parent_index != 0);
+        let parent = BVHNode::Node {
+            parent_index: grandparent_index,
+            depth,
+            child_l_aabb: sibling_aabb,
+            child_l_index: sibling_index,
+            child_r_aabb: leaf_aabb,
+            child_r_index: leaf_index,
+        };
+        if parent_index == 0 {
+            self.nodes[0] = parent;
+        } else {
+            self.nodes.push(parent);
+        }
+        self.nodes.push(BVHNode::Leaf {
+            parent_index,
+            depth: depth + 1,
+            shape_index,
+            shape_count: 1,
+        });
+        *self.nodes[sibling_index].parent_mut() = parent_index;
+        self.update_depth_recursively(sibling_index, depth + 1);
+
+        // Grow the bounds which the ancestors store for the path to the new leaf.
+        let mut child_index = parent_index;
+        while child_index != 0 {
+            let node_index = self.nodes[child_index].parent();
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    ref mut child_l_aabb,
+                    ref mut child_r_aabb,
+                    ..
+                } => {
+                    let child_aabb = if child_l_index == child_index {
+                        child_l_aabb
+                    } else {
+                        child_r_aabb
+                    };
+                    *child_aabb = child_aabb.join(&leaf_aabb);
+                }
+                _ => unreachable!(),
+            }
+            child_index = node_index;
+        }
+        shape_index
+    }
+
+    /// Removes the shape at `shape_index` from the `BVH`, e.g. after despawning an object, and
+    /// returns whether it was part of the `BVH`. Once removed, no query returns the shape.
+    ///
+    /// The leaf of the shape is found by descending only into nodes whose bounds contain the
+    /// [`AABB`] of the shape, so the shape must not have moved since it was inserted, built or
+    /// refitted. If the leaf holds only this shape, it is removed together with its parent,
+    /// whose place is taken by the sibling, and the bounds of the ancestors are shrunk. This
+    /// takes time proportional to the depth of the leaf, plus the size of the sibling's
+    /// subtree, whose depths change. A leaf with several shapes keeps the others instead.
+    ///
+    /// Shape indices are never reused: the shape stays in `shapes`, and [`BVH::insert`]
+    /// always inserts the last shape. So the indices of all other shapes stay the same, and
+    /// the slot of a removed shape is only a tombstone which the caller may reuse by itself.
+    /// Like [`BVH::insert`], this does not update node indices stored in `shapes`.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..10)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let mut bvh = BVH::build_dyn(&boxes);
+    ///
+    /// let ray = Ray::new(Point3::new(-1.0, 0.5, 0.5), Vector3::new(1.0, 0.0, 0.0));
+    /// assert!(bvh.remove(3, &boxes));
+    /// assert!(!bvh.remove(3, &boxes));
+    ///
+    /// let mut hits = bvh.traverse_dyn(&ray, &boxes);
+    /// hits.sort_unstable();
+    /// assert_eq!(hits, vec![0, 1, 2, 4, 5, 6, 7, 8, 9]);
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::insert`]: struct.BVH.html#method.insert
+    ///
+    pub fn remove<Shape: Bounded>(&mut self, shape_index: usize, shapes: &[Shape]) -> bool {
+        let leaf_index = match self.find_leaf(shape_index, shapes) {
+            Some(leaf_index) => leaf_index,
+            None => return false,
+        };
+        let (first_index, shape_count, depth) = match self.nodes[leaf_index] {
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                depth,
+                ..
+            } => (shape_index, shape_count, depth),
+            _ => unreachable!(),
+        };
+
+        // The detached nodes which are removed from `nodes` at the end.
+        let mut removed_nodes = None;
+        let refit_index = if shape_count > 1 {
+            let last_index = first_index + shape_count - 1;
+            if shape_index == first_index || shape_index == last_index {
+                // Shrink the range of the leaf.
+                if let BVHNode::Leaf {
+                    shape_index: ref mut leaf_first_index,
+                    shape_count: ref mut leaf_shape_count,
+                    ..
+                } = self.nodes[leaf_index]
+                {
+                    if shape_index == first_index {
+                        *leaf_first_index += 1;
+                    }
+                    *leaf_shape_count -= 1;
+                }
+            } else {
+                // Split the leaf into the shapes before and after the removed one.
+                let shapes_l = first_index..shape_index;
+                let shapes_r = shape_index + 1..last_index + 1;
+                let child_l_index = self.nodes.len();
+                let child_r_index = child_l_index + 1;
+                self.nodes[leaf_index] = BVHNode::Node {
+                    parent_index: self.nodes[leaf_index].parent(),
+                    depth,
+                    child_l_aabb: AABB::from_bounded(&shapes[shapes_l.clone()]),
+                    child_l_index,
+                    child_r_aabb: AABB::from_bounded(&shapes[shapes_r.clone()]),
+                    child_r_index,
+                };
+                for shape_range in [shapes_l, shapes_r].iter() {
+                    self.nodes.push(BVHNode::Leaf {
+                        parent_index: leaf_index,
+                        depth: depth + 1,
+                        shape_index: shape_range.start,
+                        shape_count: shape_range.len(),
+                    });
+                }
+            }
+            leaf_index
+        } else if leaf_index == 0 {
+            // The shape was the only one.
+            self.nodes.clear();
+            return true;
+        } else {
+            let parent_index = self.nodes[leaf_index].parent();
+            let (sibling_index, sibling_aabb, depth) = match self.nodes[parent_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    depth,
+                    ..
+                } => {
+                    if child_l_index == leaf_index {
+                        (child_r_index, child_r_aabb, depth)
+                    } else {
+                        (child_l_index, child_l_aabb, depth)
+                    }
+                }
+                _ => unreachable!(),
+            };
+
+            if parent_index == 0 {
+                // The root has to stay at index 0, so the sibling is moved there instead.
+                let sibling = self.nodes[sibling_index];
+                self.nodes[0] = sibling;
+                *self.nodes[0].parent_mut() = 0;
+                if let BVHNode::Node {
+                    child_l_index,
+                    child_r_index,
+                    ..
+                } = sibling
+                {
+                    *self.nodes[child_l_index].parent_mut() = 0;
+                    *self.nodes[child_r_index].parent_mut() = 0;
+                }
+                self.update_depth_recursively(0, 0);
+                removed_nodes = Some((leaf_index, sibling_index));
+                0
+            } else {
+                // Let the sibling take the place of the parent.
+                let grandparent_index = self.nodes[parent_index].parent();
+                match self.nodes[grandparent_index] {
+                    BVHNode::Node {
+                        ref mut child_l_index,
+                        ref mut child_l_aabb,
+                        ref mut child_r_index,
+                        ref mut child_r_aabb,
+                        ..
+                    } => {
+                        if *child_l_index == parent_index {
+                            *child_l_index = sibling_index;
+                            *child_l_aabb = sibling_aabb;
+                        } else {
+                            *child_r_index = sibling_index;
+                            *child_r_aabb = sibling_aabb;
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+                *self.nodes[sibling_index].parent_mut() = grandparent_index;
+                self.update_depth_recursively(sibling_index, depth);
+                removed_nodes = Some((leaf_index, parent_index));
+                grandparent_index
+            }
+        };
+
+        // Shrink the bounds which the ancestors store for the path to the changed node.
+        let mut child_index = refit_index;
+        while child_index != 0 {
+            let node_index = self.nodes[child_index].parent();
+            let aabb = self.nodes[child_index].get_node_aabb(shapes);
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    ref mut child_l_aabb,
+                    ref mut child_r_aabb,
+                    ..
+                } => {
+                    if child_l_index == child_index {
+                        *child_l_aabb = aabb;
+                    } else {
+                        *child_r_aabb = aabb;
+                    }
+                }
+                _ => unreachable!(),
+            }
+            child_index = node_index;
+        }
+
+        if let Some((index_a, index_b)) = removed_nodes {
+            // Remove the higher index first, so that the lower one is not moved.
+            self.swap_remove_node(index_a.max(index_b));
+            self.swap_remove_node(index_a.min(index_b));
+        }
+        true
+    }
+
+    /// Refits the `BVH` to the `shapes` like [`BVH::refit`], and then improves it with one
+    /// pass of tree rotations, e.g. after the shapes drifted far from where they were when
+    /// the `BVH` was built. Returns the number of rotations, so that callers can repeat the
+    /// pass until it returns `0`, or stop earlier to bound the time spent.
+    ///
+    /// The pass visits every node after its descendants, and applies the swap of a child with
+    /// a grandchild, or of two grandchildren, which reduces the summed surface area of all
+    /// nodes the most, as in "Tree Rotations for Improving Bounding Volume Hierarchies" by
+    /// Kensler (2008). So the summed surface area never grows, and a rotation never changes
+    /// the shapes a query finds, because the bounds of the node itself stay the same.
+    /// Unlike [`BVH::optimize`], this neither needs to know which shapes changed nor updates
+    /// node indices stored in `shapes`, and the leaves keep their shapes.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let mut boxes = (0..100)
+    ///     .map(|i| {
+    ///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+    ///         AABB::with_bounds(min, min + Point3::ONE)
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let mut bvh = BVH::build_dyn(&boxes);
+    ///
+    /// // Shuffle the positions of the boxes.
+    /// for (i, aabb) in boxes.iter_mut().enumerate() {
+    ///     let x = (i * 37 % 100) as f32 * 2.0;
+    ///     *aabb = aabb.translate(&Vector3::new(x - aabb.min.x, 0.0, 0.0));
+    /// }
+    /// while bvh.refit_and_optimize(&boxes) > 0 {}
+    ///
+    /// assert_eq!(bvh.query_aabb(&boxes[5]), vec![5]);
+    /// ```
+    ///
+    /// [`BVH::optimize`]: struct.BVH.html#method.optimize
+    /// [`BVH::refit`]: struct.BVH.html#method.refit
+    ///
+    pub fn refit_and_optimize<Shape: Bounded>(&mut self, shapes: &[Shape]) -> usize {
+        self.refit(shapes);
+        if self.nodes.is_empty() {
+            return 0;
+        }
+
+        // Reversing the preorder puts every node after its descendants.
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            if let BVHNode::Node {
+                child_l_index,
+                child_r_index,
+                ..
+            } = self.nodes[node_index]
+            {
+                order.push(node_index);
+                stack.push(child_r_index);
+                stack.push(child_l_index);
+            }
+        }
+
+        let mut rotations = 0;
+        for &node_index in order.iter().rev() {
+            if let Some((child_l, child_r)) = self.nodes[node_index].get_children_node_data() {
+                if let Some((rotation_node_a, rotation_node_b)) =
+                    self.find_surface_area_rotation(child_l, child_r)
+                {
+                    self.rotate(rotation_node_a, rotation_node_b, shapes);
+                    self.fix_children_and_own_aabbs(node_index, shapes);
+                    rotations += 1;
+                }
+            }
+        }
+        rotations
+    }
+
+    /// Returns the index of the leaf which holds the shape at `shape_index`, descending only
+    /// into nodes whose bounds contain the [`AABB`] of the shape.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn find_leaf<Shape: Bounded>(&self, shape_index: usize, shapes: &[Shape]) -> Option<usize> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let aabb = shapes[shape_index].aabb();
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            match self.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    if child_r_aabb.contains_aabb(&aabb) {
+                        stack.push(child_r_index);
+                    }
+                    if child_l_aabb.contains_aabb(&aabb) {
+                        stack.push(child_l_index);
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index: first_index,
+                    shape_count,
+                    ..
+                } => {
+                    if (first_index..first_index + shape_count).contains(&shape_index) {
+                        return Some(node_index);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Removes the detached node at `node_index` by moving the last node into its place.
+    fn swap_remove_node(&mut self, node_index: usize) {
+        let last_index = self.nodes.len() - 1;
+        self.nodes.swap_remove(node_index);
+        if node_index == last_index {
+            return;
+        }
+
+        // Redirect the references to the moved node.
+        let parent_index = self.nodes[node_index].parent();
+        match self.nodes[parent_index] {
+            BVHNode::Node {
+                ref mut child_l_index,
+                ref mut child_r_index,
+                ..
+            } => {
+                if *child_l_index == last_index {
+                    *child_l_index = node_index;
+                } else {
+                    *child_r_index = node_index;
+                }
+            }
+            _ => unreachable!(),
+        }
+        if let BVHNode::Node {
+            child_l_index,
+            child_r_index,
+            ..
+        } = self.nodes[node_index]
+        {
+            *self.nodes[child_l_index].parent_mut() = node_index;
+            *self.nodes[child_r_index].parent_mut() = node_index;
+        }
+    }
+
     /// This method is called for each node which has been modified and needs to be updated.
     /// If the specified node is a grandparent, then try to optimize the `BVH` by rotating its
     /// children.
@@ -161,16 +695,12 @@ impl BVH {
         info!("   [{}]\t", node_index);
 
         match self.nodes[node_index] {
-            BVHNode::Leaf {
-                parent_index,
-                shape_index,
-                ..
-            } => {
+            BVHNode::Leaf { parent_index, .. } => {
                 // The current node is a leaf.
                 info!(
                     "Leaf node. Queueing parent ({}). {}.",
                     parent_index,
-                    shapes[shape_index].aabb()
+                    self.nodes[node_index].get_node_aabb(shapes)
                 );
                 Some(OptimizationIndex::Refit(parent_index))
             }
@@ -181,27 +711,21 @@ impl BVH {
                 ..
             } => {
                 // The current node is a parent.
-                if let (
-                    &BVHNode::Leaf {
-                        shape_index: shape_l_index,
-                        ..
-                    },
-                    &BVHNode::Leaf {
-                        shape_index: shape_r_index,
-                        ..
-                    },
-                ) = (&self.nodes[child_l_index], &self.nodes[child_r_index])
+                if let (&BVHNode::Leaf { .. }, &BVHNode::Leaf { .. }) =
+                    (&self.nodes[child_l_index], &self.nodes[child_r_index])
                 {
                     // The current node is a final parent. Update its `AABB`s, because at least
                     // one of its children was updated and queue its parent for refitting.
+                    let shape_l_aabb = self.nodes[child_l_index].get_node_aabb(shapes);
+                    let shape_r_aabb = self.nodes[child_r_index].get_node_aabb(shapes);
                     if let BVHNode::Node {
                         ref mut child_l_aabb,
                         ref mut child_r_aabb,
                         ..
                     } = self.nodes[node_index]
                     {
-                        *child_l_aabb = shapes[shape_l_index].aabb();
-                        *child_r_aabb = shapes[shape_r_index].aabb();
+                        *child_l_aabb = shape_l_aabb;
+                        *child_r_aabb = shape_r_aabb;
                         info!("Setting {} from {}", child_l_aabb, child_l_index);
                         info!("\tand {} from {}.", child_r_aabb, child_r_index);
                         return Some(OptimizationIndex::Refit(parent_index));
@@ -273,6 +797,58 @@ impl BVH {
         best_rotation
     }
 
+    /// Returns the rotation of a child and a grandchild, or of two grandchildren, of the node
+    /// with the children `child_l` and `child_r` which reduces the summed surface area of all
+    /// nodes the most, or `None` if no rotation reduces it. Only the children of the node
+    /// change their bounds, so only their surface areas are compared.
+    fn find_surface_area_rotation(
+        &self,
+        child_l: NodeData,
+        child_r: NodeData,
+    ) -> Option<(usize, usize)> {
+        let mut best_reduction = 0.0;
+        let mut best_rotation = None;
+        let mut consider_rotation = |rotation: (usize, usize), reduction: f32| {
+            if reduction > best_reduction {
+                best_reduction = reduction;
+                best_rotation = Some(rotation);
+            }
+        };
+
+        let area_l = child_l.aabb.surface_area();
+        let area_r = child_r.aabb.surface_area();
+        let right_children_nodes = self.nodes[child_r.index].get_children_node_data();
+        if let Some((child_rl, child_rr)) = right_children_nodes {
+            // Swapping the left child with a right grandchild only changes the right child.
+            let area_l_rl = child_l.aabb.join(&child_rr.aabb).surface_area();
+            consider_rotation((child_l.index, child_rl.index), area_r - area_l_rl);
+            let area_l_rr = child_l.aabb.join(&child_rl.aabb).surface_area();
+            consider_rotation((child_l.index, child_rr.index), area_r - area_l_rr);
+        }
+        if let Some((child_ll, child_lr)) = self.nodes[child_l.index].get_children_node_data() {
+            let area_r_ll = child_r.aabb.join(&child_lr.aabb).surface_area();
+            consider_rotation((child_r.index, child_ll.index), area_l - area_r_ll);
+            let area_r_lr = child_r.aabb.join(&child_ll.aabb).surface_area();
+            consider_rotation((child_r.index, child_lr.index), area_l - area_r_lr);
+
+            if let Some((child_rl, child_rr)) = right_children_nodes {
+                let area_ll_rl = child_rl.aabb.join(&child_lr.aabb).surface_area()
+                    + child_ll.aabb.join(&child_rr.aabb).surface_area();
+                consider_rotation(
+                    (child_ll.index, child_rl.index),
+                    area_l + area_r - area_ll_rl,
+                );
+                let area_ll_rr = child_ll.aabb.join(&child_rl.aabb).surface_area()
+                    + child_lr.aabb.join(&child_rr.aabb).surface_area();
+                consider_rotation(
+                    (child_ll.index, child_rr.index),
+                    area_l + area_r - area_ll_rr,
+                );
+            }
+        }
+        best_rotation
+    }
+
     /// Checks if there is a way to rotate a child and a grandchild (or two grandchildren) of
     /// the given node (specified by `node_index`) that would improve the `BVH`.
     /// If there is, the best rotation found is performed.
@@ -351,7 +927,7 @@ impl BVH {
 
     /// Sets child_l_aabb and child_r_aabb of a BVHNode::Node to match its children,
     /// right after updating the children themselves. Not recursive.
-    fn fix_children_and_own_aabbs<Shape: BHShape>(&mut self, node_index: usize, shapes: &[Shape]) {
+    fn fix_children_and_own_aabbs<Shape: Bounded>(&mut self, node_index: usize, shapes: &[Shape]) {
         let (child_l_index, child_r_index) = if let BVHNode::Node {
             child_l_index,
             child_r_index,
@@ -374,7 +950,7 @@ impl BVH {
 
     /// Updates `child_l_aabb` and `child_r_aabb` of the `BVHNode::Node`
     /// with the index `node_index` from its children.
-    fn fix_aabbs<Shape: BHShape>(
+    fn fix_aabbs<Shape: Bounded>(
         &mut self,
         node_index: usize,
         shapes: &[Shape],
@@ -404,7 +980,7 @@ impl BVH {
 
     /// Switch two nodes by rewiring the involved indices (not by moving them in the nodes slice).
     /// Also updates the AABBs of the parents.
-    fn rotate<Shape: BHShape>(
+    fn rotate<Shape: Bounded>(
         &mut self,
         node_a_index: usize,
         node_b_index: usize,
@@ -469,7 +1045,7 @@ impl BVH {
         child_l_index == node_index
     }
 
-    fn connect_nodes<Shape: BHShape>(
+    fn connect_nodes<Shape: Bounded>(
         &mut self,
         child_index: usize,
         parent_index: usize,
@@ -514,14 +1090,20 @@ impl BVH {
 
 #[cfg(test)]
 mod tests {
-    use crate::aabb::Bounded;
+    use crate::aabb::{Bounded, AABB};
     use crate::bounding_hierarchy::BHShape;
-    use crate::bvh::{BVHNode, BVH};
+    use crate::bvh::{BVHConfig, BVHNode, BVH};
+    use crate::ray::Ray;
     use crate::testbase::{
-        build_some_bh, create_n_cubes, default_bounds, randomly_transform_scene, UnitBox,
+        build_some_bh, build_with_max_leaf_size, create_n_cubes, default_bounds, next_point3,
+        randomly_transform_scene, tuple_to_point, tuple_to_vector, unit_boxes, UnitBox,
     };
     use crate::Point3;
     use crate::EPSILON;
+    use proptest::prelude::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
     use std::collections::HashSet;
 
     #[test]
@@ -667,21 +1249,25 @@ mod tests {
                 parent_index: 1,
                 depth: 2,
                 shape_index: 0,
+                shape_count: 1,
             },
             BVHNode::Leaf {
                 parent_index: 1,
                 depth: 2,
                 shape_index: 1,
+                shape_count: 1,
             },
             BVHNode::Leaf {
                 parent_index: 2,
                 depth: 2,
                 shape_index: 2,
+                shape_count: 1,
             },
             BVHNode::Leaf {
                 parent_index: 2,
                 depth: 2,
                 shape_index: 3,
+                shape_count: 1,
             },
         ];
 
@@ -933,6 +1519,309 @@ mod tests {
         bvh.assert_consistent(&triangles);
         bvh.assert_tight(&triangles);
     }
+
+    #[test]
+    /// Tests if `optimize` keeps a `BVH` with several shapes per leaf consistent.
+    fn test_optimize_bvh_12k_75p_max_leaf_size_4() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(1_000, &bounds);
+        let config = BVHConfig {
+            max_leaf_size: 4,
+            ..Default::default()
+        };
+        let mut bvh = BVH::build_with_config(&mut triangles, &config);
+        bvh.assert_consistent(&triangles);
+        bvh.assert_tight(&triangles);
+
+        let mut seed = 0;
+        let updated = randomly_transform_scene(&mut triangles, 9_000, &bounds, None, &mut seed);
+        assert!(!bvh.is_consistent(&triangles), "BVH is consistent.");
+
+        bvh.optimize(&updated, &triangles);
+        bvh.assert_consistent(&triangles);
+        bvh.assert_tight(&triangles);
+    }
+
+    /// Checks whether the rays between pairs of random points and random boxes find the same
+    /// shapes in the `BVH` as testing every shape which is `alive`, after dropping the
+    /// candidates they miss.
+    fn assert_queries_match_brute_force(
+        bvh: &BVH,
+        shapes: &[UnitBox],
+        alive: &[bool],
+        seed: &mut u64,
+    ) {
+        let bounds = default_bounds();
+        for _ in 0..100 {
+            let origin = next_point3(seed, &bounds);
+            let target = next_point3(seed, &bounds);
+            let ray = Ray::new(origin, target - origin);
+            let mut found = bvh.traverse_dyn(&ray, shapes);
+            assert!(found.iter().all(|&index| alive[index]));
+            found.retain(|&index| ray.intersects_aabb(&shapes[index].aabb()));
+            found.sort_unstable();
+            let expected = (0..shapes.len())
+                .filter(|&index| alive[index] && ray.intersects_aabb(&shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+
+            let query = AABB::empty().grow(&origin).grow(&target);
+            let mut found = bvh.query_aabb(&query);
+            assert!(found.iter().all(|&index| alive[index]));
+            found.retain(|&index| query.intersects_aabb(&shapes[index].aabb()));
+            found.sort_unstable();
+            let expected = (0..shapes.len())
+                .filter(|&index| alive[index] && query.intersects_aabb(&shapes[index].aabb()))
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    /// Tests whether inserting 10k shapes one by one into an empty `BVH` gives a consistent
+    /// tree of reasonable depth, which finds the same shapes as a brute force search.
+    fn test_insert_10k_shapes() {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let mut shapes = Vec::new();
+        let mut bvh = BVH { nodes: Vec::new() };
+        for id in 0..10_000 {
+            shapes.push(UnitBox::new(id, next_point3(&mut seed, &bounds)));
+            assert_eq!(bvh.insert(&shapes), id as usize);
+        }
+
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+        assert_eq!(bvh.nodes.len(), 2 * shapes.len() - 1);
+        let max_depth = bvh.nodes.iter().map(|node| node.depth()).max().unwrap();
+        assert!(
+            max_depth < 64,
+            "Inserting degraded the BVH to depth {}.",
+            max_depth
+        );
+        assert_queries_match_brute_force(&bvh, &shapes, &vec![true; shapes.len()], &mut seed);
+    }
+
+    #[test]
+    /// Tests whether inserting shapes into a built `BVH` with several shapes per leaf keeps
+    /// the leaves of the existing shapes, and with them their indices.
+    fn test_insert_keeps_existing_shape_indices() {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let mut shapes = (0..1_000)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect::<Vec<_>>();
+        let config = BVHConfig {
+            max_leaf_size: 4,
+            ..Default::default()
+        };
+        let mut bvh = BVH::build_with_config(&mut shapes, &config);
+        let leaf_ranges = |bvh: &BVH| {
+            let mut ranges = bvh
+                .nodes
+                .iter()
+                .filter_map(|node| node.shape_range())
+                .map(|range| (range.start, range.end))
+                .collect::<Vec<_>>();
+            ranges.sort_unstable();
+            ranges
+        };
+        let old_ranges = leaf_ranges(&bvh);
+
+        for id in 1_000..1_500 {
+            shapes.push(UnitBox::new(id, next_point3(&mut seed, &bounds)));
+            assert_eq!(bvh.insert(&shapes), id as usize);
+        }
+
+        let new_ranges = leaf_ranges(&bvh);
+        assert!(old_ranges.iter().all(|range| new_ranges.contains(range)));
+        assert_eq!(new_ranges.len(), old_ranges.len() + 500);
+        bvh.assert_consistent(&shapes);
+        bvh.assert_tight(&shapes);
+        assert_queries_match_brute_force(&bvh, &shapes, &vec![true; shapes.len()], &mut seed);
+    }
+
+    #[test]
+    /// Tests whether removing the only shape of a `BVH` leaves it empty, and whether the
+    /// `BVH` can grow again afterwards.
+    fn test_remove_only_shape() {
+        let mut shapes = vec![UnitBox::new(0, Point3::new(1.0, 2.0, 3.0))];
+        let mut bvh = BVH { nodes: Vec::new() };
+        bvh.insert(&shapes);
+
+        assert!(bvh.remove(0, &shapes));
+        assert!(bvh.nodes.is_empty());
+        assert!(!bvh.remove(0, &shapes));
+        assert!(bvh.query_aabb(&shapes[0].aabb()).is_empty());
+
+        shapes.push(UnitBox::new(1, Point3::new(4.0, 5.0, 6.0)));
+        assert_eq!(bvh.insert(&shapes), 1);
+        assert_eq!(bvh.query_aabb(&shapes[1].aabb()), vec![1]);
+        assert!(!bvh.remove(0, &shapes));
+    }
+
+    #[test]
+    /// Tests whether removing every shape one by one in random order keeps the `BVH`
+    /// consistent and tight, with one or several shapes per leaf.
+    fn test_remove_every_shape() {
+        let bounds = default_bounds();
+        for &max_leaf_size in &[1, 4] {
+            let mut seed = 0;
+            let mut shapes = (0..200)
+                .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+                .collect::<Vec<_>>();
+            let mut bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+
+            let mut order = (0..shapes.len()).collect::<Vec<_>>();
+            order.shuffle(&mut StdRng::seed_from_u64(max_leaf_size as u64));
+            let mut alive = vec![true; shapes.len()];
+            for (removed, &shape_index) in order.iter().enumerate() {
+                assert!(bvh.remove(shape_index, &shapes));
+                assert!(!bvh.remove(shape_index, &shapes));
+                alive[shape_index] = false;
+                if removed + 1 < shapes.len() {
+                    bvh.assert_consistent(&shapes);
+                    bvh.assert_tight(&shapes);
+                }
+                if removed % 20 == 0 {
+                    assert_queries_match_brute_force(&bvh, &shapes, &alive, &mut seed);
+                }
+            }
+            assert!(bvh.nodes.is_empty());
+        }
+    }
+
+    #[test]
+    /// Tests whether a random sequence of insertions and removals keeps the `BVH`
+    /// consistent, and whether it always finds exactly the shapes which were not removed.
+    fn test_interleaved_insert_and_remove() {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut shapes = Vec::new();
+        let mut alive = Vec::new();
+        let mut bvh = BVH { nodes: Vec::new() };
+        for step in 0..3_000 {
+            let alive_indices = (0..shapes.len())
+                .filter(|&index| alive[index])
+                .collect::<Vec<_>>();
+            if alive_indices.is_empty() || rng.gen_bool(0.6) {
+                shapes.push(UnitBox::new(
+                    shapes.len() as i32,
+                    next_point3(&mut seed, &bounds),
+                ));
+                alive.push(true);
+                assert_eq!(bvh.insert(&shapes), shapes.len() - 1);
+            } else {
+                let shape_index = alive_indices[rng.gen_range(0..alive_indices.len())];
+                assert!(bvh.remove(shape_index, &shapes));
+                alive[shape_index] = false;
+            }
+
+            if step % 100 == 0 && !bvh.nodes.is_empty() {
+                bvh.assert_consistent(&shapes);
+                bvh.assert_tight(&shapes);
+                assert_queries_match_brute_force(&bvh, &shapes, &alive, &mut seed);
+            }
+        }
+        assert_queries_match_brute_force(&bvh, &shapes, &alive, &mut seed);
+    }
+
+    /// Returns the summed surface area of the bounds of all nodes but the root.
+    fn summed_surface_area(bvh: &BVH) -> f64 {
+        bvh.nodes
+            .iter()
+            .filter_map(|node| node.get_children_node_data())
+            .map(|(child_l, child_r)| {
+                child_l.aabb.surface_area() as f64 + child_r.aabb.surface_area() as f64
+            })
+            .sum()
+    }
+
+    #[test]
+    /// Tests whether rotation passes after shuffling the positions of all shapes reduce the
+    /// summed surface area with every pass, and keep the `BVH` correct.
+    fn test_refit_and_optimize_shuffled_shapes() {
+        let bounds = default_bounds();
+        let mut seed = 0;
+        let mut shapes = (0..1_000)
+            .map(|id| UnitBox::new(id, next_point3(&mut seed, &bounds)))
+            .collect::<Vec<_>>();
+        let mut bvh = BVH::build(&mut shapes);
+        let built_surface_area = summed_surface_area(&bvh);
+
+        let mut positions = shapes.iter().map(|shape| shape.pos).collect::<Vec<_>>();
+        positions.shuffle(&mut StdRng::seed_from_u64(0));
+        for (shape, position) in shapes.iter_mut().zip(positions) {
+            shape.pos = position;
+        }
+        bvh.refit(&shapes);
+        let refitted_surface_area = summed_surface_area(&bvh);
+        assert!(refitted_surface_area > 2.0 * built_surface_area);
+        let mut surface_area = refitted_surface_area;
+
+        let ranges = bvh
+            .nodes
+            .iter()
+            .filter_map(|node| node.shape_range())
+            .collect::<HashSet<_>>();
+        let alive = vec![true; shapes.len()];
+        for _ in 0..10 {
+            let rotations = bvh.refit_and_optimize(&shapes);
+            let new_surface_area = summed_surface_area(&bvh);
+            assert!(new_surface_area <= surface_area * (1.0 + 1e-9));
+            if rotations > 0 {
+                assert!(new_surface_area < surface_area);
+            }
+            surface_area = new_surface_area;
+
+            bvh.assert_consistent(&shapes);
+            bvh.assert_tight(&shapes);
+            assert_queries_match_brute_force(&bvh, &shapes, &alive, &mut seed);
+        }
+        assert!(surface_area < 0.5 * refitted_surface_area);
+
+        // The leaves still hold the same shapes.
+        assert!(bvh
+            .nodes
+            .iter()
+            .filter_map(|node| node.shape_range())
+            .all(|range| ranges.contains(&range)));
+    }
+
+    proptest! {
+        // Test whether every pass of rotations after moving the shapes keeps the `BVH`
+        // consistent and tight, and whether rays still find every shape they hit.
+        #[test]
+        fn test_refit_and_optimize_keeps_queries(
+            positions in prop::collection::vec((-8.0f32..8.0, -8.0f32..8.0, -8.0f32..8.0), 2..64),
+            offsets in prop::collection::vec((-8.0f32..8.0, -8.0f32..8.0, -8.0f32..8.0), 64),
+            origin in (-12.0f32..12.0, -12.0f32..12.0, -12.0f32..12.0),
+            direction in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let mut bvh = BVH::build(&mut shapes);
+            for (shape, offset) in shapes.iter_mut().zip(&offsets) {
+                shape.pos += tuple_to_vector(offset);
+            }
+
+            let ray = Ray::new(tuple_to_point(&origin), tuple_to_vector(&direction));
+            let expected = shapes
+                .iter()
+                .filter(|shape| ray.intersects_aabb(&shape.aabb()))
+                .map(|shape| shape.id)
+                .collect::<Vec<_>>();
+            for _ in 0..4 {
+                bvh.refit_and_optimize(&shapes);
+                bvh.assert_consistent(&shapes);
+                bvh.assert_tight(&shapes);
+
+                let mut found = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+                found.sort_unstable();
+                assert_eq!(found, expected);
+            }
+        }
+    }
 }
 
 #[cfg(all(feature = "bench", test))]
@@ -996,7 +1885,7 @@ mod bench {
     /// `BVH`. Iterate this procedure `iterations` times. Afterwards benchmark the performance
     /// of intersecting this scene/`BVH`.
     fn intersect_scene_after_optimize(
-        triangles: &mut Vec<Triangle>,
+        triangles: &mut [Triangle],
         bounds: &AABB,
         percent: f32,
         max_offset: Option<f32>,
@@ -1044,12 +1933,32 @@ mod bench {
         intersect_scene_after_optimize(&mut triangles, &bounds, 0.5, None, 10, b);
     }
 
+    #[bench]
+    /// Like `bench_intersect_120k_after_optimize_50p`, but restores the depth-first
+    /// node order with `reorder_dfs` before intersecting.
+    fn bench_intersect_120k_after_optimize_50p_reorder_dfs(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let mut bvh = BVH::build(&mut triangles);
+        let num_move = (triangles.len() as f32 * 0.5) as usize;
+        let mut seed = 0;
+
+        for _ in 0..10 {
+            let updated =
+                randomly_transform_scene(&mut triangles, num_move, &bounds, None, &mut seed);
+            bvh.optimize(&updated, &triangles);
+        }
+        bvh.reorder_dfs(&mut triangles);
+
+        intersect_bh(&bvh, &triangles, &bounds, b);
+    }
+
     /// Move `percent` `Triangle`s in the scene given by `triangles` `iterations` times.
     /// Afterwards optimize the `BVH` and benchmark the performance of intersecting this
     /// scene/`BVH`. Used to compare optimizing with rebuilding. For reference see
     /// `intersect_scene_after_optimize`.
     fn intersect_scene_with_rebuild(
-        triangles: &mut Vec<Triangle>,
+        triangles: &mut [Triangle],
         bounds: &AABB,
         percent: f32,
         max_offset: Option<f32>,
@@ -0,0 +1,276 @@
+//! This module defines [`TraversalState`], reusable scratch memory for queries of a [`BVH`].
+//!
+//! [`BVH`]: struct.BVH.html
+//! [`TraversalState`]: struct.TraversalState.html
+//!
+
+use crate::aabb::AABB;
+use crate::bvh::{BVHNode, BVH};
+use crate::ray::Ray;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// The scratch memory of a traversal of a [`BVH`], which can be reused across queries and
+/// [`BVH`]s to avoid allocating it for every query.
+///
+/// The box, predicate and closest hit queries of [`BVH`] allocate a fresh traversal stack for
+/// every query.
+/// A worker thread running millions of queries can instead own one `TraversalState` and pass
+/// it the output buffer, so that neither allocates once they have grown large enough. Only
+/// the capacity of the stack is kept between queries, so one state can serve any number of
+/// [`BVH`]s.
+/// `TraversalState` is [`Send`], so every rayon worker can own one.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::AABB;
+/// use bvh::bvh::{TraversalState, BVH};
+/// use bvh::ray::Ray;
+/// use bvh::{Point3, Vector3};
+///
+/// let boxes = (0..100)
+///     .map(|i| {
+///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+///         AABB::with_bounds(min, min + Point3::ONE)
+///     })
+///     .collect::<Vec<_>>();
+/// let bvh = BVH::build_dyn(&boxes);
+///
+/// let mut state = TraversalState::new();
+/// let mut out = Vec::new();
+/// for i in 0..100 {
+///     let origin = Point3::new(i as f32 * 2.0 + 0.5, -10.0, 0.5);
+///     state.traverse(&bvh, &Ray::new(origin, Vector3::new(0.0, 1.0, 0.0)), &mut out);
+///     assert_eq!(out, vec![i]);
+/// }
+/// ```
+///
+/// [`BVH`]: struct.BVH.html
+/// [`Send`]: https://doc.rust-lang.org/std/marker/trait.Send.html
+///
+#[derive(Debug, Clone, Default)]
+pub struct TraversalState {
+    /// Traversal stack of the nodes which still have to be visited. Empty between queries.
+    stack: Vec<usize>,
+    /// Queue of the nodes of a closest hit query, ordered by the bits of their entry distance.
+    /// Empty between queries.
+    queue: BinaryHeap<Reverse<(u32, usize)>>,
+}
+
+impl TraversalState {
+    /// Creates a new `TraversalState`, which allocates nothing until it is used.
+    pub fn new() -> TraversalState {
+        TraversalState::default()
+    }
+
+    /// Traverses `bvh` like [`BVH::traverse_into`], and writes the indices of the candidate
+    /// shapes into `out`, in the same order. `out` is cleared first.
+    ///
+    /// [`BVH::traverse_into`]: struct.BVH.html#method.traverse_into
+    ///
+    pub fn traverse(&mut self, bvh: &BVH, ray: &Ray, out: &mut Vec<usize>) {
+        out.clear();
+        self.traverse_predicate_with(
+            bvh,
+            |aabb| ray.intersects_aabb(aabb),
+            |shape_index| out.push(shape_index),
+        );
+    }
+
+    /// Finds the shapes of `bvh` like [`BVH::query_aabb_into`], and writes their indices into
+    /// `out`, in the same order. `out` is cleared first.
+    ///
+    /// [`BVH::query_aabb_into`]: struct.BVH.html#method.query_aabb_into
+    ///
+    pub fn query_aabb(&mut self, bvh: &BVH, query: &AABB, out: &mut Vec<usize>) {
+        out.clear();
+        self.traverse_predicate_with(
+            bvh,
+            |aabb| query.intersects_aabb(aabb),
+            |shape_index| out.push(shape_index),
+        );
+    }
+
+    /// Finds the nearest of the `shapes` of `bvh` which is hit by `ray` like
+    /// [`BVH::traverse_nearest`], and returns the index of the shape and the distance of the hit.
+    ///
+    /// [`BVH::traverse_nearest`]: struct.BVH.html#method.traverse_nearest
+    ///
+    pub fn traverse_nearest<Shape, F>(
+        &mut self,
+        bvh: &BVH,
+        ray: &Ray,
+        shapes: &[Shape],
+        mut intersect: F,
+    ) -> Option<(usize, f32)>
+    where
+        F: FnMut(&Shape, &Ray) -> Option<f32>,
+    {
+        if bvh.nodes.is_empty() {
+            return None;
+        }
+
+        // The entry distances are never negative, so their bits sort like the distances.
+        // `Reverse` turns the max-heap into a min-heap.
+        self.queue.clear();
+        self.queue.push(Reverse((0.0f32.to_bits(), 0)));
+
+        let mut best = None;
+        let mut best_distance = f32::INFINITY;
+        while let Some(Reverse((entry_bits, node_index))) = self.queue.pop() {
+            // All remaining nodes are entered even later than this one.
+            if f32::from_bits(entry_bits) > best_distance {
+                break;
+            }
+            match bvh.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    for &(child_aabb, child_index) in
+                        &[(child_l_aabb, child_l_index), (child_r_aabb, child_r_index)]
+                    {
+                        if let Some((entry, _)) = ray.intersection_interval(child_aabb) {
+                            let entry = entry.max(0.0);
+                            if entry <= best_distance {
+                                self.queue.push(Reverse((entry.to_bits(), child_index)));
+                            }
+                        }
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    let leaf_shapes = shapes[shape_index..shape_index + shape_count].iter();
+                    for (index, shape) in (shape_index..).zip(leaf_shapes) {
+                        if let Some(distance) = intersect(shape, ray) {
+                            // Of several hits at the same distance, keep the smallest index.
+                            if distance < best_distance
+                                || (distance == best_distance && Some(index) < best)
+                            {
+                                best_distance = distance;
+                                best = Some(index);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Leave the state empty for the next query.
+        self.queue.clear();
+        best.map(|shape_index| (shape_index, best_distance))
+    }
+
+    /// Traverses `bvh` like [`BVH::traverse_predicate_with`], and calls `visit` with the index
+    /// of every shape of each leaf whose bounds satisfy `predicate`.
+    ///
+    /// [`BVH::traverse_predicate_with`]: struct.BVH.html#method.traverse_predicate_with
+    ///
+    pub fn traverse_predicate_with<P, F>(&mut self, bvh: &BVH, mut predicate: P, mut visit: F)
+    where
+        P: FnMut(&AABB) -> bool,
+        F: FnMut(usize),
+    {
+        if bvh.nodes.is_empty() {
+            return;
+        }
+
+        // A previous query may have been interrupted by a panic in `predicate` or `visit`.
+        self.stack.clear();
+        self.stack.push(0);
+        while let Some(node_index) = self.stack.pop() {
+            match bvh.nodes[node_index] {
+                BVHNode::Node {
+                    ref child_l_aabb,
+                    child_l_index,
+                    ref child_r_aabb,
+                    child_r_index,
+                    ..
+                } => {
+                    // Push the right child first, so that the left subtree is visited first.
+                    if predicate(child_r_aabb) {
+                        self.stack.push(child_r_index);
+                    }
+                    if predicate(child_l_aabb) {
+                        self.stack.push(child_l_index);
+                    }
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => {
+                    (shape_index..shape_index + shape_count).for_each(&mut visit);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::{Bounded, AABB};
+    use crate::bvh::{BVHConfig, TraversalState, BVH};
+    use crate::ray::Ray;
+    use crate::testbase::{create_n_cubes, default_bounds, next_point3, UnitBox};
+    use crate::Point3;
+
+    #[test]
+    /// Tests whether one `TraversalState` can be moved to other threads.
+    fn test_traversal_state_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<TraversalState>();
+    }
+
+    #[test]
+    /// Tests whether interleaving queries of two different `BVH`s through one
+    /// `TraversalState` gives the results of the queries of each `BVH` on its own.
+    fn test_traversal_state_interleaved_bvhs() {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(200, &bounds);
+        let triangle_bvh = BVH::build(&mut triangles);
+
+        let mut boxes = (0..50)
+            .map(|id| UnitBox::new(id, Point3::new(id as f32 * 3.0, 0.0, 0.0)))
+            .collect::<Vec<_>>();
+        let config = BVHConfig {
+            max_leaf_size: 3,
+            ..Default::default()
+        };
+        let box_bvh = BVH::build_with_config(&mut boxes, &config);
+
+        let mut state = TraversalState::new();
+        let mut out = Vec::new();
+        let mut expected = Vec::new();
+        let mut seed = 0;
+        for i in 0..200 {
+            let origin = next_point3(&mut seed, &bounds);
+            let target = next_point3(&mut seed, &bounds);
+            let ray = Ray::new(origin, target - origin);
+            let query = AABB::empty()
+                .grow(&origin)
+                .grow(&(origin + Point3::splat(20.0)));
+            let bvh = if i % 2 == 0 { &triangle_bvh } else { &box_bvh };
+
+            state.traverse(bvh, &ray, &mut out);
+            bvh.traverse_into(&ray, &mut expected);
+            assert_eq!(out, expected);
+
+            state.query_aabb(bvh, &query, &mut out);
+            assert_eq!(out, bvh.query_aabb(&query));
+        }
+
+        // The results refer to the right shapes of each `BVH`.
+        let query = boxes[10].aabb();
+        state.query_aabb(&box_bvh, &query, &mut out);
+        assert!(out.iter().any(|&index| boxes[index].id == 10));
+        state.query_aabb(&BVH { nodes: Vec::new() }, &query, &mut out);
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,682 @@
+//! This module defines [`BVH4`], a 4-wide variant of the [`BVH`] which tests all children
+//! of a node against a ray at once.
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`BVH4`]: struct.BVH4.html
+//!
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
+use crate::bvh::{BVHNode, BVH};
+use crate::ray::Ray;
+use crate::Point3;
+
+/// A child slot of a [`BVH4Node`].
+///
+/// [`BVH4Node`]: struct.BVH4Node.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub enum BVH4Child {
+    /// An unused slot.
+    Empty,
+
+    /// An inner node, referenced by its index in [`BVH4::nodes`].
+    ///
+    /// [`BVH4::nodes`]: struct.BVH4.html#structfield.nodes
+    ///
+    Node(u32),
+
+    /// A leaf, referenced by the index of its first shape and its number of shapes.
+    Leaf(u32, u32),
+}
+
+/// A node of a [`BVH4`] with up to four children.
+///
+/// The [`AABB`]s of the children are stored in structure-of-arrays form, so that
+/// the four children can be tested against a ray with a single sequence of SIMD
+/// instructions. Lane `i` of every array belongs to `children[i]`.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`BVH4`]: struct.BVH4.html
+///
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C, align(16))]
+pub struct BVH4Node {
+    /// The minimum x coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub min_x: [f32; 4],
+
+    /// The minimum y coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub min_y: [f32; 4],
+
+    /// The minimum z coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub min_z: [f32; 4],
+
+    /// The maximum x coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub max_x: [f32; 4],
+
+    /// The maximum y coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub max_y: [f32; 4],
+
+    /// The maximum z coordinates of the children's [`AABB`]s.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub max_z: [f32; 4],
+
+    /// The children of this node. Used slots come first, followed by
+    /// [`BVH4Child::Empty`] slots.
+    ///
+    /// [`BVH4Child::Empty`]: enum.BVH4Child.html#variant.Empty
+    ///
+    pub children: [BVH4Child; 4],
+
+    /// The number of used slots in `children`.
+    pub num_children: u32,
+}
+
+impl BVH4Node {
+    /// Creates a node without children.
+    fn empty() -> BVH4Node {
+        let empty = AABB::empty();
+        BVH4Node {
+            min_x: [empty.min.x; 4],
+            min_y: [empty.min.y; 4],
+            min_z: [empty.min.z; 4],
+            max_x: [empty.max.x; 4],
+            max_y: [empty.max.y; 4],
+            max_z: [empty.max.z; 4],
+            children: [BVH4Child::Empty; 4],
+            num_children: 0,
+        }
+    }
+
+    /// Appends `child` with the bounding box `aabb` to the used slots.
+    fn push_child(&mut self, child: BVH4Child, aabb: &AABB) {
+        let slot = self.num_children as usize;
+        self.min_x[slot] = aabb.min.x;
+        self.min_y[slot] = aabb.min.y;
+        self.min_z[slot] = aabb.min.z;
+        self.max_x[slot] = aabb.max.x;
+        self.max_y[slot] = aabb.max.y;
+        self.max_z[slot] = aabb.max.z;
+        self.children[slot] = child;
+        self.num_children += 1;
+    }
+
+    /// Returns the [`AABB`] of the child in `slot`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn child_aabb(&self, slot: usize) -> AABB {
+        AABB::with_bounds(
+            Point3::new(self.min_x[slot], self.min_y[slot], self.min_z[slot]),
+            Point3::new(self.max_x[slot], self.max_y[slot], self.max_z[slot]),
+        )
+    }
+
+    /// Tests the [`AABB`]s of all children against `ray`.
+    /// Returns a mask in which bit `i` is set if the [`AABB`] of `children[i]` is hit.
+    /// Bits of unused slots are never set.
+    ///
+    /// Uses SSE on x86_64 and NEON on aarch64, and [`intersect_mask_scalar`] elsewhere.
+    /// All variants give exactly the same results as [`Ray::intersects_aabb`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Ray::intersects_aabb`]: ../ray/struct.Ray.html#method.intersects_aabb
+    /// [`intersect_mask_scalar`]: struct.BVH4Node.html#method.intersect_mask_scalar
+    ///
+    #[inline]
+    pub fn intersect_mask(&self, ray: &Ray) -> u8 {
+        #[cfg(target_arch = "x86_64")]
+        let mask = self.intersect_mask_sse(ray);
+        #[cfg(target_arch = "aarch64")]
+        let mask = self.intersect_mask_neon(ray);
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        let mask = self.intersect_mask_scalar(ray);
+
+        mask & ((1 << self.num_children) - 1)
+    }
+
+    /// Scalar version of [`intersect_mask`], testing one child after the other.
+    ///
+    /// [`intersect_mask`]: struct.BVH4Node.html#method.intersect_mask
+    ///
+    pub fn intersect_mask_scalar(&self, ray: &Ray) -> u8 {
+        (0..self.num_children as usize)
+            .filter(|&slot| ray.intersects_aabb(&self.child_aabb(slot)))
+            .fold(0, |mask, slot| mask | (1 << slot))
+    }
+
+    /// Returns the lanes of the near and far planes on each axis as seen from `ray`.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    #[allow(clippy::type_complexity)]
+    fn slabs(&self, ray: &Ray) -> [(&[f32; 4], &[f32; 4]); 3] {
+        let order = |sign: usize, min, max| if sign == 0 { (min, max) } else { (max, min) };
+        [
+            order(ray.sign_x, &self.min_x, &self.max_x),
+            order(ray.sign_y, &self.min_y, &self.max_y),
+            order(ray.sign_z, &self.min_z, &self.max_z),
+        ]
+    }
+
+    /// SSE version of [`intersect_mask`]. Mirrors the comparisons of
+    /// [`Ray::intersects_aabb`] lane by lane, including its handling of NaNs.
+    ///
+    /// [`intersect_mask`]: struct.BVH4Node.html#method.intersect_mask
+    /// [`Ray::intersects_aabb`]: ../ray/struct.Ray.html#method.intersects_aabb
+    ///
+    #[cfg(target_arch = "x86_64")]
+    fn intersect_mask_sse(&self, ray: &Ray) -> u8 {
+        use std::arch::x86_64::*;
+
+        let [(near_x, far_x), (near_y, far_y), (near_z, far_z)] = self.slabs(ray);
+
+        // SAFETY: SSE is part of the x86_64 baseline, and all loads read a whole `[f32; 4]`.
+        unsafe {
+            let plane = |lanes: &[f32; 4], origin: f32, inv_direction: f32| {
+                _mm_mul_ps(
+                    _mm_sub_ps(_mm_loadu_ps(lanes.as_ptr()), _mm_set1_ps(origin)),
+                    _mm_set1_ps(inv_direction),
+                )
+            };
+
+            let mut ray_min = plane(near_x, ray.origin.x, ray.inv_direction.x);
+            let mut ray_max = plane(far_x, ray.origin.x, ray.inv_direction.x);
+
+            let y_min = plane(near_y, ray.origin.y, ray.inv_direction.y);
+            let y_max = plane(far_y, ray.origin.y, ray.inv_direction.y);
+
+            let mut miss = _mm_or_ps(_mm_cmpgt_ps(ray_min, y_max), _mm_cmpgt_ps(y_min, ray_max));
+
+            // `_mm_max_ps(a, b)` is `if a > b { a } else { b }`, just like the scalar code.
+            ray_min = _mm_max_ps(y_min, ray_min);
+            ray_max = _mm_min_ps(y_max, ray_max);
+
+            let z_min = plane(near_z, ray.origin.z, ray.inv_direction.z);
+            let z_max = plane(far_z, ray.origin.z, ray.inv_direction.z);
+
+            miss = _mm_or_ps(
+                miss,
+                _mm_or_ps(_mm_cmpgt_ps(ray_min, z_max), _mm_cmpgt_ps(z_min, ray_max)),
+            );
+            ray_max = _mm_min_ps(z_max, ray_max);
+
+            let hit = _mm_andnot_ps(miss, _mm_cmpgt_ps(ray_max, _mm_setzero_ps()));
+            _mm_movemask_ps(hit) as u8
+        }
+    }
+
+    /// NEON version of [`intersect_mask`]. Mirrors the comparisons of
+    /// [`Ray::intersects_aabb`] lane by lane, including its handling of NaNs.
+    ///
+    /// [`intersect_mask`]: struct.BVH4Node.html#method.intersect_mask
+    /// [`Ray::intersects_aabb`]: ../ray/struct.Ray.html#method.intersects_aabb
+    ///
+    #[cfg(target_arch = "aarch64")]
+    fn intersect_mask_neon(&self, ray: &Ray) -> u8 {
+        use std::arch::aarch64::*;
+
+        let [(near_x, far_x), (near_y, far_y), (near_z, far_z)] = self.slabs(ray);
+
+        // SAFETY: NEON is part of the aarch64 baseline, and all loads read a whole `[f32; 4]`.
+        unsafe {
+            let plane = |lanes: &[f32; 4], origin: f32, inv_direction: f32| {
+                vmulq_f32(
+                    vsubq_f32(vld1q_f32(lanes.as_ptr()), vdupq_n_f32(origin)),
+                    vdupq_n_f32(inv_direction),
+                )
+            };
+
+            let mut ray_min = plane(near_x, ray.origin.x, ray.inv_direction.x);
+            let mut ray_max = plane(far_x, ray.origin.x, ray.inv_direction.x);
+
+            let y_min = plane(near_y, ray.origin.y, ray.inv_direction.y);
+            let y_max = plane(far_y, ray.origin.y, ray.inv_direction.y);
+
+            let mut miss = vorrq_u32(vcgtq_f32(ray_min, y_max), vcgtq_f32(y_min, ray_max));
+
+            // `vmaxq_f32` propagates NaNs, so select explicitly like the scalar code does.
+            ray_min = vbslq_f32(vcgtq_f32(y_min, ray_min), y_min, ray_min);
+            ray_max = vbslq_f32(vcltq_f32(y_max, ray_max), y_max, ray_max);
+
+            let z_min = plane(near_z, ray.origin.z, ray.inv_direction.z);
+            let z_max = plane(far_z, ray.origin.z, ray.inv_direction.z);
+
+            miss = vorrq_u32(
+                miss,
+                vorrq_u32(vcgtq_f32(ray_min, z_max), vcgtq_f32(z_min, ray_max)),
+            );
+            ray_max = vbslq_f32(vcltq_f32(z_max, ray_max), z_max, ray_max);
+
+            let hit = vbicq_u32(vcgtq_f32(ray_max, vdupq_n_f32(0.0)), miss);
+            let bits = [1u32, 2, 4, 8];
+            vaddvq_u32(vandq_u32(hit, vld1q_u32(bits.as_ptr()))) as u8
+        }
+    }
+}
+
+/// A 4-wide bounding volume hierarchy, created by collapsing every two levels
+/// of a binary [`BVH`] into one level.
+///
+/// The nodes are stored in depth-first order, and children are traversed in the
+/// order of the binary tree. Therefore [`BVH4::traverse`] returns exactly the same
+/// shapes in the same order as [`BVH::traverse`] does.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BVH::traverse`]: ../bvh/struct.BVH.html#method.traverse
+/// [`BVH4::traverse`]: struct.BVH4.html#method.traverse
+///
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct BVH4 {
+    /// The list of nodes of the [`BVH4`]. The root is at index `0`.
+    ///
+    /// [`BVH4`]: struct.BVH4.html
+    ///
+    pub nodes: Vec<BVH4Node>,
+}
+
+impl BVH4 {
+    /// Collapses the subtree below the binary inner node `node_index` into [`BVH4Node`]s.
+    /// Returns the index of the new node.
+    ///
+    /// [`BVH4Node`]: struct.BVH4Node.html
+    ///
+    fn collapse(bvh_nodes: &[BVHNode], node_index: usize, nodes: &mut Vec<BVH4Node>) -> u32 {
+        let index = nodes.len();
+        nodes.push(BVH4Node::empty());
+
+        // Gather the grandchildren, or the children themselves if they are leaves.
+        let mut grandchildren = Vec::with_capacity(4);
+        for (child_index, child_aabb) in [
+            (
+                bvh_nodes[node_index].child_l(),
+                bvh_nodes[node_index].child_l_aabb(),
+            ),
+            (
+                bvh_nodes[node_index].child_r(),
+                bvh_nodes[node_index].child_r_aabb(),
+            ),
+        ] {
+            match bvh_nodes[child_index] {
+                BVHNode::Node {
+                    child_l_index,
+                    child_l_aabb,
+                    child_r_index,
+                    child_r_aabb,
+                    ..
+                } => {
+                    grandchildren.push((child_l_index, child_l_aabb));
+                    grandchildren.push((child_r_index, child_r_aabb));
+                }
+                BVHNode::Leaf { .. } => grandchildren.push((child_index, child_aabb)),
+            }
+        }
+
+        for (child_index, child_aabb) in grandchildren {
+            let child = match bvh_nodes[child_index] {
+                BVHNode::Node { .. } => {
+                    BVH4Child::Node(BVH4::collapse(bvh_nodes, child_index, nodes))
+                }
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => BVH4Child::Leaf(shape_index as u32, shape_count as u32),
+            };
+            nodes[index].push_child(child, &child_aabb);
+        }
+
+        index as u32
+    }
+
+    /// Creates a [`BVH4`] from a binary [`BVH`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH4`]: struct.BVH4.html
+    ///
+    pub fn from_bvh(bvh: &BVH) -> BVH4 {
+        let mut nodes = Vec::with_capacity(bvh.nodes.len() / 3 + 1);
+        match bvh.nodes.first() {
+            None => {}
+            Some(BVHNode::Node { .. }) => {
+                BVH4::collapse(&bvh.nodes, 0, &mut nodes);
+            }
+            Some(&BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            }) => {
+                // A `BVH` consisting of a single leaf reports its shapes for every ray.
+                // An infinite `AABB` is hit by every ray, which keeps that behaviour.
+                let mut root = BVH4Node::empty();
+                root.push_child(
+                    BVH4Child::Leaf(shape_index as u32, shape_count as u32),
+                    &AABB::with_bounds(
+                        Point3::splat(f32::NEG_INFINITY),
+                        Point3::splat(f32::INFINITY),
+                    ),
+                );
+                nodes.push(root);
+            }
+        }
+        BVH4 { nodes }
+    }
+
+    /// Traverses the [`BVH4`].
+    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH4`]: struct.BVH4.html
+    ///
+    pub fn traverse<'a, Shape: Bounded>(
+        &'a self,
+        ray: &Ray,
+        shapes: &'a [Shape],
+    ) -> Vec<&'a Shape> {
+        let mut hits = Vec::new();
+        if self.nodes.is_empty() {
+            return hits;
+        }
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push(BVH4Child::Node(0));
+        while let Some(child) = stack.pop() {
+            match child {
+                BVH4Child::Node(index) => {
+                    let node = &self.nodes[index as usize];
+                    let mask = node.intersect_mask(ray);
+
+                    // Push in reverse, so that the children are visited in order.
+                    for slot in (0..node.num_children as usize).rev() {
+                        if mask & (1 << slot) != 0 {
+                            stack.push(node.children[slot]);
+                        }
+                    }
+                }
+                BVH4Child::Leaf(shape_index, shape_count) => {
+                    let shape_index = shape_index as usize;
+                    hits.extend(&shapes[shape_index..shape_index + shape_count as usize]);
+                }
+                BVH4Child::Empty => {}
+            }
+        }
+        hits
+    }
+}
+
+impl BVH {
+    /// Collapses the [`BVH`] into a [`BVH4`].
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`BVH4`]: ../bvh4/struct.BVH4.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    /// use bvh::ray::Ray;
+    /// # use bvh::bounding_hierarchy::BHShape;
+    /// # pub struct UnitBox {
+    /// #     pub id: i32,
+    /// #     pub pos: Point3,
+    /// #     node_index: usize,
+    /// # }
+    /// #
+    /// # impl UnitBox {
+    /// #     pub fn new(id: i32, pos: Point3) -> UnitBox {
+    /// #         UnitBox {
+    /// #             id: id,
+    /// #             pos: pos,
+    /// #             node_index: 0,
+    /// #         }
+    /// #     }
+    /// # }
+    /// #
+    /// # impl Bounded for UnitBox {
+    /// #     fn aabb(&self) -> AABB {
+    /// #         let min = self.pos + Vector3::new(-0.5, -0.5, -0.5);
+    /// #         let max = self.pos + Vector3::new(0.5, 0.5, 0.5);
+    /// #         AABB::with_bounds(min, max)
+    /// #     }
+    /// # }
+    /// #
+    /// # impl BHShape for UnitBox {
+    /// #     fn set_bh_node_index(&mut self, index: usize) {
+    /// #         self.node_index = index;
+    /// #     }
+    /// #
+    /// #     fn bh_node_index(&self) -> usize {
+    /// #         self.node_index
+    /// #     }
+    /// # }
+    /// #
+    /// # fn create_bhshapes() -> Vec<UnitBox> {
+    /// #     let mut shapes = Vec::new();
+    /// #     for i in 0..1000 {
+    /// #         let position = Point3::new(i as f32, i as f32, i as f32);
+    /// #         shapes.push(UnitBox::new(i, position));
+    /// #     }
+    /// #     shapes
+    /// # }
+    ///
+    /// let mut shapes = create_bhshapes();
+    /// let bvh = BVH::build(&mut shapes);
+    /// let bvh4 = bvh.to_bvh4();
+    ///
+    /// let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0));
+    /// assert_eq!(bvh4.traverse(&ray, &shapes).len(), bvh.traverse(&ray, &shapes).len());
+    /// ```
+    pub fn to_bvh4(&self) -> BVH4 {
+        BVH4::from_bvh(self)
+    }
+}
+
+impl BoundingHierarchy for BVH4 {
+    /// A [`BVH4`] is built from a regular [`BVH`] using the [`to_bvh4`] method.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH4`]: struct.BVH4.html
+    /// [`to_bvh4`]: ../bvh/struct.BVH.html#method.to_bvh4
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> BVH4 {
+        BVH::build(shapes).to_bvh4()
+    }
+
+    fn traverse<'a, Shape: Bounded>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&'a Shape> {
+        self.traverse(ray, shapes)
+    }
+
+    /// Prints a textual representation of a [`BVH4`].
+    ///
+    /// [`BVH4`]: struct.BVH4.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            println!("{}\t{:?}", i, &node.children[..node.num_children as usize]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bvh::BVH;
+    use crate::bvh4::BVH4;
+    use crate::ray::Ray;
+    use crate::testbase::{
+        build_some_bh, ray_toward_random_shape, traverse_some_bh, tuplevec_grid_strategy,
+        unit_boxes, UnitBox,
+    };
+    use crate::{Point3, Vector3};
+
+    use proptest::prelude::*;
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_bvh4() {
+        build_some_bh::<BVH4>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given as a `BVH4`.
+    fn test_traverse_bvh4() {
+        traverse_some_bh::<BVH4>();
+    }
+
+    #[test]
+    /// Tests that a `BVH4` of a single shape behaves like the `BVH` it was built from.
+    fn test_traverse_single_shape_bvh4() {
+        let mut shapes = vec![UnitBox::new(0, Point3::ZERO)];
+        let bvh = BVH::build(&mut shapes);
+        let bvh4 = bvh.to_bvh4();
+
+        let ray = Ray::new(Point3::new(0.0, 5.0, 0.0), Vector3::X);
+        assert_eq!(bvh4.traverse(&ray, &shapes).len(), 1);
+        assert_eq!(bvh.traverse(&ray, &shapes).len(), 1);
+    }
+
+    proptest! {
+        // Test whether the `BVH4` yields the same shapes in the same order as the `BVH`
+        // it was built from, and whether the SIMD and scalar child tests agree.
+        #[test]
+        fn test_bvh4_traverse_matches_bvh(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..128),
+            aim in ray_toward_random_shape(200.0, 2.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build(&mut shapes);
+            let bvh4 = bvh.to_bvh4();
+
+            let ray = aim.ray(&shapes);
+
+            let expected = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            let found = bvh4.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            assert_eq!(expected, found);
+
+            for node in &bvh4.nodes {
+                assert_eq!(node.intersect_mask(&ray), node.intersect_mask_scalar(&ray));
+            }
+        }
+
+        // Test whether the SIMD child tests agree with the scalar ones for axis aligned
+        // rays, which have infinite inverse directions.
+        #[test]
+        fn test_bvh4_intersect_mask_axis_aligned(
+            positions in prop::collection::vec(tuplevec_grid_strategy(10), 1..32),
+            origin in (-20i32..20, -20i32..20, -20i32..20),
+            axis in 0usize..3,
+            negative: bool,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh4 = BVH::build(&mut shapes).to_bvh4();
+
+            // Integer coordinates put the origin exactly on box planes every now and then.
+            let origin = Point3::new(origin.0 as f32, origin.1 as f32, origin.2 as f32) * 0.5;
+            let mut direction = Vector3::ZERO;
+            direction[axis] = if negative { -1.0 } else { 1.0 };
+            let ray = Ray::new(origin, direction);
+
+            for node in &bvh4.nodes {
+                assert_eq!(node.intersect_mask(&ray), node.intersect_mask_scalar(&ray));
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "bench", test))]
+mod bench {
+    use crate::bvh::BVH;
+    use crate::bvh4::BVH4;
+    use crate::testbase::{
+        build_1200_triangles_bh, build_120k_triangles_bh, build_12k_triangles_bh, create_n_cubes,
+        default_bounds, intersect_1200_triangles_bh, intersect_120k_triangles_bh,
+        intersect_12k_triangles_bh, intersect_bh,
+    };
+
+    #[bench]
+    /// Benchmark collapsing a `BVH` with 120,000 triangles into a `BVH4`.
+    fn bench_collapse_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        b.iter(|| {
+            bvh.to_bvh4();
+        });
+    }
+
+    #[bench]
+    /// Benchmark the construction of a `BVH4` with 1,200 triangles.
+    fn bench_build_1200_triangles_bvh4(b: &mut ::test::Bencher) {
+        build_1200_triangles_bh::<BVH4>(b);
+    }
+
+    #[bench]
+    /// Benchmark the construction of a `BVH4` with 12,000 triangles.
+    fn bench_build_12k_triangles_bvh4(b: &mut ::test::Bencher) {
+        build_12k_triangles_bh::<BVH4>(b);
+    }
+
+    #[bench]
+    /// Benchmark the construction of a `BVH4` with 120,000 triangles.
+    fn bench_build_120k_triangles_bvh4(b: &mut ::test::Bencher) {
+        build_120k_triangles_bh::<BVH4>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200 triangles using a `BVH4`.
+    fn bench_intersect_1200_triangles_bvh4(b: &mut ::test::Bencher) {
+        intersect_1200_triangles_bh::<BVH4>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 12,000 triangles using a `BVH4`.
+    fn bench_intersect_12k_triangles_bvh4(b: &mut ::test::Bencher) {
+        intersect_12k_triangles_bh::<BVH4>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a `BVH4`.
+    fn bench_intersect_120k_triangles_bvh4(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_bh::<BVH4>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200,000 triangles using the binary `BVH`,
+    /// as a baseline for `bench_intersect_1200k_triangles_bvh4`.
+    fn bench_intersect_1200k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        intersect_bh(&bvh, &triangles, &bounds, b)
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200,000 triangles using a `BVH4`.
+    fn bench_intersect_1200k_triangles_bvh4(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100_000, &bounds);
+        let bvh4 = BVH::build(&mut triangles).to_bvh4();
+        intersect_bh(&bvh4, &triangles, &bounds, b)
+    }
+}
@@ -0,0 +1,484 @@
+//! This module defines [`CompactBVH`], a flat [`BVH`] with 32 byte nodes.
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`CompactBVH`]: struct.CompactBVH.html
+//!
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
+use crate::bvh::{BVHNode, BVH};
+use crate::ray::Ray;
+
+/// A node of a [`CompactBVH`]. Every node of the [`BVH`] becomes one [`CompactNode`]
+/// of exactly 32 bytes, which lets two nodes share a 64 byte cache line.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`CompactBVH`]: struct.CompactBVH.html
+/// [`CompactNode`]: struct.CompactNode.html
+///
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct CompactNode {
+    /// The [`AABB`] of the node. The subtree of the node is only entered if it is hit.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub aabb: AABB,
+
+    /// The index of the node to continue with once this node's subtree is done, or
+    /// skipped because its [`AABB`] was missed. The subtree itself starts right after
+    /// this node, so its index is not stored.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub exit_index: u32,
+
+    /// The index of the shape in the shapes array for leaves.
+    /// Inner nodes have a `shape_index` of [`u32::MAX`].
+    ///
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/primitive.u32.html#associatedconstant.MAX
+    ///
+    pub shape_index: u32,
+}
+
+impl CompactNode {
+    /// Returns `true` if the node is a leaf.
+    pub fn is_leaf(&self) -> bool {
+        self.shape_index != u32::MAX
+    }
+}
+
+/// The error returned when a [`BVH`] has too many nodes, or refers to a shape index too
+/// large, to address them with `u32` indices.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IndexOverflowError {
+    /// The number of nodes the [`CompactBVH`] would need.
+    ///
+    /// [`CompactBVH`]: struct.CompactBVH.html
+    ///
+    pub node_count: usize,
+    /// The first shape index which does not fit into a `u32`, or `None` if the number of
+    /// nodes is too large. Shape indices may be large if the [`BVH`] was built with
+    /// [`BVH::build_from_aabbs`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`BVH::build_from_aabbs`]: ../bvh/struct.BVH.html#method.build_from_aabbs
+    ///
+    pub shape_index: Option<usize>,
+}
+
+impl fmt::Display for IndexOverflowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.shape_index {
+            Some(shape_index) => write!(
+                f,
+                "the BVH refers to shape {}, but at most {} can be addressed by u32 indices",
+                shape_index,
+                u32::MAX - 1
+            ),
+            None => write!(
+                f,
+                "the BVH needs {} nodes, but at most {} can be addressed by u32 indices",
+                self.node_count,
+                u32::MAX - 1
+            ),
+        }
+    }
+}
+
+impl Error for IndexOverflowError {}
+
+/// A flat [`BVH`] with `u32` indices and 32 byte nodes, see [`CompactNode`].
+///
+/// Like the [`FlatBVH`], the nodes are stored in depth-first order and traversed without
+/// a stack. Unlike it, every node of the [`BVH`] is stored once, and the index of the
+/// first child is implicit, which saves both nodes and bytes per node.
+/// A leaf of the [`BVH`] with several shapes is stored as one node per shape, plus one.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`CompactNode`]: struct.CompactNode.html
+/// [`FlatBVH`]: ../flat_bvh/type.FlatBVH.html
+///
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompactBVH {
+    /// The nodes in depth-first order. The root is at index `0`.
+    pub nodes: Vec<CompactNode>,
+}
+
+impl CompactBVH {
+    /// Appends the subtree below `node_index` of the [`BVH`] in depth-first order.
+    /// Fails if a leaf refers to a shape index which does not fit into a `u32`, reporting the
+    /// `node_count` of the whole [`CompactBVH`].
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`CompactBVH`]: struct.CompactBVH.html
+    ///
+    fn push_subtree(
+        bvh_nodes: &[BVHNode],
+        node_index: usize,
+        aabb: &AABB,
+        node_count: usize,
+        nodes: &mut Vec<CompactNode>,
+    ) -> Result<(), IndexOverflowError> {
+        let index = nodes.len();
+        nodes.push(CompactNode {
+            aabb: *aabb,
+            exit_index: index as u32 + 1,
+            shape_index: u32::MAX,
+        });
+
+        match bvh_nodes[node_index] {
+            BVHNode::Node {
+                ref child_l_aabb,
+                child_l_index,
+                ref child_r_aabb,
+                child_r_index,
+                ..
+            } => {
+                CompactBVH::push_subtree(
+                    bvh_nodes,
+                    child_l_index,
+                    child_l_aabb,
+                    node_count,
+                    nodes,
+                )?;
+                CompactBVH::push_subtree(
+                    bvh_nodes,
+                    child_r_index,
+                    child_r_aabb,
+                    node_count,
+                    nodes,
+                )?;
+                nodes[index].exit_index = nodes.len() as u32;
+            }
+            BVHNode::Leaf {
+                shape_index,
+                shape_count: 1,
+                ..
+            } => {
+                nodes[index].shape_index = compact_shape_index(shape_index, node_count)?;
+            }
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => {
+                // The `AABB` of the leaf was tested by the inner node above, so every ray
+                // which reaches its shapes reports all of them.
+                let infinite_aabb = AABB::infinite();
+                for shape_index in shape_index..shape_index + shape_count {
+                    let shape_index = compact_shape_index(shape_index, node_count)?;
+                    nodes.push(CompactNode {
+                        aabb: infinite_aabb,
+                        exit_index: nodes.len() as u32 + 1,
+                        shape_index,
+                    });
+                }
+                nodes[index].exit_index = nodes.len() as u32;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a [`CompactBVH`] from a [`BVH`].
+    /// Fails if the [`BVH`] has too many nodes, or refers to a shape index too large, to
+    /// address them with `u32` indices.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`CompactBVH`]: struct.CompactBVH.html
+    ///
+    pub fn from_bvh(bvh: &BVH) -> Result<CompactBVH, IndexOverflowError> {
+        let node_count = bvh
+            .nodes
+            .iter()
+            .map(|node| match *node {
+                BVHNode::Leaf { shape_count, .. } if shape_count > 1 => shape_count + 1,
+                _ => 1,
+            })
+            .sum();
+        // `u32::MAX` marks inner nodes, so it must not be a valid shape index either.
+        if node_count >= u32::MAX as usize {
+            return Err(IndexOverflowError {
+                node_count,
+                shape_index: None,
+            });
+        }
+
+        let mut nodes = Vec::with_capacity(node_count);
+        match bvh.nodes.first() {
+            None => {}
+            Some(BVHNode::Node {
+                child_l_aabb,
+                child_r_aabb,
+                ..
+            }) => {
+                let aabb = child_l_aabb.join(child_r_aabb);
+                CompactBVH::push_subtree(&bvh.nodes, 0, &aabb, node_count, &mut nodes)?;
+            }
+            Some(BVHNode::Leaf { .. }) => {
+                // A `BVH` consisting of a single leaf reports its shapes for every ray.
+                // An infinite `AABB` is hit by every ray, which keeps that behaviour.
+                let aabb = AABB::infinite();
+                CompactBVH::push_subtree(&bvh.nodes, 0, &aabb, node_count, &mut nodes)?;
+            }
+        }
+        Ok(CompactBVH { nodes })
+    }
+
+    /// Traverses the [`CompactBVH`] iteratively.
+    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`CompactBVH`]: struct.CompactBVH.html
+    ///
+    pub fn traverse<'a, Shape: Bounded>(
+        &'a self,
+        ray: &Ray,
+        shapes: &'a [Shape],
+    ) -> Vec<&'a Shape> {
+        let mut hit_shapes = Vec::new();
+        let mut index = 0;
+        while index < self.nodes.len() {
+            let node = &self.nodes[index];
+            if !ray.intersects_aabb(&node.aabb) {
+                // Skip the subtree.
+                index = node.exit_index as usize;
+            } else if node.is_leaf() {
+                hit_shapes.push(&shapes[node.shape_index as usize]);
+                index = node.exit_index as usize;
+            } else {
+                // Enter the subtree, which starts right after this node.
+                index += 1;
+            }
+        }
+        hit_shapes
+    }
+}
+
+/// Converts `shape_index` into the `u32` stored in a [`CompactNode`], failing like the node
+/// count of a [`CompactBVH`] with `node_count` nodes if it does not fit.
+///
+/// [`CompactBVH`]: struct.CompactBVH.html
+/// [`CompactNode`]: struct.CompactNode.html
+///
+fn compact_shape_index(shape_index: usize, node_count: usize) -> Result<u32, IndexOverflowError> {
+    match u32::try_from(shape_index) {
+        // `u32::MAX` marks inner nodes, so it must not be a valid shape index either.
+        Ok(index) if index != u32::MAX => Ok(index),
+        _ => Err(IndexOverflowError {
+            node_count,
+            shape_index: Some(shape_index),
+        }),
+    }
+}
+
+impl BVH {
+    /// Converts the [`BVH`] into a [`CompactBVH`].
+    /// Fails if the [`BVH`] has too many nodes, or refers to a shape index too large, to
+    /// address them with `u32` indices.
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`CompactBVH`]: ../compact_bvh/struct.CompactBVH.html
+    ///
+    pub fn compact(&self) -> Result<CompactBVH, IndexOverflowError> {
+        CompactBVH::from_bvh(self)
+    }
+}
+
+impl BoundingHierarchy for CompactBVH {
+    /// A [`CompactBVH`] is built from a regular [`BVH`] using the [`compact`] method.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [`BVH`] has too many nodes, or refers to a shape index too large, to
+    /// address them with `u32` indices.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`CompactBVH`]: struct.CompactBVH.html
+    /// [`compact`]: ../bvh/struct.BVH.html#method.compact
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> CompactBVH {
+        BVH::build(shapes).compact().unwrap()
+    }
+
+    fn traverse<'a, Shape: Bounded>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&'a Shape> {
+        self.traverse(ray, shapes)
+    }
+
+    /// Prints a textual representation of a [`CompactBVH`].
+    ///
+    /// [`CompactBVH`]: struct.CompactBVH.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            println!(
+                "{}\texit {}\tshape {}",
+                i, node.exit_index, node.shape_index
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bvh::BVH;
+    use crate::compact_bvh::{CompactBVH, CompactNode, IndexOverflowError};
+    use crate::testbase::{build_some_bh, ray_toward_random_shape, traverse_some_bh, unit_boxes};
+    use crate::{Point3, Vector3};
+
+    use proptest::prelude::*;
+
+    #[test]
+    /// Makes sure that the node layout does not grow unnoticed.
+    fn test_compact_node_size() {
+        assert_eq!(std::mem::size_of::<CompactNode>(), 32);
+    }
+
+    #[test]
+    /// Tests whether a shape index which does not fit into a `u32` is reported instead of
+    /// being truncated.
+    fn test_compact_shape_index_overflow() {
+        let aabb = AABB::with_bounds(Point3::ZERO, Point3::ONE);
+        // `u32::MAX` itself marks inner nodes, and `usize::MAX` would be truncated to it.
+        for &large_id in &[u32::MAX as usize, usize::MAX] {
+            let aabbs = [(0, aabb), (large_id, aabb.translate(&Vector3::X))];
+            assert_eq!(
+                BVH::build_from_aabbs(&aabbs).compact().unwrap_err(),
+                IndexOverflowError {
+                    node_count: 3,
+                    shape_index: Some(large_id),
+                }
+            );
+            let single_leaf = BVH::build_from_aabbs(&aabbs[1..]);
+            assert_eq!(
+                single_leaf.compact().unwrap_err().shape_index,
+                Some(large_id)
+            );
+        }
+
+        let compact = BVH::build_from_aabbs(&[(u32::MAX as usize - 1, aabb)])
+            .compact()
+            .unwrap();
+        assert_eq!(compact.nodes[0].shape_index, u32::MAX - 1);
+    }
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_compact_bvh() {
+        build_some_bh::<CompactBVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `CompactBVH`.
+    fn test_traverse_compact_bvh() {
+        traverse_some_bh::<CompactBVH>();
+    }
+
+    proptest! {
+        // Test whether the `CompactBVH` yields the same shapes in the same order as the `BVH`
+        // it was built from.
+        #[test]
+        fn test_compact_bvh_traverse_matches_bvh(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            aim in ray_toward_random_shape(200.0, 2.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build(&mut shapes);
+            let compact = bvh.compact().unwrap();
+            assert_eq!(compact.nodes.len(), bvh.nodes.len());
+
+            let ray = aim.ray(&shapes);
+
+            let expected = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            let found = compact.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            assert_eq!(expected, found);
+        }
+    }
+}
+
+#[cfg(all(feature = "bench", test))]
+mod bench {
+    use crate::bvh::BVH;
+    use crate::compact_bvh::CompactBVH;
+    use crate::flat_bvh::FlatBVH;
+    use crate::testbase::{
+        build_120k_triangles_bh, create_n_cubes, default_bounds, intersect_1200_triangles_bh,
+        intersect_120k_triangles_bh, intersect_12k_triangles_bh, intersect_bh,
+    };
+
+    #[bench]
+    /// Benchmark compacting a `BVH` with 120,000 triangles.
+    fn bench_compact_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        b.iter(|| {
+            bvh.compact().unwrap();
+        });
+    }
+
+    #[bench]
+    /// Benchmark the construction of a `CompactBVH` with 120,000 triangles.
+    fn bench_build_120k_triangles_compact_bvh(b: &mut ::test::Bencher) {
+        build_120k_triangles_bh::<CompactBVH>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200 triangles using a `CompactBVH`.
+    fn bench_intersect_1200_triangles_compact_bvh(b: &mut ::test::Bencher) {
+        intersect_1200_triangles_bh::<CompactBVH>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 12,000 triangles using a `CompactBVH`.
+    fn bench_intersect_12k_triangles_compact_bvh(b: &mut ::test::Bencher) {
+        intersect_12k_triangles_bh::<CompactBVH>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a `CompactBVH`.
+    fn bench_intersect_120k_triangles_compact_bvh(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_bh::<CompactBVH>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200,000 triangles using a `FlatBVH`, as a baseline
+    /// for `bench_intersect_1200k_triangles_compact_bvh`. Prints the size of the nodes.
+    fn bench_intersect_1200k_triangles_flat_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100_000, &bounds);
+        let flat_bvh = BVH::build(&mut triangles).flatten();
+        println!(
+            "FlatBVH nodes: {} bytes",
+            flat_bvh.len() * std::mem::size_of_val(&flat_bvh[0])
+        );
+        intersect_bh::<FlatBVH>(&flat_bvh, &triangles, &bounds, b)
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200,000 triangles using a `CompactBVH`. Prints the size of
+    /// the nodes.
+    fn bench_intersect_1200k_triangles_compact_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100_000, &bounds);
+        let compact = BVH::build(&mut triangles).compact().unwrap();
+        println!(
+            "CompactBVH nodes: {} bytes",
+            compact.nodes.len() * std::mem::size_of_val(&compact.nodes[0])
+        );
+        intersect_bh(&compact, &triangles, &bounds, b)
+    }
+}
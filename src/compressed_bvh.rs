@@ -0,0 +1,493 @@
+//! This module defines [`CompressedBVH`], a flat [`BVH`] whose nodes store their bounds
+//! as 16 bit offsets relative to the bounds of their parent.
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`CompressedBVH`]: struct.CompressedBVH.html
+//!
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
+use crate::bvh::BVH;
+use crate::flat_bvh::FlatNode;
+use crate::ray::Ray;
+use crate::Point3;
+
+/// The largest quantized coordinate, which maps to the maximum bound of the parent.
+const QUANT_MAX: f32 = u16::MAX as f32;
+
+/// Maps the quantized coordinate `q` back into the interval `[lo, hi]`.
+/// The two ends of the range map exactly to `lo` and `hi`.
+fn dequantize(q: u16, lo: f32, hi: f32) -> f32 {
+    match q {
+        0 => lo,
+        u16::MAX => hi,
+        _ => lo + (hi - lo) * (q as f32 / QUANT_MAX),
+    }
+}
+
+/// Quantizes `value` in the interval `[lo, hi]`, rounding down so that
+/// the dequantized coordinate is never larger than `value`.
+fn quantize_min(value: f32, lo: f32, hi: f32) -> u16 {
+    let extent = hi - lo;
+    if !(extent > 0.0 && extent.is_finite()) {
+        return 0;
+    }
+    let mut q = ((value - lo) / extent * QUANT_MAX)
+        .floor()
+        .clamp(0.0, QUANT_MAX) as u16;
+    while q > 0 && dequantize(q, lo, hi) > value {
+        q -= 1;
+    }
+    q
+}
+
+/// Quantizes `value` in the interval `[lo, hi]`, rounding up so that
+/// the dequantized coordinate is never smaller than `value`.
+fn quantize_max(value: f32, lo: f32, hi: f32) -> u16 {
+    let extent = hi - lo;
+    if !(extent > 0.0 && extent.is_finite()) {
+        return u16::MAX;
+    }
+    let mut q = ((value - lo) / extent * QUANT_MAX)
+        .ceil()
+        .clamp(0.0, QUANT_MAX) as u16;
+    while q < u16::MAX && dequantize(q, lo, hi) < value {
+        q += 1;
+    }
+    q
+}
+
+/// A node of a [`CompressedBVH`] of 20 bytes. The bounds of inner nodes are quantized
+/// relative to the bounds of their parent and always rounded outward, so the dequantized
+/// [`AABB`] contains the exact one.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`CompressedBVH`]: struct.CompressedBVH.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct CompressedNode {
+    /// The quantized minimum bound. `0` maps to the minimum bound of the parent.
+    pub min: [u16; 3],
+
+    /// The quantized maximum bound. [`u16::MAX`] maps to the maximum bound of the parent.
+    ///
+    /// [`u16::MAX`]: https://doc.rust-lang.org/std/primitive.u16.html#associatedconstant.MAX
+    ///
+    pub max: [u16; 3],
+
+    /// The index of the node to continue with once this node's subtree is done, or
+    /// skipped because its [`AABB`] was missed. The subtree itself starts right after
+    /// this node, so its index is not stored.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub exit_index: u32,
+
+    /// The index of the shape in the shapes array for leaves.
+    /// Inner nodes have a `shape_index` of [`u32::MAX`]. The bounds of leaves are unused,
+    /// because the [`AABB`] of the shape itself is tested instead.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/primitive.u32.html#associatedconstant.MAX
+    ///
+    pub shape_index: u32,
+}
+
+impl CompressedNode {
+    /// Returns `true` if the node is a leaf.
+    pub fn is_leaf(&self) -> bool {
+        self.shape_index != u32::MAX
+    }
+
+    /// Returns the dequantized [`AABB`] of the node, given the dequantized [`AABB`]
+    /// of its parent.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn aabb(&self, parent: &AABB) -> AABB {
+        let min = Point3::new(
+            dequantize(self.min[0], parent.min.x, parent.max.x),
+            dequantize(self.min[1], parent.min.y, parent.max.y),
+            dequantize(self.min[2], parent.min.z, parent.max.z),
+        );
+        let max = Point3::new(
+            dequantize(self.max[0], parent.min.x, parent.max.x),
+            dequantize(self.max[1], parent.min.y, parent.max.y),
+            dequantize(self.max[2], parent.min.z, parent.max.z),
+        );
+        AABB::with_bounds(min, max)
+    }
+
+    /// Creates an inner node whose bounds contain `aabb`, quantized relative to `parent`.
+    fn quantize(aabb: &AABB, parent: &AABB, exit_index: u32) -> CompressedNode {
+        CompressedNode {
+            min: [
+                quantize_min(aabb.min.x, parent.min.x, parent.max.x),
+                quantize_min(aabb.min.y, parent.min.y, parent.max.y),
+                quantize_min(aabb.min.z, parent.min.z, parent.max.z),
+            ],
+            max: [
+                quantize_max(aabb.max.x, parent.min.x, parent.max.x),
+                quantize_max(aabb.max.y, parent.min.y, parent.max.y),
+                quantize_max(aabb.max.z, parent.min.z, parent.max.z),
+            ],
+            exit_index,
+            shape_index: u32::MAX,
+        }
+    }
+}
+
+/// A flat [`BVH`] with quantized bounds, see [`CompressedNode`].
+///
+/// The nodes are the nodes of a [`FlatBVH`] in the same depth-first order, but take
+/// 20 instead of 36 bytes each, which helps when the traversal of a large scene is limited
+/// by memory bandwidth. Since the bounds of a node are relative to its parent, the traversal
+/// keeps a small stack of the dequantized bounds of the nodes it entered.
+///
+/// The quantized bounds are never smaller than the exact ones, so a traversal of a
+/// [`CompressedBVH`] returns every shape the [`FlatBVH`] it was created from returns.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`CompressedBVH`]: struct.CompressedBVH.html
+/// [`CompressedNode`]: struct.CompressedNode.html
+/// [`FlatBVH`]: ../flat_bvh/type.FlatBVH.html
+///
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct CompressedBVH {
+    /// The exact bounds of the whole hierarchy, which the top level nodes are relative to.
+    pub bounds: AABB,
+
+    /// The nodes in depth-first order.
+    pub nodes: Vec<CompressedNode>,
+}
+
+impl CompressedBVH {
+    /// Creates a [`CompressedBVH`] from a [`FlatBVH`] as created by [`BVH::flatten`].
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::compressed_bvh::CompressedBVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// struct Sphere {
+    ///     position: Point3,
+    ///     node_index: usize,
+    /// }
+    ///
+    /// impl Bounded for Sphere {
+    ///     fn aabb(&self) -> AABB {
+    ///         let half_size = Vector3::new(1.0, 1.0, 1.0);
+    ///         AABB::with_bounds(self.position - half_size, self.position + half_size)
+    ///     }
+    /// }
+    ///
+    /// impl BHShape for Sphere {
+    ///     fn set_bh_node_index(&mut self, index: usize) {
+    ///         self.node_index = index;
+    ///     }
+    ///
+    ///     fn bh_node_index(&self) -> usize {
+    ///         self.node_index
+    ///     }
+    /// }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32 * 3.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let flat_bvh = BVH::build(&mut spheres).flatten();
+    /// let compressed = CompressedBVH::from_flat_bvh(&flat_bvh);
+    ///
+    /// let ray = Ray::new(Point3::new(30.0, -10.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    /// let hits = compressed.traverse(&ray, &spheres);
+    /// assert_eq!(hits.len(), 1);
+    /// assert_eq!(hits[0].position, Point3::new(30.0, 0.0, 0.0));
+    /// ```
+    ///
+    /// [`BVH::flatten`]: ../bvh/struct.BVH.html#method.flatten
+    /// [`CompressedBVH`]: struct.CompressedBVH.html
+    /// [`FlatBVH`]: ../flat_bvh/type.FlatBVH.html
+    ///
+    pub fn from_flat_bvh(flat_bvh: &[FlatNode]) -> CompressedBVH {
+        let bounds = flat_bvh
+            .iter()
+            .filter(|node| node.entry_index != u32::MAX)
+            .fold(AABB::empty(), |bounds, node| bounds.join(&node.aabb));
+
+        // Children are quantized relative to the dequantized bounds of their parent,
+        // because those are the bounds the traversal knows.
+        let mut parents: Vec<(AABB, usize)> = Vec::new();
+        let mut nodes = Vec::with_capacity(flat_bvh.len());
+        for (index, node) in flat_bvh.iter().enumerate() {
+            while parents.last().is_some_and(|&(_, exit)| index >= exit) {
+                parents.pop();
+            }
+            let parent = parents.last().map_or(bounds, |&(aabb, _)| aabb);
+
+            if node.entry_index == u32::MAX {
+                nodes.push(CompressedNode {
+                    min: [0; 3],
+                    max: [u16::MAX; 3],
+                    exit_index: node.exit_index,
+                    shape_index: node.shape_index,
+                });
+            } else {
+                let compressed = CompressedNode::quantize(&node.aabb, &parent, node.exit_index);
+                parents.push((compressed.aabb(&parent), node.exit_index as usize));
+                nodes.push(compressed);
+            }
+        }
+
+        CompressedBVH { bounds, nodes }
+    }
+
+    /// Traverses the [`CompressedBVH`] iteratively.
+    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`CompressedBVH`]: struct.CompressedBVH.html
+    ///
+    pub fn traverse<'a, Shape: Bounded>(
+        &'a self,
+        ray: &Ray,
+        shapes: &'a [Shape],
+    ) -> Vec<&'a Shape> {
+        let mut hit_shapes = Vec::new();
+        let mut parents: Vec<(AABB, usize)> = Vec::new();
+        let mut index = 0;
+        while index < self.nodes.len() {
+            // Leave the subtrees which are done.
+            while parents.last().is_some_and(|&(_, exit)| index >= exit) {
+                parents.pop();
+            }
+
+            let node = &self.nodes[index];
+            if node.is_leaf() {
+                let shape = &shapes[node.shape_index as usize];
+                if ray.intersects_aabb(&shape.aabb()) {
+                    hit_shapes.push(shape);
+                }
+                index = node.exit_index as usize;
+                continue;
+            }
+
+            let parent = parents.last().map_or(&self.bounds, |(aabb, _)| aabb);
+            let aabb = node.aabb(parent);
+            if ray.intersects_aabb(&aabb) {
+                // Enter the subtree, which starts right after this node.
+                parents.push((aabb, node.exit_index as usize));
+                index += 1;
+            } else {
+                index = node.exit_index as usize;
+            }
+        }
+        hit_shapes
+    }
+}
+
+impl BVH {
+    /// Flattens the [`BVH`] and converts it into a [`CompressedBVH`].
+    ///
+    /// Each node then takes 20 instead of the 36 bytes of a [`FlatNode`], at the cost of
+    /// accuracy: the bounds of a node are rounded outward to a grid of 65535 steps across the
+    /// bounds of its parent. The traversal never misses a shape because of that, but a ray
+    /// which passes within one step of a node's exact bounds enters it, so a traversal may
+    /// return a few more candidates than the [`BVH`] does. The bounds of leaves are not
+    /// quantized, since the [`AABB`]s of the shapes themselves are tested.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::compressed_bvh::CompressedNode;
+    /// use bvh::flat_bvh::FlatNode;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..1000)
+    ///     .map(|i| {
+    ///         let min = Point3::new((i % 10) as f32, (i / 10 % 10) as f32, (i / 100) as f32);
+    ///         AABB::with_bounds(min, min + Vector3::splat(0.5))
+    ///     })
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    /// let compressed = bvh.compress();
+    ///
+    /// let flat_size = bvh.flatten().len() * std::mem::size_of::<FlatNode>();
+    /// let compressed_size = compressed.nodes.len() * std::mem::size_of::<CompressedNode>();
+    /// assert!(compressed_size * 9 <= flat_size * 5);
+    ///
+    /// // The compressed hierarchy finds every box the exact one finds.
+    /// let ray = Ray::new(Point3::new(-1.0, 0.25, 0.25), Vector3::new(1.0, 0.1, 0.1));
+    /// let exact = bvh.traverse(&ray, &boxes);
+    /// let approximate = compressed.traverse(&ray, &boxes);
+    /// assert!(exact.iter().all(|hit| approximate.iter().any(|other| std::ptr::eq(*hit, *other))));
+    /// ```
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH`]: struct.BVH.html
+    /// [`CompressedBVH`]: ../compressed_bvh/struct.CompressedBVH.html
+    /// [`FlatNode`]: ../flat_bvh/struct.FlatNode.html
+    ///
+    pub fn compress(&self) -> CompressedBVH {
+        CompressedBVH::from_flat_bvh(&self.flatten())
+    }
+}
+
+impl BoundingHierarchy for CompressedBVH {
+    /// A [`CompressedBVH`] is built from a regular [`BVH`] using the [`compress`] method.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`CompressedBVH`]: struct.CompressedBVH.html
+    /// [`compress`]: ../bvh/struct.BVH.html#method.compress
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> CompressedBVH {
+        BVH::build(shapes).compress()
+    }
+
+    fn traverse<'a, Shape: Bounded>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&'a Shape> {
+        self.traverse(ray, shapes)
+    }
+
+    /// Prints a textual representation of a [`CompressedBVH`].
+    ///
+    /// [`CompressedBVH`]: struct.CompressedBVH.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            println!(
+                "{}\tmin {:?}\tmax {:?}\texit {}\tshape {}",
+                i, node.min, node.max, node.exit_index, node.shape_index
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bounding_hierarchy::BoundingHierarchy;
+    use crate::bvh::BVH;
+    use crate::compressed_bvh::{CompressedBVH, CompressedNode};
+    use crate::testbase::{build_some_bh, ray_toward_random_shape, traverse_some_bh, unit_boxes};
+    use crate::{Point3, Vector3};
+
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+
+    #[test]
+    /// Makes sure that the node layout does not grow unnoticed.
+    fn test_compressed_node_size() {
+        assert_eq!(std::mem::size_of::<CompressedNode>(), 20);
+    }
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_compressed_bvh() {
+        build_some_bh::<CompressedBVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given
+    /// as a `CompressedBVH`.
+    fn test_traverse_compressed_bvh() {
+        traverse_some_bh::<CompressedBVH>();
+    }
+
+    proptest! {
+        // Test whether the quantized bounds of a box contain the box.
+        #[test]
+        fn test_quantize_is_conservative(
+            a in (-1000.0f32..1000.0, -1000.0f32..1000.0, -1000.0f32..1000.0),
+            b in (-1000.0f32..1000.0, -1000.0f32..1000.0, -1000.0f32..1000.0),
+            t in (0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0),
+            s in (0.0f32..1.0, 0.0f32..1.0, 0.0f32..1.0),
+        ) {
+            let parent = AABB::empty()
+                .grow(&Point3::new(a.0, a.1, a.2))
+                .grow(&Point3::new(b.0, b.1, b.2));
+            let p = parent.min + parent.size() * Vector3::new(t.0, t.1, t.2);
+            let q = parent.min + parent.size() * Vector3::new(s.0, s.1, s.2);
+            let aabb = AABB::empty().grow(&p).grow(&q);
+
+            let node = CompressedNode::quantize(&aabb, &parent, 0);
+            let dequantized = node.aabb(&parent);
+            assert!(dequantized.min.cmple(aabb.min).all());
+            assert!(dequantized.max.cmpge(aabb.max).all());
+        }
+
+        // Test whether the `CompressedBVH` yields every shape the `FlatBVH` it was created
+        // from yields, for random scenes and rays.
+        #[test]
+        fn test_compressed_bvh_traverse_is_conservative(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            aim in ray_toward_random_shape(200.0, 2.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let flat_bvh = BVH::build(&mut shapes).flatten();
+            let compressed = CompressedBVH::from_flat_bvh(&flat_bvh);
+            assert_eq!(compressed.nodes.len(), flat_bvh.len());
+
+            let ray = aim.ray(&shapes);
+
+            let expected = flat_bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<HashSet<_>>();
+            let found = compressed.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<HashSet<_>>();
+            assert!(expected.is_subset(&found));
+        }
+    }
+}
+
+#[cfg(all(feature = "bench", test))]
+mod bench {
+    use crate::bvh::BVH;
+    use crate::compressed_bvh::CompressedBVH;
+    use crate::testbase::{
+        create_n_cubes, default_bounds, intersect_120k_triangles_bh, intersect_12k_triangles_bh,
+        intersect_bh,
+    };
+
+    #[bench]
+    /// Benchmark compressing a `BVH` with 120,000 triangles.
+    fn bench_compress_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        b.iter(|| {
+            bvh.compress();
+        });
+    }
+
+    #[bench]
+    /// Benchmark intersecting 12,000 triangles using a `CompressedBVH`.
+    fn bench_intersect_12k_triangles_compressed_bvh(b: &mut ::test::Bencher) {
+        intersect_12k_triangles_bh::<CompressedBVH>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a `CompressedBVH`.
+    fn bench_intersect_120k_triangles_compressed_bvh(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_bh::<CompressedBVH>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 1,200,000 triangles using a `CompressedBVH`. Prints the size
+    /// of the nodes, compare with `bench_intersect_1200k_triangles_flat_bvh`.
+    fn bench_intersect_1200k_triangles_compressed_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(100_000, &bounds);
+        let compressed = BVH::build(&mut triangles).compress();
+        println!(
+            "CompressedBVH nodes: {} bytes",
+            compressed.nodes.len() * std::mem::size_of_val(&compressed.nodes[0])
+        );
+        intersect_bh(&compressed, &triangles, &bounds, b)
+    }
+}
@@ -1,33 +1,40 @@
 //! This module exports methods to flatten the `BVH` and traverse it iteratively.
 
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
 use crate::aabb::{Bounded, AABB};
 use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
 use crate::bvh::{BVHNode, BVH};
+pub use crate::gpu::GpuNode;
 use crate::ray::Ray;
+use crate::Point3;
 
 /// A structure of a node of a flat [`BVH`]. The structure of the nodes allows for an
 /// iterative traversal approach without the necessity to maintain a stack or queue.
 ///
 /// [`BVH`]: ../bvh/struct.BVH.html
 ///
+#[derive(Debug, Copy, Clone)]
 pub struct FlatNode {
     /// The [`AABB`] of the [`BVH`] node. Prior to testing the [`AABB`] bounds,
-    /// the `entry_index` must be checked. In case the entry_index is [`u32::max_value()`],
+    /// the `entry_index` must be checked. In case the entry_index is [`u32::MAX`],
     /// the [`AABB`] is undefined.
     ///
     /// [`AABB`]: ../aabb/struct.AABB.html
     /// [`BVH`]: ../bvh/struct.BVH.html
-    /// [`u32::max_value()`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/primitive.u32.html#associatedconstant.MAX
     ///
     pub aabb: AABB,
 
     /// The index of the `FlatNode` to jump to, if the [`AABB`] test is positive.
-    /// If this value is [`u32::max_value()`] then the current node is a leaf node.
+    /// If this value is [`u32::MAX`] then the current node is a leaf node.
     /// Leaf nodes contain a shape index and an exit index. In leaf nodes the
     /// [`AABB`] is undefined.
     ///
     /// [`AABB`]: ../aabb/struct.AABB.html
-    /// [`u32::max_value()`]: https://doc.rust-lang.org/std/u32/constant.MAX.html
+    /// [`u32::MAX`]: https://doc.rust-lang.org/std/primitive.u32.html#associatedconstant.MAX
     ///
     pub entry_index: u32,
 
@@ -70,7 +77,7 @@ impl BVHNode {
             this_aabb,
             (next_free + 1) as u32,
             index_after_subtree as u32,
-            u32::max_value(),
+            u32::MAX,
         );
         vec[next_free] = navigator_node;
         index_after_subtree
@@ -114,16 +121,23 @@ impl BVHNode {
                     constructor,
                 )
             }
-            BVHNode::Leaf { shape_index, .. } => {
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => {
+                // Every shape of the leaf gets its own flat leaf node.
                 let mut next_shape = next_free;
-                next_shape += 1;
-                let leaf_node = constructor(
-                    &AABB::empty(),
-                    u32::max_value(),
-                    next_shape as u32,
-                    shape_index as u32,
-                );
-                vec.push(leaf_node);
+                for shape_index in shape_index..shape_index + shape_count {
+                    next_shape += 1;
+                    let leaf_node = constructor(
+                        &AABB::empty(),
+                        u32::MAX,
+                        next_shape as u32,
+                        shape_index as u32,
+                    );
+                    vec.push(leaf_node);
+                }
 
                 next_shape
             }
@@ -134,6 +148,10 @@ impl BVHNode {
 /// A flat [`BVH`]. Represented by a vector of [`FlatNode`]s. The [`FlatBVH`] is designed for use
 /// where a recursive traversal of a data structure is not possible, for example shader programs.
 ///
+/// The nodes are stored contiguously in depth-first order: the first node of a subtree directly
+/// follows the node that enters it, and the `exit_index` of a node skips over its whole subtree.
+/// This keeps the traversal cache friendly and makes it possible without any stack.
+///
 /// [`BVH`]: ../bvh/struct.BVH.html
 /// [`FlatNode`]: struct.FlatNode.html
 /// [`FlatBVH`]: struct.FlatBVH.html
@@ -304,6 +322,440 @@ impl BVH {
     }
 }
 
+impl GpuNode {
+    /// Appends the subtree below `node_index` of the [`BVH`] in depth-first order.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    ///
+    fn push_subtree(bvh_nodes: &[BVHNode], node_index: usize, aabb: &AABB, nodes: &mut Vec<Self>) {
+        let index = nodes.len();
+        nodes.push(GpuNode {
+            min: aabb.min.into(),
+            index: 0,
+            max: aabb.max.into(),
+            shape_count: 0,
+        });
+
+        match bvh_nodes[node_index] {
+            BVHNode::Node {
+                ref child_l_aabb,
+                child_l_index,
+                ref child_r_aabb,
+                child_r_index,
+                ..
+            } => {
+                GpuNode::push_subtree(bvh_nodes, child_l_index, child_l_aabb, nodes);
+                GpuNode::push_subtree(bvh_nodes, child_r_index, child_r_aabb, nodes);
+                nodes[index].index = nodes.len() as u32;
+            }
+            BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            } => {
+                nodes[index].index = shape_index as u32;
+                nodes[index].shape_count = shape_count as u32;
+            }
+        }
+    }
+}
+
+impl BVH {
+    /// Flattens the [`BVH`] into [`GpuNode`]s, which can be copied into a GPU buffer
+    /// and traversed by a shader without a stack. See [`GpuNode`] for the layout of the
+    /// nodes and how to traverse them.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// struct Sphere {
+    ///     position: Point3,
+    ///     node_index: usize,
+    /// }
+    ///
+    /// impl Bounded for Sphere {
+    ///     fn aabb(&self) -> AABB {
+    ///         let half_size = Vector3::new(1.0, 1.0, 1.0);
+    ///         AABB::with_bounds(self.position - half_size, self.position + half_size)
+    ///     }
+    /// }
+    ///
+    /// impl BHShape for Sphere {
+    ///     fn set_bh_node_index(&mut self, index: usize) {
+    ///         self.node_index = index;
+    ///     }
+    ///
+    ///     fn bh_node_index(&self) -> usize {
+    ///         self.node_index
+    ///     }
+    /// }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32 * 3.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let nodes = BVH::build(&mut spheres).flatten_gpu();
+    ///
+    /// // The root is an inner node whose subtree spans all nodes.
+    /// assert!(!nodes[0].is_leaf());
+    /// assert_eq!(nodes[0].index as usize, nodes.len());
+    /// assert_eq!(nodes.iter().map(|node| node.shape_count).sum::<u32>(), 100);
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`GpuNode`]: ../gpu/struct.GpuNode.html
+    ///
+    pub fn flatten_gpu(&self) -> Vec<GpuNode> {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        match self.nodes.first() {
+            None => {}
+            Some(BVHNode::Node {
+                child_l_aabb,
+                child_r_aabb,
+                ..
+            }) => {
+                let aabb = child_l_aabb.join(child_r_aabb);
+                GpuNode::push_subtree(&self.nodes, 0, &aabb, &mut nodes);
+            }
+            Some(BVHNode::Leaf { .. }) => {
+                // A `BVH` consisting of a single leaf reports its shapes for every ray.
+                // An infinite `AABB` is hit by every ray, which keeps that behaviour.
+                let aabb = AABB::infinite();
+                GpuNode::push_subtree(&self.nodes, 0, &aabb, &mut nodes);
+            }
+        }
+        nodes
+    }
+}
+
+/// The magic bytes at the start of a [`FlatBVH`] written by [`write_flat_bvh`].
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`write_flat_bvh`]: fn.write_flat_bvh.html
+///
+pub const FLAT_BVH_MAGIC: [u8; 4] = *b"BVHF";
+
+/// The version of the binary format written by [`write_flat_bvh`].
+///
+/// [`write_flat_bvh`]: fn.write_flat_bvh.html
+///
+pub const FLAT_BVH_FORMAT_VERSION: u32 = 1;
+
+/// Written in little endian, this marker reads as a different number if the data is
+/// interpreted with the wrong byte order.
+const ENDIANNESS_MARKER: u32 = 0x0102_0304;
+
+/// The error returned by [`read_flat_bvh`] for data which is not a valid [`FlatBVH`].
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`read_flat_bvh`]: fn.read_flat_bvh.html
+///
+#[derive(Debug)]
+pub enum ReadFlatBVHError {
+    /// Reading from the underlying reader failed, for example because the data ended early.
+    Io(io::Error),
+
+    /// The data does not start with [`FLAT_BVH_MAGIC`].
+    ///
+    /// [`FLAT_BVH_MAGIC`]: constant.FLAT_BVH_MAGIC.html
+    ///
+    InvalidMagic([u8; 4]),
+
+    /// The data was written in a version of the format which is not supported.
+    UnsupportedVersion(u32),
+
+    /// The data was written with a different byte order.
+    InvalidEndianness(u32),
+
+    /// There are more nodes than `u32` indices can address.
+    InvalidNodeCount(u64),
+
+    /// A node references a node or shape which does not exist, or would make the
+    /// traversal run backwards.
+    InvalidIndex {
+        /// The index of the invalid node.
+        node: usize,
+
+        /// The name of the invalid field of the node.
+        field: &'static str,
+
+        /// The invalid value.
+        value: u32,
+    },
+}
+
+impl fmt::Display for ReadFlatBVHError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadFlatBVHError::Io(error) => write!(f, "failed to read the flat BVH: {}", error),
+            ReadFlatBVHError::InvalidMagic(magic) => {
+                write!(f, "invalid magic bytes {:?}, this is not a flat BVH", magic)
+            }
+            ReadFlatBVHError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported format version {}, expected {}",
+                version, FLAT_BVH_FORMAT_VERSION
+            ),
+            ReadFlatBVHError::InvalidEndianness(marker) => {
+                write!(f, "invalid endianness marker {:#010x}", marker)
+            }
+            ReadFlatBVHError::InvalidNodeCount(count) => {
+                write!(f, "invalid node count {}", count)
+            }
+            ReadFlatBVHError::InvalidIndex { node, field, value } => {
+                write!(f, "node {} has an invalid {} of {}", node, field, value)
+            }
+        }
+    }
+}
+
+impl Error for ReadFlatBVHError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReadFlatBVHError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReadFlatBVHError {
+    fn from(error: io::Error) -> ReadFlatBVHError {
+        ReadFlatBVHError::Io(error)
+    }
+}
+
+/// Writes a [`FlatBVH`] to `writer` in a stable binary format, so that it can be cached
+/// instead of being built again. All values are little endian:
+///
+/// 1 - [u8; 4]: The magic bytes [`FLAT_BVH_MAGIC`]
+/// 2 - u32: The format version [`FLAT_BVH_FORMAT_VERSION`]
+/// 3 - u32: The endianness marker `0x01020304`
+/// 4 - u64: The number of nodes
+/// 5 - The nodes, each as its `AABB`'s minimum and maximum as 6 `f32`s, followed by
+/// the `entry_index`, the `exit_index` and the `shape_index` as `u32`s
+///
+/// Many small writes are issued, so `writer` should be buffered.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{AABB, Bounded};
+/// use bvh::bounding_hierarchy::BHShape;
+/// use bvh::bvh::BVH;
+/// use bvh::flat_bvh::{read_flat_bvh, write_flat_bvh};
+/// use bvh::{Point3, Vector3};
+///
+/// struct Sphere {
+///     position: Point3,
+///     node_index: usize,
+/// }
+///
+/// impl Bounded for Sphere {
+///     fn aabb(&self) -> AABB {
+///         let half_size = Vector3::new(1.0, 1.0, 1.0);
+///         AABB::with_bounds(self.position - half_size, self.position + half_size)
+///     }
+/// }
+///
+/// impl BHShape for Sphere {
+///     fn set_bh_node_index(&mut self, index: usize) {
+///         self.node_index = index;
+///     }
+///
+///     fn bh_node_index(&self) -> usize {
+///         self.node_index
+///     }
+/// }
+///
+/// let mut spheres = (0..100)
+///     .map(|i| Sphere { position: Point3::new(i as f32 * 3.0, 0.0, 0.0), node_index: 0 })
+///     .collect::<Vec<_>>();
+/// let flat_bvh = BVH::build(&mut spheres).flatten();
+///
+/// let mut bytes = Vec::new();
+/// write_flat_bvh(&flat_bvh, &mut bytes).unwrap();
+/// let loaded = read_flat_bvh(&bytes[..], spheres.len()).unwrap();
+/// assert_eq!(format!("{:?}", loaded), format!("{:?}", flat_bvh));
+/// ```
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`FLAT_BVH_FORMAT_VERSION`]: constant.FLAT_BVH_FORMAT_VERSION.html
+/// [`FLAT_BVH_MAGIC`]: constant.FLAT_BVH_MAGIC.html
+///
+pub fn write_flat_bvh<W: Write>(flat_bvh: &[FlatNode], mut writer: W) -> io::Result<()> {
+    writer.write_all(&FLAT_BVH_MAGIC)?;
+    writer.write_all(&FLAT_BVH_FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&ENDIANNESS_MARKER.to_le_bytes())?;
+    writer.write_all(&(flat_bvh.len() as u64).to_le_bytes())?;
+    for node in flat_bvh {
+        let floats = [node.aabb.min.to_array(), node.aabb.max.to_array()];
+        for value in floats.iter().flatten() {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for value in &[node.entry_index, node.exit_index, node.shape_index] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+    }
+    writer.flush()
+}
+
+/// Reads a [`FlatBVH`] written by [`write_flat_bvh`] from `reader`.
+///
+/// The data is validated for a [`FlatBVH`] of `shape_count` shapes. Every node must
+/// reference existing nodes and shapes, and the traversal must always move forward, so
+/// the result can be traversed without panicking or looping forever. Invalid data yields
+/// a [`ReadFlatBVHError`] describing the problem.
+///
+/// Many small reads are issued, so `reader` should be buffered.
+///
+/// [`FlatBVH`]: type.FlatBVH.html
+/// [`ReadFlatBVHError`]: enum.ReadFlatBVHError.html
+/// [`write_flat_bvh`]: fn.write_flat_bvh.html
+///
+pub fn read_flat_bvh<R: Read>(
+    mut reader: R,
+    shape_count: usize,
+) -> Result<FlatBVH, ReadFlatBVHError> {
+    fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_f32<R: Read>(reader: &mut R) -> io::Result<f32> {
+        read_u32(reader).map(f32::from_bits)
+    }
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != FLAT_BVH_MAGIC {
+        return Err(ReadFlatBVHError::InvalidMagic(magic));
+    }
+    let version = read_u32(&mut reader)?;
+    if version != FLAT_BVH_FORMAT_VERSION {
+        return Err(ReadFlatBVHError::UnsupportedVersion(version));
+    }
+    let marker = read_u32(&mut reader)?;
+    if marker != ENDIANNESS_MARKER {
+        return Err(ReadFlatBVHError::InvalidEndianness(marker));
+    }
+    let mut count = [0; 8];
+    reader.read_exact(&mut count)?;
+    let count = u64::from_le_bytes(count);
+    // `u32::MAX` marks leaves, so it must not be a valid node index.
+    if count >= u64::from(u32::MAX) {
+        return Err(ReadFlatBVHError::InvalidNodeCount(count));
+    }
+    let count = count as usize;
+
+    // Don't trust the count for the allocation, the data may end much earlier.
+    let mut nodes = Vec::with_capacity(count.min(1 << 16));
+    for index in 0..count {
+        let min = Point3::new(
+            read_f32(&mut reader)?,
+            read_f32(&mut reader)?,
+            read_f32(&mut reader)?,
+        );
+        let max = Point3::new(
+            read_f32(&mut reader)?,
+            read_f32(&mut reader)?,
+            read_f32(&mut reader)?,
+        );
+        let node = FlatNode {
+            aabb: AABB::with_bounds(min, max),
+            entry_index: read_u32(&mut reader)?,
+            exit_index: read_u32(&mut reader)?,
+            shape_index: read_u32(&mut reader)?,
+        };
+
+        let invalid = |field, value| ReadFlatBVHError::InvalidIndex {
+            node: index,
+            field,
+            value,
+        };
+        if node.entry_index == u32::MAX {
+            if node.shape_index as usize >= shape_count {
+                return Err(invalid("shape_index", node.shape_index));
+            }
+        } else if node.entry_index as usize != index + 1 || node.entry_index as usize >= count {
+            // The subtree of an inner node starts right after it and is never empty.
+            return Err(invalid("entry_index", node.entry_index));
+        }
+        if node.exit_index as usize <= index || node.exit_index as usize > count {
+            return Err(invalid("exit_index", node.exit_index));
+        }
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+/// Traverses `flat_bvh` with an explicit stack and returns the indices of the shapes whose
+/// leaves are reached by `ray`, in depth-first order. Like [`BVH::traverse_into`], the shapes
+/// themselves are not tested, so the result may contain shapes which `ray` misses.
+///
+/// The stackless traversal of [`BoundingHierarchy::traverse`] follows the exit indices and
+/// needs the shapes to test every leaf. This traversal only tests the bounds of the inner
+/// nodes, and pushes the children of every hit node onto a stack, so it suits CPU code which
+/// keeps the shapes elsewhere. [`FlatBVH`] is a type alias of `Vec<FlatNode>`, which can't
+/// have inherent methods, and `traverse` is already taken by [`BoundingHierarchy`], so this
+/// is a free function.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::AABB;
+/// use bvh::bvh::BVH;
+/// use bvh::flat_bvh::traverse_flat_bvh;
+/// use bvh::ray::Ray;
+/// use bvh::{Point3, Vector3};
+///
+/// let boxes = (0..10)
+///     .map(|i| {
+///         let min = Point3::new(i as f32 * 2.0, 0.0, 0.0);
+///         AABB::with_bounds(min, min + Point3::ONE)
+///     })
+///     .collect::<Vec<_>>();
+/// let flat_bvh = BVH::build_dyn(&boxes).flatten();
+///
+/// let ray = Ray::new(Point3::new(6.5, -10.0, 0.5), Vector3::new(0.0, 1.0, 0.0));
+/// assert_eq!(traverse_flat_bvh(&flat_bvh, &ray), vec![3]);
+/// ```
+///
+/// [`BoundingHierarchy`]: ../bounding_hierarchy/trait.BoundingHierarchy.html
+/// [`BoundingHierarchy::traverse`]: ../bounding_hierarchy/trait.BoundingHierarchy.html#tymethod.traverse
+/// [`BVH::traverse_into`]: ../bvh/struct.BVH.html#method.traverse_into
+/// [`FlatBVH`]: type.FlatBVH.html
+///
+pub fn traverse_flat_bvh(flat_bvh: &[FlatNode], ray: &Ray) -> Vec<usize> {
+    /// Pushes the siblings from `first` up to `end` onto `stack`, so that `first` is popped
+    /// first. The siblings are chained by their exit indices.
+    fn push_children(flat_bvh: &[FlatNode], first: usize, end: usize, stack: &mut Vec<usize>) {
+        let start = stack.len();
+        let mut index = first;
+        while index < end {
+            stack.push(index);
+            index = flat_bvh[index].exit_index as usize;
+        }
+        stack[start..].reverse();
+    }
+
+    let mut hits = Vec::new();
+    // The children of the root are the top level of the flat nodes.
+    let mut stack = Vec::new();
+    push_children(flat_bvh, 0, flat_bvh.len(), &mut stack);
+    while let Some(index) = stack.pop() {
+        let node = &flat_bvh[index];
+        if node.entry_index == u32::MAX {
+            hits.push(node.shape_index as usize);
+        } else if ray.intersects_aabb(&node.aabb) {
+            let (entry, exit) = (node.entry_index as usize, node.exit_index as usize);
+            push_children(flat_bvh, entry, exit, &mut stack);
+        }
+    }
+    hits
+}
+
 impl BoundingHierarchy for FlatBVH {
     /// A [`FlatBVH`] is built from a regular [`BVH`] using the [`flatten`] method.
     ///
@@ -378,7 +830,7 @@ impl BoundingHierarchy for FlatBVH {
     /// let flat_bvh = FlatBVH::build(&mut shapes);
     /// let hit_shapes = flat_bvh.traverse(&ray, &shapes);
     /// ```
-    fn traverse<'a, T: Bounded>(&'a self, ray: &Ray, shapes: &'a [T]) -> Vec<&T> {
+    fn traverse<'a, T: Bounded>(&'a self, ray: &Ray, shapes: &'a [T]) -> Vec<&'a T> {
         let mut hit_shapes = Vec::new();
         let mut index = 0;
 
@@ -389,7 +841,7 @@ impl BoundingHierarchy for FlatBVH {
         while index < max_length {
             let node = &self[index];
 
-            if node.entry_index == u32::max_value() {
+            if node.entry_index == u32::MAX {
                 // If the entry_index is MAX_UINT32, then it's a leaf node.
                 let shape = &shapes[node.shape_index as usize];
                 if ray.intersects_aabb(&shape.aabb()) {
@@ -428,8 +880,44 @@ impl BoundingHierarchy for FlatBVH {
 
 #[cfg(test)]
 mod tests {
-    use crate::flat_bvh::FlatBVH;
-    use crate::testbase::{build_some_bh, traverse_some_bh};
+    use crate::bounding_hierarchy::BoundingHierarchy;
+    use crate::bvh::BVH;
+    use crate::flat_bvh::{
+        read_flat_bvh, traverse_flat_bvh, write_flat_bvh, FlatBVH, GpuNode, ReadFlatBVHError,
+    };
+    use crate::ray::Ray;
+    use crate::testbase::{
+        build_some_bh, build_with_max_leaf_size, ray_toward_random_shape, traverse_some_bh,
+        unit_boxes,
+    };
+    use crate::{Point3, Vector3};
+
+    use proptest::prelude::*;
+    use std::collections::HashSet;
+    use std::io;
+    use std::mem::{align_of, offset_of, size_of};
+
+    #[test]
+    /// Makes sure that the layout of `GpuNode` matches the documented `std430` struct.
+    fn test_gpu_node_layout() {
+        assert_eq!(size_of::<GpuNode>(), 32);
+        assert_eq!(align_of::<GpuNode>(), 4);
+        assert_eq!(offset_of!(GpuNode, min), 0);
+        assert_eq!(offset_of!(GpuNode, index), 12);
+        assert_eq!(offset_of!(GpuNode, max), 16);
+        assert_eq!(offset_of!(GpuNode, shape_count), 28);
+    }
+
+    #[cfg(feature = "bytemuck")]
+    #[test]
+    /// Tests whether `GpuNode`s can be cast to bytes and back.
+    fn test_gpu_node_bytemuck() {
+        let (_, bvh) = build_some_bh::<BVH>();
+        let nodes = bvh.flatten_gpu();
+        let bytes: &[u8] = bytemuck::cast_slice(&nodes);
+        assert_eq!(bytes.len(), nodes.len() * 32);
+        assert_eq!(bytemuck::cast_slice::<u8, GpuNode>(bytes), &nodes[..]);
+    }
 
     #[test]
     /// Tests whether the building procedure succeeds in not failing.
@@ -443,13 +931,177 @@ mod tests {
     fn test_traverse_flat_bvh() {
         traverse_some_bh::<FlatBVH>();
     }
+
+    #[test]
+    /// Tests whether a `FlatBVH` survives writing and reading it unchanged.
+    fn test_write_read_flat_bvh() {
+        let (shapes, bvh) = build_some_bh::<BVH>();
+        let flat_bvh = bvh.flatten();
+
+        let mut bytes = Vec::new();
+        write_flat_bvh(&flat_bvh, &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 20 + 36 * flat_bvh.len());
+        let loaded = read_flat_bvh(&bytes[..], shapes.len()).unwrap();
+        assert_eq!(format!("{:?}", loaded), format!("{:?}", flat_bvh));
+
+        let ray = Ray::new(Point3::new(-20.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let expected = flat_bvh
+            .traverse(&ray, &shapes)
+            .iter()
+            .map(|shape| shape.id)
+            .collect::<Vec<_>>();
+        let found = loaded
+            .traverse(&ray, &shapes)
+            .iter()
+            .map(|shape| shape.id)
+            .collect::<Vec<_>>();
+        assert_eq!(expected, found);
+
+        // An empty `FlatBVH` works as well.
+        let mut bytes = Vec::new();
+        write_flat_bvh(&[], &mut bytes).unwrap();
+        assert!(read_flat_bvh(&bytes[..], 0).unwrap().is_empty());
+    }
+
+    #[test]
+    /// Tests whether corrupted data is rejected with a matching error.
+    fn test_read_corrupted_flat_bvh() {
+        let (shapes, bvh) = build_some_bh::<BVH>();
+        let mut bytes = Vec::new();
+        write_flat_bvh(&bvh.flatten(), &mut bytes).unwrap();
+        let read = |bytes: &[u8]| read_flat_bvh(bytes, shapes.len()).unwrap_err();
+
+        let mut corrupted = bytes.clone();
+        corrupted[0] = b'X';
+        assert!(matches!(
+            read(&corrupted),
+            ReadFlatBVHError::InvalidMagic(_)
+        ));
+
+        let mut corrupted = bytes.clone();
+        corrupted[4..8].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(
+            read(&corrupted),
+            ReadFlatBVHError::UnsupportedVersion(2)
+        ));
+
+        let mut corrupted = bytes.clone();
+        corrupted[8..12].reverse();
+        assert!(matches!(
+            read(&corrupted),
+            ReadFlatBVHError::InvalidEndianness(_)
+        ));
+
+        let mut corrupted = bytes.clone();
+        corrupted[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(
+            read(&corrupted),
+            ReadFlatBVHError::InvalidNodeCount(_)
+        ));
+
+        // The data ends in the middle of a node.
+        let error = read(&bytes[..bytes.len() - 1]);
+        assert!(
+            matches!(error, ReadFlatBVHError::Io(ref e) if e.kind() == io::ErrorKind::UnexpectedEof)
+        );
+
+        // The exit index of the first node points past the last node.
+        let mut corrupted = bytes.clone();
+        corrupted[20 + 28..20 + 32].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            read(&corrupted),
+            ReadFlatBVHError::InvalidIndex {
+                node: 0,
+                field: "exit_index",
+                ..
+            }
+        ));
+
+        // There are fewer shapes than the leaves reference.
+        let error = read_flat_bvh(&bytes[..], 1).unwrap_err();
+        assert!(matches!(
+            error,
+            ReadFlatBVHError::InvalidIndex {
+                field: "shape_index",
+                ..
+            }
+        ));
+        assert!(error.to_string().contains("invalid shape_index"));
+    }
+
+    proptest! {
+        // Test whether traversing the `FlatBVH` yields exactly the shapes which
+        // the recursive traversal of the `BVH` yields, for random scenes and rays.
+        #[test]
+        fn test_flat_bvh_traverse_matches_bvh(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 2..64),
+            aim in ray_toward_random_shape(200.0, 2.0),
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = BVH::build(&mut shapes);
+            let flat_bvh = bvh.flatten();
+
+            let ray = aim.ray(&shapes);
+
+            let expected = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<HashSet<_>>();
+            let hits = flat_bvh.traverse(&ray, &shapes);
+            let found = hits.iter().map(|shape| shape.id).collect::<HashSet<_>>();
+
+            assert_eq!(hits.len(), found.len());
+            assert_eq!(expected, found);
+        }
+
+        // Test whether `traverse_flat_bvh` yields the candidates of `BVH::traverse_into` in the
+        // same order, with one or several shapes per leaf.
+        #[test]
+        fn test_traverse_flat_bvh_matches_traverse_into(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            aim in ray_toward_random_shape(200.0, 2.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let flat_bvh = bvh.flatten();
+
+            let ray = aim.ray(&shapes);
+
+            let mut expected = Vec::new();
+            bvh.traverse_into(&ray, &mut expected);
+            assert_eq!(traverse_flat_bvh(&flat_bvh, &ray), expected);
+        }
+
+        // Test whether the traversal of the `GpuNode`s yields the same shapes in the
+        // same order as the `BVH` they were created from, with one or several shapes per leaf.
+        #[test]
+        fn test_gpu_traverse_matches_bvh(
+            positions in prop::collection::vec((-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0), 1..64),
+            aim in ray_toward_random_shape(200.0, 2.0),
+            max_leaf_size in 1usize..5,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let nodes = bvh.flatten_gpu();
+
+            let ray = aim.ray(&shapes);
+
+            let expected = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            let mut hits = vec![0; shapes.len()];
+            let count = GpuNode::traverse(&nodes, &ray, &mut hits);
+            let found = hits[..count].iter().map(|&index| shapes[index].id).collect::<Vec<_>>();
+            assert_eq!(expected, found);
+
+            // A buffer that is too small receives a prefix of the hits, but the full count.
+            let mut truncated = vec![0; count / 2];
+            assert_eq!(GpuNode::traverse(&nodes, &ray, &mut truncated), count);
+            assert_eq!(&truncated[..], &hits[..count / 2]);
+        }
+    }
 }
 
 #[cfg(all(feature = "bench", test))]
 mod bench {
     use crate::bvh::BVH;
     use crate::flat_bvh::FlatBVH;
-
     use crate::testbase::{
         build_1200_triangles_bh, build_120k_triangles_bh, build_12k_triangles_bh, create_n_cubes,
         default_bounds, intersect_1200_triangles_bh, intersect_120k_triangles_bh,
@@ -0,0 +1,340 @@
+//! This module defines view frustums and their intersection tests with axis aligned
+//! bounding boxes, e.g. for visibility culling.
+//!
+//! This module only depends on `core`, so it is available in `no_std` builds.
+//! The [`BVH`] query for frustums is [`BVH::query_frustum`], which requires the `std` feature.
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`BVH::query_frustum`]: ../bvh/struct.BVH.html#method.query_frustum
+//!
+
+use crate::aabb::{HalfSpaceClass, AABB};
+use crate::{Point3, Vector3};
+
+/// A plane which splits space into an inside and an outside half.
+/// A point `p` lies inside if `normal.dot(p) + distance >= 0`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Plane {
+    /// The normal of the plane, which points to the inside.
+    pub normal: Vector3,
+
+    /// The signed distance of the origin to the plane, in multiples of the length of `normal`.
+    pub distance: f32,
+}
+
+impl Plane {
+    /// Creates a new [`Plane`] from its `normal`, which points to the inside,
+    /// and the signed `distance` of the origin to it.
+    ///
+    /// [`Plane`]: struct.Plane.html
+    ///
+    pub fn new(normal: Vector3, distance: f32) -> Plane {
+        Plane { normal, distance }
+    }
+
+    /// Creates a new [`Plane`] through `point` with the given `normal`, which points to the
+    /// inside.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::frustum::Plane;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let plane = Plane::from_point_normal(Point3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, 1.0));
+    ///
+    /// assert_eq!(plane.signed_distance(&Point3::new(1.0, 2.0, 7.0)), 2.0);
+    /// ```
+    ///
+    /// [`Plane`]: struct.Plane.html
+    ///
+    pub fn from_point_normal(point: Point3, normal: Vector3) -> Plane {
+        Plane {
+            normal,
+            distance: -normal.dot(point),
+        }
+    }
+
+    /// Returns the signed distance of `p` to the [`Plane`], in multiples of the length of its
+    /// normal. It is positive on the inside and negative on the outside.
+    ///
+    /// [`Plane`]: struct.Plane.html
+    ///
+    pub fn signed_distance(&self, p: &Point3) -> f32 {
+        self.normal.dot(*p) + self.distance
+    }
+
+    /// Scales the [`Plane`] so that its normal has unit length, which makes
+    /// [`Plane::signed_distance`] return euclidean distances.
+    ///
+    /// [`Plane`]: struct.Plane.html
+    /// [`Plane::signed_distance`]: struct.Plane.html#method.signed_distance
+    ///
+    pub fn normalize(&self) -> Plane {
+        let length = self.normal.length();
+        Plane {
+            normal: self.normal / length,
+            distance: self.distance / length,
+        }
+    }
+}
+
+/// How an [`AABB`] lies relative to a [`Frustum`].
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`Frustum`]: struct.Frustum.html
+///
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Containment {
+    /// The [`AABB`] lies completely outside of at least one plane.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    Outside,
+
+    /// The [`AABB`] may overlap the [`Frustum`], but doesn't lie completely inside it.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Frustum`]: struct.Frustum.html
+    ///
+    Intersecting,
+
+    /// The [`AABB`] lies completely inside of all planes.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    Inside,
+}
+
+/// A convex volume bounded by six [`Plane`]s, such as the view frustum of a camera.
+///
+/// [`Plane`]: struct.Plane.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frustum {
+    /// The bounding planes, with their normals pointing to the inside.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Creates a new [`Frustum`] from its six bounding `planes`.
+    /// Their normals must point to the inside.
+    ///
+    /// [`Frustum`]: struct.Frustum.html
+    ///
+    pub fn new(planes: [Plane; 6]) -> Frustum {
+        Frustum { planes }
+    }
+
+    /// Creates the [`Frustum`] of a view-projection matrix, which maps world space to clip
+    /// space. The planes are extracted with the method of Gribb and Hartmann and normalized.
+    ///
+    /// Like the projections of `glam`, such as `Mat4::perspective_rh`, the depth of the clip
+    /// space is expected to range from `0` to `1`. For OpenGL style projections, whose depth
+    /// ranges from `-1` to `1`, the near plane lies further out than necessary, so the
+    /// [`Frustum`] is slightly too large.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::frustum::Frustum;
+    /// use bvh::{Point3, Vector3};
+    /// use glam::Mat4;
+    ///
+    /// let view = Mat4::look_at_rh(Point3::ZERO, Point3::new(0.0, 0.0, -1.0), Vector3::Y);
+    /// let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+    /// let frustum = Frustum::from_view_projection(&(projection * view));
+    ///
+    /// let in_front = AABB::with_bounds(Point3::new(-1.0, -1.0, -11.0), Point3::new(1.0, 1.0, -10.0));
+    /// let behind = AABB::with_bounds(Point3::new(-1.0, -1.0, 10.0), Point3::new(1.0, 1.0, 11.0));
+    /// assert!(frustum.intersects_aabb(&in_front));
+    /// assert!(!frustum.intersects_aabb(&behind));
+    /// ```
+    ///
+    /// [`Frustum`]: struct.Frustum.html
+    ///
+    pub fn from_view_projection(m: &glam::Mat4) -> Frustum {
+        let row = |i: usize| m.row(i);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ];
+        let mut frustum = Frustum {
+            planes: [Plane::new(Vector3::ZERO, 0.0); 6],
+        };
+        for (plane, coefficients) in frustum.planes.iter_mut().zip(planes.iter()) {
+            *plane = Plane::new(coefficients.truncate(), coefficients.w).normalize();
+        }
+        frustum
+    }
+
+    /// Classifies how `aabb` lies relative to the [`Frustum`]. For each plane, only the
+    /// corner furthest inside (the p-vertex) and the corner furthest outside (the n-vertex)
+    /// are tested.
+    ///
+    /// The test is conservative: an [`AABB`] which lies outside of the [`Frustum`], but not
+    /// completely outside of any single plane, is classified as
+    /// [`Containment::Intersecting`]. An empty [`AABB`] is [`Containment::Outside`].
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Containment::Intersecting`]: enum.Containment.html#variant.Intersecting
+    /// [`Containment::Outside`]: enum.Containment.html#variant.Outside
+    /// [`Frustum`]: struct.Frustum.html
+    ///
+    pub fn classify_aabb(&self, aabb: &AABB) -> Containment {
+        if aabb.is_empty() {
+            return Containment::Outside;
+        }
+
+        let mut containment = Containment::Inside;
+        for plane in self.planes.iter() {
+            match aabb.classify_half_space(&plane.normal, plane.distance) {
+                HalfSpaceClass::Negative => return Containment::Outside,
+                HalfSpaceClass::Spanning => containment = Containment::Intersecting,
+                HalfSpaceClass::Positive => {}
+            }
+        }
+        containment
+    }
+
+    /// Returns true if `aabb` may overlap the [`Frustum`], i.e. if it doesn't lie completely
+    /// outside of any of its planes. See [`Frustum::classify_aabb`].
+    ///
+    /// [`Frustum`]: struct.Frustum.html
+    /// [`Frustum::classify_aabb`]: struct.Frustum.html#method.classify_aabb
+    ///
+    pub fn intersects_aabb(&self, aabb: &AABB) -> bool {
+        self.classify_aabb(aabb) != Containment::Outside
+    }
+
+    /// Returns true if `aabb` lies completely inside the [`Frustum`].
+    ///
+    /// [`Frustum`]: struct.Frustum.html
+    ///
+    pub fn contains_aabb(&self, aabb: &AABB) -> bool {
+        self.classify_aabb(aabb) == Containment::Inside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::frustum::{Containment, Frustum, Plane};
+    use crate::{Point3, Vector3};
+
+    use glam::Mat4;
+    use proptest::prelude::*;
+
+    /// Creates the frustum of the box `[-1, 1] x [-1, 1] x [0, 10]`.
+    fn box_frustum() -> Frustum {
+        Frustum::new([
+            Plane::new(Vector3::new(1.0, 0.0, 0.0), 1.0),
+            Plane::new(Vector3::new(-1.0, 0.0, 0.0), 1.0),
+            Plane::new(Vector3::new(0.0, 1.0, 0.0), 1.0),
+            Plane::new(Vector3::new(0.0, -1.0, 0.0), 1.0),
+            Plane::new(Vector3::new(0.0, 0.0, 1.0), 0.0),
+            Plane::new(Vector3::new(0.0, 0.0, -1.0), 10.0),
+        ])
+    }
+
+    #[test]
+    /// Tests boxes inside, outside and straddling each plane of a hand-constructed frustum.
+    fn test_classify_aabb() {
+        let frustum = box_frustum();
+        let cube = |center: Point3| {
+            AABB::with_bounds(center - Vector3::splat(0.25), center + Vector3::splat(0.25))
+        };
+
+        assert_eq!(
+            frustum.classify_aabb(&cube(Point3::new(0.0, 0.0, 5.0))),
+            Containment::Inside
+        );
+        assert_eq!(frustum.classify_aabb(&AABB::empty()), Containment::Outside);
+
+        // Just inside, straddling and just outside of each plane.
+        let directions = [
+            Vector3::new(-1.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(0.0, 0.0, -5.0),
+            Vector3::new(0.0, 0.0, 5.0),
+        ];
+        let center = Point3::new(0.0, 0.0, 5.0);
+        for (plane, direction) in frustum.planes.iter().zip(directions.iter()) {
+            let on_plane = center + *direction;
+            assert_eq!(plane.signed_distance(&on_plane), 0.0);
+
+            let outward = direction.normalize();
+            assert_eq!(
+                frustum.classify_aabb(&cube(on_plane - outward * 0.5)),
+                Containment::Inside
+            );
+            assert_eq!(
+                frustum.classify_aabb(&cube(on_plane)),
+                Containment::Intersecting
+            );
+            assert_eq!(
+                frustum.classify_aabb(&cube(on_plane + outward * 0.5)),
+                Containment::Outside
+            );
+        }
+    }
+
+    #[test]
+    /// Tests whether the frustum of a view-projection matrix has the expected planes.
+    fn test_from_view_projection() {
+        let projection = Mat4::orthographic_rh(-1.0, 1.0, -1.0, 1.0, 0.0, 10.0);
+        let view = Mat4::look_at_rh(Point3::ZERO, Point3::new(0.0, 0.0, 1.0), Vector3::Y);
+        let frustum = Frustum::from_view_projection(&(projection * view));
+
+        // The camera looks along +z, so these are the planes of `box_frustum`. Its x axis is
+        // mirrored, so the left and right planes are swapped.
+        for plane in frustum.planes.iter() {
+            assert!((plane.normal.length() - 1.0).abs() < 1e-6);
+            assert!(box_frustum().planes.iter().any(|expected| {
+                (plane.normal - expected.normal).length() < 1e-5
+                    && (plane.distance - expected.distance).abs() < 1e-5
+            }));
+        }
+    }
+
+    proptest! {
+        // Test whether `classify_aabb` agrees with testing the corners of the `AABB`
+        // against each plane.
+        #[test]
+        fn test_classify_aabb_matches_corners(a in (-20.0f32..20.0, -20.0f32..20.0, -20.0f32..20.0),
+                                              b in (-20.0f32..20.0, -20.0f32..20.0, -20.0f32..20.0)) {
+            let frustum = Frustum::from_view_projection(
+                &(Mat4::perspective_rh(1.0, 1.5, 0.5, 15.0)
+                    * Mat4::look_at_rh(Point3::new(1.0, 2.0, 3.0), Point3::ZERO, Vector3::Y)),
+            );
+            let aabb = AABB::empty()
+                .grow(&Point3::new(a.0, a.1, a.2))
+                .grow(&Point3::new(b.0, b.1, b.2));
+            let corners = aabb.to_wireframe().0;
+
+            let outside_a_plane = frustum
+                .planes
+                .iter()
+                .any(|plane| corners.iter().all(|corner| plane.signed_distance(corner) < 0.0));
+            let inside = frustum
+                .planes
+                .iter()
+                .all(|plane| corners.iter().all(|corner| plane.signed_distance(corner) >= 0.0));
+            let expected = if outside_a_plane {
+                Containment::Outside
+            } else if inside {
+                Containment::Inside
+            } else {
+                Containment::Intersecting
+            };
+            assert_eq!(frustum.classify_aabb(&aabb), expected);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Flat nodes for stackless traversal on the GPU or on targets without an allocator.
+//!
+//! This module only depends on `core`, so it is available in `no_std` builds.
+//! The nodes are created by [`BVH::flatten_gpu`], which requires the `std` feature.
+//!
+//! [`BVH::flatten_gpu`]: ../bvh/struct.BVH.html#method.flatten_gpu
+//!
+
+use crate::aabb::AABB;
+use crate::ray::Ray;
+use crate::Point3;
+
+/// A node of a flat [`BVH`] for traversal on the GPU, created by [`BVH::flatten_gpu`].
+///
+/// The node only consists of `f32` and `u32` fields, and its layout matches the following
+/// struct in a GLSL `std430` storage buffer. A `vec3` has an alignment of 16 bytes,
+/// so the `u32` after it fills the gap without any padding.
+///
+/// ```glsl
+/// struct Node {
+///     vec3 min;
+///     uint index;
+///     vec3 max;
+///     uint shape_count;
+/// };
+/// ```
+///
+/// The nodes are stored in depth-first order. Nodes with a `shape_count` of `0` are inner
+/// nodes: their subtree starts at the next node and `index` is the node after their subtree.
+/// All other nodes are leaves, which cover the shapes `index..index + shape_count`.
+/// The traversal of a node tests its [`AABB`] first. If it is hit, the traversal reports
+/// the shapes of a leaf, and continues with the next node. Otherwise it skips an inner node's
+/// subtree by continuing with `index`, or continues with the next node after a leaf.
+///
+/// With the `bytemuck` feature, the node implements `bytemuck::Pod`, so a `&[GpuNode]`
+/// can be cast to bytes and uploaded as is.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BVH::flatten_gpu`]: ../bvh/struct.BVH.html#method.flatten_gpu
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "bytemuck", derive(bytemuck::Pod, bytemuck::Zeroable))]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C)]
+pub struct GpuNode {
+    /// The minimum bound of the [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub min: [f32; 3],
+
+    /// The index of the node after the subtree of an inner node,
+    /// or the index of the first shape of a leaf.
+    pub index: u32,
+
+    /// The maximum bound of the [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub max: [f32; 3],
+
+    /// The number of shapes of a leaf, or `0` for inner nodes.
+    pub shape_count: u32,
+}
+
+impl GpuNode {
+    /// Returns `true` if the node is a leaf.
+    pub fn is_leaf(&self) -> bool {
+        self.shape_count != 0
+    }
+
+    /// Returns the [`AABB`] of the node.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub fn aabb(&self) -> AABB {
+        AABB::with_bounds(Point3::from(self.min), Point3::from(self.max))
+    }
+
+    /// Traverses the `nodes` with the [`Ray`] without allocating, and writes the indices
+    /// of the shapes whose leaves are hit into `hits`.
+    ///
+    /// Returns the number of candidate shapes. If it is larger than `hits.len()`,
+    /// only the first `hits.len()` indices were written, and the caller can retry
+    /// with a larger buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bvh::aabb::{AABB, Bounded};
+    /// use bvh::bounding_hierarchy::BHShape;
+    /// use bvh::bvh::BVH;
+    /// use bvh::flat_bvh::GpuNode;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// struct Sphere {
+    ///     position: Point3,
+    ///     node_index: usize,
+    /// }
+    ///
+    /// impl Bounded for Sphere {
+    ///     fn aabb(&self) -> AABB {
+    ///         AABB::with_bounds(self.position - Vector3::ONE, self.position + Vector3::ONE)
+    ///     }
+    /// }
+    ///
+    /// impl BHShape for Sphere {
+    ///     fn set_bh_node_index(&mut self, index: usize) {
+    ///         self.node_index = index;
+    ///     }
+    ///
+    ///     fn bh_node_index(&self) -> usize {
+    ///         self.node_index
+    ///     }
+    /// }
+    ///
+    /// let mut spheres = (0..100)
+    ///     .map(|i| Sphere { position: Point3::new(i as f32 * 3.0, 0.0, 0.0), node_index: 0 })
+    ///     .collect::<Vec<_>>();
+    /// let nodes = BVH::build(&mut spheres).flatten_gpu();
+    ///
+    /// let ray = Ray::new(Point3::new(30.0, 0.0, -10.0), Vector3::new(0.0, 0.0, 1.0));
+    /// let mut hits = [0; 16];
+    /// let count = GpuNode::traverse(&nodes, &ray, &mut hits);
+    /// assert!(hits[..count].contains(&10));
+    /// ```
+    ///
+    /// [`Ray`]: ../ray/struct.Ray.html
+    ///
+    pub fn traverse(nodes: &[GpuNode], ray: &Ray, hits: &mut [usize]) -> usize {
+        let mut count = 0;
+        let mut index = 0;
+        while index < nodes.len() {
+            let node = &nodes[index];
+            let hit = ray.intersects_aabb(&node.aabb());
+            if hit && node.is_leaf() {
+                let first = node.index as usize;
+                for shape_index in first..first + node.shape_count as usize {
+                    if let Some(slot) = hits.get_mut(count) {
+                        *slot = shape_index;
+                    }
+                    count += 1;
+                }
+            }
+            index = if hit || node.is_leaf() {
+                index + 1
+            } else {
+                node.index as usize
+            };
+        }
+        count
+    }
+}
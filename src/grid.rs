@@ -0,0 +1,373 @@
+//! This module defines [`UniformGrid`], an acceleration structure which divides the bounds
+//! of a scene into equally sized cells. For scenes with uniformly distributed shapes it can
+//! be faster to traverse than a [`BVH`].
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`UniformGrid`]: struct.UniformGrid.html
+//!
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
+use crate::ray::Ray;
+use crate::{Point3, Vector3};
+
+/// A uniform grid over the joint [`AABB`] of a set of shapes.
+/// Every cell stores the indices of the shapes whose [`AABB`]s overlap it, so a shape
+/// may be referenced by several cells.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{AABB, Bounded};
+/// use bvh::grid::UniformGrid;
+/// use bvh::ray::Ray;
+/// use bvh::{Point3, Vector3};
+///
+/// struct Sphere { position: Point3 }
+/// impl Bounded for Sphere {
+///     fn aabb(&self) -> AABB {
+///         AABB::with_bounds(self.position - Point3::ONE, self.position + Point3::ONE)
+///     }
+/// }
+///
+/// let spheres = (0..100)
+///     .map(|i| Sphere { position: Point3::new(i as f32 * 4.0, 0.0, 0.0) })
+///     .collect::<Vec<_>>();
+/// let grid = UniformGrid::build(&spheres, [32, 1, 1]);
+///
+/// let ray = Ray::new(Point3::new(8.5, -10.0, 0.5), Vector3::new(0.0, 1.0, 0.0));
+/// let hits = grid.traverse(&ray, &spheres);
+/// assert_eq!(hits.len(), 1);
+/// ```
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+///
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct UniformGrid {
+    /// The joint [`AABB`] of all shapes, which is covered by the cells.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub bounds: AABB,
+
+    /// The number of cells along each axis.
+    pub resolution: [usize; 3],
+
+    /// The indices of the shapes overlapping each cell. The cell `(x, y, z)` is stored at
+    /// `x + resolution[0] * (y + resolution[1] * z)`.
+    pub cells: Vec<Vec<usize>>,
+}
+
+impl UniformGrid {
+    /// Creates a new [`UniformGrid`] with `resolution` cells along the axes, which covers
+    /// the joint [`AABB`] of `shapes`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resolution along any axis is `0`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`UniformGrid`]: struct.UniformGrid.html
+    ///
+    pub fn build<T: Bounded>(shapes: &[T], resolution: [usize; 3]) -> UniformGrid {
+        assert!(
+            resolution.iter().all(|&cells| cells > 0),
+            "A grid needs at least one cell along every axis."
+        );
+
+        let bounds = shapes
+            .iter()
+            .fold(AABB::empty(), |aabb, shape| aabb.join(&shape.aabb()));
+        let mut grid = UniformGrid {
+            bounds,
+            resolution,
+            cells: vec![Vec::new(); resolution[0] * resolution[1] * resolution[2]],
+        };
+
+        for (shape_index, shape) in shapes.iter().enumerate() {
+            let aabb = shape.aabb();
+            let min = grid.cell_of(&aabb.min);
+            let max = grid.cell_of(&aabb.max);
+            for z in min[2]..=max[2] {
+                for y in min[1]..=max[1] {
+                    for x in min[0]..=max[0] {
+                        let cell_index = grid.cell_index([x, y, z]);
+                        grid.cells[cell_index].push(shape_index);
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// Returns the size of a single cell.
+    pub fn cell_size(&self) -> Vector3 {
+        self.bounds.size()
+            / Vector3::new(
+                self.resolution[0] as f32,
+                self.resolution[1] as f32,
+                self.resolution[2] as f32,
+            )
+    }
+
+    /// Returns the coordinates of the cell containing `p`.
+    /// Points outside of the grid are clamped to the closest cell.
+    fn cell_of(&self, p: &Point3) -> [usize; 3] {
+        let cell_size = self.cell_size();
+        let mut cell = [0; 3];
+        for axis in 0..3 {
+            // A flat grid has a cell size of zero, but only one cell along that axis anyway.
+            if cell_size[axis] > 0.0 {
+                let relative = ((p[axis] - self.bounds.min[axis]) / cell_size[axis]).max(0.0);
+                cell[axis] = (relative as usize).min(self.resolution[axis] - 1);
+            }
+        }
+        cell
+    }
+
+    /// Returns the index in `cells` of the cell with the given coordinates.
+    fn cell_index(&self, cell: [usize; 3]) -> usize {
+        cell[0] + self.resolution[0] * (cell[1] + self.resolution[1] * cell[2])
+    }
+
+    /// Traverses the [`UniformGrid`] with the 3D-DDA algorithm, visiting the cells pierced
+    /// by `ray` in order.
+    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    /// Every shape is returned once, in the order of `shapes`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`UniformGrid`]: struct.UniformGrid.html
+    ///
+    pub fn traverse<'a, Shape: Bounded>(
+        &'a self,
+        ray: &Ray,
+        shapes: &'a [Shape],
+    ) -> Vec<&'a Shape> {
+        if self.bounds.is_empty() {
+            return Vec::new();
+        }
+        let (entry, exit) = match ray.intersection_interval(&self.bounds) {
+            Some(interval) => interval,
+            None => return Vec::new(),
+        };
+
+        let cell_size = self.cell_size();
+        let mut cell = self.cell_of(&ray.at(entry.max(0.0)));
+        let mut step = [0isize; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+        for axis in 0..3 {
+            // Along axes with a single cell the ray can only leave the grid, which is
+            // detected by comparing with `exit`.
+            if self.resolution[axis] == 1 || ray.direction[axis] == 0.0 {
+                continue;
+            }
+            let inv_direction = ray.inv_direction[axis];
+            if ray.direction[axis] > 0.0 {
+                step[axis] = 1;
+                let boundary = self.bounds.min[axis] + (cell[axis] + 1) as f32 * cell_size[axis];
+                t_max[axis] = (boundary - ray.origin[axis]) * inv_direction;
+            } else {
+                step[axis] = -1;
+                let boundary = self.bounds.min[axis] + cell[axis] as f32 * cell_size[axis];
+                t_max[axis] = (boundary - ray.origin[axis]) * inv_direction;
+            }
+            t_delta[axis] = cell_size[axis] * inv_direction.abs();
+        }
+
+        let mut hit_indices = Vec::new();
+        loop {
+            for &shape_index in &self.cells[self.cell_index(cell)] {
+                if ray.intersects_aabb(&shapes[shape_index].aabb()) {
+                    hit_indices.push(shape_index);
+                }
+            }
+
+            // Step into the neighbouring cell whose boundary is crossed first.
+            let axis = if t_max[0] < t_max[1] && t_max[0] < t_max[2] {
+                0
+            } else if t_max[1] < t_max[2] {
+                1
+            } else {
+                2
+            };
+            if t_max[axis] > exit {
+                break;
+            }
+            let next = cell[axis] as isize + step[axis];
+            if next < 0 || next >= self.resolution[axis] as isize {
+                break;
+            }
+            cell[axis] = next as usize;
+            t_max[axis] += t_delta[axis];
+        }
+
+        // Shapes spanning several cells are found once per cell.
+        hit_indices.sort_unstable();
+        hit_indices.dedup();
+        hit_indices
+            .into_iter()
+            .map(|shape_index| &shapes[shape_index])
+            .collect()
+    }
+}
+
+impl BoundingHierarchy for UniformGrid {
+    /// Builds a [`UniformGrid`] with about one cell per shape. The cells are as close to
+    /// cubes as the proportions of the joint [`AABB`] of the shapes allow.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`UniformGrid`]: struct.UniformGrid.html
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> UniformGrid {
+        let bounds = shapes
+            .iter()
+            .fold(AABB::empty(), |aabb, shape| aabb.join(&shape.aabb()));
+        let size = if bounds.is_empty() {
+            Vector3::ZERO
+        } else {
+            bounds.size()
+        };
+        let cells_per_longest_axis = (shapes.len() as f32).cbrt().max(1.0);
+        let longest_axis = size.max_element();
+
+        let mut resolution = [1; 3];
+        if longest_axis > 0.0 {
+            for (axis, cells) in resolution.iter_mut().enumerate() {
+                *cells =
+                    ((cells_per_longest_axis * size[axis] / longest_axis).round() as usize).max(1);
+            }
+        }
+        UniformGrid::build(shapes, resolution)
+    }
+
+    fn traverse<'a, Shape: Bounded>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&'a Shape> {
+        self.traverse(ray, shapes)
+    }
+
+    /// Prints the non-empty cells of the [`UniformGrid`].
+    ///
+    /// [`UniformGrid`]: struct.UniformGrid.html
+    ///
+    fn pretty_print(&self) {
+        for (i, cell) in self.cells.iter().enumerate() {
+            if !cell.is_empty() {
+                println!("{}\t{:?}", i, cell);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::Bounded;
+    use crate::grid::UniformGrid;
+    use crate::ray::Ray;
+    use crate::testbase::{
+        build_some_bh, traverse_some_bh, tuplevec_grid_strategy, unit_boxes, UnitBox,
+    };
+    use crate::{Point3, Vector3};
+
+    use proptest::prelude::*;
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_grid() {
+        build_some_bh::<UniformGrid>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given as a
+    /// `UniformGrid`.
+    fn test_traverse_grid() {
+        traverse_some_bh::<UniformGrid>();
+    }
+
+    #[test]
+    /// Tests that an empty `UniformGrid` is never hit.
+    fn test_traverse_empty_grid() {
+        let shapes: Vec<UnitBox> = Vec::new();
+        let grid = UniformGrid::build(&shapes, [4, 4, 4]);
+        let ray = Ray::new(Point3::ZERO, Vector3::X);
+        assert!(grid.traverse(&ray, &shapes).is_empty());
+    }
+
+    proptest! {
+        // Test whether the `UniformGrid` returns exactly the shapes whose `AABB`s are hit,
+        // including rays parallel to the axes and rays starting inside the grid.
+        #[test]
+        fn test_grid_traverse_matches_brute_force(
+            positions in prop::collection::vec(tuplevec_grid_strategy(50), 1..128),
+            resolution in (1usize..16, 1usize..16, 1usize..16),
+            origin in (-100i32..100, -100i32..100, -100i32..100),
+            direction in (-2i32..=2, -2i32..=2, -2i32..=2),
+        ) {
+            prop_assume!(direction != (0, 0, 0));
+            let shapes = unit_boxes(&positions);
+            let grid = UniformGrid::build(&shapes, [resolution.0, resolution.1, resolution.2]);
+
+            // Keep the origin off the faces of the boxes, where `Ray::intersects_aabb` yields
+            // NaNs for rays parallel to the face.
+            let origin = Point3::new(origin.0 as f32, origin.1 as f32, origin.2 as f32) * 0.5
+                + Vector3::splat(0.25);
+            let direction = Vector3::new(direction.0 as f32, direction.1 as f32, direction.2 as f32);
+            let ray = Ray::new(origin, direction);
+
+            let found = grid.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            let expected = shapes
+                .iter()
+                .filter(|shape| ray.intersects_aabb(&shape.aabb()))
+                .map(|shape| shape.id)
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+        }
+    }
+}
+
+#[cfg(all(feature = "bench", test))]
+mod bench {
+    use crate::aabb::AABB;
+    use crate::bvh::BVH;
+    use crate::grid::UniformGrid;
+    use crate::testbase::{
+        build_120k_triangles_bh, create_n_cubes, intersect_120k_triangles_bh, intersect_bh,
+    };
+    use crate::Point3;
+
+    /// The bounds of a 512×512×512 scene.
+    fn bounds_512() -> AABB {
+        AABB::with_bounds(Point3::splat(-256.0), Point3::splat(256.0))
+    }
+
+    #[bench]
+    /// Benchmark the construction of a `UniformGrid` with 120,000 triangles.
+    fn bench_build_120k_triangles_grid(b: &mut ::test::Bencher) {
+        build_120k_triangles_bh::<UniformGrid>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a `UniformGrid`.
+    fn bench_intersect_120k_triangles_grid(b: &mut ::test::Bencher) {
+        intersect_120k_triangles_bh::<UniformGrid>(b);
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 uniformly distributed triangles in a 512×512×512 scene
+    /// using a `BVH`, as a baseline for `bench_intersect_512_scene_grid`.
+    fn bench_intersect_512_scene_bvh(b: &mut ::test::Bencher) {
+        let bounds = bounds_512();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+        intersect_bh(&bvh, &triangles, &bounds, b)
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 uniformly distributed triangles in a 512×512×512 scene
+    /// using a `UniformGrid`.
+    fn bench_intersect_512_scene_grid(b: &mut ::test::Bencher) {
+        let bounds = bounds_512();
+        let triangles = create_n_cubes(10_000, &bounds);
+        let grid = UniformGrid::build(&triangles, [64, 64, 64]);
+        intersect_bh(&grid, &triangles, &bounds, b)
+    }
+}
@@ -66,12 +66,27 @@
 //!
 //! ## Features
 //!
+//! - `std` (default **enabled**) - everything that needs the standard library, which is all
+//!   hierarchies and their builders. Without it, the crate is `no_std` (but needs `alloc`) and
+//!   only provides [`AABB`](aabb/struct.AABB.html), [`Ray`](ray/struct.Ray.html),
+//!   [`Intersectable`](ray/trait.Intersectable.html), [`Frustum`](frustum/struct.Frustum.html)
+//!   and the allocation-free
+//!   [`GpuNode::traverse`](gpu/struct.GpuNode.html#method.traverse)
+//! - `libm` (default **disabled**) - float math for `no_std` builds, required when `std` is disabled
 //! - `serde_impls` (default **disabled**) - adds `Serialize` and `Deserialize` implementations for some types
+//! - `rayon` (default **disabled**) - adds [`BVH::par_traverse`](bvh/struct.BVH.html#method.par_traverse), [`BVH::traverse_batch`](bvh/struct.BVH.html#method.traverse_batch) and [`BVH::nearest_batch`](bvh/struct.BVH.html#method.nearest_batch) for traversing batches of rays in parallel
+//! - `bytemuck` (default **disabled**) - implements `bytemuck::Pod` for [`GpuNode`](gpu/struct.GpuNode.html), so that the nodes can be uploaded to the GPU as bytes
 //!
 
 #![deny(missing_docs)]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(feature = "bench", feature(test))]
 
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("bvh requires either the `std` or the `libm` feature for float math");
+
+extern crate alloc;
+
 #[cfg(all(feature = "bench", test))]
 extern crate test;
 
@@ -87,11 +102,27 @@ pub type Vector3 = glam::Vec3;
 
 pub mod aabb;
 pub mod axis;
+#[cfg(feature = "std")]
 pub mod bounding_hierarchy;
+#[cfg(feature = "std")]
 pub mod bvh;
+#[cfg(feature = "std")]
+pub mod bvh4;
+#[cfg(feature = "std")]
+pub mod compact_bvh;
+#[cfg(feature = "std")]
+pub mod compressed_bvh;
+#[cfg(feature = "std")]
 pub mod flat_bvh;
+pub mod frustum;
+pub mod gpu;
+#[cfg(feature = "std")]
+pub mod grid;
 pub mod ray;
+#[cfg(feature = "std")]
 mod utils;
+#[cfg(feature = "std")]
+pub mod wide_bvh;
 
 #[cfg(test)]
 mod testbase;
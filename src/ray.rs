@@ -1,10 +1,18 @@
 //! This module defines a Ray structure and intersection algorithms
 //! for axis aligned bounding boxes and triangles.
 
-use crate::aabb::AABB;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+#[cfg(not(feature = "std"))]
+use num::Float;
+
+use crate::aabb::{Bounded, AABB};
 use crate::EPSILON;
 use crate::{Point3, Vector3};
-use std::f32::INFINITY;
 
 /// A struct which defines a ray and some of its cached values.
 #[derive(Debug)]
@@ -19,28 +27,28 @@ pub struct Ray {
     ///
     /// [`AABB`]: struct.AABB.html
     ///
-    inv_direction: Vector3,
+    pub(crate) inv_direction: Vector3,
 
     /// Sign of the X direction. 0 means positive, 1 means negative.
     /// Cached for use in [`AABB`] intersections.
     ///
     /// [`AABB`]: struct.AABB.html
     ///
-    sign_x: usize,
+    pub(crate) sign_x: usize,
 
     /// Sign of the Y direction. 0 means positive, 1 means negative.
     /// Cached for use in [`AABB`] intersections.
     ///
     /// [`AABB`]: struct.AABB.html
     ///
-    sign_y: usize,
+    pub(crate) sign_y: usize,
 
     /// Sign of the Z direction. 0 means positive, 1 means negative.
     /// Cached for use in [`AABB`] intersections.
     ///
     /// [`AABB`]: struct.AABB.html
     ///
-    sign_z: usize,
+    pub(crate) sign_z: usize,
 }
 
 /// A struct which is returned by the `intersects_triangle` method.
@@ -63,6 +71,263 @@ impl Intersection {
     }
 }
 
+/// The result of casting a [`Ray`] at a shape: either a hit at some distance `t`
+/// along the [`Ray`], or a miss.
+///
+/// Results are ordered by distance, and a [`Miss`] is greater than any hit.
+/// Thus the minimum of a mix of results is the nearest hit, and sorting results
+/// puts the hits first, nearest to farthest. Distances are compared with
+/// [`f32::total_cmp`], so this is a total order even in the presence of NaNs.
+///
+/// # Examples
+/// ```
+/// use bvh::ray::RaycastResult;
+///
+/// let results = [RaycastResult::Miss, RaycastResult::Hit(4.0), RaycastResult::Hit(2.5)];
+/// let nearest = results.iter().min().unwrap();
+///
+/// assert_eq!(nearest.t(), Some(2.5));
+/// ```
+///
+/// [`Ray`]: struct.Ray.html
+/// [`Miss`]: enum.RaycastResult.html#variant.Miss
+/// [`f32::total_cmp`]: https://doc.rust-lang.org/std/primitive.f32.html#method.total_cmp
+///
+#[derive(Debug, Copy, Clone)]
+pub enum RaycastResult {
+    /// The ray missed.
+    Miss,
+
+    /// The ray hit at the given distance from its origin.
+    Hit(f32),
+}
+
+impl RaycastResult {
+    /// Returns the distance of the hit, or `None` for a miss.
+    pub fn t(&self) -> Option<f32> {
+        match *self {
+            RaycastResult::Miss => None,
+            RaycastResult::Hit(t) => Some(t),
+        }
+    }
+
+    /// Returns `true` if the ray hit.
+    pub fn is_hit(&self) -> bool {
+        matches!(*self, RaycastResult::Hit(_))
+    }
+
+    /// Applies `f` to the distance of a hit, and returns a miss unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::RaycastResult;
+    ///
+    /// // Convert a distance along a ray of length 2 into a distance in world units.
+    /// assert_eq!(RaycastResult::Hit(1.5).map(|t| t * 2.0), RaycastResult::Hit(3.0));
+    /// assert_eq!(RaycastResult::Miss.map(|t| t * 2.0), RaycastResult::Miss);
+    /// ```
+    ///
+    pub fn map<F: Fn(f32) -> f32>(&self, f: F) -> RaycastResult {
+        match *self {
+            RaycastResult::Miss => RaycastResult::Miss,
+            RaycastResult::Hit(t) => RaycastResult::Hit(f(t)),
+        }
+    }
+
+    /// Keeps a hit only if its distance satisfies `predicate`, and turns it into a miss
+    /// otherwise. A miss is returned unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::RaycastResult;
+    ///
+    /// // Ignore hits beyond the far plane.
+    /// let far = 10.0;
+    /// assert_eq!(RaycastResult::Hit(4.0).filter(|t| t <= far), RaycastResult::Hit(4.0));
+    /// assert_eq!(RaycastResult::Hit(12.0).filter(|t| t <= far), RaycastResult::Miss);
+    /// ```
+    ///
+    pub fn filter<P: Fn(f32) -> bool>(&self, predicate: P) -> RaycastResult {
+        match *self {
+            RaycastResult::Hit(t) if predicate(t) => RaycastResult::Hit(t),
+            _ => RaycastResult::Miss,
+        }
+    }
+}
+
+/// Converts the distance returned by [`Intersectable::intersect`] into a [`RaycastResult`].
+///
+/// [`Intersectable::intersect`]: trait.Intersectable.html#tymethod.intersect
+/// [`RaycastResult`]: enum.RaycastResult.html
+///
+impl From<Option<f32>> for RaycastResult {
+    fn from(t: Option<f32>) -> RaycastResult {
+        t.map_or(RaycastResult::Miss, RaycastResult::Hit)
+    }
+}
+
+impl Ord for RaycastResult {
+    fn cmp(&self, other: &RaycastResult) -> Ordering {
+        match (*self, *other) {
+            (RaycastResult::Miss, RaycastResult::Miss) => Ordering::Equal,
+            (RaycastResult::Miss, RaycastResult::Hit(_)) => Ordering::Greater,
+            (RaycastResult::Hit(_), RaycastResult::Miss) => Ordering::Less,
+            (RaycastResult::Hit(a), RaycastResult::Hit(b)) => a.total_cmp(&b),
+        }
+    }
+}
+
+impl PartialOrd for RaycastResult {
+    fn partial_cmp(&self, other: &RaycastResult) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RaycastResult {
+    fn eq(&self, other: &RaycastResult) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RaycastResult {}
+
+/// A trait implemented by shapes which can be intersected by a [`Ray`].
+/// It is used by queries which need to confirm an actual hit of a shape,
+/// instead of just a hit of the shape's [`AABB`].
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`Ray`]: struct.Ray.html
+///
+pub trait Intersectable {
+    /// Returns the distance from the origin of `ray` to the closest intersection
+    /// with this shape, or `None` if `ray` misses it.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::ray::{Intersectable, Ray};
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let ray = Ray::new(Point3::new(0.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    /// let aabb = AABB::with_bounds(Point3::new(5.0,-1.0,-1.0), Point3::new(6.0,1.0,1.0));
+    ///
+    /// assert_eq!(aabb.intersect(&ray), Some(5.0));
+    /// ```
+    ///
+    fn intersect(&self, ray: &Ray) -> Option<f32>;
+}
+
+/// A shape which is both [`Bounded`] and [`Intersectable`], implemented for all such types.
+/// Since a trait object can only name one trait, `Box<dyn IntersectableShape>` is the way to
+/// store different kinds of shapes in one slice for ray queries.
+///
+/// # Examples
+/// ```
+/// use bvh::aabb::{AABB, Bounded};
+/// use bvh::bvh::BVH;
+/// use bvh::ray::{Intersectable, IntersectableShape, Ray};
+/// use bvh::{Point3, Vector3};
+///
+/// struct Sphere {
+///     center: Point3,
+///     radius: f32,
+/// }
+///
+/// impl Bounded for Sphere {
+///     fn aabb(&self) -> AABB {
+///         let half_size = Vector3::splat(self.radius);
+///         AABB::with_bounds(self.center - half_size, self.center + half_size)
+///     }
+/// }
+///
+/// impl Intersectable for Sphere {
+///     fn intersect(&self, ray: &Ray) -> Option<f32> {
+///         let offset = ray.origin - self.center;
+///         let b = offset.dot(ray.direction);
+///         let discriminant = b * b - offset.length_squared() + self.radius * self.radius;
+///         (discriminant >= 0.0).then(|| -b - discriminant.sqrt()).filter(|&t| t >= 0.0)
+///     }
+/// }
+///
+/// struct Triangle {
+///     a: Point3,
+///     b: Point3,
+///     c: Point3,
+/// }
+///
+/// impl Bounded for Triangle {
+///     fn aabb(&self) -> AABB {
+///         AABB::empty().grow(&self.a).grow(&self.b).grow(&self.c)
+///     }
+/// }
+///
+/// impl Intersectable for Triangle {
+///     fn intersect(&self, ray: &Ray) -> Option<f32> {
+///         let distance = ray.intersects_triangle(&self.a, &self.b, &self.c).distance;
+///         Some(distance).filter(|distance| distance.is_finite())
+///     }
+/// }
+///
+/// let shapes: Vec<Box<dyn IntersectableShape>> = vec![
+///     Box::new(Sphere { center: Point3::new(0.0, 0.0, 10.0), radius: 1.0 }),
+///     Box::new(Triangle {
+///         a: Point3::new(-1.0, -1.0, 5.0),
+///         b: Point3::new(0.0, 1.0, 5.0),
+///         c: Point3::new(1.0, -1.0, 5.0),
+///     }),
+///     Box::new(AABB::with_bounds(Point3::new(-1.0, -1.0, 7.0), Point3::new(1.0, 1.0, 8.0))),
+/// ];
+/// let bvh = BVH::build_dyn(&shapes);
+///
+/// let ray = Ray::new(Point3::ZERO, Vector3::new(0.0, 0.0, 1.0));
+/// assert_eq!(bvh.nearest_hit(&ray, &shapes), Some((1, 5.0)));
+/// assert_eq!(bvh.traverse_nearest(&ray, &shapes, |shape, ray| shape.intersect(ray)), Some((1, 5.0)));
+///
+/// // Behind the triangle.
+/// let ray = Ray::new(Point3::new(0.0, 0.0, 6.0), Vector3::new(0.0, 0.0, 1.0));
+/// assert_eq!(bvh.nearest_hit(&ray, &shapes), Some((2, 1.0)));
+/// ```
+///
+/// [`Bounded`]: ../aabb/trait.Bounded.html
+/// [`Intersectable`]: trait.Intersectable.html
+///
+pub trait IntersectableShape: Bounded + Intersectable {}
+
+impl<T: Bounded + Intersectable + ?Sized> IntersectableShape for T {}
+
+/// A trait implemented by solid shapes which can report every interval along a [`Ray`]
+/// that lies inside of them, instead of only the first hit like [`Intersectable`].
+/// This is the building block for constructive solid geometry, where the intervals of
+/// several shapes are combined into unions, intersections or differences.
+///
+/// [`Intersectable`]: trait.Intersectable.html
+/// [`Ray`]: struct.Ray.html
+///
+pub trait SpanIntersectable {
+    /// Returns the intervals `(entry, exit)` in which `ray` lies inside this shape,
+    /// sorted by distance and not overlapping each other. An interval starts at a negative
+    /// distance if the origin of `ray` lies inside the shape. Intervals which lie entirely
+    /// behind the origin are omitted.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::ray::{Ray, SpanIntersectable};
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let ray = Ray::new(Point3::new(0.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    /// let a = AABB::with_bounds(Point3::new(2.0,-1.0,-1.0), Point3::new(6.0,1.0,1.0));
+    /// let b = AABB::with_bounds(Point3::new(3.0,-1.0,-1.0), Point3::new(4.0,1.0,1.0));
+    ///
+    /// // The difference `a - b` is entered at the entry of `a` and left at the entry of `b`.
+    /// let (a_entry, _) = a.intervals(&ray)[0];
+    /// let (b_entry, _) = b.intervals(&ray)[0];
+    /// assert_eq!((a_entry, b_entry), (2.0, 3.0));
+    /// ```
+    ///
+    fn intervals(&self, ray: &Ray) -> Vec<(f32, f32)>;
+}
+
 impl Ray {
     /// Creates a new [`Ray`] from an `origin` and a `direction`.
     /// `direction` will be normalized.
@@ -94,9 +359,55 @@ impl Ray {
         }
     }
 
+    /// Returns the point at distance `t` along this [`Ray`], e.g. the point where it hits a
+    /// shape. Negative values of `t` give points behind the origin.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let ray = Ray::new(Point3::new(1.0,0.0,0.0), Vector3::new(0.0,2.0,0.0));
+    /// assert_eq!(ray.at(3.0), Point3::new(1.0,3.0,0.0));
+    /// ```
+    ///
+    /// [`Ray`]: struct.Ray.html
+    ///
+    pub fn at(&self, t: f32) -> Point3 {
+        self.origin + self.direction * t
+    }
+
+    /// Returns the points at distances `t_min` and `t_max` along this [`Ray`], which bound
+    /// the segment of the [`Ray`] between them.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0,-1.0,-1.0), Point3::new(1.0,1.0,1.0));
+    /// let ray = Ray::new(Point3::new(-5.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    ///
+    /// let (entry, exit) = ray.intersection_interval(&aabb).unwrap();
+    /// let (a, b) = ray.segment(entry, exit);
+    /// assert_eq!(a, Point3::new(-1.0,0.0,0.0));
+    /// assert_eq!(b, Point3::new(1.0,0.0,0.0));
+    /// ```
+    ///
+    /// [`Ray`]: struct.Ray.html
+    ///
+    pub fn segment(&self, t_min: f32, t_max: f32) -> (Point3, Point3) {
+        (self.at(t_min), self.at(t_max))
+    }
+
     /// Tests the intersection of a [`Ray`] with an [`AABB`] using the optimized algorithm
     /// from [this paper](http://www.cs.utah.edu/~awilliam/box/box.pdf).
     ///
+    /// Only the part of the ray in front of its origin is tested. A ray whose origin lies
+    /// inside the [`AABB`] therefore always intersects it, while an [`AABB`] which lies
+    /// entirely behind the origin is never intersected.
+    ///
     /// # Examples
     /// ```
     /// use bvh::aabb::AABB;
@@ -161,7 +472,41 @@ impl Ray {
         ray_max > 0.0
     }
 
+    /// Tests the intersection of a [`Ray`] with an [`AABB`] enlarged by `epsilon` on every side.
+    /// Like [`AABB::approx_contains_eps`], this errs on the side of reporting a hit.
+    ///
+    /// [`Ray::intersects_aabb`] is exact up to float rounding, so a ray which only grazes an
+    /// edge or corner of an [`AABB`] can be reported as missing it. Using this test for the
+    /// traversal of a hierarchy makes sure no shape is missed that way, at the cost of a few
+    /// extra candidates whose bounds pass within `epsilon` of the ray.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3,Vector3,EPSILON};
+    ///
+    /// // The ray exactly hits the corner of the AABB.
+    /// let corner = Point3::new(1.11,0.333,0.777);
+    /// let aabb = AABB::with_bounds(corner - Vector3::new(0.0,1.0,0.0), corner + Vector3::new(1.0,0.0,1.0));
+    /// let origin = Point3::new(0.1,0.2,0.3);
+    /// let ray = Ray::new(origin, corner - origin);
+    ///
+    /// assert!(ray.intersects_aabb_eps(&aabb, EPSILON));
+    /// ```
+    ///
+    /// [`Ray`]: struct.Ray.html
+    /// [`AABB`]: struct.AABB.html
+    /// [`AABB::approx_contains_eps`]: ../aabb/struct.AABB.html#method.approx_contains_eps
+    /// [`Ray::intersects_aabb`]: struct.Ray.html#method.intersects_aabb
+    ///
+    pub fn intersects_aabb_eps(&self, aabb: &AABB, epsilon: f32) -> bool {
+        let padding = Vector3::splat(epsilon);
+        self.intersects_aabb(&AABB::with_bounds(aabb.min - padding, aabb.max + padding))
+    }
+
     /// Naive implementation of a [`Ray`]/[`AABB`] intersection algorithm.
+    /// Treats rays starting inside the [`AABB`] like [`Ray::intersects_aabb`] does.
     ///
     /// # Examples
     /// ```
@@ -182,6 +527,7 @@ impl Ray {
     ///
     /// [`Ray`]: struct.Ray.html
     /// [`AABB`]: struct.AABB.html
+    /// [`Ray::intersects_aabb`]: struct.Ray.html#method.intersects_aabb
     ///
     pub fn intersects_aabb_naive(&self, aabb: &AABB) -> bool {
         let hit_min_x = (aabb.min.x - self.origin.x) * self.inv_direction.x;
@@ -208,6 +554,7 @@ impl Ray {
 
     /// Implementation of the algorithm described [here]
     /// (https://tavianator.com/fast-branchless-raybounding-box-intersections/).
+    /// Treats rays starting inside the [`AABB`] like [`Ray::intersects_aabb`] does.
     ///
     /// # Examples
     /// ```
@@ -228,6 +575,7 @@ impl Ray {
     ///
     /// [`Ray`]: struct.Ray.html
     /// [`AABB`]: struct.AABB.html
+    /// [`Ray::intersects_aabb`]: struct.Ray.html#method.intersects_aabb
     ///
     pub fn intersects_aabb_branchless(&self, aabb: &AABB) -> bool {
         let tx1 = (aabb.min.x - self.origin.x) * self.inv_direction.x;
@@ -251,6 +599,52 @@ impl Ray {
         tmax >= tmin && tmax >= 0.0
     }
 
+    /// Computes the distances along the [`Ray`] at which it enters and exits the [`AABB`].
+    /// Returns `None` if the [`Ray`] misses the [`AABB`], or if the [`AABB`] lies entirely
+    /// behind the origin.
+    ///
+    /// The entry distance is negative if the origin lies inside the [`AABB`], in which
+    /// case the exit distance is where the [`Ray`] leaves it. This is useful for volume
+    /// rendering, where the segment of the [`Ray`] inside the [`AABB`] is of interest.
+    /// [`AABB::intersect_ray_points`] returns the end points of that segment instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3,Vector3};
+    ///
+    /// let aabb = AABB::with_bounds(Point3::new(-1.0,-1.0,-1.0), Point3::new(1.0,1.0,1.0));
+    ///
+    /// let outside = Ray::new(Point3::new(-5.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    /// assert_eq!(outside.intersection_interval(&aabb), Some((4.0, 6.0)));
+    ///
+    /// let inside = Ray::new(Point3::new(0.0,0.0,0.0), Vector3::new(1.0,0.0,0.0));
+    /// assert_eq!(inside.intersection_interval(&aabb), Some((-1.0, 1.0)));
+    /// ```
+    ///
+    /// [`Ray`]: struct.Ray.html
+    /// [`AABB`]: struct.AABB.html
+    /// [`AABB::intersect_ray_points`]: ../aabb/struct.AABB.html#method.intersect_ray_points
+    ///
+    pub fn intersection_interval(&self, aabb: &AABB) -> Option<(f32, f32)> {
+        let tx1 = (aabb.min.x - self.origin.x) * self.inv_direction.x;
+        let tx2 = (aabb.max.x - self.origin.x) * self.inv_direction.x;
+        let ty1 = (aabb.min.y - self.origin.y) * self.inv_direction.y;
+        let ty2 = (aabb.max.y - self.origin.y) * self.inv_direction.y;
+        let tz1 = (aabb.min.z - self.origin.z) * self.inv_direction.z;
+        let tz2 = (aabb.max.z - self.origin.z) * self.inv_direction.z;
+
+        let entry = tx1.min(tx2).max(ty1.min(ty2)).max(tz1.min(tz2));
+        let exit = tx1.max(tx2).min(ty1.max(ty2)).min(tz1.max(tz2));
+
+        if exit >= entry && exit >= 0.0 {
+            Some((entry, exit))
+        } else {
+            None
+        }
+    }
+
     /// Implementation of the [Möller-Trumbore triangle/ray intersection algorithm]
     /// (https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm).
     /// Returns the distance to the intersection, as well as
@@ -276,7 +670,7 @@ impl Ray {
         // If backface culling is not desired write:
         // det < EPSILON && det > -EPSILON
         if det < EPSILON {
-            return Intersection::new(INFINITY, 0.0, 0.0);
+            return Intersection::new(f32::INFINITY, 0.0, 0.0);
         }
 
         let inv_det = 1.0 / det;
@@ -289,7 +683,7 @@ impl Ray {
 
         // Test bounds: u < 0 || u > 1 => outside of triangle
         if !(0.0..=1.0).contains(&u) {
-            return Intersection::new(INFINITY, u, 0.0);
+            return Intersection::new(f32::INFINITY, u, 0.0);
         }
 
         // Prepare to test v parameter
@@ -299,7 +693,7 @@ impl Ray {
         let v = self.direction.dot(v_vec) * inv_det;
         // The intersection lies outside of the triangle
         if v < 0.0 || u + v > 1.0 {
-            return Intersection::new(INFINITY, u, v);
+            return Intersection::new(f32::INFINITY, u, v);
         }
 
         let dist = a_to_c.dot(v_vec) * inv_det;
@@ -307,23 +701,200 @@ impl Ray {
         if dist > EPSILON {
             Intersection::new(dist, u, v)
         } else {
-            Intersection::new(INFINITY, u, v)
+            Intersection::new(f32::INFINITY, u, v)
+        }
+    }
+}
+
+/// A [`Ray`] which widens into a cone with its apex at the origin of the [`Ray`].
+/// The cone describes the footprint of a pixel along the [`Ray`], which can be used for
+/// texture filtering or to select a level of detail at the hit point.
+///
+/// # Examples
+/// ```
+/// use bvh::ray::RayCone;
+/// use bvh::{Point3,Vector3};
+///
+/// let cone = RayCone::new(Point3::new(0.0,0.0,0.0), Vector3::new(0.0,0.0,1.0), 0.5);
+///
+/// assert_eq!(cone.radius_at(0.0), 0.0);
+/// assert_eq!(cone.radius_at(4.0), 4.0 * 0.25f32.tan());
+/// ```
+///
+/// [`Ray`]: struct.Ray.html
+///
+#[derive(Debug)]
+pub struct RayCone {
+    /// The axis of the cone.
+    pub ray: Ray,
+
+    /// The full opening angle of the cone in radians.
+    pub spread_angle: f32,
+}
+
+impl RayCone {
+    /// Creates a new [`RayCone`] from an `origin`, a `direction` and a `spread_angle`.
+    /// `direction` will be normalized.
+    ///
+    /// [`RayCone`]: struct.RayCone.html
+    ///
+    pub fn new(origin: Point3, direction: Vector3, spread_angle: f32) -> RayCone {
+        RayCone {
+            ray: Ray::new(origin, direction),
+            spread_angle,
         }
     }
+
+    /// Returns the radius of the footprint of the cone at distance `t` along its [`Ray`].
+    ///
+    /// [`Ray`]: struct.Ray.html
+    ///
+    pub fn radius_at(&self, t: f32) -> f32 {
+        t * (self.spread_angle * 0.5).tan()
+    }
+}
+
+/// Implementation of [`Intersectable`] for [`AABB`]. The returned distance is the distance
+/// at which `ray` enters the [`AABB`], or `0.0` if the origin of `ray` lies inside of it.
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`Intersectable`]: trait.Intersectable.html
+///
+impl Intersectable for AABB {
+    /// Treats the [`AABB`] as a solid box: a ray starting inside it hits it at distance `0.0`.
+    /// Use [`Ray::intersection_interval`] to get the point where such a ray exits the box.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`Ray::intersection_interval`]: struct.Ray.html#method.intersection_interval
+    ///
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        ray.intersection_interval(self)
+            .map(|(entry, _)| entry.max(0.0))
+    }
+}
+
+/// Implementation of [`Intersectable`] for references to [`Intersectable`] shapes.
+///
+/// [`Intersectable`]: trait.Intersectable.html
+///
+impl<T: Intersectable + ?Sized> Intersectable for &T {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        (**self).intersect(ray)
+    }
+}
+
+/// Implementation of [`Intersectable`] for boxed shapes, including trait objects.
+///
+/// [`Intersectable`]: trait.Intersectable.html
+///
+impl<T: Intersectable + ?Sized> Intersectable for Box<T> {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        (**self).intersect(ray)
+    }
+}
+
+/// Implementation of [`Intersectable`] for reference counted shapes, including trait objects.
+///
+/// [`Intersectable`]: trait.Intersectable.html
+///
+impl<T: Intersectable + ?Sized> Intersectable for Rc<T> {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        (**self).intersect(ray)
+    }
+}
+
+/// Implementation of [`Intersectable`] for atomically reference counted shapes,
+/// including trait objects.
+///
+/// [`Intersectable`]: trait.Intersectable.html
+///
+#[cfg(target_has_atomic = "ptr")]
+impl<T: Intersectable + ?Sized> Intersectable for Arc<T> {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        (**self).intersect(ray)
+    }
+}
+
+/// Implementation of [`SpanIntersectable`] for [`AABB`]. A ray spends at most one interval
+/// inside an [`AABB`], see [`Ray::intersection_interval`].
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`Ray::intersection_interval`]: struct.Ray.html#method.intersection_interval
+/// [`SpanIntersectable`]: trait.SpanIntersectable.html
+///
+impl SpanIntersectable for AABB {
+    fn intervals(&self, ray: &Ray) -> Vec<(f32, f32)> {
+        ray.intersection_interval(self).into_iter().collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::cmp;
-    use std::f32::INFINITY;
 
     use crate::aabb::AABB;
-    use crate::ray::Ray;
+    use crate::ray::{Intersectable, Ray, RaycastResult, SpanIntersectable};
     use crate::testbase::{tuple_to_point, tuplevec_small_strategy, TupleVec};
-    use crate::EPSILON;
+    use crate::{Point3, Vector3, EPSILON};
 
     use proptest::prelude::*;
 
+    #[test]
+    /// Tests the accessors of `RaycastResult`.
+    fn test_raycast_result_t() {
+        assert_eq!(RaycastResult::Miss.t(), None);
+        assert_eq!(RaycastResult::Hit(1.5).t(), Some(1.5));
+        assert!(!RaycastResult::Miss.is_hit());
+        assert!(RaycastResult::Hit(0.0).is_hit());
+        assert_eq!(RaycastResult::from(None), RaycastResult::Miss);
+        assert_eq!(RaycastResult::from(Some(2.0)), RaycastResult::Hit(2.0));
+    }
+
+    #[test]
+    /// Tests whether `map` and `filter` only touch hits, and `filter` masks rejected hits.
+    fn test_raycast_result_map_filter() {
+        assert_eq!(
+            RaycastResult::Hit(2.0).map(|t| t - 0.5),
+            RaycastResult::Hit(1.5)
+        );
+        assert_eq!(RaycastResult::Miss.map(|_| 1.0), RaycastResult::Miss);
+        assert_eq!(
+            RaycastResult::Hit(2.0).filter(|t| t > 1.0),
+            RaycastResult::Hit(2.0)
+        );
+        assert_eq!(
+            RaycastResult::Hit(0.5).filter(|t| t > 1.0),
+            RaycastResult::Miss
+        );
+        assert_eq!(RaycastResult::Miss.filter(|_| true), RaycastResult::Miss);
+    }
+
+    #[test]
+    /// Tests whether sorting `RaycastResult`s orders the hits by distance, followed by the misses.
+    fn test_raycast_result_ord() {
+        let mut results = vec![
+            RaycastResult::Miss,
+            RaycastResult::Hit(3.0),
+            RaycastResult::Hit(-1.0),
+            RaycastResult::Miss,
+            RaycastResult::Hit(0.5),
+        ];
+        assert_eq!(results.iter().min(), Some(&RaycastResult::Hit(-1.0)));
+
+        results.sort();
+        assert_eq!(
+            results,
+            vec![
+                RaycastResult::Hit(-1.0),
+                RaycastResult::Hit(0.5),
+                RaycastResult::Hit(3.0),
+                RaycastResult::Miss,
+                RaycastResult::Miss,
+            ]
+        );
+        assert!(RaycastResult::Hit(f32::INFINITY) < RaycastResult::Miss);
+    }
+
     /// Generates a random `Ray` which points at at a random `AABB`.
     fn gen_ray_to_aabb(data: (TupleVec, TupleVec, TupleVec)) -> (Ray, AABB) {
         // Generate a random AABB
@@ -340,6 +911,29 @@ mod tests {
         (ray, aabb)
     }
 
+    proptest! {
+        // Test whether the points along a `Ray` lie at the right distance from its origin,
+        // in its direction.
+        #[test]
+        fn test_ray_at(origin in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                       direction in (-1.0f32..1.0, -1.0f32..1.0, -1.0f32..1.0),
+                       t_min in -100.0f32..100.0,
+                       t_max in -100.0f32..100.0) {
+            let direction = tuple_to_point(&direction);
+            prop_assume!(direction.length() > 0.1);
+            let ray = Ray::new(tuple_to_point(&origin), direction);
+
+            assert_eq!(ray.at(0.0), ray.origin);
+            let point = ray.at(t_max);
+            assert!(((point - ray.origin).length() - t_max.abs()).abs() < EPSILON * 100.0);
+            assert!(((point - ray.origin).dot(ray.direction) - t_max).abs() < EPSILON * 100.0);
+
+            let (a, b) = ray.segment(t_min, t_max);
+            assert_eq!(a, ray.at(t_min));
+            assert_eq!(b, ray.at(t_max));
+        }
+    }
+
     proptest! {
         // Test whether a `Ray` which points at the center of an `AABB` intersects it.
         // Uses the optimized algorithm.
@@ -371,6 +965,29 @@ mod tests {
             assert!(ray.intersects_aabb_branchless(&aabb));
         }
 
+        // Test whether the padded test reports a `Ray` aimed exactly at a corner of an `AABB`,
+        // which the exact test misses for some inputs due to rounding, and whether it accepts
+        // everything the exact test accepts.
+        #[test]
+        fn test_ray_points_at_aabb_corner_eps(corner in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                                              size in (0.1f32..10.0, 0.1f32..10.0, 0.1f32..10.0),
+                                              origin in (-100.0f32..100.0, -100.0f32..100.0, -100.0f32..100.0),
+                                              signs in 0usize..8) {
+            let corner = Point3::new(corner.0, corner.1, corner.2);
+            let size = Vector3::new(
+                if signs & 1 == 0 { size.0 } else { -size.0 },
+                if signs & 2 == 0 { size.1 } else { -size.1 },
+                if signs & 4 == 0 { size.2 } else { -size.2 },
+            );
+            let aabb = AABB::empty().grow(&corner).grow(&(corner + size));
+            let origin = Point3::new(origin.0, origin.1, origin.2);
+            let ray = Ray::new(origin, corner - origin);
+
+            assert!(ray.intersects_aabb_eps(&aabb, 1e-3));
+            let exact = ray.intersects_aabb(&aabb);
+            assert!(!exact || ray.intersects_aabb_eps(&aabb, 0.0));
+        }
+
         // Test whether a `Ray` which points away from the center of an `AABB`
         // does not intersect it, unless its origin is inside the `AABB`.
         // Uses the optimized algorithm.
@@ -451,12 +1068,12 @@ mod tests {
             // Either the intersection is in the back side (including the triangle-plane)
             if on_back_side {
                 // Intersection must be INFINITY, u and v are undefined
-                assert!(intersects.distance == INFINITY);
+                assert!(intersects.distance == f32::INFINITY);
             } else {
                 // Or it is on the front side
                 // Either the intersection is inside the triangle, which it should be
                 // for all u, v such that u+v <= 1.0
-                let intersection_inside = (0.0..=1.0).contains(&uv_sum) && intersects.distance < INFINITY;
+                let intersection_inside = (0.0..=1.0).contains(&uv_sum) && intersects.distance < f32::INFINITY;
 
                 // Or the input data was close to the border
                 let close_to_border =
@@ -475,6 +1092,39 @@ mod tests {
                 assert!(intersection_inside || close_to_border);
             }
         }
+
+        // Test whether rays starting inside an `AABB` intersect it, enter it behind
+        // their origin and exit it in front of it.
+        #[test]
+        fn test_ray_starts_inside_aabb(data in (tuplevec_small_strategy(),
+                                                tuplevec_small_strategy(),
+                                                tuplevec_small_strategy())) {
+            let (ray, aabb) = gen_ray_to_aabb(data);
+            let ray = Ray::new(aabb.center(), ray.direction);
+
+            assert!(ray.intersects_aabb(&aabb));
+            assert!(ray.intersects_aabb_naive(&aabb));
+            assert!(ray.intersects_aabb_branchless(&aabb));
+
+            let (entry, exit) = ray.intersection_interval(&aabb).unwrap();
+            assert!(entry <= 0.0);
+            assert!(exit >= 0.0);
+            assert_eq!(aabb.intersect(&ray), Some(0.0));
+            assert_eq!(aabb.intervals(&ray), vec![(entry, exit)]);
+        }
+    }
+
+    #[test]
+    /// Tests the intervals of a ray which passes through, misses or points away from an `AABB`.
+    fn test_aabb_intervals() {
+        let aabb = AABB::with_bounds(Point3::new(2.0, -1.0, -1.0), Point3::new(6.0, 1.0, 1.0));
+        let hit = Ray::new(Point3::ZERO, Vector3::new(1.0, 0.0, 0.0));
+        let miss = Ray::new(Point3::ZERO, Vector3::new(0.0, 1.0, 0.0));
+        let behind = Ray::new(Point3::ZERO, Vector3::new(-1.0, 0.0, 0.0));
+
+        assert_eq!(aabb.intervals(&hit), vec![(2.0, 6.0)]);
+        assert!(aabb.intervals(&miss).is_empty());
+        assert!(aabb.intervals(&behind).is_empty());
     }
 }
 
@@ -13,9 +13,10 @@ use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 use rand::SeedableRng;
 
-use crate::aabb::{Bounded, AABB};
+use crate::aabb::{Bounded, SurfacePoint, AABB};
 use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
-use crate::ray::Ray;
+use crate::bvh::{BVHConfig, BVH};
+use crate::ray::{Intersectable, Ray};
 
 /// A vector represented as a tuple
 pub type TupleVec = (f32, f32, f32);
@@ -41,6 +42,13 @@ pub fn tuplevec_large_strategy() -> impl Strategy<Value = TupleVec> {
     )
 }
 
+/// Generate a `TupleVec` of whole numbers from `-extent` to `extent - 1`.
+/// Points on a grid make ties and touching boxes likely.
+pub fn tuplevec_grid_strategy(extent: i32) -> impl Strategy<Value = TupleVec> {
+    (-extent..extent, -extent..extent, -extent..extent)
+        .prop_map(|(x, y, z)| (x as f32, y as f32, z as f32))
+}
+
 /// Convert a `TupleVec` to a [`Point3`].
 pub fn tuple_to_point(tpl: &TupleVec) -> Point3 {
     Point3::new(tpl.0, tpl.1, tpl.2)
@@ -87,6 +95,76 @@ impl BHShape for UnitBox {
     }
 }
 
+/// `UnitBox`es are intersected like their `AABB`s.
+impl Intersectable for UnitBox {
+    fn intersect(&self, ray: &Ray) -> Option<f32> {
+        self.aabb().intersect(ray)
+    }
+}
+
+/// `UnitBox`es are solid boxes, so their closest point is the one of their `AABB`s.
+impl SurfacePoint for UnitBox {
+    fn closest_point_on(&self, p: &Point3) -> Point3 {
+        self.aabb().closest_point_on(p)
+    }
+}
+
+/// Create a `UnitBox` at each of the `positions`, with the index of the position as its id.
+pub fn unit_boxes(positions: &[TupleVec]) -> Vec<UnitBox> {
+    positions
+        .iter()
+        .enumerate()
+        .map(|(id, p)| UnitBox::new(id as i32, tuple_to_point(p)))
+        .collect()
+}
+
+/// Build a [`BVH`] over `shapes` with the default config, but at most `max_leaf_size`
+/// shapes per leaf.
+pub fn build_with_max_leaf_size(shapes: &mut [UnitBox], max_leaf_size: usize) -> BVH {
+    let config = BVHConfig {
+        max_leaf_size,
+        ..Default::default()
+    };
+    BVH::build_with_config(shapes, &config)
+}
+
+/// A ray from a random origin towards a random shape, generated by [`ray_toward_random_shape`].
+#[derive(Clone, Debug)]
+pub struct RayAim {
+    origin: TupleVec,
+    target: prop::sample::Index,
+    jitter: TupleVec,
+}
+
+impl RayAim {
+    /// The center of the chosen shape, offset by the jitter.
+    pub fn target(&self, shapes: &[UnitBox]) -> Point3 {
+        shapes[self.target.index(shapes.len())].pos + tuple_to_vector(&self.jitter)
+    }
+
+    /// Create the ray from the origin towards the [`target`](RayAim::target).
+    pub fn ray(&self, shapes: &[UnitBox]) -> Ray {
+        let origin = tuple_to_point(&self.origin);
+        Ray::new(origin, self.target(shapes) - origin)
+    }
+}
+
+/// Generate a [`RayAim`] with its origin from `-extent` to `extent` and at most `jitter` on
+/// each axis between its target and the center of the chosen shape.
+/// Aiming somewhere close to a random shape makes the ray hit something most of the time.
+pub fn ray_toward_random_shape(extent: f32, jitter: f32) -> impl Strategy<Value = RayAim> {
+    (
+        (-extent..extent, -extent..extent, -extent..extent),
+        any::<prop::sample::Index>(),
+        (-jitter..=jitter, -jitter..=jitter, -jitter..=jitter),
+    )
+        .prop_map(|(origin, target, jitter)| RayAim {
+            origin,
+            target,
+            jitter,
+        })
+}
+
 /// Generate 21 `UnitBox`s along the X axis centered on whole numbers (-10,9,..,10).
 /// The index is set to the rounded x-coordinate of the box center.
 pub fn generate_aligned_boxes() -> Vec<UnitBox> {
@@ -338,7 +416,6 @@ pub fn next_point3_raw(seed: &mut u64) -> (i32, i32, i32) {
 /// Generates a new `Point3`, which will lie inside the given `aabb`. Mutates the seed.
 pub fn next_point3(seed: &mut u64, aabb: &AABB) -> Point3 {
     let (a, b, c) = next_point3_raw(seed);
-    use std::i32;
     let float_vector = Vector3::new(
         (a as f32 / i32::MAX as f32) + 1.0,
         (b as f32 / i32::MAX as f32) + 1.0,
@@ -400,7 +477,7 @@ pub fn load_sponza_scene() -> (Vec<Triangle>, AABB) {
 /// offset of a shape. This is used to simulate a realistic scene.
 /// Returns a `HashSet` of indices of modified triangles.
 pub fn randomly_transform_scene(
-    triangles: &mut Vec<Triangle>,
+    triangles: &mut [Triangle],
     amount: usize,
     bounds: &AABB,
     max_offset_option: Option<f32>,
@@ -416,11 +493,7 @@ pub fn randomly_transform_scene(
     indices.shuffle(&mut rng);
     indices.truncate(amount);
 
-    let max_offset = if let Some(value) = max_offset_option {
-        value
-    } else {
-        f32::INFINITY
-    };
+    let max_offset = max_offset_option.unwrap_or(f32::INFINITY);
 
     for index in &indices {
         let aabb = triangles[*index].aabb();
@@ -456,6 +529,13 @@ pub fn create_ray(seed: &mut u64, bounds: &AABB) -> Ray {
     Ray::new(origin, direction)
 }
 
+/// Creates `n` deterministic random `Ray`s inside the `bounds`.
+#[cfg(feature = "bench")]
+pub fn create_rays(n: usize, bounds: &AABB) -> Vec<Ray> {
+    let mut seed = 0;
+    (0..n).map(|_| create_ray(&mut seed, bounds)).collect()
+}
+
 /// Benchmark the construction of a `BoundingHierarchy` with `n` triangles.
 #[cfg(feature = "bench")]
 fn build_n_triangles_bh<T: BoundingHierarchy>(n: usize, b: &mut ::test::Bencher) {
@@ -3,19 +3,28 @@
 use crate::aabb::AABB;
 use crate::bounding_hierarchy::BHShape;
 
-/// Concatenates the list of vectors into a single vector.
-/// Drains the elements from the source `vectors`.
-pub fn concatenate_vectors<T: Sized>(vectors: &mut [Vec<T>]) -> Vec<T> {
-    let mut result = Vec::new();
-    for vector in vectors.iter_mut() {
-        result.append(vector);
+/// Reorders `items` in place, so that the element at position `i` afterwards is the element
+/// which was at position `order[i]` before. `order` must be a permutation of
+/// `0..items.len()`, and is turned into the identity permutation in the process.
+pub fn apply_permutation<T>(items: &mut [T], order: &mut [usize]) {
+    assert_eq!(items.len(), order.len());
+    for start in 0..items.len() {
+        // Walk the cycle of the permutation which contains `start`. Elements which are in
+        // place already point to themselves.
+        let mut current = start;
+        while order[current] != start {
+            let next = order[current];
+            items.swap(current, next);
+            order[current] = current;
+            current = next;
+        }
+        order[current] = current;
     }
-    result
 }
 
 /// Defines a Bucket utility object. Used to store the properties of shape-partitions
 /// in the BVH build procedure using SAH.
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct Bucket {
     /// The number of shapes in this `Bucket`.
     pub size: usize,
@@ -59,25 +68,15 @@ pub fn joint_aabb_of_shapes<Shape: BHShape>(indices: &[usize], shapes: &[Shape])
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::concatenate_vectors;
-
-    #[test]
-    /// Test if concatenating no `Vec`s yields an empty `Vec`.
-    fn test_concatenate_empty() {
-        let mut vectors: Vec<Vec<usize>> = vec![];
-        let expected = vec![];
-        assert_eq!(concatenate_vectors(vectors.as_mut_slice()), expected);
-        let expected_remainder: Vec<Vec<usize>> = vec![];
-        assert_eq!(vectors, expected_remainder);
-    }
+    use crate::utils::apply_permutation;
 
     #[test]
-    /// Test if concatenating some `Vec`s yields the concatenation of the vectors.
-    fn test_concatenate_vectors() {
-        let mut vectors = vec![vec![1, 2, 3], vec![], vec![4, 5, 6], vec![7, 8], vec![9]];
-        let result = concatenate_vectors(vectors.as_mut_slice());
-        let expected = vec![1, 2, 3, 4, 5, 6, 7, 8, 9];
-        assert_eq!(result, expected);
-        assert_eq!(vectors, vec![vec![], vec![], vec![], vec![], vec![]]);
+    /// Test if applying a permutation moves every element to its new position.
+    fn test_apply_permutation() {
+        let mut items = vec!['a', 'b', 'c', 'd', 'e', 'f'];
+        let mut order = vec![3, 0, 4, 1, 2, 5];
+        apply_permutation(&mut items, &mut order);
+        assert_eq!(items, vec!['d', 'a', 'e', 'b', 'c', 'f']);
+        assert_eq!(order, vec![0, 1, 2, 3, 4, 5]);
     }
 }
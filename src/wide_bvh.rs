@@ -0,0 +1,481 @@
+//! This module defines [`WideBVH`], a variant of the [`BVH`] whose nodes have up to
+//! 4 or 8 children, which are all tested against a query at once.
+//!
+//! [`BVH`]: ../bvh/struct.BVH.html
+//! [`WideBVH`]: struct.WideBVH.html
+//!
+
+use crate::aabb::{Bounded, AABB};
+use crate::bounding_hierarchy::{BHShape, BoundingHierarchy};
+use crate::bvh::{BVHNode, BVH};
+use crate::ray::Ray;
+
+/// A child of a [`WideNode`].
+///
+/// [`WideNode`]: struct.WideNode.html
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub enum WideChild {
+    /// An inner node, referenced by its index in [`WideBVH::nodes`].
+    ///
+    /// [`WideBVH::nodes`]: struct.WideBVH.html#structfield.nodes
+    ///
+    Node(u32),
+
+    /// A leaf, referenced by the index of its first shape and its number of shapes.
+    Leaf(u32, u32),
+}
+
+/// A node of a [`WideBVH`]. Its children and their [`AABB`]s are stored next to each
+/// other in [`WideBVH::children`] and [`WideBVH::child_aabbs`].
+///
+/// [`AABB`]: ../aabb/struct.AABB.html
+/// [`WideBVH`]: struct.WideBVH.html
+/// [`WideBVH::children`]: struct.WideBVH.html#structfield.children
+/// [`WideBVH::child_aabbs`]: struct.WideBVH.html#structfield.child_aabbs
+///
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct WideNode {
+    /// The index of the first child in [`WideBVH::children`].
+    ///
+    /// [`WideBVH::children`]: struct.WideBVH.html#structfield.children
+    ///
+    pub first_child: u32,
+
+    /// The number of children, between 2 and the branching factor of the [`WideBVH`].
+    /// Only the root of a [`WideBVH`] consisting of a single leaf has one child.
+    ///
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    pub num_children: u32,
+}
+
+impl WideNode {
+    /// Returns the range of this node's children in [`WideBVH::children`].
+    ///
+    /// [`WideBVH::children`]: struct.WideBVH.html#structfield.children
+    ///
+    pub fn child_range(&self) -> std::ops::Range<usize> {
+        let first = self.first_child as usize;
+        first..first + self.num_children as usize
+    }
+}
+
+/// A bounding volume hierarchy with up to 4 or 8 children per node, created by
+/// collapsing the subtrees of a binary [`BVH`].
+///
+/// The nodes are stored in depth-first order, and children are traversed in the
+/// order of the binary tree. Therefore [`WideBVH::traverse`] returns exactly the same
+/// shapes in the same order as [`BVH::traverse`] does.
+///
+/// [`BVH`]: ../bvh/struct.BVH.html
+/// [`BVH::traverse`]: ../bvh/struct.BVH.html#method.traverse
+/// [`WideBVH::traverse`]: struct.WideBVH.html#method.traverse
+///
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde_impls", derive(serde::Serialize, serde::Deserialize))]
+pub struct WideBVH {
+    /// The maximum number of children of a node, either 4 or 8.
+    pub branching: usize,
+
+    /// The list of nodes of the [`WideBVH`]. The root is at index `0`.
+    ///
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    pub nodes: Vec<WideNode>,
+
+    /// The children of all nodes. The children of a node are stored contiguously.
+    pub children: Vec<WideChild>,
+
+    /// The [`AABB`]s of the `children`, at the same indices.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    pub child_aabbs: Vec<AABB>,
+}
+
+impl WideBVH {
+    /// Collapses the subtree below the binary inner node `node_index` into [`WideNode`]s.
+    /// Returns the index of the new node.
+    ///
+    /// [`WideNode`]: struct.WideNode.html
+    ///
+    fn collapse(&mut self, bvh_nodes: &[BVHNode], node_index: usize) -> u32 {
+        let node = &bvh_nodes[node_index];
+        let mut slots = vec![
+            (node.child_l(), node.child_l_aabb()),
+            (node.child_r(), node.child_r_aabb()),
+        ];
+
+        // Open the inner child with the largest surface area until the node is full.
+        // Its children take its place, so that the order of the binary tree is kept.
+        while slots.len() < self.branching {
+            let mut largest: Option<(usize, f32)> = None;
+            for (slot, &(child_index, ref child_aabb)) in slots.iter().enumerate() {
+                if let BVHNode::Node { .. } = bvh_nodes[child_index] {
+                    let surface_area = child_aabb.surface_area();
+                    match largest {
+                        Some((_, largest_area)) if largest_area >= surface_area => {}
+                        _ => largest = Some((slot, surface_area)),
+                    }
+                }
+            }
+
+            let slot = match largest {
+                Some((slot, _)) => slot,
+                None => break,
+            };
+            let opened = &bvh_nodes[slots[slot].0];
+            slots.splice(
+                slot..=slot,
+                [
+                    (opened.child_l(), opened.child_l_aabb()),
+                    (opened.child_r(), opened.child_r_aabb()),
+                ],
+            );
+        }
+
+        let index = self.nodes.len();
+        let first_child = self.children.len();
+        self.nodes.push(WideNode {
+            first_child: first_child as u32,
+            num_children: slots.len() as u32,
+        });
+        for &(_, child_aabb) in &slots {
+            self.children.push(WideChild::Leaf(0, 0));
+            self.child_aabbs.push(child_aabb);
+        }
+
+        for (slot, (child_index, _)) in slots.into_iter().enumerate() {
+            self.children[first_child + slot] = match bvh_nodes[child_index] {
+                BVHNode::Node { .. } => WideChild::Node(self.collapse(bvh_nodes, child_index)),
+                BVHNode::Leaf {
+                    shape_index,
+                    shape_count,
+                    ..
+                } => WideChild::Leaf(shape_index as u32, shape_count as u32),
+            };
+        }
+
+        index as u32
+    }
+
+    /// Creates a [`WideBVH`] with up to `branching` children per node from a binary [`BVH`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `branching` is neither 4 nor 8.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    pub fn from_bvh(bvh: &BVH, branching: usize) -> WideBVH {
+        assert!(
+            branching == 4 || branching == 8,
+            "the branching factor of a WideBVH must be 4 or 8, not {}",
+            branching
+        );
+
+        let mut wide = WideBVH {
+            branching,
+            nodes: Vec::new(),
+            children: Vec::new(),
+            child_aabbs: Vec::new(),
+        };
+        match bvh.nodes.first() {
+            None => {}
+            Some(BVHNode::Node { .. }) => {
+                wide.collapse(&bvh.nodes, 0);
+            }
+            Some(&BVHNode::Leaf {
+                shape_index,
+                shape_count,
+                ..
+            }) => {
+                // A `BVH` consisting of a single leaf reports its shapes for every query.
+                // An infinite `AABB` is hit by every query, which keeps that behaviour.
+                wide.nodes.push(WideNode {
+                    first_child: 0,
+                    num_children: 1,
+                });
+                wide.children
+                    .push(WideChild::Leaf(shape_index as u32, shape_count as u32));
+                wide.child_aabbs.push(AABB::infinite());
+            }
+        }
+        wide
+    }
+
+    /// Visits the leaves below the children whose [`AABB`]s pass `test`, in the order
+    /// of the binary tree. `visit` is called with the index of the first shape and the
+    /// number of shapes of each leaf.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    ///
+    fn traverse_with<T: Fn(&AABB) -> bool, F: FnMut(usize, usize)>(&self, test: T, mut visit: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        let mut stack = Vec::with_capacity(64);
+        stack.push(WideChild::Node(0));
+        while let Some(child) = stack.pop() {
+            match child {
+                WideChild::Node(index) => {
+                    let range = self.nodes[index as usize].child_range();
+
+                    // Test all children before descending into any of them.
+                    let mut mask = 0u8;
+                    for (slot, child_aabb) in self.child_aabbs[range.clone()].iter().enumerate() {
+                        if test(child_aabb) {
+                            mask |= 1 << slot;
+                        }
+                    }
+
+                    // Push in reverse, so that the children are visited in order.
+                    for (slot, child) in self.children[range].iter().enumerate().rev() {
+                        if mask & (1 << slot) != 0 {
+                            stack.push(*child);
+                        }
+                    }
+                }
+                WideChild::Leaf(shape_index, shape_count) => {
+                    visit(shape_index as usize, shape_count as usize);
+                }
+            }
+        }
+    }
+
+    /// Traverses the [`WideBVH`].
+    /// Returns a subset of `shapes`, in which the [`AABB`]s of the elements were hit by `ray`.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    pub fn traverse<'a, Shape: Bounded>(
+        &'a self,
+        ray: &Ray,
+        shapes: &'a [Shape],
+    ) -> Vec<&'a Shape> {
+        let mut hits = Vec::new();
+        self.traverse_with(
+            |aabb| ray.intersects_aabb(aabb),
+            |shape_index, shape_count| {
+                hits.extend(&shapes[shape_index..shape_index + shape_count]);
+            },
+        );
+        hits
+    }
+
+    /// Returns the indices of the shapes in all leaves whose [`AABB`]s intersect `query`,
+    /// like [`BVH::query_aabb`] does.
+    ///
+    /// [`AABB`]: ../aabb/struct.AABB.html
+    /// [`BVH::query_aabb`]: ../bvh/struct.BVH.html#method.query_aabb
+    ///
+    pub fn query_aabb(&self, query: &AABB) -> Vec<usize> {
+        let mut out = Vec::new();
+        self.traverse_with(
+            |aabb| query.intersects_aabb(aabb),
+            |shape_index, shape_count| out.extend(shape_index..shape_index + shape_count),
+        );
+        out
+    }
+}
+
+impl BVH {
+    /// Collapses the [`BVH`] into a [`WideBVH`] with up to `branching` children per node.
+    /// `branching` must be 4 or 8. Inner nodes are opened greedily, largest surface area
+    /// first, until a node is full or only has leaves left.
+    ///
+    /// Compared to [`to_bvh4`], the children are not stored for SIMD tests, but any
+    /// binary tree collapses into fully used nodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `branching` is neither 4 nor 8.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bvh::aabb::AABB;
+    /// use bvh::bvh::BVH;
+    /// use bvh::ray::Ray;
+    /// use bvh::{Point3, Vector3};
+    ///
+    /// let boxes = (0..1000)
+    ///     .map(|i| AABB::with_bounds(Point3::splat(i as f32), Point3::splat(i as f32 + 1.0)))
+    ///     .collect::<Vec<_>>();
+    /// let bvh = BVH::build_dyn(&boxes);
+    /// let wide = bvh.collapse_wide(8);
+    /// assert!(wide.nodes.len() < bvh.nodes.len() / 3);
+    ///
+    /// let ray = Ray::new(Point3::new(0.5, 0.5, 0.0), Vector3::new(0.0, 0.0, 1.0));
+    /// assert_eq!(wide.traverse(&ray, &boxes).len(), bvh.traverse(&ray, &boxes).len());
+    ///
+    /// let query = AABB::with_bounds(Point3::splat(10.5), Point3::splat(20.5));
+    /// assert_eq!(wide.query_aabb(&query), bvh.query_aabb(&query));
+    /// ```
+    ///
+    /// [`BVH`]: struct.BVH.html
+    /// [`WideBVH`]: ../wide_bvh/struct.WideBVH.html
+    /// [`to_bvh4`]: struct.BVH.html#method.to_bvh4
+    ///
+    pub fn collapse_wide(&self, branching: usize) -> WideBVH {
+        WideBVH::from_bvh(self, branching)
+    }
+}
+
+impl BoundingHierarchy for WideBVH {
+    /// A [`WideBVH`] is built from a regular [`BVH`] using the [`collapse_wide`] method,
+    /// with up to 8 children per node.
+    ///
+    /// [`BVH`]: ../bvh/struct.BVH.html
+    /// [`WideBVH`]: struct.WideBVH.html
+    /// [`collapse_wide`]: ../bvh/struct.BVH.html#method.collapse_wide
+    ///
+    fn build<T: BHShape>(shapes: &mut [T]) -> WideBVH {
+        BVH::build(shapes).collapse_wide(8)
+    }
+
+    fn traverse<'a, Shape: Bounded>(&'a self, ray: &Ray, shapes: &'a [Shape]) -> Vec<&'a Shape> {
+        self.traverse(ray, shapes)
+    }
+
+    /// Prints a textual representation of a [`WideBVH`].
+    ///
+    /// [`WideBVH`]: struct.WideBVH.html
+    ///
+    fn pretty_print(&self) {
+        for (i, node) in self.nodes.iter().enumerate() {
+            println!("{}\t{:?}", i, &self.children[node.child_range()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aabb::AABB;
+    use crate::bvh::BVH;
+    use crate::ray::Ray;
+    use crate::testbase::{
+        build_some_bh, build_with_max_leaf_size, ray_toward_random_shape, traverse_some_bh,
+        unit_boxes, UnitBox,
+    };
+    use crate::wide_bvh::{WideBVH, WideChild};
+    use crate::{Point3, Vector3};
+
+    use proptest::prelude::*;
+
+    #[test]
+    /// Tests whether the building procedure succeeds in not failing.
+    fn test_build_wide_bvh() {
+        build_some_bh::<WideBVH>();
+    }
+
+    #[test]
+    /// Runs some primitive tests for intersections of a ray with a fixed scene given as a `WideBVH`.
+    fn test_traverse_wide_bvh() {
+        traverse_some_bh::<WideBVH>();
+    }
+
+    #[test]
+    #[should_panic]
+    /// Tests that only branching factors of 4 and 8 are accepted.
+    fn test_collapse_wide_rejects_branching() {
+        let mut shapes = vec![UnitBox::new(0, Point3::ZERO), UnitBox::new(1, Point3::X)];
+        BVH::build(&mut shapes).collapse_wide(3);
+    }
+
+    #[test]
+    /// Tests that a `WideBVH` of a single shape behaves like the `BVH` it was built from.
+    fn test_traverse_single_shape_wide_bvh() {
+        let mut shapes = vec![UnitBox::new(0, Point3::ZERO)];
+        let bvh = BVH::build(&mut shapes);
+        let wide = bvh.collapse_wide(4);
+
+        let ray = Ray::new(Point3::new(0.0, 5.0, 0.0), Vector3::X);
+        assert_eq!(wide.traverse(&ray, &shapes).len(), 1);
+        assert_eq!(bvh.traverse(&ray, &shapes).len(), 1);
+    }
+
+    proptest! {
+        // Test whether the `WideBVH` yields the same shapes in the same order as the `BVH`
+        // it was built from, for rays as well as `AABB` queries, and whether every node
+        // but the root of a single leaf has between 2 and `branching` children.
+        #[test]
+        fn test_wide_bvh_queries_match_bvh(
+            positions in prop::collection::vec((-20.0f32..20.0, -20.0f32..20.0, -20.0f32..20.0), 1..128),
+            aim in ray_toward_random_shape(50.0, 2.0),
+            half_size in 0.0f32..10.0,
+            max_leaf_size in 1usize..4,
+            eight: bool,
+        ) {
+            let mut shapes = unit_boxes(&positions);
+            let bvh = build_with_max_leaf_size(&mut shapes, max_leaf_size);
+            let branching = if eight { 8 } else { 4 };
+            let wide = bvh.collapse_wide(branching);
+
+            for node in &wide.nodes {
+                assert!(node.num_children as usize <= branching);
+                assert!(node.num_children >= 2 || wide.nodes.len() == 1);
+            }
+            let leaf_shapes = wide
+                .children
+                .iter()
+                .map(|child| match child {
+                    WideChild::Leaf(_, shape_count) => *shape_count as usize,
+                    WideChild::Node(_) => 0,
+                })
+                .sum::<usize>();
+            assert_eq!(leaf_shapes, shapes.len());
+
+            let ray = aim.ray(&shapes);
+            let expected = bvh.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            let found = wide.traverse(&ray, &shapes).iter().map(|shape| shape.id).collect::<Vec<_>>();
+            assert_eq!(expected, found);
+
+            let center = aim.target(&shapes);
+            let query = AABB::with_bounds(center - Vector3::splat(half_size), center + Vector3::splat(half_size));
+            assert_eq!(wide.query_aabb(&query), bvh.query_aabb(&query));
+        }
+    }
+}
+
+#[cfg(all(feature = "bench", test))]
+mod bench {
+    use crate::bvh::BVH;
+    use crate::testbase::{create_n_cubes, default_bounds, intersect_bh};
+
+    #[bench]
+    /// Benchmark collapsing a `BVH` with 120,000 triangles into an 8-wide `WideBVH`.
+    fn bench_collapse_wide_120k_triangles_bvh(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let bvh = BVH::build(&mut triangles);
+
+        b.iter(|| {
+            bvh.collapse_wide(8);
+        });
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using a 4-wide `WideBVH`.
+    fn bench_intersect_120k_triangles_wide4(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let wide = BVH::build(&mut triangles).collapse_wide(4);
+        intersect_bh(&wide, &triangles, &bounds, b)
+    }
+
+    #[bench]
+    /// Benchmark intersecting 120,000 triangles using an 8-wide `WideBVH`.
+    fn bench_intersect_120k_triangles_wide8(b: &mut ::test::Bencher) {
+        let bounds = default_bounds();
+        let mut triangles = create_n_cubes(10_000, &bounds);
+        let wide = BVH::build(&mut triangles).collapse_wide(8);
+        intersect_bh(&wide, &triangles, &bounds, b)
+    }
+}